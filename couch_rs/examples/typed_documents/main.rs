@@ -1,7 +1,7 @@
 use couch_rs::document::TypedCouchDocument;
+use couch_rs::error::CouchError;
 use couch_rs::types::document::DocumentId;
 use couch_rs::CouchDocument;
-use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 
 const TEST_DB: &str = "test_db";
@@ -46,20 +46,16 @@ async fn main() {
             println!("Document has been previously created with Rev: {}", e._rev);
             println!("Name: {} {}", e.first_name, e.last_name);
         }
-        Err(e) => {
-            match e.status {
-                StatusCode::NOT_FOUND => {
-                    // create the document
-                    match db.create(serde_json::to_value(td).unwrap()).await {
-                        Ok(r) => println!("Document was created with ID: {} and Rev: {}", r.get_id(), r.get_rev()),
-                        Err(err) => println!("Oops: {:?}", err),
-                    }
-                }
-                _ => {
-                    println!("Unexpected error: {:?}", e);
-                }
+        Err(CouchError::NotFound { .. }) => {
+            // create the document
+            match db.create(serde_json::to_value(td).unwrap()).await {
+                Ok(r) => println!("Document was created with ID: {} and Rev: {}", r.get_id(), r.get_rev()),
+                Err(err) => println!("Oops: {:?}", err),
             }
         }
+        Err(e) => {
+            println!("Unexpected error: {:?}", e);
+        }
     }
 
     println!("All operations are done")
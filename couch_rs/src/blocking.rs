@@ -0,0 +1,364 @@
+//! Synchronous versions of `Client` and `Database`, for programs that do not use async I/O.
+//!
+//! Requests are run on an internal single-threaded runtime, owned by the client and shared with
+//! its databases. The blocking client must not be used from within an async runtime; calls made
+//! from async code panic.
+//!
+//! ```
+//! use couch_rs::blocking::Client;
+//! use couch_rs::error::CouchResult;
+//! use couch_rs::types::find::FindQuery;
+//! use serde_json::Value;
+//!
+//! const TEST_DB: &str = "test_db";
+//!
+//! fn main() -> CouchResult<()> {
+//!     let client = Client::new_local_test()?;
+//!     let db = client.db(TEST_DB)?;
+//!     let docs = db.find::<Value>(&FindQuery::find_all())?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Operations that are not mirrored here can be run through `Client::block_on`, with the async
+//! database returned by `Database::as_async`.
+
+use crate::document::{DocumentCollection, TypedCouchDocument};
+use crate::error::{CouchError, CouchResult};
+use crate::types::design::{DesignCreated, DesignDocument};
+use crate::types::document::{
+    BulkDeleteResult, DocumentCreatedResult, DocumentDeletedResult, DocumentId, GetOptions, WriteOptions,
+};
+use crate::types::find::{ExplainResult, FindDocuments, FindQuery};
+use crate::types::index::{DatabaseIndexList, IndexFields};
+use crate::types::query::QueryParams;
+use crate::types::system::{ActiveTask, CouchStatus, DbInfo};
+use crate::types::view::ViewCollection;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::runtime::{Builder, Runtime};
+
+/// Blocking version of `couch_rs::Client`
+#[derive(Debug, Clone)]
+pub struct Client {
+    inner: crate::Client,
+    runtime: Arc<Runtime>,
+}
+
+impl Client {
+    /// Blocking version of `couch_rs::Client::new`
+    pub fn new(uri: &str, username: &str, password: &str) -> CouchResult<Client> {
+        Client::from_async(crate::Client::new(uri, username, password)?)
+    }
+
+    /// Blocking version of `couch_rs::Client::new_no_auth`
+    pub fn new_no_auth(uri: &str) -> CouchResult<Client> {
+        Client::from_async(crate::Client::new_no_auth(uri)?)
+    }
+
+    /// Blocking version of `couch_rs::Client::new_local_test`. Use this only for testing!!!
+    pub fn new_local_test() -> CouchResult<Client> {
+        Client::from_async(crate::Client::new_local_test()?)
+    }
+
+    /// Blocking version of `couch_rs::Client::new_with_timeout`. Timeout is in seconds.
+    pub fn new_with_timeout(
+        uri: &str,
+        username: Option<&str>,
+        password: Option<&str>,
+        timeout: u64,
+    ) -> CouchResult<Client> {
+        Client::from_async(crate::Client::new_with_timeout(uri, username, password, timeout)?)
+    }
+
+    /// Wraps a configured async client, such as one with a database prefix or request compression
+    pub fn from_async(client: crate::Client) -> CouchResult<Client> {
+        let runtime = Builder::new_current_thread().enable_all().build().map_err(|err| {
+            CouchError::new(
+                format!("could not start the runtime: {}", err),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+        Ok(Client {
+            inner: client,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// The async client that runs the requests
+    pub fn as_async(&self) -> &crate::Client {
+        &self.inner
+    }
+
+    /// Runs a future of the async API to completion, for operations that have no blocking version
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Blocking version of `couch_rs::Client::list_dbs`
+    pub fn list_dbs(&self) -> CouchResult<Vec<String>> {
+        self.block_on(self.inner.list_dbs())
+    }
+
+    /// Blocking version of `couch_rs::Client::db`
+    pub fn db(&self, dbname: &str) -> CouchResult<Database> {
+        let db = self.block_on(self.inner.db(dbname))?;
+        Ok(self.database(db))
+    }
+
+    /// Blocking version of `couch_rs::Client::make_db`
+    pub fn make_db(&self, dbname: &str) -> CouchResult<Database> {
+        let db = self.block_on(self.inner.make_db(dbname))?;
+        Ok(self.database(db))
+    }
+
+    /// Blocking version of `couch_rs::Client::destroy_db`
+    pub fn destroy_db(&self, dbname: &str) -> CouchResult<bool> {
+        self.block_on(self.inner.destroy_db(dbname))
+    }
+
+    /// Blocking version of `couch_rs::Client::exists`
+    pub fn exists(&self, dbname: &str) -> CouchResult<bool> {
+        self.block_on(self.inner.exists(dbname))
+    }
+
+    /// Blocking version of `couch_rs::Client::get_info`
+    pub fn get_info(&self, dbname: &str) -> CouchResult<DbInfo> {
+        self.block_on(self.inner.get_info(dbname))
+    }
+
+    /// Blocking version of `couch_rs::Client::active_tasks`
+    pub fn active_tasks(&self) -> CouchResult<Vec<ActiveTask>> {
+        self.block_on(self.inner.active_tasks())
+    }
+
+    /// Blocking version of `couch_rs::Client::check_status`
+    pub fn check_status(&self) -> CouchResult<CouchStatus> {
+        self.block_on(self.inner.check_status())
+    }
+
+    fn database(&self, db: crate::database::Database) -> Database {
+        Database {
+            inner: db,
+            runtime: self.runtime.clone(),
+        }
+    }
+}
+
+/// Blocking version of `couch_rs::database::Database`
+#[derive(Debug, Clone)]
+pub struct Database {
+    inner: crate::database::Database,
+    runtime: Arc<Runtime>,
+}
+
+impl Database {
+    /// The async database that runs the requests; run its futures with `Client::block_on`
+    pub fn as_async(&self) -> &crate::database::Database {
+        &self.inner
+    }
+
+    pub fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Blocking version of `Database::try_compact`
+    pub fn try_compact(&self) -> CouchResult<()> {
+        self.block_on(self.inner.try_compact())
+    }
+
+    /// Blocking version of `Database::info`
+    pub fn info(&self) -> CouchResult<DbInfo> {
+        self.block_on(self.inner.info())
+    }
+
+    /// Blocking version of `Database::doc_count`
+    pub fn doc_count(&self) -> CouchResult<u64> {
+        self.block_on(self.inner.doc_count())
+    }
+
+    /// Blocking version of `Database::exists`
+    pub fn exists(&self, id: &str) -> bool {
+        self.block_on(self.inner.exists(id))
+    }
+
+    /// Blocking version of `Database::try_exists`
+    pub fn try_exists(&self, id: &str) -> CouchResult<bool> {
+        self.block_on(self.inner.try_exists(id))
+    }
+
+    /// Blocking version of `Database::get_raw`
+    pub fn get_raw(&self, id: &str) -> CouchResult<Value> {
+        self.block_on(self.inner.get_raw(id))
+    }
+
+    /// Blocking version of `Database::get`
+    pub fn get<T: TypedCouchDocument>(&self, id: &str) -> CouchResult<T> {
+        self.block_on(self.inner.get(id))
+    }
+
+    /// Blocking version of `Database::get_with_options`
+    pub fn get_with_options<T: TypedCouchDocument>(&self, id: &str, options: &GetOptions) -> CouchResult<T> {
+        self.block_on(self.inner.get_with_options(id, options))
+    }
+
+    /// Blocking version of `Database::get_as`
+    pub fn get_as<T: DeserializeOwned>(&self, id: &str) -> CouchResult<T> {
+        self.block_on(self.inner.get_as(id))
+    }
+
+    /// Blocking version of `Database::get_bulk`
+    pub fn get_bulk<T: TypedCouchDocument>(&self, ids: Vec<DocumentId>) -> CouchResult<DocumentCollection<T>> {
+        self.block_on(self.inner.get_bulk(ids))
+    }
+
+    /// Blocking version of `Database::get_bulk_params`
+    pub fn get_bulk_params<T: TypedCouchDocument>(
+        &self,
+        ids: Vec<DocumentId>,
+        params: Option<QueryParams>,
+    ) -> CouchResult<DocumentCollection<T>> {
+        self.block_on(self.inner.get_bulk_params(ids, params))
+    }
+
+    /// Blocking version of `Database::get_all`
+    pub fn get_all<T: TypedCouchDocument>(&self) -> CouchResult<DocumentCollection<T>> {
+        self.block_on(self.inner.get_all())
+    }
+
+    /// Blocking version of `Database::get_all_params`
+    pub fn get_all_params<T: TypedCouchDocument>(
+        &self,
+        params: Option<QueryParams>,
+    ) -> CouchResult<DocumentCollection<T>> {
+        self.block_on(self.inner.get_all_params(params))
+    }
+
+    /// Blocking version of `Database::find`
+    pub fn find<T: TypedCouchDocument>(&self, query: &FindQuery) -> CouchResult<DocumentCollection<T>> {
+        self.block_on(self.inner.find(query))
+    }
+
+    /// Blocking version of `Database::find_one`
+    pub fn find_one<T: TypedCouchDocument>(&self, query: &FindQuery) -> CouchResult<Option<T>> {
+        self.block_on(self.inner.find_one(query))
+    }
+
+    /// Blocking version of `Database::find_as`
+    pub fn find_as<T: DeserializeOwned>(&self, query: &FindQuery) -> CouchResult<FindDocuments<T>> {
+        self.block_on(self.inner.find_as(query))
+    }
+
+    /// Blocking version of `Database::count`
+    pub fn count(&self, selector: &Value) -> CouchResult<u64> {
+        self.block_on(self.inner.count(selector))
+    }
+
+    /// Blocking version of `Database::explain`
+    pub fn explain(&self, query: &FindQuery) -> CouchResult<ExplainResult> {
+        self.block_on(self.inner.explain(query))
+    }
+
+    /// Blocking version of `Database::save`
+    pub fn save<T: TypedCouchDocument>(&self, doc: T) -> CouchResult<T> {
+        self.block_on(self.inner.save(doc))
+    }
+
+    /// Blocking version of `Database::save_with_options`
+    pub fn save_with_options<T: TypedCouchDocument>(&self, doc: T, options: &WriteOptions) -> CouchResult<T> {
+        self.block_on(self.inner.save_with_options(doc, options))
+    }
+
+    /// Blocking version of `Database::create`
+    pub fn create<T: TypedCouchDocument>(&self, doc: T) -> CouchResult<T> {
+        self.block_on(self.inner.create(doc))
+    }
+
+    /// Blocking version of `Database::upsert`
+    pub fn upsert<T: TypedCouchDocument>(&self, doc: T) -> CouchResult<T> {
+        self.block_on(self.inner.upsert(doc))
+    }
+
+    /// Blocking version of `Database::bulk_docs`
+    pub fn bulk_docs<T: TypedCouchDocument>(&self, raw_docs: Vec<T>) -> CouchResult<Vec<DocumentCreatedResult>> {
+        self.block_on(self.inner.bulk_docs(raw_docs))
+    }
+
+    /// Blocking version of `Database::remove`
+    pub fn remove<T: TypedCouchDocument>(&self, doc: T) -> bool {
+        self.block_on(self.inner.remove(doc))
+    }
+
+    /// Blocking version of `Database::remove_checked`
+    pub fn remove_checked<T: TypedCouchDocument>(&self, doc: T) -> CouchResult<DocumentDeletedResult> {
+        self.block_on(self.inner.remove_checked(doc))
+    }
+
+    /// Blocking version of `Database::bulk_remove`
+    pub fn bulk_remove<T: TypedCouchDocument>(&self, docs: Vec<T>) -> CouchResult<BulkDeleteResult> {
+        self.block_on(self.inner.bulk_remove(docs))
+    }
+
+    /// Blocking version of `Database::create_view`
+    pub fn create_view<T: Into<Value>>(&self, design_name: &str, views: T) -> CouchResult<DesignCreated> {
+        self.block_on(self.inner.create_view(design_name, views))
+    }
+
+    /// Blocking version of `Database::get_design`
+    pub fn get_design(&self, design_name: &str) -> CouchResult<DesignDocument> {
+        self.block_on(self.inner.get_design(design_name))
+    }
+
+    /// Blocking version of `Database::put_design`
+    pub fn put_design(&self, design: DesignDocument) -> CouchResult<DesignDocument> {
+        self.block_on(self.inner.put_design(design))
+    }
+
+    /// Blocking version of `Database::delete_design`
+    pub fn delete_design(&self, design_name: &str) -> CouchResult<DocumentDeletedResult> {
+        self.block_on(self.inner.delete_design(design_name))
+    }
+
+    /// Blocking version of `Database::query_raw`
+    pub fn query_raw(
+        &self,
+        design_name: &str,
+        view_name: &str,
+        options: Option<QueryParams>,
+    ) -> CouchResult<ViewCollection<Value, Value, Value>> {
+        self.block_on(self.inner.query_raw(design_name, view_name, options))
+    }
+
+    /// Blocking version of `Database::query`
+    pub fn query<K: DeserializeOwned, V: DeserializeOwned, T: DeserializeOwned>(
+        &self,
+        design_name: &str,
+        view_name: &str,
+        options: Option<QueryParams>,
+    ) -> CouchResult<ViewCollection<K, V, T>> {
+        self.block_on(self.inner.query(design_name, view_name, options))
+    }
+
+    /// Blocking version of `Database::insert_index`
+    pub fn insert_index(&self, name: &str, spec: IndexFields) -> CouchResult<DesignCreated> {
+        self.block_on(self.inner.insert_index(name, spec))
+    }
+
+    /// Blocking version of `Database::read_indexes`
+    pub fn read_indexes(&self) -> CouchResult<DatabaseIndexList> {
+        self.block_on(self.inner.read_indexes())
+    }
+
+    /// Blocking version of `Database::ensure_index`
+    pub fn ensure_index(&self, name: &str, spec: IndexFields) -> CouchResult<bool> {
+        self.block_on(self.inner.ensure_index(name, spec))
+    }
+}
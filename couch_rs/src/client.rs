@@ -1,14 +1,48 @@
 use crate::database::Database;
-use crate::error::{CouchError, CouchResult};
-use crate::types::system::{CouchResponse, CouchStatus, DbInfo};
+use crate::error::{CheckStatus, CouchError, CouchResult, SendWithContext};
+use crate::runtime::{sleep, Instant};
+use crate::slow_requests::{SlowRequestHook, SlowRequests};
+use crate::stream::JsonBody;
+use crate::types::document::RetryPolicy;
+use crate::types::system::{ActiveTask, CouchResponse, CouchStatus, DbInfo, RenameOptions, RenameProgress};
+use crate::types::user::{User, USERS_DB};
 use base64::write::EncoderWriter as Base64Encoder;
-use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, REFERER, USER_AGENT};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_ENCODING, CONTENT_TYPE, REFERER, USER_AGENT};
 use reqwest::{self, Method, StatusCode, Url};
-use reqwest::{header, RequestBuilder};
+use reqwest::{header, RequestBuilder, Response};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
 use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::time::Duration;
 
+/// Header that identifies a request, sent with every request unless the `RequestOptions` set it
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Header with which CouchDB identifies a request in its log
+pub(crate) const COUCH_REQUEST_ID_HEADER: &str = "x-couch-request-id";
+
+/// Interval at which `rename_db` checks the state of its replication
+const REPLICATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Database that holds the replications run by the server
+const REPLICATOR_DB: &str = "_replicator";
+
+/// A new request ID: a random number per process, followed by a counter
+fn new_request_id() -> String {
+    static PROCESS_ID: OnceLock<u64> = OnceLock::new();
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let process_id = PROCESS_ID.get_or_init(|| RandomState::new().build_hasher().finish());
+    format!("{:016x}-{:x}", process_id, COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
 fn construct_json_headers(uri: Option<&str>) -> HeaderMap {
     let mut headers = HeaderMap::new();
     headers.insert(USER_AGENT, HeaderValue::from_static("reqwest"));
@@ -27,30 +61,160 @@ fn parse_server(uri: &str) -> CouchResult<Url> {
     Ok(parsed_url)
 }
 
-pub(crate) async fn is_accepted(request: RequestBuilder) -> bool {
-    if let Ok(res) = request.send().await {
-        res.status() == StatusCode::ACCEPTED
-    } else {
-        false
-    }
+fn gzip(body: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(body.len() / 4), Compression::fast());
+    encoder.write_all(body)?;
+    encoder.finish()
 }
 
-pub(crate) async fn is_ok(request: RequestBuilder) -> bool {
-    if let Ok(res) = request.send().await {
+pub(crate) async fn is_ok(request: CouchRequest) -> bool {
+    if let Ok(res) = request.send_with_context().await {
         matches!(res.status(), StatusCode::OK | StatusCode::NOT_MODIFIED)
     } else {
         false
     }
 }
 
+/// Compression of the responses sent by CouchDB, or a proxy in front of it, negotiated through the
+/// `Accept-Encoding` header. Compressed responses are decompressed transparently, including those
+/// that are streamed, such as changes feeds and large queries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResponseCompression {
+    /// Accepts gzip-compressed responses; the default
+    #[default]
+    Gzip,
+    /// Accepts Brotli- as well as gzip-compressed responses. CouchDB itself only compresses with
+    /// gzip, so this helps behind a proxy that compresses with Brotli. Requires the `brotli` feature.
+    #[cfg(feature = "brotli")]
+    Brotli,
+    /// Asks for uncompressed responses, for proxies that misbehave with compressed chunked responses
+    Off,
+}
+
+/// Basic authentication header for the username and password, if any
+fn auth_headers(username: Option<&str>, password: Option<&str>) -> HeaderMap {
+    let mut headers = header::HeaderMap::new();
+
+    if let Some(username) = username {
+        let mut header_value = b"Basic ".to_vec();
+        {
+            let mut encoder = Base64Encoder::new(&mut header_value, base64::STANDARD);
+            // The unwraps here are fine because Vec::write* is infallible.
+            write!(encoder, "{}:", username).unwrap();
+            if let Some(password) = password {
+                write!(encoder, "{}", password).unwrap();
+            }
+        }
+
+        let auth_header = header::HeaderValue::from_bytes(&header_value).expect("can not set AUTHORIZATION header");
+        headers.insert(header::AUTHORIZATION, auth_header);
+    }
+
+    headers
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn build_http_client(
+    username: Option<&str>,
+    password: Option<&str>,
+    timeout: u64,
+    compression: ResponseCompression,
+) -> CouchResult<reqwest::Client> {
+    let mut headers = auth_headers(username, password);
+
+    let compressed = compression != ResponseCompression::Off;
+    if !compressed {
+        headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static("identity"));
+    }
+
+    let builder = reqwest::Client::builder()
+        .default_headers(headers)
+        .gzip(compressed)
+        .timeout(Duration::new(timeout, 0));
+    #[cfg(feature = "brotli")]
+    let builder = builder.brotli(compression == ResponseCompression::Brotli);
+    Ok(builder.build()?)
+}
+
+/// The wasm client sends requests through the fetch API, which decompresses responses itself and
+/// has no timeout
+#[cfg(target_arch = "wasm32")]
+fn build_http_client(
+    username: Option<&str>,
+    password: Option<&str>,
+    _timeout: u64,
+    _compression: ResponseCompression,
+) -> CouchResult<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .default_headers(auth_headers(username, password))
+        .build()?)
+}
+
+/// Headers and a timeout to send with requests, such as tenant or tracing headers, or the
+/// `X-Cloudant-*` options of Cloudant. Every request gets a new `X-Request-ID` header, unless the
+/// options set one, for example to propagate the ID of the request being served. Set them on the
+/// requests of a database with `Database::with_request_options`, or on all requests of a client
+/// with `Client::set_request_options`.
+///
+/// ```
+/// use couch_rs::error::CouchResult;
+/// use couch_rs::RequestOptions;
+/// use std::time::Duration;
+///
+/// fn options(tenant: &str) -> CouchResult<RequestOptions> {
+///     Ok(RequestOptions::default()
+///         .header("X-Tenant", tenant)?
+///         .timeout(Duration::from_secs(30)))
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    pub headers: HeaderMap,
+    /// Replaces the timeout of the client; the wasm client has no timeouts
+    pub timeout: Option<Duration>,
+}
+
+impl RequestOptions {
+    /// Adds a header, replacing an earlier header with the same name. Fails when the name or value
+    /// is not a valid header.
+    pub fn header(mut self, name: &str, value: &str) -> CouchResult<Self> {
+        let invalid = |err: &dyn std::fmt::Display| {
+            CouchError::new(format!("invalid header {}: {}", name, err), StatusCode::BAD_REQUEST)
+        };
+        let header_name = HeaderName::from_bytes(name.as_bytes()).map_err(|err| invalid(&err))?;
+        let header_value = HeaderValue::from_str(value).map_err(|err| invalid(&err))?;
+        self.headers.insert(header_name, header_value);
+        Ok(self)
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Adds the given options, which take precedence over these
+    pub(crate) fn merge(&mut self, other: RequestOptions) {
+        for (name, value) in other.headers {
+            if let Some(name) = name {
+                self.headers.insert(name, value);
+            }
+        }
+        if other.timeout.is_some() {
+            self.timeout = other.timeout;
+        }
+    }
+}
+
 /// Client handles the URI manipulation logic and the HTTP calls to the CouchDB REST API.
 /// It is also responsible for the creation/access/destruction of databases.
 #[derive(Debug, Clone)]
 pub struct Client {
     _client: reqwest::Client,
-    dbs: Vec<&'static str>,
-    _gzip: bool,
+    _compression: ResponseCompression,
     _timeout: u64,
+    compress_requests_from: Option<usize>,
+    slow_requests: Option<SlowRequests>,
+    request_options: RequestOptions,
     uri: Url,
     username: Option<String>,
     password: Option<String>,
@@ -86,6 +250,8 @@ impl Client {
     }
 
     /// new_with_timeout creates a new Couch client. The URI has to be in this format: http://hostname:5984,
+    /// or include a path when CouchDB sits behind a reverse proxy, such as https://hostname/couchdb/;
+    /// the path then prefixes the path of every request.
     /// The timeout is applied from when the request starts connecting until the response body has finished.
     /// Timeout is in seconds.
     pub fn new_with_timeout(
@@ -94,35 +260,16 @@ impl Client {
         password: Option<&str>,
         timeout: u64,
     ) -> CouchResult<Client> {
-        let mut headers = header::HeaderMap::new();
-
-        if let Some(username) = username {
-            let mut header_value = b"Basic ".to_vec();
-            {
-                let mut encoder = Base64Encoder::new(&mut header_value, base64::STANDARD);
-                // The unwraps here are fine because Vec::write* is infallible.
-                write!(encoder, "{}:", username).unwrap();
-                if let Some(password) = password {
-                    write!(encoder, "{}", password).unwrap();
-                }
-            }
-
-            let auth_header = header::HeaderValue::from_bytes(&header_value).expect("can not set AUTHORIZATION header");
-            headers.insert(header::AUTHORIZATION, auth_header);
-        }
-
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .gzip(true)
-            .timeout(Duration::new(timeout, 0))
-            .build()?;
+        let client = build_http_client(username, password, timeout, ResponseCompression::Gzip)?;
 
         Ok(Client {
             _client: client,
             uri: parse_server(uri)?,
-            _gzip: true,
+            _compression: ResponseCompression::Gzip,
             _timeout: timeout,
-            dbs: Vec::new(),
+            compress_requests_from: None,
+            slow_requests: None,
+            request_options: RequestOptions::default(),
             db_prefix: String::new(),
             username: username.map(|u| u.to_string()),
             password: password.map(|p| p.to_string()),
@@ -143,6 +290,53 @@ impl Client {
         self
     }
 
+    /// Sets the compression accepted for responses; see `ResponseCompression`. Databases created before
+    /// calling this keep their previous setting.
+    pub fn set_response_compression(&mut self, compression: ResponseCompression) -> CouchResult<&Self> {
+        self._client = build_http_client(
+            self.username.as_deref(),
+            self.password.as_deref(),
+            self._timeout,
+            compression,
+        )?;
+        self._compression = compression;
+        Ok(self)
+    }
+
+    /// The compression accepted for responses
+    pub fn response_compression(&self) -> ResponseCompression {
+        self._compression
+    }
+
+    /// Gzip-compresses the bodies of POST and PUT requests, such as `_bulk_docs`, of at least
+    /// `threshold` bytes, and sends them with `Content-Encoding: gzip`. This saves bandwidth on slow
+    /// links, at the cost of some CPU time. Use `None`, the default, to never compress requests.
+    /// Databases created before calling this keep their previous setting.
+    pub fn set_request_compression(&mut self, threshold: Option<usize>) -> &Self {
+        self.compress_requests_from = threshold;
+        self
+    }
+
+    /// Calls the hook for every request that takes at least `threshold`, with the database, and the
+    /// selector or view of the request; `LogSlowRequests` logs them. See `SlowRequestHook`.
+    /// Databases created before calling this keep their previous setting.
+    pub fn set_slow_request_hook<H: SlowRequestHook + 'static>(&mut self, threshold: Duration, hook: H) -> &Self {
+        self.slow_requests = Some(SlowRequests::new(threshold, Arc::new(hook)));
+        self
+    }
+
+    /// Sends the headers of the options with every request, and replaces the timeout when the
+    /// options have one. Databases created before calling this keep their previous setting.
+    pub fn set_request_options(&mut self, options: RequestOptions) -> &Self {
+        self.request_options = options;
+        self
+    }
+
+    /// Adds options to the requests of a database, see `Database::with_request_options`
+    pub(crate) fn merge_request_options(&mut self, options: RequestOptions) {
+        self.request_options.merge(options);
+    }
+
     /// List the databases in CouchDB
     ///
     /// Usage:
@@ -164,12 +358,19 @@ impl Client {
     /// }
     ///```     
     pub async fn list_dbs(&self) -> CouchResult<Vec<String>> {
-        let response = self.get(String::from("/_all_dbs"), None).send().await?;
-        let data = response.json().await?;
+        let response = self.get(String::from("/_all_dbs"), None).send_with_context().await?;
+        let data = response.read_json().await?;
 
         Ok(data)
     }
 
+    /// The timeout of requests
+    pub(crate) fn timeout(&self) -> Duration {
+        self.request_options
+            .timeout
+            .unwrap_or_else(|| Duration::from_secs(self._timeout))
+    }
+
     fn build_dbname(&self, dbname: &str) -> String {
         self.db_prefix.clone() + dbname
     }
@@ -183,7 +384,7 @@ impl Client {
         let head_response = self
             .head(name, None)
             .headers(construct_json_headers(None))
-            .send()
+            .send_with_context()
             .await?;
 
         match head_response.status() {
@@ -194,24 +395,31 @@ impl Client {
 
     /// Create a new database with the given name
     pub async fn make_db(&self, dbname: &str) -> CouchResult<Database> {
+        self.create_db(dbname, &[]).await
+    }
+
+    /// Creates a database with the given query parameters, such as its number of shards `q`
+    async fn create_db(&self, dbname: &str, params: &[(&str, String)]) -> CouchResult<Database> {
         let name = self.build_dbname(dbname);
 
         let db = Database::new(name.clone(), self.clone());
 
         let put_response = self
             .put(name, String::default())
+            .query(params)
             .headers(construct_json_headers(None))
-            .send()
+            .send_with_context()
             .await?;
 
         let status = put_response.status();
-        let s: CouchResponse = put_response.json().await?;
+        let s: CouchResponse = put_response.read_json().await?;
 
         match s.ok {
             Some(true) => Ok(db),
             _ => {
-                let err = s.error.unwrap_or_else(|| s!("unspecified error"));
-                Err(CouchError::new(err, status))
+                let error = s.error.unwrap_or_else(|| s!("unspecified error"));
+                let reason = s.reason.unwrap_or_else(|| error.clone());
+                Err(CouchError::from_couch(None, status, &error, reason))
             }
         }
     }
@@ -221,14 +429,207 @@ impl Client {
         let response = self
             .delete(self.build_dbname(dbname), None)
             .headers(construct_json_headers(None))
-            .send()
+            .send_with_context()
             .await?;
 
-        let s: CouchResponse = response.json().await?;
+        let s: CouchResponse = response.read_json().await?;
 
         Ok(s.ok.unwrap_or(false))
     }
 
+    /// Renames a database, which CouchDB can not do itself: creates the `new` database with the
+    /// shards, replicas and partitioning of the `old` one, replicates all documents to it,
+    /// including design documents, checks that it holds as many documents as the source, and
+    /// deletes the source. `on_progress` is called after every step, and while replicating.
+    ///
+    /// The replication runs on the server, through the `_replicator` database; the server must
+    /// reach itself at the URI of the client. The credentials of the client are not passed on: set
+    /// the credentials of the replicator in the options, which must be those of an admin to copy
+    /// design documents. A replication that crashes is retried by the server until the timeout of
+    /// the options. Local documents, such as checkpoints, are not copied. Stop writing to the
+    /// database before renaming it: when the document counts differ after the replication, a
+    /// `CONFLICT` error is returned. On any error, the source is kept, and so is the new database.
+    ///
+    /// Usage:
+    /// ```no_run
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::system::{RenameOptions, RenameProgress};
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let options = RenameOptions::default()
+    ///         .credentials("replicator", "secret")
+    ///         .timeout(Duration::from_secs(3600));
+    ///     let db = client
+    ///         .rename_db("orders", "orders_2021", &options, |progress| {
+    ///             if let RenameProgress::Replicating { docs_written, doc_count } = progress {
+    ///                 println!("copied {} of {} documents", docs_written, doc_count);
+    ///             }
+    ///         })
+    ///         .await?;
+    ///     println!("renamed to {}", db.name());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn rename_db<F>(
+        &self,
+        old: &str,
+        new: &str,
+        options: &RenameOptions,
+        mut on_progress: F,
+    ) -> CouchResult<Database>
+    where
+        F: FnMut(&RenameProgress),
+    {
+        let source = self.get_info(old).await?;
+        let partitioned = source.props.partitioned == Some(true);
+        let mut params = vec![("q", source.cluster.q.to_string()), ("n", source.cluster.n.to_string())];
+        if partitioned {
+            params.push(("partitioned", s!("true")));
+        }
+        let db = self.create_db(new, &params).await?;
+        on_progress(&RenameProgress::Created {
+            q: source.cluster.q,
+            n: source.cluster.n,
+            partitioned,
+        });
+
+        self.replicate(old, new, source.doc_count, options, &mut on_progress)
+            .await?;
+
+        let doc_count = self.get_info(old).await?.doc_count;
+        let copied = self.get_info(new).await?.doc_count;
+        if copied != doc_count {
+            return Err(CouchError::new(
+                format!(
+                    "{} holds {} documents after the replication, {} holds {}; {} was not deleted",
+                    new, copied, old, doc_count, old
+                ),
+                StatusCode::CONFLICT,
+            ));
+        }
+        on_progress(&RenameProgress::Verified { doc_count });
+
+        self.destroy_db(old).await?;
+        on_progress(&RenameProgress::Deleted);
+        Ok(db)
+    }
+
+    /// Replicates one database to another through a document in the `_replicator` database, until
+    /// the replication completed or the timeout passed, and removes the document
+    async fn replicate<F>(
+        &self,
+        source: &str,
+        target: &str,
+        doc_count: u64,
+        options: &RenameOptions,
+        on_progress: &mut F,
+    ) -> CouchResult<()>
+    where
+        F: FnMut(&RenameProgress),
+    {
+        let started = Instant::now();
+        // the replicator database is not subject to the prefix of the client
+        let replicator = Database::new(REPLICATOR_DB.to_string(), self.clone());
+        let replication = replicator
+            .create(json!({
+                "source": self.replication_endpoint(source, options),
+                "target": self.replication_endpoint(target, options),
+                "create_target": false
+            }))
+            .await?;
+        let id = match replication["_id"].as_str() {
+            Some(id) => id.to_string(),
+            None => {
+                return Err(CouchError::new(
+                    format!("the replication document of {} to {} has no id", source, target),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ))
+            }
+        };
+
+        let mut crash = None;
+        let result = loop {
+            let state = match self.replication_state(&id).await {
+                Ok(state) => state,
+                // the scheduler does not know the replication until it picked up the document
+                Err(err) if err.is_not_found() => json!({"state": "pending"}),
+                Err(err) => break Err(err),
+            };
+
+            let docs_written = state["info"]["docs_written"].as_u64().unwrap_or_default();
+            on_progress(&RenameProgress::Replicating {
+                docs_written,
+                doc_count,
+            });
+            let reason = |fallback: &str| state["info"]["error"].as_str().unwrap_or(fallback).to_string();
+            match state["state"].as_str() {
+                Some("completed") => break Ok(()),
+                Some(failed @ "failed") | Some(failed @ "error") => {
+                    break Err(CouchError::new(
+                        format!("replication of {} to {} failed: {}", source, target, reason(failed)),
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    ));
+                }
+                // the scheduler restarts a crashed replication after a backoff
+                Some(crashing @ "crashing") => crash = Some(reason(crashing)),
+                _ => {}
+            }
+
+            if started.elapsed() >= options.timeout {
+                let crashed = crash.map(|reason| format!(", it crashed: {}", reason));
+                break Err(CouchError::new(
+                    format!(
+                        "replication of {} to {} did not complete within {:?}{}",
+                        source,
+                        target,
+                        options.timeout,
+                        crashed.unwrap_or_default()
+                    ),
+                    StatusCode::REQUEST_TIMEOUT,
+                ));
+            }
+            sleep(REPLICATION_POLL_INTERVAL).await;
+        };
+
+        // the document of a completed replication is kept until it is deleted
+        if let Ok(replication) = replicator.get::<Value>(&id).await {
+            replicator.remove(replication).await;
+        }
+        result
+    }
+
+    /// The state of a replication, as reported by the scheduler
+    async fn replication_state(&self, id: &str) -> CouchResult<Value> {
+        self.get(format!("_scheduler/docs/{}/{}", REPLICATOR_DB, url_encode!(id)), None)
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?
+            .read_json()
+            .await
+    }
+
+    /// The URL of a database for the replicator, with the credentials of the options, if any
+    fn replication_endpoint(&self, dbname: &str, options: &RenameOptions) -> Value {
+        let name = self.build_dbname(dbname);
+        let name = name.as_str();
+        let mut url = self.uri.clone();
+        url.set_path(&format!(
+            "{}/{}",
+            self.uri.path().trim_end_matches('/'),
+            url_encode!(name)
+        ));
+
+        let mut endpoint = json!({ "url": url.as_str() });
+        if let Some((username, password)) = &options.credentials {
+            endpoint["auth"] = json!({"basic": {"username": username, "password": password}});
+        }
+        endpoint
+    }
+
     /// Checks if a database exists
     ///
     /// Usage:
@@ -250,7 +651,7 @@ impl Client {
     /// }
     /// ```
     pub async fn exists(&self, dbname: &str) -> CouchResult<bool> {
-        let result = self.head(self.build_dbname(dbname), None).send().await;
+        let result = self.head(self.build_dbname(dbname), None).send_with_context().await;
         Ok(result.is_ok())
     }
 
@@ -259,13 +660,27 @@ impl Client {
     pub async fn get_info(&self, dbname: &str) -> CouchResult<DbInfo> {
         let response = self
             .get(self.build_dbname(dbname), None)
-            .send()
+            .send_with_context()
             .await?
-            .error_for_status()?;
-        let info = response.json().await?;
+            .check_status()
+            .await?;
+        let info = response.read_json().await?;
         Ok(info)
     }
 
+    /// Lists the tasks running on the server, such as compactions, replications and index builds.
+    /// See [active tasks](https://docs.couchdb.org/en/stable/api/server/common.html#active-tasks) for more details.
+    pub async fn active_tasks(&self) -> CouchResult<Vec<ActiveTask>> {
+        let response = self
+            .get(s!("_active_tasks"), None)
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?;
+        let tasks = response.read_json().await?;
+        Ok(tasks)
+    }
+
     /// Returns meta information about the instance. The response contains information about the server,
     /// including a welcome message and the version of the server.
     /// See [common](https://docs.couchdb.org/en/stable/api/server/common.html) for more details.
@@ -273,16 +688,109 @@ impl Client {
         let response = self
             .get(String::default(), None)
             .headers(construct_json_headers(None))
-            .send()
+            .send_with_context()
             .await?;
 
-        let status = response.json().await?;
+        let status = response.read_json().await?;
         Ok(status)
     }
 
+    /// Whether the server runs Nouveau full-text search, which is enabled on CouchDB 3.4 and later
+    /// when the Nouveau server is configured
+    pub async fn supports_nouveau(&self) -> CouchResult<bool> {
+        Ok(self.check_status().await?.has_feature("nouveau"))
+    }
+
+    /// Creates a user in the `_users` database. CouchDB hashes the password when it stores the user,
+    /// so the returned user holds no password.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     client.create_user("john", "secret", vec!["reader".to_string()]).await?;
+    ///
+    ///     client.add_user_roles("john", &["writer".to_string()]).await?;
+    ///     client.set_user_password("john", "new secret").await?;
+    ///
+    ///     let john = client.get_user("john").await?.expect("john exists");
+    ///     assert_eq!(john.roles, vec!["reader", "writer"]);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn create_user(&self, name: &str, password: &str, roles: Vec<String>) -> CouchResult<User> {
+        let mut user = self.users_db().create(User::new(name, password, roles)).await?;
+        user.password = None;
+        Ok(user)
+    }
+
+    /// Gets the user with the given name, or `None` when there is no such user
+    pub async fn get_user(&self, name: &str) -> CouchResult<Option<User>> {
+        match self.users_db().get(&User::id_for(name)).await {
+            Ok(user) => Ok(Some(user)),
+            Err(err) if err.is_not_found() => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Sets a new password for an existing user. Other changes to the user, such as its roles, are
+    /// kept: a conflicting write is retried on the latest revision.
+    pub async fn set_user_password(&self, name: &str, password: &str) -> CouchResult<User> {
+        self.update_user(name, |user| user.password = Some(password.to_string()))
+            .await
+    }
+
+    /// Adds roles to an existing user, retrying on the latest revision when the user was changed
+    /// concurrently. Roles the user already has are not added twice.
+    pub async fn add_user_roles(&self, name: &str, roles: &[String]) -> CouchResult<User> {
+        self.update_user(name, |user| user.add_roles(roles)).await
+    }
+
+    /// Removes roles from an existing user, retrying on the latest revision when the user was
+    /// changed concurrently
+    pub async fn remove_user_roles(&self, name: &str, roles: &[String]) -> CouchResult<User> {
+        self.update_user(name, |user| user.remove_roles(roles)).await
+    }
+
+    fn users_db(&self) -> Database {
+        // the system database is not subject to the prefix of the client
+        Database::new(USERS_DB.to_string(), self.clone())
+    }
+
+    /// Applies `change` to the stored user and saves it, retrying conflicts with the default
+    /// `RetryPolicy`
+    async fn update_user<F: Fn(&mut User)>(&self, name: &str, change: F) -> CouchResult<User> {
+        let db = self.users_db();
+        let id = User::id_for(name);
+        let retry = RetryPolicy::default();
+        let mut attempt = 1;
+
+        loop {
+            let mut user: User = db.get(&id).await?;
+            change(&mut user);
+
+            match db.save(user).await {
+                Ok(mut user) => {
+                    user.password = None;
+                    return Ok(user);
+                }
+                Err(err) if err.is_conflict() && attempt < retry.max_attempts => {
+                    sleep(retry.backoff_for(attempt)).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
     pub fn req(&self, method: Method, path: String, opts: Option<HashMap<String, String>>) -> RequestBuilder {
+        // the path of the server URI, such as the location of a reverse proxy, prefixes every path
         let mut uri = self.uri.clone();
-        uri.set_path(&path);
+        let base = self.uri.path().trim_end_matches('/');
+        uri.set_path(&format!("{}/{}", base, path.trim_start_matches('/')));
 
         if let Some(ref map) = opts {
             let mut qp = uri.query_pairs_mut();
@@ -296,23 +804,197 @@ impl Client {
             .headers(construct_json_headers(Some(uri.as_str())))
     }
 
-    pub(crate) fn get(&self, path: String, args: Option<HashMap<String, String>>) -> RequestBuilder {
-        self.req(Method::GET, path, args)
+    pub(crate) fn get(&self, path: String, args: Option<HashMap<String, String>>) -> CouchRequest {
+        self.request(self.req(Method::GET, path, args))
+    }
+
+    pub(crate) fn post(&self, path: String, body: String) -> CouchRequest {
+        self.request(self.with_body(self.req(Method::POST, path, None), body))
+    }
+
+    pub(crate) fn put(&self, path: String, body: String) -> CouchRequest {
+        self.request(self.with_body(self.req(Method::PUT, path, None), body))
+    }
+
+    /// Adds the body to the request, compressing it when it is large enough
+    fn with_body(&self, request: RequestBuilder, body: String) -> RequestBuilder {
+        match self.compress_requests_from {
+            Some(threshold) if body.len() >= threshold => match gzip(body.as_bytes()) {
+                Ok(compressed) => request.header(CONTENT_ENCODING, "gzip").body(compressed),
+                Err(_) => request.body(body),
+            },
+            _ => request.body(body),
+        }
+    }
+
+    pub(crate) fn head(&self, path: String, args: Option<HashMap<String, String>>) -> CouchRequest {
+        self.request(self.req(Method::HEAD, path, args))
+    }
+
+    pub(crate) fn delete(&self, path: String, args: Option<HashMap<String, String>>) -> CouchRequest {
+        self.request(self.req(Method::DELETE, path, args))
     }
 
-    pub(crate) fn post(&self, path: String, body: String) -> RequestBuilder {
-        self.req(Method::POST, path, None).body(body)
+    fn request(&self, builder: RequestBuilder) -> CouchRequest {
+        let mut headers = self.request_options.headers.clone();
+        if !headers.contains_key(REQUEST_ID_HEADER) {
+            if let Ok(request_id) = HeaderValue::from_str(&new_request_id()) {
+                headers.insert(REQUEST_ID_HEADER, request_id);
+            }
+        }
+
+        let request = CouchRequest {
+            builder,
+            slow_requests: self.slow_requests.clone(),
+        }
+        .headers(headers);
+        match self.request_options.timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        }
     }
+}
 
-    pub(crate) fn put(&self, path: String, body: String) -> RequestBuilder {
-        self.req(Method::PUT, path, None).body(body)
+/// A request of the `Client`, which is sent with the settings of the client that a `RequestBuilder`
+/// can not hold
+pub(crate) struct CouchRequest {
+    builder: RequestBuilder,
+    slow_requests: Option<SlowRequests>,
+}
+
+impl CouchRequest {
+    pub(crate) fn headers(mut self, headers: HeaderMap) -> Self {
+        self.builder = self.builder.headers(headers);
+        self
     }
 
-    pub(crate) fn head(&self, path: String, args: Option<HashMap<String, String>>) -> RequestBuilder {
-        self.req(Method::HEAD, path, args)
+    pub(crate) fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Self {
+        self.builder = self.builder.query(query);
+        self
     }
 
-    pub(crate) fn delete(&self, path: String, args: Option<HashMap<String, String>>) -> RequestBuilder {
-        self.req(Method::DELETE, path, args)
+    /// Replaces the timeout of the client, for requests that take long by design, such as feeds.
+    /// The wasm client has no timeouts.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn timeout(mut self, timeout: Duration) -> Self {
+        self.builder = self.builder.timeout(timeout);
+        self
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn timeout(self, _timeout: Duration) -> Self {
+        self
+    }
+
+    #[cfg(test)]
+    pub(crate) fn build(self) -> reqwest::Result<reqwest::Request> {
+        self.builder.build()
+    }
+}
+
+impl SendWithContext for CouchRequest {
+    async fn send_with_context(self) -> CouchResult<Response> {
+        match self.slow_requests {
+            Some(slow_requests) => slow_requests.send(self.builder).await,
+            None => self.builder.send_with_context().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_request_compression() {
+        let mut client = Client::new_local_test().unwrap();
+        client.set_request_compression(Some(16));
+        let body = r#"{"docs":[{"_id":"a"},{"_id":"b"}]}"#.to_string();
+
+        let request = client.post(s!("db/_bulk_docs"), body.clone()).build().unwrap();
+        assert_eq!(request.headers()[CONTENT_ENCODING], "gzip");
+        let compressed = request.body().and_then(|b| b.as_bytes()).unwrap();
+        let mut decompressed = String::new();
+        GzDecoder::new(compressed).read_to_string(&mut decompressed).unwrap();
+        assert_eq!(decompressed, body);
+
+        let request = client.put(s!("db/a"), s!("{}")).build().unwrap();
+        assert!(request.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!(request.body().and_then(|b| b.as_bytes()), Some("{}".as_bytes()));
+    }
+
+    #[test]
+    fn test_base_path() {
+        let mut client = Client::new_local_test().unwrap();
+        let request = client.get(s!("db/_design/app/_view/all"), None).build().unwrap();
+        assert_eq!(request.url().as_str(), "http://localhost:5984/db/_design/app/_view/all");
+
+        for uri in &["https://example.com/couchdb", "https://example.com/couchdb/"] {
+            client.set_uri(uri).unwrap();
+            let request = client.get(s!("db/doc/photo.jpg"), None).build().unwrap();
+            assert_eq!(request.url().as_str(), "https://example.com/couchdb/db/doc/photo.jpg");
+            let request = client.get(s!("/_session"), None).build().unwrap();
+            assert_eq!(request.url().as_str(), "https://example.com/couchdb/_session");
+        }
+    }
+
+    #[test]
+    fn test_request_options() {
+        let mut client = Client::new_local_test().unwrap();
+        let options = RequestOptions::default()
+            .header("X-Tenant", "acme")
+            .unwrap()
+            .header("X-Cloudant-User", "reader")
+            .unwrap();
+        client.set_request_options(options);
+        client.merge_request_options(
+            RequestOptions::default()
+                .header("X-Tenant", "initech")
+                .unwrap()
+                .timeout(Duration::from_secs(3)),
+        );
+
+        let request = client.get(s!("db/doc"), None).build().unwrap();
+        assert_eq!(request.headers()["x-tenant"], "initech");
+        assert_eq!(request.headers()["x-cloudant-user"], "reader");
+        assert_eq!(request.timeout(), Some(&Duration::from_secs(3)));
+        assert_eq!(client.timeout(), Duration::from_secs(3));
+
+        assert!(RequestOptions::default()
+            .header("X Tenant", "acme")
+            .unwrap_err()
+            .is_bad_request());
+        assert!(RequestOptions::default().header("X-Tenant", "a\nb").is_err());
+    }
+
+    #[test]
+    fn test_request_id() {
+        let mut client = Client::new_local_test().unwrap();
+        let first = client.get(s!("db/doc"), None).build().unwrap();
+        let second = client.get(s!("db/doc"), None).build().unwrap();
+        assert_ne!(first.headers()[REQUEST_ID_HEADER], second.headers()[REQUEST_ID_HEADER]);
+
+        client.set_request_options(RequestOptions::default().header("X-Request-ID", "incoming-42").unwrap());
+        let request = client.get(s!("db/doc"), None).build().unwrap();
+        assert_eq!(request.headers()[REQUEST_ID_HEADER], "incoming-42");
+        assert_eq!(request.headers().get_all(REQUEST_ID_HEADER).iter().count(), 1);
+    }
+
+    #[test]
+    fn test_response_compression() {
+        let mut client = Client::new_local_test().unwrap();
+        assert_eq!(client.response_compression(), ResponseCompression::Gzip);
+
+        client.set_response_compression(ResponseCompression::Off).unwrap();
+        assert_eq!(client.response_compression(), ResponseCompression::Off);
+        assert!(client.username.is_some());
+
+        #[cfg(feature = "brotli")]
+        {
+            client.set_response_compression(ResponseCompression::Brotli).unwrap();
+            assert_eq!(client.response_compression(), ResponseCompression::Brotli);
+        }
     }
 }
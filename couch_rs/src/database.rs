@@ -1,17 +1,123 @@
-use crate::client::Client;
-use crate::client::{is_accepted, is_ok};
-use crate::document::{DocumentCollection, TypedCouchDocument};
-use crate::error::{CouchError, CouchResult};
-use crate::types::design::DesignCreated;
-use crate::types::document::{DocumentCreatedResponse, DocumentCreatedResult, DocumentId};
-use crate::types::find::{FindQuery, FindResult};
-use crate::types::index::{DatabaseIndexList, IndexFields};
-use crate::types::query::{QueriesCollection, QueriesParams, QueryParams};
-use crate::types::view::ViewCollection;
+use crate::client::is_ok;
+use crate::client::{Client, RequestOptions};
+use crate::document::{AllDocsResponse, DocResponse, DocumentCollection, TypedCouchDocument};
+use crate::error::{CheckStatus, CouchError, CouchResult, ErrorContext, SendWithContext};
+use crate::pager::{id_ranges, interpolated_ids, Paginator, ViewPager};
+use crate::partition::Partition;
+use crate::repository::Repository;
+use crate::runtime::{sleep, BoxFuture, Instant};
+use crate::stream::{response_json, response_json_idle, response_rows, JsonBody};
+use crate::types::changes::{ChangeEvent, ChangesOptions, ChangesResponse, Seq};
+use crate::types::design::{
+    DesignCreated, DesignDeployment, DesignDocument, DesignInfo, RenderedResponse, UpdateResponse,
+};
+use crate::types::document::{
+    BulkDeleteResult, BulkDocResult, BulkImportOptions, BulkImportResult, DocumentCreatedDetails,
+    DocumentCreatedResponse, DocumentCreatedResult, DocumentDeletedResult, DocumentId, DocumentIdRev, GetOptions,
+    RetryPolicy, WriteOptions, WriteResult,
+};
+use crate::types::expiry::{self, ExpiryOptions};
+use crate::types::export::{self, ExportOptions, ExportSummary, ExportedRevs, RestoreSummary};
+use crate::types::find::{ExplainResult, FindDocuments, FindQuery, FindResult, SortSpec};
+use crate::types::index::{DatabaseIndexList, IndexAdvice, IndexFields};
+use crate::types::merge::MergePolicy;
+use crate::types::nouveau::{NouveauQuery, NouveauResult};
+use crate::types::patch::{apply_patch, PatchOp};
+use crate::types::query::{QueriesCollection, QueriesParams, QueryParams, UpdateView};
+use crate::types::search::{SearchQuery, SearchResult};
+use crate::types::system::DbInfo;
+use crate::types::view::{RawViewCollection, ViewCollection, ViewItem};
+use crate::validation::{ValidationHook, ValidationHooks};
+use crate::writer::{BulkWriter, BulkWriterOptions};
+use futures_util::future;
+use futures_util::stream::{self, FuturesOrdered, Stream, StreamExt, TryStreamExt};
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json::{json, to_string, Value};
-use std::collections::HashMap;
-use tokio::sync::mpsc::Sender;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::poll_fn;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Number of documents requested per batch, when no batch size is provided
+pub(crate) const DEFAULT_BATCH_SIZE: u64 = 1000;
+
+/// Number of times a conflicting document is retried by the bulk operations
+const CONFLICT_RETRIES: u32 = 3;
+const INDEX_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Time CouchDB waits for changes in a long polling request, unless a timeout is given
+const DEFAULT_FEED_TIMEOUT: Duration = Duration::from_secs(60);
+/// A long polling request with heartbeats only returns on a change; a dead connection is detected
+/// by the heartbeats missing instead
+const FEED_WITH_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// A running `_bulk_docs` request of `bulk_import`, resolving to the number of documents it holds
+/// and its result
+type ImportChunk = BoxFuture<(usize, CouchResult<Vec<DocumentCreatedResult>>)>;
+
+/// Document that deletes the given revision, when sent through `_bulk_docs`
+fn tombstone(id: &str, rev: &str) -> Value {
+    json!({
+        "_id": id,
+        "_rev": rev,
+        "_deleted": true
+    })
+}
+
+/// Collects the documents found by a Mango query, leaving out the design documents unless the
+/// query includes them
+pub(crate) fn found_collection<T: TypedCouchDocument>(
+    found: FindDocuments<T>,
+    include_design_docs: bool,
+) -> DocumentCollection<T> {
+    let documents: Vec<T> = found
+        .docs
+        .into_iter()
+        .filter(|d| include_design_docs || !d.get_id().starts_with('_'))
+        .collect();
+
+    let mut collection = DocumentCollection::new_from_documents(documents, found.bookmark);
+    collection.execution_stats = found.execution_stats;
+    collection.warning = found.warning;
+    collection
+}
+
+/// Sending half of a channel, that receives the batches of `find_batched` and `get_all_batched`.
+/// Implemented for the channels of `futures`, and of tokio with the `tokio` feature; implement it
+/// for the channels of other runtimes.
+pub trait BatchSender<T>: Send {
+    /// Sends a batch, waiting while a bounded channel is full. Returns `false` when the receiver
+    /// has been dropped.
+    fn send_batch(&mut self, batch: T) -> future::BoxFuture<'_, bool>;
+}
+
+impl<T: Send> BatchSender<T> for futures_channel::mpsc::Sender<T> {
+    fn send_batch(&mut self, batch: T) -> future::BoxFuture<'_, bool> {
+        Box::pin(async move { poll_fn(|cx| self.poll_ready(cx)).await.is_ok() && self.start_send(batch).is_ok() })
+    }
+}
+
+impl<T: Send> BatchSender<T> for futures_channel::mpsc::UnboundedSender<T> {
+    fn send_batch(&mut self, batch: T) -> future::BoxFuture<'_, bool> {
+        Box::pin(future::ready(self.unbounded_send(batch).is_ok()))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T: Send> BatchSender<T> for tokio::sync::mpsc::Sender<T> {
+    fn send_batch(&mut self, batch: T) -> future::BoxFuture<'_, bool> {
+        Box::pin(async move { self.send(batch).await.is_ok() })
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T: Send> BatchSender<T> for tokio::sync::mpsc::UnboundedSender<T> {
+    fn send_batch(&mut self, batch: T) -> future::BoxFuture<'_, bool> {
+        Box::pin(future::ready(self.send(batch).is_ok()))
+    }
+}
 
 /// Database operations on a CouchDB Database
 /// (sometimes called Collection in other NoSQL flavors such as MongoDB).
@@ -19,11 +125,68 @@ use tokio::sync::mpsc::Sender;
 pub struct Database {
     _client: Client,
     name: String,
+    hooks: ValidationHooks,
 }
 
 impl Database {
     pub fn new(name: String, client: Client) -> Database {
-        Database { _client: client, name }
+        Database {
+            _client: client,
+            name,
+            hooks: ValidationHooks::default(),
+        }
+    }
+
+    /// Adds a hook that checks, and may change, every document before it is written. Hooks run in
+    /// the order they were added. See `ValidationHook`.
+    pub fn with_validation_hook<H: ValidationHook + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Sends the headers of the options with every request of the database, and replaces the timeout
+    /// when the options have one. The options are added to those of the client and to earlier
+    /// options of the database; clone the database to use options for specific calls only.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::RequestOptions;
+    /// use serde_json::Value;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let options = RequestOptions::default().header("X-Request-Source", "nightly-report")?;
+    ///     let docs = db.clone().with_request_options(options).get_all::<Value>().await?;
+    ///     println!("{} documents", docs.rows.len());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn with_request_options(mut self, options: RequestOptions) -> Self {
+        self._client.merge_request_options(options);
+        self
+    }
+
+    /// Runs the validation hooks on a document that is about to be written. Returns `None` when there
+    /// are no hooks, in which case the document is written as it is.
+    pub(crate) fn validate<T: Serialize>(&self, doc: &T) -> CouchResult<Option<Value>> {
+        if self.hooks.is_empty() {
+            return Ok(None);
+        }
+        self.hooks.apply(doc).map(Some)
+    }
+
+    /// Serializes a document that is about to be written, after running the validation hooks
+    pub(crate) fn write_body<T: Serialize>(&self, doc: &T) -> CouchResult<String> {
+        match self.validate(doc)? {
+            Some(value) => Ok(to_string(&value)?),
+            None => Ok(to_string(doc)?),
+        }
     }
 
     // convenience function to retrieve the name of the database
@@ -36,6 +199,14 @@ impl Database {
     }
 
     fn create_document_path(&self, id: &str) -> String {
+        // the slash of the design and local document prefixes is part of the path
+        for prefix in &["_design/", "_local/"] {
+            if let Some(name) = id.strip_prefix(prefix) {
+                let encoded = url_encode!(name);
+                return format!("{}/{}{}", self.name, prefix, encoded);
+            }
+        }
+
         let encoded = url_encode!(id);
         format!("{}/{}", self.name, encoded)
     }
@@ -51,6 +222,18 @@ impl Database {
         format!("{}/_design/{}/_view/{}", self.name, encoded_design, encoded_view)
     }
 
+    fn create_search_path(&self, design_id: &str, index_id: &str) -> String {
+        let encoded_design = url_encode!(design_id);
+        let encoded_index = url_encode!(index_id);
+        format!("{}/_design/{}/_search/{}", self.name, encoded_design, encoded_index)
+    }
+
+    fn create_nouveau_path(&self, design_id: &str, index_id: &str) -> String {
+        let encoded_design = url_encode!(design_id);
+        let encoded_index = url_encode!(index_id);
+        format!("{}/_design/{}/_nouveau/{}", self.name, encoded_design, encoded_index)
+    }
+
     fn create_execute_update_path(&self, design_id: &str, update_id: &str, document_id: &str) -> String {
         let encoded_design = url_encode!(design_id);
         let encoded_update = url_encode!(update_id);
@@ -61,33 +244,332 @@ impl Database {
         )
     }
 
+    fn create_execute_update_without_id_path(&self, design_id: &str, update_id: &str) -> String {
+        let encoded_design = url_encode!(design_id);
+        let encoded_update = url_encode!(update_id);
+        format!("{}/_design/{}/_update/{}", self.name, encoded_design, encoded_update)
+    }
+
+    fn create_execute_show_path(&self, design_id: &str, show_id: &str, document_id: Option<&str>) -> String {
+        let encoded_design = url_encode!(design_id);
+        let encoded_show = url_encode!(show_id);
+        let path = format!("{}/_design/{}/_show/{}", self.name, encoded_design, encoded_show);
+
+        match document_id {
+            Some(document_id) => {
+                let encoded_document = url_encode!(document_id);
+                format!("{}/{}", path, encoded_document)
+            }
+            None => path,
+        }
+    }
+
+    fn create_execute_list_path(&self, design_id: &str, list_id: &str, view_id: &str) -> String {
+        let encoded_design = url_encode!(design_id);
+        let encoded_list = url_encode!(list_id);
+        let encoded_view = url_encode!(view_id);
+        format!(
+            "{}/_design/{}/_list/{}/{}",
+            self.name, encoded_design, encoded_list, encoded_view
+        )
+    }
+
     fn create_compact_path(&self, design_name: &str) -> String {
         let encoded_design = url_encode!(design_name);
         format!("{}/_compact/{}", self.name, encoded_design)
     }
 
     /// Launches the compact process
+    #[deprecated(note = "use `try_compact`, which returns the error when compaction could not be started")]
     pub async fn compact(&self) -> bool {
+        self.try_compact().await.is_ok()
+    }
+
+    /// Starts the compaction of all views
+    #[deprecated(note = "use `try_compact_views`, which returns the error when compaction could not be started")]
+    pub async fn compact_views(&self) -> bool {
+        self.try_compact_views().await.is_ok()
+    }
+
+    /// Starts the compaction of a given index
+    #[deprecated(note = "use `try_compact_index`, which returns the error when compaction could not be started")]
+    pub async fn compact_index(&self, index: &str) -> bool {
+        self.try_compact_index(index).await.is_ok()
+    }
+
+    /// Launches the compact process. The error holds the HTTP status, for example `UNAUTHORIZED`
+    /// when compaction is not allowed; connection failures have no HTTP status.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///     db.try_compact().await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn try_compact(&self) -> CouchResult<()> {
         let mut path: String = self.name.clone();
         path.push_str("/_compact");
 
-        let request = self._client.post(path, "".into());
-        is_accepted(request).await
+        self.post_empty(path).await
     }
 
-    /// Starts the compaction of all views
-    pub async fn compact_views(&self) -> bool {
+    /// Starts the compaction of all views. See `try_compact` for the errors.
+    pub async fn try_compact_views(&self) -> CouchResult<()> {
         let mut path: String = self.name.clone();
         path.push_str("/_view_cleanup");
 
-        let request = self._client.post(path, "".into());
-        is_accepted(request).await
+        self.post_empty(path).await
     }
 
-    /// Starts the compaction of a given index
-    pub async fn compact_index(&self, index: &str) -> bool {
-        let request = self._client.post(self.create_compact_path(index), "".into());
-        is_accepted(request).await
+    /// Starts the compaction of a given index. See `try_compact` for the errors.
+    pub async fn try_compact_index(&self, index: &str) -> CouchResult<()> {
+        self.post_empty(self.create_compact_path(index)).await
+    }
+
+    async fn post_empty(&self, path: String) -> CouchResult<()> {
+        self._client
+            .post(path, "".into())
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?;
+        Ok(())
+    }
+
+    /// Gets information about this database, such as its document count and sizes.
+    /// See [common](https://docs.couchdb.org/en/stable/api/database/common.html) for more details.
+    pub async fn info(&self) -> CouchResult<DbInfo> {
+        let response = self
+            ._client
+            .get(self.name.clone(), None)
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?;
+        let info = response.read_json().await?;
+        Ok(info)
+    }
+
+    /// Returns the number of live documents, from the database information; deleted documents are
+    /// not counted. Unlike counting `_all_docs` rows, this does not read any documents.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///     println!("{} documents", db.doc_count().await?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn doc_count(&self) -> CouchResult<u64> {
+        Ok(self.info().await?.doc_count)
+    }
+
+    /// Gets the changes of the database since a sequence. A normal feed returns the changes at once;
+    /// with `longpoll(true)`, CouchDB waits for a change when there is none yet. With a `heartbeat`,
+    /// the request fails when nothing, not even a heartbeat, is received for twice the heartbeat
+    /// interval, so a connection that died behind a load balancer does not hang the feed.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::changes::ChangesOptions;
+    /// use std::time::Duration;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let changes = db.changes(&ChangesOptions::default().limit(100)).await?;
+    ///     for change in &changes.results {
+    ///         println!("{} changed", change.id);
+    ///     }
+    ///
+    ///     let options = ChangesOptions::default()
+    ///         .since(changes.last_seq)
+    ///         .longpoll(true)
+    ///         .timeout(Duration::from_secs(5));
+    ///     let next = db.changes(&options).await?;
+    ///     println!("{} changes while waiting", next.results.len());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn changes(&self, options: &ChangesOptions) -> CouchResult<ChangesResponse> {
+        let mut request = self
+            ._client
+            .post(self.create_raw_path("_changes"), js!(options.to_body()))
+            .query(&options.to_query());
+        if options.longpoll {
+            // the request lasts as long as CouchDB waits for changes, which is unbounded with heartbeats
+            let wait = match options.heartbeat {
+                Some(_) => FEED_WITH_HEARTBEAT_TIMEOUT,
+                None => options.timeout.unwrap_or(DEFAULT_FEED_TIMEOUT),
+            };
+            request = request.timeout(wait + self._client.timeout());
+        }
+
+        let response = request.send_with_context().await?.check_status().await?;
+        match options.heartbeat {
+            Some(heartbeat) => response_json_idle(response, heartbeat * 2).await,
+            None => response_json(response).await,
+        }
+    }
+
+    /// Gets the changes of the database, like `changes`, deserializing the documents included with
+    /// `include_docs(true)` into `T`, so they do not have to be handled as raw `Value`s. A deleted
+    /// document that does not fit `T` has no `doc`; any other document that does not fit is an error.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::changes::ChangesOptions;
+    /// use serde::Deserialize;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Reading {
+    ///     value: f64,
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let options = ChangesOptions::default().include_docs(true).selector(serde_json::json!({"value": {"$exists": true}}));
+    ///     let changes = db.changes_as::<Reading>(&options).await?;
+    ///     for reading in changes.results.iter().filter_map(|change| change.doc.as_ref()) {
+    ///         println!("{}", reading.value);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn changes_as<T: DeserializeOwned>(&self, options: &ChangesOptions) -> CouchResult<ChangesResponse<T>> {
+        self.changes(options).await?.into_typed()
+    }
+
+    /// Follows the changes feed of the database, as a stream of changes that does not end. The
+    /// changes are requested through long polling, continuing from the last sequence of the
+    /// previous request, so the feed resumes where it left off. An error ends the stream; the
+    /// `seq` of the last change received can be used to start a new one.
+    ///
+    /// Usage:
+    /// ```no_run
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::changes::ChangesOptions;
+    /// use futures_util::stream::TryStreamExt;
+    /// use std::time::Duration;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let options = ChangesOptions::default()
+    ///         .since_now()
+    ///         .include_docs(true)
+    ///         .heartbeat(Duration::from_secs(10));
+    ///     let mut changes = Box::pin(db.changes_stream(options));
+    ///     while let Some(change) = changes.try_next().await? {
+    ///         println!("{} changed: {:?}", change.id, change.doc);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn changes_stream(&self, options: ChangesOptions) -> impl Stream<Item = CouchResult<ChangeEvent>> {
+        self.changes_stream_as(options)
+    }
+
+    /// Follows the changes feed of the database, like `changes_stream`, deserializing the documents
+    /// included with `include_docs(true)` into `T`
+    pub fn changes_stream_as<T: DeserializeOwned>(
+        &self,
+        options: ChangesOptions,
+    ) -> impl Stream<Item = CouchResult<ChangeEvent<T>>> {
+        let mut options = options.longpoll(true);
+        options.descending = None;
+
+        stream::unfold(
+            (self.clone(), options, VecDeque::new(), false),
+            |(db, mut options, mut received, failed)| async move {
+                loop {
+                    if let Some(change) = received.pop_front() {
+                        return Some((Ok(change), (db, options, received, failed)));
+                    }
+                    if failed {
+                        return None;
+                    }
+                    match db.changes_as::<T>(&options).await {
+                        Ok(changes) => {
+                            options.since = Some(changes.last_seq);
+                            received.extend(changes.results);
+                        }
+                        Err(err) => return Some((Err(err), (db, options, received, true))),
+                    }
+                }
+            },
+        )
+    }
+
+    /// Stores a sequence in the local document `_local/{checkpoint}`, so a changes feed can resume
+    /// where it left off, for example after a restart. Local documents are not replicated and do
+    /// not show up in the changes feed, so storing the checkpoint does not cause a change itself.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::changes::ChangesOptions;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let mut options = ChangesOptions::default();
+    ///     if let Some(seq) = db.get_checkpoint("indexer").await? {
+    ///         options = options.since(seq);
+    ///     }
+    ///     let changes = db.changes(&options).await?;
+    ///     // process the changes, then remember how far the indexer got
+    ///     db.save_checkpoint("indexer", &changes.last_seq).await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn save_checkpoint(&self, checkpoint: &str, seq: &Seq) -> CouchResult<()> {
+        let doc = json!({"_id": checkpoint_id(checkpoint), "seq": seq});
+        self.upsert(doc).await?;
+        Ok(())
+    }
+
+    /// Gets the sequence stored by `save_checkpoint`, or `None` when no sequence was stored yet
+    pub async fn get_checkpoint(&self, checkpoint: &str) -> CouchResult<Option<Seq>> {
+        match self.get::<Value>(&checkpoint_id(checkpoint)).await {
+            Ok(mut doc) => Ok(Some(serde_json::from_value(doc["seq"].take())?)),
+            Err(err) if err.is_not_found() => Ok(None),
+            Err(err) => Err(err),
+        }
     }
 
     /// Checks if a document ID exists
@@ -116,6 +598,45 @@ impl Database {
         is_ok(request).await
     }
 
+    /// Checks if a document ID exists. Unlike `exists`, only a missing document results in `false`;
+    /// any other failure, such as a connection or authorization error, is returned as an error.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use serde_json::json;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     if !db.try_exists("settings").await? {
+    ///         db.create(json!({"_id": "settings"})).await?;
+    ///     }
+    ///
+    ///     return Ok(());
+    /// }
+    /// ```
+    pub async fn try_exists(&self, id: &str) -> CouchResult<bool> {
+        let response = self
+            ._client
+            .head(self.create_document_path(id), None)
+            .send_with_context()
+            .await?;
+        match response.status() {
+            reqwest::StatusCode::OK | reqwest::StatusCode::NOT_MODIFIED => Ok(true),
+            reqwest::StatusCode::NOT_FOUND => Ok(false),
+            status => Err(CouchError::new_with_id(
+                Some(id.to_string()),
+                format!("could not check whether the document exists: {}", status),
+                status,
+            )),
+        }
+    }
+
     /// Convenience wrapper around get::<Value>(id)
     pub async fn get_raw(&self, id: &str) -> CouchResult<Value> {
         self.get(id).await
@@ -172,14 +693,80 @@ impl Database {
     /// }
     ///```
     pub async fn get<T: TypedCouchDocument>(&self, id: &str) -> CouchResult<T> {
+        self.get_as(id).await
+    }
+
+    /// Gets one document, with the given options. For example, `att_encoding_info` adds the
+    /// compressed length of the attachments to their stubs, so storage can be accounted for without
+    /// downloading the attachments, and a read quorum of `r(2)` makes two replicas of a cluster
+    /// return the document.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::document::{attachments, GetOptions};
+    /// use serde_json::Value;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let doc: Value = db.get_with_options("1234", &GetOptions::default().att_encoding_info(true)).await?;
+    ///     let stored: u64 = attachments(&doc)?.values().filter_map(|stub| stub.stored_length()).sum();
+    ///     println!("attachments take {} bytes", stored);
+    ///     Ok(())
+    /// }
+    ///```
+    pub async fn get_with_options<T: TypedCouchDocument>(&self, id: &str, options: &GetOptions) -> CouchResult<T> {
         self._client
-            .get(self.create_document_path(id), None)
-            .send()
+            .get(self.create_document_path(id), Some(options.to_query()))
+            .send_with_context()
             .await?
-            .error_for_status()?
-            .json()
+            .check_status()
+            .await?
+            .read_json()
+            .await
+    }
+
+    /// Gets one document, deserialized into any type; unlike `get` the type does not need to
+    /// implement `TypedCouchDocument`. Include `_id` and `_rev` fields in the type to receive them.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use serde::Deserialize;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[derive(Deserialize)]
+    /// pub struct UserDetails {
+    ///     #[serde(rename = "_rev")]
+    ///     pub rev: String,
+    ///     pub last_name: String,
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let user: UserDetails = db.get_as("1234").await?;
+    ///     println!("{} at revision {}", user.last_name, user.rev);
+    ///     Ok(())
+    /// }
+    ///```
+    pub async fn get_as<T: DeserializeOwned>(&self, id: &str) -> CouchResult<T> {
+        self._client
+            .get(self.create_document_path(id), None)
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?
+            .read_json()
             .await
-            .map_err(CouchError::from)
     }
 
     /// Gets documents in bulk with provided IDs list
@@ -225,21 +812,217 @@ impl Database {
     /// }
     /// ```
     pub async fn bulk_docs<T: TypedCouchDocument>(&self, raw_docs: Vec<T>) -> CouchResult<Vec<DocumentCreatedResult>> {
-        let mut body = HashMap::new();
-        body.insert(s!("docs"), raw_docs);
+        self.post_bulk_docs(&raw_docs).await
+    }
+
+    /// Writes documents in bulk, just like `bulk_docs`, but returns a `BulkDocResult` per document that
+    /// tells conflicts and rejected documents apart. The results are in the same order as the provided
+    /// documents, so the failed subset can be retried.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::document::BulkDocResult;
+    /// use serde_json::json;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///     let docs = vec![json!({"_id": "john"}), json!({"_id": "jane"})];
+    ///
+    ///     let results = db.bulk_docs_detailed(docs.clone()).await?;
+    ///     let retry: Vec<_> = docs
+    ///         .into_iter()
+    ///         .zip(results)
+    ///         .filter(|(_, result)| result.is_conflict())
+    ///         .map(|(doc, _)| doc)
+    ///         .collect();
+    ///
+    ///     println!("{} documents to retry", retry.len());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn bulk_docs_detailed<T: TypedCouchDocument>(&self, raw_docs: Vec<T>) -> CouchResult<Vec<BulkDocResult>> {
+        self.post_bulk_docs(&raw_docs).await
+    }
+
+    async fn post_bulk_docs<T: Serialize, R: From<DocumentCreatedResponse>>(
+        &self,
+        raw_docs: &[T],
+    ) -> CouchResult<Vec<R>> {
+        let body = if self.hooks.is_empty() {
+            to_string(&HashMap::from([("docs", raw_docs)]))?
+        } else {
+            let docs = raw_docs
+                .iter()
+                .map(|doc| self.hooks.apply(doc))
+                .collect::<CouchResult<Vec<Value>>>()?;
+            to_string(&HashMap::from([("docs", docs)]))?
+        };
 
         let response = self
             ._client
-            .post(self.create_raw_path("_bulk_docs"), to_string(&body)?)
-            .send()
+            .post(self.create_raw_path("_bulk_docs"), body)
+            .send_with_context()
             .await?;
 
-        let data: Vec<DocumentCreatedResponse> = response.json().await?;
+        let data: Vec<DocumentCreatedResponse> = response.read_json().await?;
+        Ok(data.into_iter().map(|r| r.into()).collect())
+    }
+
+    /// Imports a large number of documents. The documents are split into `_bulk_docs` requests of
+    /// at most `chunk_size` documents and `max_bytes` bytes, of which `concurrency` requests run in
+    /// parallel. Failures are collected in the result, together with some throughput statistics,
+    /// instead of aborting the import.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::document::BulkImportOptions;
+    /// use serde_json::json;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let docs = (0..10_000).map(|i| json!({"count": i}));
+    ///     let options = BulkImportOptions::default().chunk_size(500).concurrency(8);
+    ///     let result = db.bulk_import(docs, options).await?;
+    ///
+    ///     println!("imported {} documents, {:.0} docs/s", result.written, result.docs_per_second());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn bulk_import<T, I>(&self, docs: I, options: BulkImportOptions) -> CouchResult<BulkImportResult>
+    where
+        T: TypedCouchDocument,
+        I: IntoIterator<Item = T>,
+    {
+        let started = Instant::now();
+        let mut result = BulkImportResult::default();
+        // the requests run concurrently while the oldest one is awaited
+        let mut running = FuturesOrdered::new();
+        let mut chunk: Vec<String> = vec![];
+        let mut chunk_bytes = 0;
+
+        for doc in docs {
+            let doc = self.write_body(&doc)?;
+            if !chunk.is_empty() && (chunk.len() >= options.chunk_size || chunk_bytes + doc.len() > options.max_bytes) {
+                let full_chunk = std::mem::take(&mut chunk);
+                self.start_import_chunk(full_chunk, &options, &mut running, &mut result)
+                    .await;
+                chunk_bytes = 0;
+            }
+
+            chunk_bytes += doc.len();
+            chunk.push(doc);
+        }
+
+        if !chunk.is_empty() {
+            self.start_import_chunk(chunk, &options, &mut running, &mut result)
+                .await;
+        }
+
+        while let Some((docs, outcome)) = running.next().await {
+            result.record(docs, outcome);
+        }
+
+        result.elapsed = started.elapsed();
+        Ok(result)
+    }
+
+    async fn start_import_chunk(
+        &self,
+        chunk: Vec<String>,
+        options: &BulkImportOptions,
+        running: &mut FuturesOrdered<ImportChunk>,
+        result: &mut BulkImportResult,
+    ) {
+        // wait for the oldest request to finish, before starting a new one
+        if running.len() >= options.concurrency {
+            if let Some((docs, outcome)) = running.next().await {
+                result.record(docs, outcome);
+            }
+        }
+
+        let docs = chunk.len();
+        let body = format!("{{\"docs\":[{}]}}", chunk.join(","));
+        result.requests += 1;
+        result.bytes += body.len() as u64;
+
+        let db = self.clone();
+        running.push_back(Box::pin(async move { (docs, db.post_bulk_body(body).await) }));
+    }
+
+    pub(crate) async fn post_bulk_body(&self, body: String) -> CouchResult<Vec<DocumentCreatedResult>> {
+        let data: Vec<DocumentCreatedResponse> = self
+            ._client
+            .post(self.create_raw_path("_bulk_docs"), body)
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?
+            .read_json()
+            .await?;
         Ok(data.into_iter().map(|r| r.into()).collect())
     }
 
+    /// Creates a `Repository` for the documents of type `T` in this database.
+    /// See `Repository` for an example.
+    pub fn repository<T: TypedCouchDocument>(&self) -> Repository<T> {
+        Repository::new(self.clone())
+    }
+
+    /// Returns a handle on a partition of this partitioned database, whose queries only read the
+    /// documents of the partition. See `Partition` for an example.
+    pub fn partition(&self, partition: &str) -> Partition {
+        Partition::new(self.clone(), partition)
+    }
+
+    /// Creates a `BulkWriter` that buffers documents and writes them to this database in bulk.
+    /// See `BulkWriter` for an example.
+    pub fn bulk_writer<T: TypedCouchDocument>(&self, options: BulkWriterOptions) -> BulkWriter<T> {
+        BulkWriter::new(self.clone(), options)
+    }
+
+    /// Creates a `ViewPager` that iterates the rows of a view in pages of `page_size` rows.
+    /// See `ViewPager` for an example.
+    pub fn view_pager<K: DeserializeOwned, V: DeserializeOwned, T: DeserializeOwned>(
+        &self,
+        design_name: &str,
+        view_name: &str,
+        options: Option<QueryParams>,
+        page_size: u64,
+    ) -> ViewPager<K, V, T> {
+        ViewPager::new(self.clone(), design_name, view_name, options, page_size)
+    }
+
+    /// Creates a `Paginator` over the documents found by a Mango query, in pages of `page_size`
+    /// documents. See `Paginator` for an example.
+    pub fn find_paginator<T: TypedCouchDocument>(&self, query: FindQuery, page_size: u64) -> Paginator<T> {
+        Paginator::find(self.clone(), query, page_size)
+    }
+
+    /// Creates a `Paginator` over all documents of this database, in pages of `page_size` documents.
+    /// See `Paginator` for an example.
+    pub fn all_docs_paginator<T: TypedCouchDocument>(
+        &self,
+        params: Option<QueryParams>,
+        page_size: u64,
+    ) -> Paginator<T> {
+        Paginator::all_docs(self.clone(), params, page_size)
+    }
+
     /// Gets documents in bulk with provided IDs list, with added params. Params description can be found here:
     /// [_all_docs](https://docs.couchdb.org/en/latest/api/database/bulk-api.html?highlight=_all_docs)
+    /// The documents are included, unless `include_docs(false)` is set; the documents then only hold
+    /// their `_id` and `_rev`.
     ///
     /// Usage:
     ///
@@ -283,20 +1066,131 @@ impl Database {
         &self,
         ids: Vec<DocumentId>,
         params: Option<QueryParams>,
+    ) -> CouchResult<DocumentCollection<T>> {
+        self.get_bulk_chunked(ids, params, DEFAULT_BATCH_SIZE as usize, 1).await
+    }
+
+    /// Gets documents in bulk, like `get_bulk_params`, but splits the IDs list into multiple `_all_docs`
+    /// requests of at most `chunk_size` IDs. Up to `concurrency` requests run in parallel. The documents
+    /// are returned in the order of the provided IDs.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use serde_json::Value;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let ids = (0..10_000).map(|i| format!("doc_{}", i)).collect();
+    ///     let docs = db.get_bulk_chunked::<Value>(ids, None, 500, 4).await?;
+    ///     println!("found {} documents", docs.rows.len());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn get_bulk_chunked<T: TypedCouchDocument>(
+        &self,
+        ids: Vec<DocumentId>,
+        params: Option<QueryParams>,
+        chunk_size: usize,
+        concurrency: usize,
     ) -> CouchResult<DocumentCollection<T>> {
         let mut options = params.unwrap_or_default();
+        options.include_docs = options.include_docs.or(Some(true));
+
+        if ids.len() <= chunk_size {
+            options.keys = ids.into_iter().map(Value::from).collect();
+            return self.get_bulk_chunk(options).await;
+        }
+
+        let requests = ids.chunks(chunk_size.max(1)).map(|keys| {
+            let mut options = options.clone();
+            options.keys = keys.iter().map(|key| Value::from(key.as_str())).collect();
+            self.get_bulk_chunk(options)
+        });
+        let collections: Vec<DocumentCollection<T>> = stream::iter(requests)
+            .buffered(concurrency.max(1))
+            .try_collect()
+            .await?;
+
+        let mut merged = DocumentCollection::default();
+        for collection in collections {
+            merged.offset = merged.offset.or(collection.offset);
+            merged.update_seq = merged.update_seq.or(collection.update_seq);
+            merged.total_rows += collection.total_rows;
+            merged.rows.extend(collection.rows);
+        }
+        Ok(merged)
+    }
+
+    /// Gets documents one request per ID, with up to `parallelism` requests running at once. The
+    /// result of every ID is returned in the order of the provided IDs, so missing documents are
+    /// `NotFound` errors at their position. `get_bulk_chunked` reads many documents per request,
+    /// which is cheaper; individual requests are for options that `_all_docs` does not apply.
+    ///
+    /// Usage:
+    /// ```no_run
+    /// use couch_rs::error::CouchResult;
+    /// use serde_json::Value;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let ids = vec!["john".to_string(), "jane".to_string()];
+    ///     for (id, result) in ids.iter().zip(db.get_many_concurrent::<Value>(ids.clone(), 8).await) {
+    ///         match result {
+    ///             Ok(doc) => println!("{}: {}", id, doc),
+    ///             Err(err) if err.is_not_found() => println!("{} is missing", id),
+    ///             Err(err) => return Err(err),
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn get_many_concurrent<T: TypedCouchDocument>(
+        &self,
+        ids: Vec<DocumentId>,
+        parallelism: usize,
+    ) -> Vec<CouchResult<T>> {
+        self.get_many_concurrent_with_options(ids, parallelism, &GetOptions::default())
+            .await
+    }
 
-        options.include_docs = Some(true);
-        options.keys = ids;
+    /// Gets documents one request per ID, like `get_many_concurrent`, with the given options
+    pub async fn get_many_concurrent_with_options<T: TypedCouchDocument>(
+        &self,
+        ids: Vec<DocumentId>,
+        parallelism: usize,
+        options: &GetOptions,
+    ) -> Vec<CouchResult<T>> {
+        stream::iter(ids.iter().map(|id| self.get_with_options(id, options)))
+            .buffered(parallelism.max(1))
+            .collect()
+            .await
+    }
 
+    async fn get_bulk_chunk<T: TypedCouchDocument>(&self, options: QueryParams) -> CouchResult<DocumentCollection<T>> {
+        let include_design_docs = options.include_design_docs;
         let response = self
             ._client
             .post(self.create_raw_path("_all_docs"), to_string(&options)?)
-            .send()
+            .send_with_context()
             .await?
-            .error_for_status()?;
+            .check_status()
+            .await?;
 
-        Ok(DocumentCollection::new(response.json().await?))
+        Ok(DocumentCollection::from_all_docs(
+            response_json(response).await?,
+            include_design_docs,
+        ))
     }
 
     /// Gets all the documents in database
@@ -315,11 +1209,12 @@ impl Database {
     /// 1000 is used. max_results of 0 means all documents will be returned. A given max_results is
     /// always rounded *up* to the nearest multiplication of batch_size.
     /// This operation is identical to find_batched(FindQuery::find_all(), tx, batch_size, max_results)
+    /// See `all_docs_stream` for a stream of the batches, without a channel.
     ///
     /// Check out the async_batch_read example for usage details
     pub async fn get_all_batched<T: TypedCouchDocument>(
         &self,
-        tx: Sender<DocumentCollection<T>>,
+        tx: impl BatchSender<DocumentCollection<T>>,
         batch_size: u64,
         max_results: u64,
     ) -> CouchResult<u64> {
@@ -328,61 +1223,139 @@ impl Database {
     }
 
     /// Finds documents in the database, using bookmarks to iterate through all the documents.
-    /// Results are returned through an mpcs channel for async processing. Use this for very large
-    /// databases only. Batch size can be requested. A value of 0, means the default batch_size of
-    /// 1000 is used. max_results of 0 means all documents will be returned. A given max_results is
-    /// always rounded *up* to the nearest multiplication of batch_size.
+    /// Results are returned through an mpsc channel, any `BatchSender`, for async processing. Use
+    /// this for very large databases only. Batch size can be requested. A value of 0, means the
+    /// default batch_size of 1000 is used. max_results of 0 means all documents will be returned. A
+    /// given max_results is always rounded *up* to the nearest multiplication of batch_size.
+    /// Returns the number of documents sent. Sending waits while a bounded channel is full, and stops
+    /// with a `GONE` error when the receiver has been dropped.
+    /// See `find_stream` for a stream of the batches, without a channel.
     ///
     /// Check out the async_batch_read example for usage details
     pub async fn find_batched<T: TypedCouchDocument>(
         &self,
-        mut query: FindQuery,
-        tx: Sender<DocumentCollection<T>>,
+        query: FindQuery,
+        mut tx: impl BatchSender<DocumentCollection<T>>,
         batch_size: u64,
         max_results: u64,
     ) -> CouchResult<u64> {
-        let mut bookmark = Option::None;
-        let limit = if batch_size > 0 { batch_size } else { 1000 };
-
+        let mut paginator = self.find_paginator::<T>(query, batch_size);
         let mut results: u64 = 0;
-        query.limit = Option::Some(limit);
-
-        let maybe_err = loop {
-            let mut segment_query = query.clone();
-            segment_query.bookmark = bookmark.clone();
-            let all_docs = match self.find(&segment_query).await {
-                Ok(docs) => docs,
-                Err(err) => break Some(err),
-            };
 
-            if all_docs.total_rows == 0 {
-                // no more rows
-                break None;
+        while let Some(batch) = paginator.next_page().await? {
+            let rows = batch.rows.len() as u64;
+            // waits while a bounded channel is full
+            if !tx.send_batch(batch).await {
+                return Err(CouchError::new(
+                    format!("the receiver hung up after {} documents", results),
+                    reqwest::StatusCode::GONE,
+                ));
             }
+            results += rows;
 
-            if all_docs.bookmark.is_some() && all_docs.bookmark != bookmark {
-                bookmark.replace(all_docs.bookmark.clone().unwrap_or_default());
-            } else {
-                // no bookmark, break the query loop
-                break None;
+            if max_results > 0 && results >= max_results {
+                break;
             }
+        }
 
-            results += all_docs.total_rows as u64;
+        Ok(results)
+    }
 
-            if let Err(_err) = tx.send(all_docs).await {
-                break None;
-            }
+    /// Finds documents through a Mango query, as a stream of batches that are requested while the
+    /// stream is consumed. Unlike `find_batched`, no channel or consumer task is needed, and the
+    /// stream composes with `StreamExt`; `take` limits the number of batches. A batch size of 0 means
+    /// the default of 1000 documents. Use `Paginator::documents` for a stream of single documents.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::find::FindQuery;
+    /// use futures_util::stream::TryStreamExt;
+    /// use serde_json::{json, Value};
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let mut batches = Box::pin(db.find_stream::<Value>(FindQuery::new(json!({"last_name": "Doe"})), 100));
+    ///     while let Some(batch) = batches.try_next().await? {
+    ///         println!("{} Does", batch.rows.len());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn find_stream<T: TypedCouchDocument>(
+        &self,
+        query: FindQuery,
+        batch_size: u64,
+    ) -> impl Stream<Item = CouchResult<DocumentCollection<T>>> {
+        self.find_paginator(query, batch_size).pages()
+    }
 
-            if max_results > 0 && results >= max_results {
-                break None;
-            }
-        };
+    /// Gets all documents in the database through `_all_docs`, as a stream of batches that are
+    /// requested while the stream is consumed. See `find_stream`.
+    pub fn all_docs_stream<T: TypedCouchDocument>(
+        &self,
+        batch_size: u64,
+    ) -> impl Stream<Item = CouchResult<DocumentCollection<T>>> {
+        self.all_docs_paginator(None, batch_size).pages()
+    }
 
-        if let Some(err) = maybe_err {
-            Err(err)
-        } else {
-            Ok(results)
+    /// Reads all documents through `_all_docs` in `partitions` ranges of document ids, which are read
+    /// concurrently, in batches of `batch_size` documents. The batches of all ranges are merged into
+    /// one stream, so they are not returned in id order. The ranges split the ids between the first
+    /// and the last document evenly, which takes a single request; they hold about the same number of
+    /// documents when the ids are spread evenly, such as generated UUIDs, and less so for skewed ids.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use futures_util::stream::TryStreamExt;
+    /// use serde_json::Value;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let mut batches = db.scan_all_docs::<Value>(8, 1000).await?;
+    ///     while let Some(batch) = batches.try_next().await? {
+    ///         println!("{} documents", batch.rows.len());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn scan_all_docs<T: TypedCouchDocument>(
+        &self,
+        partitions: usize,
+        batch_size: u64,
+    ) -> CouchResult<impl Stream<Item = CouchResult<DocumentCollection<T>>> + Unpin> {
+        let edges = vec![
+            QueryParams::default().limit(1),
+            QueryParams::default().descending(true).limit(1),
+        ];
+        let mut edges = self.query_many_all_docs(QueriesParams::new(edges)).await?.into_iter();
+        let first = edges.next().unwrap_or_default();
+        let last = edges.next().unwrap_or_default();
+        let total_rows = first.total_rows.unwrap_or_default() as usize;
+
+        let mut boundaries = vec![];
+        if let (Some(first), Some(last)) = (
+            first.rows.into_iter().next().and_then(|row| row.id),
+            last.rows.into_iter().next().and_then(|row| row.id),
+        ) {
+            boundaries = interpolated_ids(&first, &last, partitions.clamp(1, total_rows.max(1)));
         }
+
+        let scans = id_ranges(&boundaries)
+            .into_iter()
+            .map(|range| Box::pin(self.all_docs_paginator::<T>(Some(range), batch_size).pages()));
+        Ok(stream::select_all(scans))
     }
 
     /// Executes multiple specified built-in view queries of all documents in this database.
@@ -409,13 +1382,8 @@ impl Database {
     ///     // planes have IDs starting with "plane:", and boats have IDs starting with "boat:"
     ///     //
     ///     // let's query for all cars and all boats, sending just 1 request
-    ///     let mut cars = QueryParams::default();
-    ///     cars.start_key = Some("car".to_string());
-    ///     cars.end_key = Some("car:\u{fff0}".to_string());
-    ///
-    ///     let mut boats = QueryParams::default();
-    ///     boats.start_key = Some("boat".to_string());
-    ///     boats.end_key = Some("boat:\u{fff0}".to_string());
+    ///     let cars = QueryParams::default().start_key("car").end_key("car:\u{fff0}");
+    ///     let boats = QueryParams::default().start_key("boat").end_key("boat:\u{fff0}");
     ///
     ///     let mut collections = db.query_many_all_docs(QueriesParams::new(vec![cars, boats])).await?;
     ///     println!("Succeeded querying for cars and boats");
@@ -436,6 +1404,42 @@ impl Database {
             .await
     }
 
+    /// Executes multiple queries on the design documents of this database, in place of multiple
+    /// POST /{db}/_design_docs requests, like `query_many_all_docs` does for all documents. The
+    /// keys of the rows are the IDs of the design documents, such as `_design/app`, so ranges of
+    /// keys list the design documents whose names share a prefix.
+    ///
+    /// Usage:
+    /// ```no_run
+    /// use couch_rs::types::query::{QueryParams, QueriesParams};
+    /// use couch_rs::error::CouchResult;
+    ///
+    /// const TEST_DB: &str = "vehicles";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     // design documents are named after the type of vehicle whose views they hold
+    ///     let cars = QueryParams::default().start_key("_design/car").end_key("_design/car\u{fff0}");
+    ///     let boats = QueryParams::default().start_key("_design/boat").end_key("_design/boat\u{fff0}");
+    ///
+    ///     let collections = db.query_many_design_docs(QueriesParams::new(vec![cars, boats])).await?;
+    ///     for row in &collections[0].rows {
+    ///         println!("Car design document {}", row.key);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn query_many_design_docs(
+        &self,
+        queries: QueriesParams,
+    ) -> CouchResult<Vec<ViewCollection<Value, Value, Value>>> {
+        self.query_view_many(self.create_raw_path("_design_docs/queries"), queries)
+            .await
+    }
+
     /// Executes multiple queries against a view.
     pub async fn query_many(
         &self,
@@ -443,25 +1447,39 @@ impl Database {
         view_name: &str,
         queries: QueriesParams,
     ) -> CouchResult<Vec<ViewCollection<Value, Value, Value>>> {
+        self.query_many_as(design_name, view_name, queries).await
+    }
+
+    /// Executes multiple queries against a view, deserializing the keys, values and included
+    /// documents of the rows into the given types.
+    pub async fn query_many_as<K: DeserializeOwned, V: DeserializeOwned, T: DeserializeOwned>(
+        &self,
+        design_name: &str,
+        view_name: &str,
+        queries: QueriesParams,
+    ) -> CouchResult<Vec<ViewCollection<K, V, T>>> {
         self.query_view_many(self.create_query_view_path(design_name, view_name), queries)
             .await
     }
 
-    async fn query_view_many(
+    async fn query_view_many<K: DeserializeOwned, V: DeserializeOwned, T: DeserializeOwned>(
         &self,
         view_path: String,
         queries: QueriesParams,
-    ) -> CouchResult<Vec<ViewCollection<Value, Value, Value>>> {
+    ) -> CouchResult<Vec<ViewCollection<K, V, T>>> {
+        queries.queries.iter().try_for_each(QueryParams::validate)?;
+
         // we use POST here, because this allows for a larger set of keys to be provided, compared
         // to a GET call. It provides the same functionality
         let response = self
             ._client
             .post(view_path, js!(&queries))
-            .send()
+            .send_with_context()
             .await?
-            .error_for_status()?;
+            .check_status()
+            .await?;
 
-        let results: QueriesCollection<Value, Value, Value> = response.json().await?;
+        let results: QueriesCollection<K, V, T> = response_json(response).await?;
         Ok(results.results)
     }
 
@@ -471,24 +1489,316 @@ impl Database {
 
     /// Gets all the documents in database, with applied parameters.
     /// Parameters description can be found here: [api-ddoc-view](https://docs.couchdb.org/en/latest/api/ddoc/views.html#api-ddoc-view)
+    /// The documents are included, unless `include_docs(false)` is set; the documents then only hold
+    /// their `_id` and `_rev`, which is much cheaper. Design documents are left out, unless
+    /// `include_design_docs(true)` is set.
     pub async fn get_all_params<T: TypedCouchDocument>(
         &self,
         params: Option<QueryParams>,
     ) -> CouchResult<DocumentCollection<T>> {
+        let params = params.unwrap_or_default();
+        let include_design_docs = params.include_design_docs;
+        let response = self.all_docs_response(params).await?;
+        Ok(DocumentCollection::from_all_docs(response, include_design_docs))
+    }
+
+    /// Gets the local documents of the database, with applied parameters, such as the checkpoints
+    /// of replications, of PouchDB clients and of `save_checkpoint`. Local documents are neither
+    /// replicated nor listed by `get_all`; their IDs start with `_local/`, and `remove` deletes them.
+    /// The documents are included, unless `include_docs(false)` is set.
+    ///
+    /// Usage:
+    /// ```no_run
+    /// use couch_rs::error::CouchResult;
+    /// use serde_json::Value;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     for checkpoint in db.get_local_docs::<Value>(None).await? {
+    ///         println!("{} was last written at {}", checkpoint["_id"], checkpoint["_rev"]);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn get_local_docs<T: TypedCouchDocument>(
+        &self,
+        params: Option<QueryParams>,
+    ) -> CouchResult<DocumentCollection<T>> {
+        let response = self
+            .all_docs_response_at(self.create_raw_path("_local_docs"), params.unwrap_or_default())
+            .await?;
+        Ok(DocumentCollection::from_all_docs(response, true))
+    }
+
+    /// Gets all the documents in the database, with applied parameters, as a stream. The rows are
+    /// parsed while the response is being received, so memory use stays bounded by the size of a
+    /// single document, however large the result is. Errors of the request itself are returned
+    /// before the stream is; design documents are skipped, unless `include_design_docs(true)` is set.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use futures_util::stream::TryStreamExt;
+    /// use serde_json::Value;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let mut docs = Box::pin(db.get_all_stream::<Value>(None).await?);
+    ///     while let Some(doc) = docs.try_next().await? {
+    ///         println!("{}", doc);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn get_all_stream<T: TypedCouchDocument>(
+        &self,
+        params: Option<QueryParams>,
+    ) -> CouchResult<impl Stream<Item = CouchResult<T>>> {
         let mut options = params.unwrap_or_default();
+        options.validate()?;
+        options.include_docs = options.include_docs.or(Some(true));
+
+        let response = self
+            ._client
+            .post(self.create_raw_path("_all_docs"), js!(&options))
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?;
+
+        let include_design_docs = options.include_design_docs;
+        Ok(response_rows(response)
+            .try_filter_map(move |row: DocResponse<T>| future::ready(Ok(row.into_doc_with(include_design_docs)))))
+    }
+
+    /// Exports all documents of the database, including design documents, to `writer`, as one JSON
+    /// document per line. With `attachments(true)`, the content of every attachment is exported,
+    /// base64 encoded next to its digest, so `restore` brings the attachments back; otherwise they
+    /// are left out. The documents are streamed, so memory use stays bounded by the size of a
+    /// single document. Local documents are not exported.
+    ///
+    /// Documents written during the export are exported as they were when they were read. With
+    /// `snapshot(true)`, the export holds the database as of a single update sequence instead: the
+    /// `update_seq` is recorded before the documents are streamed, and the changes feed since is read
+    /// afterwards, page by page, up to its `last_seq`, which the summary returns. The documents
+    /// that changed after they were read are exported once more, with their revision history, at
+    /// their winning revision; the ones already exported at it are not written again. `restore`
+    /// applies the lines in order, so the later revisions win, and deleted documents are restored
+    /// as deleted.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::export::ExportOptions;
+    /// use std::fs::File;
+    /// use std::io::BufWriter;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///     let file = File::create(std::env::temp_dir().join("test_db.jsonl")).expect("can not create the export");
+    ///
+    ///     let summary = db.export(BufWriter::new(file), &ExportOptions::default().attachments(true)).await?;
+    ///     println!("exported {} documents", summary.doc_count);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn export<W: Write>(&self, mut writer: W, options: &ExportOptions) -> CouchResult<ExportSummary> {
+        let since = if options.snapshot {
+            Some(Seq::from(self.info().await?.update_seq))
+        } else {
+            None
+        };
+        let params = QueryParams::default()
+            .include_design_docs(true)
+            .attachments(options.attachments);
+        let mut docs = Box::pin(self.get_all_stream::<Value>(Some(params)).await?);
+        let mut summary = ExportSummary::default();
+        let mut exported = ExportedRevs::default();
+
+        while let Some(doc) = docs.try_next().await? {
+            if since.is_some() {
+                exported.insert(
+                    doc["_id"].as_str().unwrap_or_default(),
+                    doc["_rev"].as_str().unwrap_or_default(),
+                );
+            }
+            summary.attachment_count += export::write_doc(&mut writer, doc, options.attachments)?;
+            summary.doc_count += 1;
+        }
+
+        if let Some(mut seq) = since {
+            loop {
+                let changes = self
+                    .changes(&ChangesOptions::default().since(seq).limit(DEFAULT_BATCH_SIZE))
+                    .await?;
+                let caught_up = changes.pending == Some(0) || changes.results.len() < DEFAULT_BATCH_SIZE as usize;
+                // the documents already exported at their winning revision are left out, and so are
+                // the ones deleted before they were exported, which have nothing to supersede
+                let revs: Vec<(String, String)> = changes
+                    .results
+                    .into_iter()
+                    .filter(|change| !change.id.starts_with("_local/"))
+                    .filter(|change| !change.deleted || exported.contains_id(&change.id))
+                    .filter_map(|change| Some((change.id, change.changes.into_iter().next()?.rev)))
+                    .filter(|(id, rev)| !exported.contains(id, rev))
+                    .collect();
+
+                if !revs.is_empty() {
+                    for doc in self.get_revisions(&revs, options.attachments).await? {
+                        let exported_before = exported.insert(
+                            doc["_id"].as_str().unwrap_or_default(),
+                            doc["_rev"].as_str().unwrap_or_default(),
+                        );
+                        summary.attachment_count += export::write_doc(&mut writer, doc, options.attachments)?;
+                        if exported_before {
+                            summary.changed_count += 1;
+                        } else {
+                            summary.doc_count += 1;
+                        }
+                    }
+                }
+
+                seq = changes.last_seq;
+                if caught_up {
+                    break;
+                }
+            }
+            summary.seq = Some(seq);
+        }
+
+        writer.flush().map_err(|err| export::io_failed("write", err))?;
+        Ok(summary)
+    }
+
+    /// Gets the given revisions of documents through `_bulk_get`, with their revision history, and
+    /// the content of their attachments when `attachments` is set
+    async fn get_revisions(&self, revs: &[(String, String)], attachments: bool) -> CouchResult<Vec<Value>> {
+        let docs: Vec<Value> = revs.iter().map(|(id, rev)| json!({"id": id, "rev": rev})).collect();
+        let mut query = HashMap::new();
+        query.insert(s!("revs"), s!("true"));
+        query.insert(s!("attachments"), attachments.to_string());
+
+        let response: Value = self
+            ._client
+            .post(self.create_raw_path("_bulk_get"), js!(json!({ "docs": docs })))
+            .query(&query)
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?
+            .read_json()
+            .await?;
+
+        let mut found = vec![];
+        for result in response["results"].as_array().into_iter().flatten() {
+            for doc in result["docs"].as_array().into_iter().flatten() {
+                match (&doc["ok"], &doc["error"]) {
+                    (Value::Object(_), _) => found.push(doc["ok"].clone()),
+                    (_, error) => {
+                        let reason = error["reason"].as_str().unwrap_or_default().to_string();
+                        return Err(CouchError::from_error(
+                            error["id"].as_str().map(String::from),
+                            error["error"].as_str().unwrap_or("not_found"),
+                            reason,
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Restores the documents of an export, written by `export`, into this database. The documents
+    /// are written with the revisions they had, like a replication does, so restoring into an empty
+    /// database recreates the exported one, and restoring twice changes nothing. Validation hooks
+    /// are not applied. The client must be an admin to restore design documents; documents that
+    /// could not be written are reported in the summary.
+    pub async fn restore<R: BufRead>(&self, reader: R) -> CouchResult<RestoreSummary> {
+        let mut summary = RestoreSummary::default();
+        let mut batch = vec![];
+
+        for line in reader.lines() {
+            let line = line.map_err(|err| export::io_failed("read", err))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            batch.push(serde_json::from_str::<Value>(&line)?);
+            if batch.len() as u64 == DEFAULT_BATCH_SIZE {
+                summary.failures.extend(self.write_revisions(&batch).await?);
+                summary.doc_count += batch.len() as u64;
+                batch.clear();
+            }
+        }
+        if !batch.is_empty() {
+            summary.failures.extend(self.write_revisions(&batch).await?);
+            summary.doc_count += batch.len() as u64;
+        }
+        Ok(summary)
+    }
+
+    /// Writes documents with the revisions they hold, through `_bulk_docs` without `new_edits`,
+    /// returning the documents that could not be written
+    async fn write_revisions(&self, docs: &[Value]) -> CouchResult<Vec<CouchError>> {
+        let body = json!({"docs": docs, "new_edits": false});
+        let response = self
+            ._client
+            .post(self.create_raw_path("_bulk_docs"), js!(&body))
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?;
+
+        // without new_edits, only the documents that failed are listed
+        let failed: Vec<DocumentCreatedResponse> = response.read_json().await?;
+        Ok(failed
+            .into_iter()
+            .filter_map(|response| DocumentCreatedResult::from(response).err())
+            .collect())
+    }
+
+    /// Queries `_all_docs`, keeping the rows as returned. The documents are included, unless
+    /// `include_docs` is disabled.
+    pub(crate) async fn all_docs_response<T: TypedCouchDocument>(
+        &self,
+        options: QueryParams,
+    ) -> CouchResult<AllDocsResponse<T>> {
+        self.all_docs_response_at(self.create_raw_path("_all_docs"), options)
+            .await
+    }
 
-        options.include_docs = Some(true);
+    /// Reads `_all_docs`, or the `_all_docs` of a partition, at the given path
+    pub(crate) async fn all_docs_response_at<T: TypedCouchDocument>(
+        &self,
+        path: String,
+        mut options: QueryParams,
+    ) -> CouchResult<AllDocsResponse<T>> {
+        options.include_docs = options.include_docs.or(Some(true));
 
         // we use POST here, because this allows for a larger set of keys to be provided, compared
         // to a GET call. It provides the same functionality
         let response = self
             ._client
-            .post(self.create_raw_path("_all_docs"), js!(&options))
-            .send()
+            .post(path, js!(&options))
+            .send_with_context()
             .await?
-            .error_for_status()?;
+            .check_status()
+            .await?;
 
-        Ok(DocumentCollection::new(response.json().await?))
+        response_json(response).await
     }
 
     /// Finds a document in the database through a Mango query as raw Values.
@@ -550,170 +1860,119 @@ impl Database {
     /// }
     /// ```
     pub async fn find<T: TypedCouchDocument>(&self, query: &FindQuery) -> CouchResult<DocumentCollection<T>> {
-        let path = self.create_raw_path("_find");
-        let response = self._client.post(path, js!(query)).send().await?;
-        let status = response.status();
-        let data: FindResult<T> = response.json().await?;
-
-        if let Some(doc_val) = data.docs {
-            let documents: Vec<T> = doc_val
-                .into_iter()
-                .filter(|d| {
-                    // Remove _design documents
-                    let id: String = d.get_id().into_owned();
-                    !id.starts_with('_')
-                })
-                .collect();
-
-            let mut bookmark = Option::None;
-            let returned_bookmark = data.bookmark.unwrap_or_default();
-
-            if returned_bookmark != "nil" && !returned_bookmark.is_empty() {
-                // a valid bookmark has been returned
-                bookmark.replace(returned_bookmark);
-            }
-
-            Ok(DocumentCollection::new_from_documents(documents, bookmark))
-        } else if let Some(err) = data.error {
-            Err(CouchError::new(err, status))
-        } else {
-            Ok(DocumentCollection::default())
-        }
+        let found = self.find_as::<T>(query).await?;
+        Ok(found_collection(found, query.include_design_docs))
     }
 
-    /// Saves a document to CouchDB. When the provided document includes both an `_id` and a `_rev`
-    /// CouchDB will attempt to update the document. When only an `_id` is provided, the `save`
-    /// method behaves like `create` and will attempt to create the document.
+    /// Finds the first document matching a Mango query. The `limit` of the query is set to 1.
+    /// Returns `None` when no document matches.
     ///
     /// Usage:
     /// ```
     /// use couch_rs::types::find::FindQuery;
     /// use couch_rs::error::CouchResult;
-    /// use serde_json::{from_value, to_value};
-    /// use couch_rs::types::document::DocumentId;
-    /// use couch_rs::document::TypedCouchDocument;
-    /// use couch_rs::CouchDocument;
-    /// use serde::{Deserialize, Serialize};
+    /// use serde_json::{json, Value};
     ///
     /// const TEST_DB: &str = "test_db";
     ///
-    /// #[derive(Serialize, Deserialize, CouchDocument)]
-    /// pub struct UserDetails {
-    ///     #[serde(skip_serializing_if = "String::is_empty")]
-    ///     pub _id: DocumentId,
-    ///     #[serde(skip_serializing_if = "String::is_empty")]
-    ///     pub _rev: String,
-    ///     #[serde(rename = "firstName")]
-    ///     pub first_name: Option<String>,
-    ///     #[serde(rename = "lastName")]
-    ///     pub last_name: String,
-    /// }
-    ///
     /// #[tokio::main]
     /// async fn main() -> CouchResult<()> {
     ///     let client = couch_rs::Client::new_local_test()?;
     ///     let db = client.db(TEST_DB).await?;
+    ///     let query = FindQuery::new(json!({"last_name": "Doe"}));
     ///
-    ///     // before we can get the document, we need to create it first...
-    ///     let seed_doc = UserDetails {
-    ///         _id: "123".to_string(),
-    ///         _rev: "".to_string(),
-    ///         first_name: None,
-    ///         last_name: "Doe".to_string(),
-    ///     };
-    ///     let value = to_value(seed_doc)?;
-    ///     db.create(value).await?;
-    ///
-    ///     // now that the document is created, we can get it, update it, and save it...
-    ///     let mut user_details: UserDetails = db.get("123").await?;
-    ///     user_details.first_name = Some("John".to_string());
-    ///
-    ///     db.save(user_details).await?;
+    ///     if let Some(doe) = db.find_one::<Value>(&query).await? {
+    ///         println!("Found {}", doe);
+    ///     }
     ///     Ok(())
     /// }
-    ///```
-    pub async fn save<T: TypedCouchDocument>(&self, mut doc: T) -> CouchResult<T> {
-        let id = doc.get_id().to_string();
-        let body = to_string(&doc)?;
-        let response = self._client.put(self.create_document_path(&id), body).send().await?;
-        let status = response.status();
-        let data: DocumentCreatedResponse = response.json().await?;
-
-        match data.ok {
-            Some(true) => {
-                doc.set_id(&data.id.unwrap_or_default());
-                doc.set_rev(&data.rev.unwrap_or_default());
-                Ok(doc)
-            }
-            _ => {
-                let err = data.error.unwrap_or_else(|| s!("unspecified error"));
-                Err(CouchError::new(err, status))
-            }
-        }
+    /// ```
+    pub async fn find_one<T: TypedCouchDocument>(&self, query: &FindQuery) -> CouchResult<Option<T>> {
+        let query = query.clone().limit(1);
+        let collection = self.find::<T>(&query).await?;
+        Ok(collection.rows.into_iter().next())
     }
 
-    /// Creates a document from a raw JSON document Value.
-    /// Usage:
+    /// Finds documents in the database through a Mango query, deserializing them into any type.
+    /// Unlike `find`, the type does not need to implement `TypedCouchDocument`, which makes this
+    /// useful for queries that only return a selection of `fields`.
     ///
+    /// Usage:
     /// ```
     /// use couch_rs::types::find::FindQuery;
     /// use couch_rs::error::CouchResult;
-    /// use serde_json::json;
-    /// use couch_rs::document::TypedCouchDocument;
+    /// use serde::Deserialize;
     ///
-    /// const TEST_DB: &str = "test_db";
+    /// const TEST_DB: &str = "user_db";
+    ///
+    /// #[derive(Deserialize)]
+    /// pub struct Name {
+    ///     pub first_name: String,
+    /// }
     ///
     /// #[tokio::main]
     /// async fn main() -> CouchResult<()> {
-    /// let client = couch_rs::Client::new_local_test()?;
+    ///     let client = couch_rs::Client::new_local_test()?;
     ///     let db = client.db(TEST_DB).await?;
-    ///     let doc = json!({
-    ///                     "first_name": "John",
-    ///                     "last_name": "Doe"
-    ///                 });
+    ///     let query = FindQuery::find_all().fields(vec!["first_name".to_string()]);
+    ///     let found = db.find_as::<Name>(&query).await?;
     ///
-    ///     let created_doc = db.create(doc).await?;
-    ///
-    ///     // verify that this is the 1st revision of the document
-    ///     assert!(created_doc.get_rev().starts_with('1'));
+    ///     for name in found.docs {
+    ///         println!("{}", name.first_name);
+    ///     }
     ///     Ok(())
     /// }
     /// ```
-    pub async fn create<T: TypedCouchDocument>(&self, mut doc: T) -> CouchResult<T> {
-        let response = self._client.post(self.name.clone(), to_string(&doc)?).send().await?;
+    pub async fn find_as<T: DeserializeOwned>(&self, query: &FindQuery) -> CouchResult<FindDocuments<T>> {
+        self.find_as_at(self.create_raw_path("_find"), query).await
+    }
 
+    /// Runs a Mango query on `_find`, or on the `_find` of a partition, at the given path
+    pub(crate) async fn find_as_at<T: DeserializeOwned>(
+        &self,
+        path: String,
+        query: &FindQuery,
+    ) -> CouchResult<FindDocuments<T>> {
+        let response = self
+            ._client
+            .post(path, js!(query))
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?;
         let status = response.status();
-        let data: DocumentCreatedResponse = response.json().await?;
-
-        match data.ok {
-            Some(true) => {
-                let data_id = data.id.ok_or_else(|| CouchError::new(s!("invalid id"), status))?;
-                let data_rev = data.rev.ok_or_else(|| CouchError::new(s!("invalid rev"), status))?;
+        let data: FindResult<T> = response_json(response).await?;
 
-                doc.set_id(&data_id);
-                doc.set_rev(&data_rev);
-                Ok(doc)
-            }
-            _ => {
-                let err = data.error.unwrap_or_else(|| s!("unspecified error"));
-                Err(CouchError::new(err, status))
+        if data.docs.is_none() {
+            if let Some(error) = data.error {
+                let reason = data.reason.unwrap_or_else(|| error.clone());
+                return Err(CouchError::from_couch(None, status, &error, reason));
             }
         }
+
+        let mut bookmark = Option::None;
+        let returned_bookmark = data.bookmark.unwrap_or_default();
+
+        if returned_bookmark != "nil" && !returned_bookmark.is_empty() {
+            // a valid bookmark has been returned
+            bookmark.replace(returned_bookmark);
+        }
+
+        Ok(FindDocuments {
+            docs: data.docs.unwrap_or_default(),
+            bookmark,
+            warning: data.warning,
+            execution_stats: data.execution_stats,
+        })
     }
 
-    /// The upsert function combines a `get` with a `save` function. If the document with the
-    /// provided `_id` can be found it will be merged with the provided Document's value, otherwise
-    /// the document will be created.
-    /// This operation always performs a `get`, so if you have a documents `_rev` using a `save` is
-    /// quicker. Same is true when you know a document does *not* exist.
+    /// Counts the documents matching a Mango selector. Only the `_id` of the matching documents is
+    /// retrieved, in batches of 1000, so the documents themselves are never transferred.
     ///
     /// Usage:
-    ///
     /// ```
-    /// use couch_rs::types::find::FindQuery;
     /// use couch_rs::error::CouchResult;
-    /// use couch_rs::document::TypedCouchDocument;
-    /// use serde_json::json;
+    /// use couch_rs::types::selector::Selector;
     ///
     /// const TEST_DB: &str = "test_db";
     ///
@@ -721,48 +1980,47 @@ impl Database {
     /// async fn main() -> CouchResult<()> {
     ///     let client = couch_rs::Client::new_local_test()?;
     ///     let db = client.db(TEST_DB).await?;
-    ///     let doc = json!({
-    ///                     "_id": "doe",
-    ///                     "first_name": "John",
-    ///                     "last_name": "Doe"
-    ///                 });
-    ///
-    ///     // initial call creates the document
-    ///     db.upsert(doc.clone()).await?;
-    ///
-    ///     // subsequent call updates the existing document
-    ///     let updated_doc = db.upsert(doc).await?;
-    ///
-    ///     // verify that this is the 2nd revision of the document
-    ///     assert!(updated_doc.get_rev().starts_with('2'));
+    ///     let selector = Selector::field("last_name").eq("Doe");
+    ///     let does = db.count(selector.as_value()).await?;
+    ///     println!("There are {} Does", does);
     ///     Ok(())
     /// }
     /// ```
-    pub async fn upsert<T: TypedCouchDocument>(&self, mut doc: T) -> CouchResult<T> {
-        let id = doc.get_id();
-
-        match self.get::<T>(&id).await {
-            Ok(current_doc) => {
-                doc.set_rev(&current_doc.get_rev());
-                self.save(doc).await
-            }
-            Err(err) => {
-                if err.is_not_found() {
-                    // document does not yet exist
-                    self.save(doc).await
-                } else {
-                    Err(err)
-                }
+    pub async fn count(&self, selector: &Value) -> CouchResult<u64> {
+        let mut query = FindQuery::new(selector.clone())
+            .fields(vec![s!("_id")])
+            .limit(DEFAULT_BATCH_SIZE);
+        let mut count = 0;
+
+        loop {
+            let found = self.find_as::<DocumentIdRev>(&query).await?;
+            let returned = found.docs.len() as u64;
+            // Skip _design documents, like find does
+            count += found.docs.iter().filter(|d| !d.id.starts_with('_')).count() as u64;
+
+            match found.bookmark {
+                Some(bookmark) if returned == DEFAULT_BATCH_SIZE => query.bookmark = Some(bookmark),
+                _ => break,
             }
         }
+
+        Ok(count)
     }
 
-    /// Creates a design with one of more view documents.
+    /// Updates all documents matching a Mango query. Each matching document is passed to the
+    /// `update` closure; return `Some(document)` to write the updated document back, or `None` to
+    /// leave it untouched. Documents are retrieved and written back through `bulk_docs` in batches
+    /// of the query's `limit` (default 1000). Any `fields` set on the query are ignored, because
+    /// complete documents are required to write them back.
+    ///
+    /// Returns the result per written document; documents that were modified concurrently are
+    /// reported with a `CONFLICT` status.
     ///
     /// Usage:
     /// ```
-    /// use couch_rs::types::view::{CouchFunc, CouchViews};
+    /// use couch_rs::types::find::FindQuery;
     /// use couch_rs::error::CouchResult;
+    /// use serde_json::{json, Value};
     ///
     /// const TEST_DB: &str = "test_db";
     ///
@@ -770,41 +2028,1171 @@ impl Database {
     /// async fn main() -> CouchResult<()> {
     ///     let client = couch_rs::Client::new_local_test()?;
     ///     let db = client.db(TEST_DB).await?;
+    ///     let query = FindQuery::new(json!({"last_name": "Doe"}));
     ///
-    ///     let couch_func = CouchFunc {
-    ///             map: "function (doc) { if (doc.funny == true) { emit(doc._id, doc.funny); } }".to_string(),
-    ///             reduce: None,
-    ///     };
+    ///     let results = db
+    ///         .update_by_query(&query, |mut doc: Value| {
+    ///             doc["last_name"] = json!("Dough");
+    ///             Some(doc)
+    ///         })
+    ///         .await?;
     ///
-    ///     let couch_views = CouchViews::new("clip_view", couch_func);
-    ///     db.create_view("clip_design", couch_views).await?;
+    ///     for conflict in results.iter().filter_map(|r| r.as_ref().err()) {
+    ///         println!("Could not update: {}", conflict);
+    ///     }
     ///     Ok(())
     /// }
     /// ```
-    pub async fn create_view<T: Into<serde_json::Value>>(
+    pub async fn update_by_query<T, F>(
         &self,
-        design_name: &str,
-        views: T,
-    ) -> CouchResult<DesignCreated> {
-        let doc: Value = views.into();
-        let response = self
+        query: &FindQuery,
+        mut update: F,
+    ) -> CouchResult<Vec<DocumentCreatedResult>>
+    where
+        T: TypedCouchDocument,
+        F: FnMut(T) -> Option<T>,
+    {
+        let mut query = query.clone();
+        let batch_size = query.limit.unwrap_or(DEFAULT_BATCH_SIZE);
+        query.limit = Some(batch_size);
+        query.fields = None;
+
+        // updated documents may move within the index that is used by the query, so keep track of
+        // the documents that have already been handled
+        let mut handled = HashSet::new();
+        let mut results = vec![];
+
+        loop {
+            let found = self.find_as::<T>(&query).await?;
+            let returned = found.docs.len() as u64;
+            let updated: Vec<T> = found
+                .docs
+                .into_iter()
+                .filter(|d| {
+                    let id = d.get_id();
+                    !id.starts_with('_') && handled.insert(id.into_owned())
+                })
+                .filter_map(&mut update)
+                .collect();
+
+            if !updated.is_empty() {
+                results.extend(self.bulk_docs(updated).await?);
+            }
+
+            match found.bookmark {
+                Some(bookmark) if returned == batch_size => query.bookmark = Some(bookmark),
+                _ => break,
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Deletes all documents matching a Mango selector. Only the `_id` and `_rev` of the matching
+    /// documents are retrieved, after which the documents are deleted in batches of 1000 through
+    /// `bulk_docs`.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use serde_json::json;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///     let result = db.delete_by_query(&json!({"last_name": "Doe"})).await?;
+    ///     println!("Deleted {} documents", result.deleted);
+    ///
+    ///     for failure in result.failures() {
+    ///         println!("Could not delete: {}", failure);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn delete_by_query(&self, selector: &Value) -> CouchResult<BulkDeleteResult> {
+        let mut query = FindQuery::new(selector.clone())
+            .fields(vec![s!("_id"), s!("_rev")])
+            .limit(DEFAULT_BATCH_SIZE);
+        let mut handled = HashSet::new();
+        let mut results = vec![];
+
+        loop {
+            let found = self.find_as::<DocumentIdRev>(&query).await?;
+            let returned = found.docs.len() as u64;
+            let tombstones: Vec<Value> = found
+                .docs
+                .into_iter()
+                .filter(|d| !d.id.starts_with('_') && handled.insert(d.id.clone()))
+                .map(|d| tombstone(&d.id, &d.rev))
+                .collect();
+
+            if !tombstones.is_empty() {
+                results.extend(self.bulk_docs(tombstones).await?);
+            }
+
+            match found.bookmark {
+                Some(bookmark) if returned == DEFAULT_BATCH_SIZE => query.bookmark = Some(bookmark),
+                _ => break,
+            }
+        }
+
+        Ok(BulkDeleteResult::new(results))
+    }
+
+    /// Purges document revisions: unlike deletion, purging leaves no tombstone, and is not replicated.
+    /// Takes the revisions to purge per document id, and returns the revisions that were purged.
+    /// See [purge](https://docs.couchdb.org/en/stable/api/database/misc.html#db-purge) for more details.
+    pub async fn purge(&self, revs: HashMap<String, Vec<String>>) -> CouchResult<HashMap<String, Vec<String>>> {
+        let response: Value = self
+            ._client
+            .post(self.create_raw_path("_purge"), js!(&revs))
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?
+            .read_json()
+            .await?;
+
+        Ok(serde_json::from_value(response["purged"].clone())?)
+    }
+
+    /// Gets the number of purges of which the database keeps a record, 1000 by default. Replicas
+    /// and indexes that fall behind by more purges than this miss purges.
+    /// See [purged infos limit](https://docs.couchdb.org/en/stable/api/database/misc.html#db-purged-infos-limit) for more details.
+    pub async fn purged_infos_limit(&self) -> CouchResult<u64> {
+        self._client
+            .get(self.create_raw_path("_purged_infos_limit"), None)
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?
+            .read_json()
+            .await
+    }
+
+    /// Sets the number of purges of which the database keeps a record. Raise it before purging more
+    /// documents than the limit in one go, so replications and indexes that are behind still
+    /// receive all purges.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     if db.purged_infos_limit().await? < 5000 {
+    ///         db.set_purged_infos_limit(5000).await?;
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn set_purged_infos_limit(&self, limit: u64) -> CouchResult<()> {
+        self._client
+            .put(self.create_raw_path("_purged_infos_limit"), limit.to_string())
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?;
+        Ok(())
+    }
+
+    /// Creates the Mango index on the expiry field that `run_expiry_sweep` uses, when it does not
+    /// exist yet. Returns whether the index was created.
+    pub async fn ensure_expiry_index(&self, options: &ExpiryOptions) -> CouchResult<bool> {
+        let spec = IndexFields::new(vec![SortSpec::Simple(options.field.clone())]);
+        self.ensure_index(&options.index_name(), spec).await
+    }
+
+    /// Removes the documents that have expired: the documents with an expiry field, `expires_at` by
+    /// default, holding a time in seconds since the Unix epoch that has passed. CouchDB has no
+    /// expiry of its own, so run this periodically, for example from a `tokio::time::interval`.
+    /// The documents are deleted, or purged, in batches; create the index on the expiry field with
+    /// `ensure_expiry_index` first, to find them without a full database scan.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::expiry::{expires_in, ExpiryOptions};
+    /// use serde_json::json;
+    /// use std::time::Duration;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///     let options = ExpiryOptions::default();
+    ///     db.ensure_expiry_index(&options).await?;
+    ///
+    ///     db.create(json!({"session": "abc", "expires_at": expires_in(Duration::from_secs(3600))})).await?;
+    ///
+    ///     let result = db.run_expiry_sweep(&options).await?;
+    ///     println!("Removed {} expired documents", result.deleted);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn run_expiry_sweep(&self, options: &ExpiryOptions) -> CouchResult<BulkDeleteResult> {
+        let selector = json!({ &options.field: { "$lte": expiry::now() } });
+        let query = FindQuery::new(selector)
+            .fields(vec![s!("_id"), s!("_rev")])
+            .limit(options.batch_size);
+        let mut results = vec![];
+
+        loop {
+            let found = self.find_as::<DocumentIdRev>(&query).await?;
+            let returned = found.docs.len() as u64;
+            let expired: Vec<DocumentIdRev> = found.docs.into_iter().filter(|d| !d.id.starts_with('_')).collect();
+            if expired.is_empty() {
+                break;
+            }
+
+            let batch = if options.purge {
+                self.purge_expired(expired).await?
+            } else {
+                let tombstones: Vec<Value> = expired.iter().map(|d| tombstone(&d.id, &d.rev)).collect();
+                self.bulk_docs(tombstones).await?
+            };
+            let removed = batch.iter().filter(|result| result.is_ok()).count();
+            results.extend(batch);
+
+            // removed documents no longer match, so every batch is found from the start; stop when
+            // the last batch was found, or when a batch could not be removed at all
+            if returned < options.batch_size || removed == 0 {
+                break;
+            }
+        }
+
+        Ok(BulkDeleteResult::new(results))
+    }
+
+    async fn purge_expired(&self, expired: Vec<DocumentIdRev>) -> CouchResult<Vec<DocumentCreatedResult>> {
+        let revs = expired.iter().map(|d| (d.id.clone(), vec![d.rev.clone()])).collect();
+        let purged = self.purge(revs).await?;
+
+        Ok(expired
+            .into_iter()
+            .map(|d| match purged.get(&d.id) {
+                Some(revs) if revs.contains(&d.rev) => Ok(DocumentCreatedDetails {
+                    id: Some(d.id),
+                    rev: Some(d.rev),
+                }),
+                _ => Err(CouchError::new_with_id(
+                    Some(d.id),
+                    s!("revision was not purged"),
+                    reqwest::StatusCode::CONFLICT,
+                )),
+            })
+            .collect())
+    }
+
+    /// Shows which index would be used to run a Mango query, without running it.
+    /// Use this to find out why a query falls back to a full database scan.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::types::find::FindQuery;
+    /// use couch_rs::error::CouchResult;
+    /// use serde_json::json;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///     let query = FindQuery::new(json!({"last_name": "Doe"}));
+    ///     let plan = db.explain(&query).await?;
+    ///
+    ///     if plan.is_full_scan() {
+    ///         println!("no index available for {}", query);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn explain(&self, query: &FindQuery) -> CouchResult<ExplainResult> {
+        self.explain_at(self.create_raw_path("_explain"), query).await
+    }
+
+    pub(crate) async fn explain_at(&self, path: String, query: &FindQuery) -> CouchResult<ExplainResult> {
+        self._client
+            .post(path, js!(query))
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?
+            .read_json()
+            .await
+    }
+
+    /// Explains a Mango query, and suggests an index when it falls back to a full database scan,
+    /// or can not run for lack of an index that serves its sort. The index is not created; see
+    /// `ensure_advised_index`.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::types::find::FindQuery;
+    /// use couch_rs::error::CouchResult;
+    /// use serde_json::json;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///     let query = FindQuery::new(json!({"last_name": "Doe", "age": {"$gt": 21}}));
+    ///     let advice = db.advise_index(&query).await?;
+    ///
+    ///     if let Some(index) = advice.suggested {
+    ///         println!("create {} for {}", serde_json::to_string(&index)?, query);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn advise_index(&self, query: &FindQuery) -> CouchResult<IndexAdvice> {
+        let explain = match self.explain(query).await {
+            Ok(explain) => Some(explain),
+            Err(err) if err.error() == "no_usable_index" => None,
+            Err(err) => return Err(err),
+        };
+        let needs_index = match &explain {
+            Some(explain) => explain.is_full_scan(),
+            None => true,
+        };
+
+        Ok(IndexAdvice {
+            explain,
+            needs_index,
+            suggested: if needs_index {
+                IndexFields::suggest_for(query)
+            } else {
+                None
+            },
+            created: false,
+        })
+    }
+
+    /// Like `advise_index`, but also creates the suggested index, named after its fields, with
+    /// `ensure_index`. The index is built when it is first used; see `wait_for_index` to build it
+    /// right away.
+    pub async fn ensure_advised_index(&self, query: &FindQuery) -> CouchResult<IndexAdvice> {
+        let mut advice = self.advise_index(query).await?;
+        if let Some(index) = &advice.suggested {
+            advice.created = self.ensure_index(&index.suggested_name(), index.clone()).await?;
+        }
+        Ok(advice)
+    }
+
+    /// Saves a document to CouchDB. When the provided document includes both an `_id` and a `_rev`
+    /// CouchDB will attempt to update the document. When only an `_id` is provided, the `save`
+    /// method behaves like `create` and will attempt to create the document.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::types::find::FindQuery;
+    /// use couch_rs::error::CouchResult;
+    /// use serde_json::{from_value, to_value};
+    /// use couch_rs::types::document::DocumentId;
+    /// use couch_rs::document::TypedCouchDocument;
+    /// use couch_rs::CouchDocument;
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[derive(Serialize, Deserialize, CouchDocument)]
+    /// pub struct UserDetails {
+    ///     #[serde(skip_serializing_if = "String::is_empty")]
+    ///     pub _id: DocumentId,
+    ///     #[serde(skip_serializing_if = "String::is_empty")]
+    ///     pub _rev: String,
+    ///     #[serde(rename = "firstName")]
+    ///     pub first_name: Option<String>,
+    ///     #[serde(rename = "lastName")]
+    ///     pub last_name: String,
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     // before we can get the document, we need to create it first...
+    ///     let seed_doc = UserDetails {
+    ///         _id: "123".to_string(),
+    ///         _rev: "".to_string(),
+    ///         first_name: None,
+    ///         last_name: "Doe".to_string(),
+    ///     };
+    ///     let value = to_value(seed_doc)?;
+    ///     db.create(value).await?;
+    ///
+    ///     // now that the document is created, we can get it, update it, and save it...
+    ///     let mut user_details: UserDetails = db.get("123").await?;
+    ///     user_details.first_name = Some("John".to_string());
+    ///
+    ///     db.save(user_details).await?;
+    ///     Ok(())
+    /// }
+    ///```
+    pub async fn save<T: TypedCouchDocument>(&self, doc: T) -> CouchResult<T> {
+        self.save_with_options(doc, &WriteOptions::default()).await
+    }
+
+    /// Saves a document, like `save`, with the given options. For example, a write quorum of `w(3)`
+    /// only acknowledges the write once three replicas of a cluster stored the document.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::document::{GetOptions, WriteOptions};
+    /// use serde_json::{json, Value};
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let doc = db.save_with_options(json!({"_id": "quorum", "n": 1}), &WriteOptions::default().w(2)).await?;
+    ///     let read: Value = db.get_with_options("quorum", &GetOptions::default().r(2)).await?;
+    ///     assert_eq!(read["_rev"], doc["_rev"]);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn save_with_options<T: TypedCouchDocument>(&self, mut doc: T, options: &WriteOptions) -> CouchResult<T> {
+        self.save_in_place(&mut doc, options).await?;
+        Ok(doc)
+    }
+
+    /// Saves a document, like `save_with_options`, and returns how CouchDB acknowledged the write
+    /// along with the saved document. A write that is only accepted, with status `202 Accepted`,
+    /// was stored by fewer replicas than the write quorum, or not stored yet in batch mode.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::document::WriteOptions;
+    /// use serde_json::json;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let options = WriteOptions::default().w(2);
+    ///     let (doc, written) = db.save_with_result(json!({"_id": "payment", "amount": 10}), &options).await?;
+    ///     if !written.is_durable() {
+    ///         println!("{} is not on two replicas yet", doc["_id"]);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn save_with_result<T: TypedCouchDocument>(
+        &self,
+        mut doc: T,
+        options: &WriteOptions,
+    ) -> CouchResult<(T, WriteResult)> {
+        let written = self.save_in_place(&mut doc, options).await?;
+        Ok((doc, written))
+    }
+
+    /// Saves the document, updating it in place with the changes of the validation hooks, and its new
+    /// `_id` and `_rev`. Unlike `save`, the document is kept when saving fails, so it can be retried.
+    async fn save_in_place<T: TypedCouchDocument>(
+        &self,
+        doc: &mut T,
+        options: &WriteOptions,
+    ) -> CouchResult<WriteResult> {
+        let id = doc.get_id().to_string();
+        let validated = self.validate(doc)?;
+        let body = match &validated {
+            Some(value) => to_string(value)?,
+            None => to_string(doc)?,
+        };
+        let response = self
+            ._client
+            .put(self.create_document_path(&id), body)
+            .query(&options.to_query())
+            .send_with_context()
+            .await?;
+        let status = response.status();
+        let context = ErrorContext::of(&response);
+        let data: DocumentCreatedResponse = response.read_json().await?;
+
+        match data.ok {
+            Some(true) => {
+                if let Some(validated) = validated.and_then(|value| serde_json::from_value(value).ok()) {
+                    *doc = validated;
+                }
+                let id = data.id.unwrap_or_default();
+                doc.set_id(&id);
+                doc.set_rev(data.rev.as_deref().unwrap_or_default());
+                Ok(WriteResult {
+                    id,
+                    rev: data.rev,
+                    status,
+                })
+            }
+            _ => {
+                let error = data.error.unwrap_or_else(|| s!("unspecified error"));
+                let reason = data.reason.unwrap_or_else(|| error.clone());
+                Err(CouchError::from_document_write(data.id, status, &error, reason).with_context(context))
+            }
+        }
+    }
+
+    /// Creates a document from a raw JSON document Value.
+    /// Usage:
+    ///
+    /// ```
+    /// use couch_rs::types::find::FindQuery;
+    /// use couch_rs::error::CouchResult;
+    /// use serde_json::json;
+    /// use couch_rs::document::TypedCouchDocument;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    /// let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///     let doc = json!({
+    ///                     "first_name": "John",
+    ///                     "last_name": "Doe"
+    ///                 });
+    ///
+    ///     let created_doc = db.create(doc).await?;
+    ///
+    ///     // verify that this is the 1st revision of the document
+    ///     assert!(created_doc.get_rev().starts_with('1'));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn create<T: TypedCouchDocument>(&self, doc: T) -> CouchResult<T> {
+        self.create_with_options(doc, &WriteOptions::default()).await
+    }
+
+    /// Creates a document, like `create`, with the given options. For example, a write quorum of
+    /// `w(3)` only acknowledges the write once three replicas of a cluster stored the document.
+    pub async fn create_with_options<T: TypedCouchDocument>(&self, doc: T, options: &WriteOptions) -> CouchResult<T> {
+        let (doc, _) = self.create_with_result(doc, options).await?;
+        Ok(doc)
+    }
+
+    /// Creates a document, like `create_with_options`, and returns how CouchDB acknowledged the
+    /// write along with the created document; see `save_with_result`. In batch mode, the created
+    /// document has no revision yet.
+    pub async fn create_with_result<T: TypedCouchDocument>(
+        &self,
+        mut doc: T,
+        options: &WriteOptions,
+    ) -> CouchResult<(T, WriteResult)> {
+        let validated = self.validate(&doc)?;
+        let body = match &validated {
+            Some(value) => to_string(value)?,
+            None => to_string(&doc)?,
+        };
+        let response = self
+            ._client
+            .post(self.name.clone(), body)
+            .query(&options.to_query())
+            .send_with_context()
+            .await?;
+
+        let status = response.status();
+        let data: DocumentCreatedResponse = response.read_json().await?;
+
+        match data.ok {
+            Some(true) => {
+                let data_id = data.id.ok_or_else(|| CouchError::new(s!("invalid id"), status))?;
+                // only writes in batch mode are acknowledged without a revision
+                if data.rev.is_none() && !options.batch {
+                    return Err(CouchError::new(s!("invalid rev"), status));
+                }
+
+                if let Some(validated) = validated.and_then(|value| serde_json::from_value(value).ok()) {
+                    doc = validated;
+                }
+                doc.set_id(&data_id);
+                doc.set_rev(data.rev.as_deref().unwrap_or_default());
+                let written = WriteResult {
+                    id: data_id,
+                    rev: data.rev,
+                    status,
+                };
+                Ok((doc, written))
+            }
+            _ => {
+                let error = data.error.unwrap_or_else(|| s!("unspecified error"));
+                let reason = data.reason.unwrap_or_else(|| error.clone());
+                Err(CouchError::from_document_write(data.id, status, &error, reason))
+            }
+        }
+    }
+
+    /// The upsert function combines a `get` with a `save` function. If the document with the
+    /// provided `_id` can be found it will be merged with the provided Document's value, otherwise
+    /// the document will be created.
+    /// This operation always performs a `get`, so if you have a documents `_rev` using a `save` is
+    /// quicker. Same is true when you know a document does *not* exist.
+    /// Conflicts are retried with the default `RetryPolicy`; see `upsert_with_retry`.
+    ///
+    /// Usage:
+    ///
+    /// ```
+    /// use couch_rs::types::find::FindQuery;
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::document::TypedCouchDocument;
+    /// use serde_json::json;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///     let doc = json!({
+    ///                     "_id": "doe",
+    ///                     "first_name": "John",
+    ///                     "last_name": "Doe"
+    ///                 });
+    ///
+    ///     // initial call creates the document
+    ///     db.upsert(doc.clone()).await?;
+    ///
+    ///     // subsequent call updates the existing document
+    ///     let updated_doc = db.upsert(doc).await?;
+    ///
+    ///     // verify that this is the 2nd revision of the document
+    ///     assert!(updated_doc.get_rev().starts_with('2'));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn upsert<T: TypedCouchDocument>(&self, doc: T) -> CouchResult<T> {
+        self.upsert_with_retry(doc, RetryPolicy::default()).await
+    }
+
+    /// Upserts a document, like `upsert`, with a custom retry policy for conflicts. A conflict occurs
+    /// when another writer updates the document between fetching its revision and saving it; the
+    /// revision is then fetched again, and the save is retried after the backoff.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::document::RetryPolicy;
+    /// use serde_json::json;
+    /// use std::time::Duration;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///     let policy = RetryPolicy::default().max_attempts(10).backoff(Duration::from_millis(20));
+    ///
+    ///     db.upsert_with_retry(json!({"_id": "counter", "count": 1}), policy).await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn upsert_with_retry<T: TypedCouchDocument>(&self, mut doc: T, policy: RetryPolicy) -> CouchResult<T> {
+        let id = doc.get_id().into_owned();
+        let mut attempt = 1;
+
+        loop {
+            // only the revision is needed; the stored document may not even deserialize into T
+            match self.get_as::<DocumentIdRev>(&id).await {
+                Ok(current_doc) => doc.set_rev(&current_doc.rev),
+                // document does not yet exist
+                Err(err) if err.is_not_found() => {}
+                Err(err) => return Err(err),
+            }
+
+            match self.save_in_place(&mut doc, &WriteOptions::default()).await {
+                Ok(_) => return Ok(doc),
+                Err(err) if err.is_conflict() && attempt < policy.max_attempts => {
+                    sleep(policy.backoff_for(attempt)).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Merges the document into the stored document, following the `MergePolicy`, or creates the
+    /// document when it does not exist yet. Conflicts are retried with the default `RetryPolicy`.
+    /// Returns the merged document.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::merge::{ArrayMerge, MergePolicy};
+    /// use serde_json::json;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     db.upsert(json!({"_id": "john", "address": {"city": "Paris", "zip": "75001"}})).await?;
+    ///
+    ///     let policy = MergePolicy::deep().arrays(ArrayMerge::Union).null_removes(true);
+    ///     let update = json!({"_id": "john", "address": {"city": "Lyon", "zip": null}});
+    ///     let merged = db.upsert_merge(update, &policy).await?;
+    ///     assert_eq!(merged["address"], json!({"city": "Lyon"}));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn upsert_merge<T: TypedCouchDocument>(&self, doc: T, policy: &MergePolicy) -> CouchResult<T> {
+        let update = serde_json::to_value(&doc)?;
+        let merged = self
+            .update_with_retry(&doc.get_id(), Some(json!({})), |current| {
+                policy.merge(current, update.clone());
+                Ok(())
+            })
+            .await?;
+
+        Ok(serde_json::from_value(merged)?)
+    }
+
+    /// Applies JSON Patch (RFC 6902) operations to a document and saves it. The document is fetched
+    /// again, and the patch reapplied, when it was modified in the meantime. A failing `test`
+    /// operation is a `PRECONDITION_FAILED` error. Returns the patched document.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::patch::PatchOp;
+    /// use serde_json::json;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     db.upsert(json!({"_id": "john", "name": "John", "tags": ["a"]})).await?;
+    ///
+    ///     let patched = db.patch("john", &[
+    ///         PatchOp::test("/name", json!("John")),
+    ///         PatchOp::add("/tags/-", json!("b")),
+    ///     ]).await?;
+    ///     assert_eq!(patched["tags"], json!(["a", "b"]));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn patch(&self, id: &str, ops: &[PatchOp]) -> CouchResult<Value> {
+        self.update_with_retry(id, None, |doc| apply_patch(doc, ops)).await
+    }
+
+    /// Applies a JSON Merge Patch (RFC 7396) to a document and saves it: objects are merged
+    /// recursively, and `null` removes a field. Like `patch`, conflicts are retried.
+    /// Returns the patched document.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use serde_json::json;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     db.upsert(json!({"_id": "john", "name": "John", "nickname": "Johnny"})).await?;
+    ///
+    ///     let patched = db.merge_patch("john", json!({"name": "Jon", "nickname": null})).await?;
+    ///     assert!(patched.get("nickname").is_none());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn merge_patch(&self, id: &str, patch: Value) -> CouchResult<Value> {
+        let policy = MergePolicy::merge_patch();
+        self.update_with_retry(id, None, |doc| {
+            policy.merge(doc, patch.clone());
+            Ok(())
+        })
+        .await
+    }
+
+    /// Applies `change` to the stored document and saves it, retrying conflicts with the default
+    /// `RetryPolicy`. A missing document starts out as `missing`, or is a `NOT_FOUND` error.
+    async fn update_with_retry<F>(&self, id: &str, missing: Option<Value>, change: F) -> CouchResult<Value>
+    where
+        F: Fn(&mut Value) -> CouchResult<()>,
+    {
+        let retry = RetryPolicy::default();
+        let mut attempt = 1;
+
+        loop {
+            let mut doc = match self.get::<Value>(id).await {
+                Ok(current) => current,
+                Err(err) if err.is_not_found() && missing.is_some() => missing.clone().unwrap_or_default(),
+                Err(err) => return Err(err),
+            };
+
+            // the stored id and revision win over whatever the change holds
+            let rev = doc.get_rev().into_owned();
+            change(&mut doc)?;
+            doc.set_id(id);
+            if !rev.is_empty() {
+                doc.set_rev(&rev);
+            }
+
+            match self.save_in_place(&mut doc, &WriteOptions::default()).await {
+                Ok(_) => return Ok(doc),
+                Err(err) if err.is_conflict() && attempt < retry.max_attempts => {
+                    sleep(retry.backoff_for(attempt)).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Upserts documents in bulk. The current revisions of the provided documents are fetched in a
+    /// single `_all_docs` request, after which all documents are written through `_bulk_docs`.
+    /// Documents that conflict, because they were modified in the meantime, are retried a few times.
+    /// The results are returned in the same order as the provided documents.
+    ///
+    /// Usage:
+    ///
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use serde_json::json;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///     let docs = vec![
+    ///         json!({"_id": "john", "first_name": "John"}),
+    ///         json!({"_id": "jane", "first_name": "Jane"}),
+    ///     ];
+    ///
+    ///     let results = db.bulk_upsert(docs).await?;
+    ///     assert!(results.iter().all(|r| r.is_ok()));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn bulk_upsert<T: TypedCouchDocument>(
+        &self,
+        mut docs: Vec<T>,
+    ) -> CouchResult<Vec<DocumentCreatedResult>> {
+        let mut results: Vec<Option<DocumentCreatedResult>> = vec![None; docs.len()];
+        let mut pending: Vec<usize> = (0..docs.len()).collect();
+
+        for attempt in 0..=CONFLICT_RETRIES {
+            let ids = pending
+                .iter()
+                .map(|&i| docs[i].get_id().into_owned())
+                .filter(|id| !id.is_empty())
+                .collect();
+            let revs = self.get_revs(ids).await?;
+
+            for &i in &pending {
+                if let Some(rev) = revs.get(docs[i].get_id().as_ref()) {
+                    docs[i].set_rev(rev);
+                }
+            }
+
+            let batch: Vec<&T> = pending.iter().map(|&i| &docs[i]).collect();
+            let batch_results: Vec<DocumentCreatedResult> = self.post_bulk_docs(&batch).await?;
+
+            let mut conflicts = vec![];
+            for (i, result) in pending.into_iter().zip(batch_results) {
+                let conflict = matches!(&result, Err(err) if err.is_conflict());
+                if conflict && attempt < CONFLICT_RETRIES && !docs[i].get_id().is_empty() {
+                    conflicts.push(i);
+                }
+                results[i] = Some(result);
+            }
+
+            if conflicts.is_empty() {
+                break;
+            }
+            pending = conflicts;
+        }
+
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    /// Fetches the current revisions of the given documents, in a single `_all_docs` request.
+    /// Documents that do not exist are not included.
+    async fn get_revs(&self, ids: Vec<DocumentId>) -> CouchResult<HashMap<DocumentId, String>> {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let response = self
+            ._client
+            .post(self.create_raw_path("_all_docs"), js!(&QueryParams::from_keys(ids)))
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?;
+
+        let data: RawViewCollection<Value, Option<Value>> = response_json(response).await?;
+        Ok(data
+            .rows
+            .into_iter()
+            .filter_map(|row| {
+                let rev = row.value?.get("rev")?.as_str()?.to_string();
+                Some((row.id?, rev))
+            })
+            .collect())
+    }
+
+    /// Creates a design with one of more view documents.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::types::view::{CouchFunc, CouchViews};
+    /// use couch_rs::error::CouchResult;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let couch_func = CouchFunc {
+    ///             map: "function (doc) { if (doc.funny == true) { emit(doc._id, doc.funny); } }".to_string(),
+    ///             reduce: None,
+    ///     };
+    ///
+    ///     let couch_views = CouchViews::new("clip_view", couch_func);
+    ///     db.create_view("clip_design", couch_views).await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn create_view<T: Into<serde_json::Value>>(
+        &self,
+        design_name: &str,
+        views: T,
+    ) -> CouchResult<DesignCreated> {
+        let doc: Value = views.into();
+        let response = self
+            ._client
+            .put(self.create_design_path(design_name), to_string(&doc)?)
+            .send_with_context()
+            .await?;
+
+        let response_status = response.status();
+        let result: DesignCreated = response.read_json().await?;
+
+        if response_status.is_success() {
+            Ok(result)
+        } else {
+            let error = result.error.unwrap_or_else(|| s!("unspecified error"));
+            let reason = result.reason.unwrap_or_else(|| error.clone());
+            Err(CouchError::from_couch(result.id, response_status, &error, reason))
+        }
+    }
+
+    /// Gets a design document, by its name without the `_design/` prefix
+    pub async fn get_design(&self, design_name: &str) -> CouchResult<DesignDocument> {
+        self.get(&DesignDocument::new(design_name).id).await
+    }
+
+    /// Creates or replaces a design document. The current revision is fetched when the design
+    /// document does not hold one. The returned design document holds the new `_rev`.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::design::DesignDocument;
+    /// use couch_rs::types::view::CouchFunc;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let design = DesignDocument::new("users")
+    ///         .view("by_name", CouchFunc::new("function (doc) { emit(doc.name, null); }", None))
+    ///         .filter("active", "function (doc, req) { return doc.active; }");
+    ///     db.put_design(design).await?;
+    ///
+    ///     let design = db.get_design("users").await?;
+    ///     assert!(design.filters.contains_key("active"));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn put_design(&self, design: DesignDocument) -> CouchResult<DesignDocument> {
+        self.upsert(design).await
+    }
+
+    /// Deploys a design document, only writing it when its content differs from the stored design
+    /// document. Rewriting an unchanged design document would needlessly rebuild its views, so
+    /// this is safe to call on every startup.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::design::{DesignDeployment, DesignDocument};
+    /// use couch_rs::types::view::MapFunction;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let design = DesignDocument::new("deployed").view("ids", MapFunction::new().emit("doc._id", "null").into());
+    ///     db.deploy_design(design.clone()).await?;
+    ///     assert_eq!(db.deploy_design(design).await?, DesignDeployment::Unchanged);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn deploy_design(&self, mut design: DesignDocument) -> CouchResult<DesignDeployment> {
+        let deployment = match self.get_design(design.name()).await {
+            Ok(current) if current.same_content(&design) => return Ok(DesignDeployment::Unchanged),
+            Ok(current) => {
+                design.rev = current.rev;
+                DesignDeployment::Updated
+            }
+            Err(err) if err.is_not_found() => {
+                design.rev = String::new();
+                DesignDeployment::Created
+            }
+            Err(err) => return Err(err),
+        };
+
+        self.save_in_place(&mut design, &WriteOptions::default()).await?;
+        Ok(deployment)
+    }
+
+    /// Deletes a design document, by its name without the `_design/` prefix
+    pub async fn delete_design(&self, design_name: &str) -> CouchResult<DocumentDeletedResult> {
+        let design = self.get_design(design_name).await?;
+        self.remove_checked(design).await
+    }
+
+    /// Runs a query on a search index of a design document. Search is available on Cloudant, and
+    /// on CouchDB with the Clouseau search service.
+    /// See [search](https://docs.couchdb.org/en/stable/ddocs/search.html) for more details.
+    ///
+    /// Usage:
+    /// ```no_run
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::design::DesignDocument;
+    /// use couch_rs::types::search::{SearchIndex, SearchQuery};
+    /// use serde_json::Value;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let index = SearchIndex::new("function (doc) { index('name', doc.name, {store: true}); }");
+    ///     db.deploy_design(DesignDocument::new("users").search_index("by_name", index)).await?;
+    ///
+    ///     let query = SearchQuery::new("name:jo*").include_docs(true).limit(10);
+    ///     let found = db.search::<Value>("users", "by_name", &query).await?;
+    ///     for row in found.rows {
+    ///         println!("{}: {:?}", row.id, row.doc);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn search<T: DeserializeOwned>(
+        &self,
+        design_name: &str,
+        index_name: &str,
+        query: &SearchQuery,
+    ) -> CouchResult<SearchResult<T>> {
+        self._client
+            .post(self.create_search_path(design_name, index_name), js!(query))
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?
+            .read_json()
+            .await
+    }
+
+    /// Runs a query on a Nouveau index of a design document. Nouveau is available on CouchDB 3.4
+    /// and later, when it is enabled; on servers without it, an error telling so is returned.
+    /// See [nouveau](https://docs.couchdb.org/en/stable/ddocs/nouveau.html) for more details.
+    ///
+    /// Usage:
+    /// ```no_run
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::design::DesignDocument;
+    /// use couch_rs::types::nouveau::{NouveauIndex, NouveauQuery};
+    /// use serde_json::Value;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let index = NouveauIndex::new("function (doc) { index('text', 'name', doc.name, {store: true}); }");
+    ///     db.deploy_design(DesignDocument::new("users").nouveau_index("by_name", index)).await?;
+    ///
+    ///     let query = NouveauQuery::new("name:jo*").include_docs(true).limit(10);
+    ///     let found = db.nouveau_search::<Value>("users", "by_name", &query).await?;
+    ///     for hit in found.hits {
+    ///         println!("{}: {:?}", hit.id, hit.doc);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn nouveau_search<T: DeserializeOwned>(
+        &self,
+        design_name: &str,
+        index_name: &str,
+        query: &NouveauQuery,
+    ) -> CouchResult<NouveauResult<T>> {
+        let result = self
             ._client
-            .put(self.create_design_path(design_name), to_string(&doc)?)
-            .send()
-            .await?;
-
-        let response_status = response.status();
-        let result: DesignCreated = response.json().await?;
-
-        if response_status.is_success() {
-            Ok(result)
-        } else {
-            let error_msg = result.error.unwrap_or_else(|| s!("unspecified error"));
-            Err(CouchError {
-                id: result.id,
-                status: response_status,
-                message: error_msg,
-            })
+            .post(self.create_nouveau_path(design_name, index_name), js!(query))
+            .send_with_context()
+            .await?
+            .check_status()
+            .await;
+
+        match result {
+            Ok(response) => response.read_json().await,
+            // a server without Nouveau does not know the endpoint, or can not reach the service
+            Err(err) if err.is_not_found() || err.is_bad_request() || err.status().is_server_error() => {
+                match self._client.supports_nouveau().await {
+                    Ok(false) => Err(CouchError::new(
+                        s!("Nouveau full-text search is not enabled on this server"),
+                        reqwest::StatusCode::NOT_IMPLEMENTED,
+                    )),
+                    _ => Err(err),
+                }
+            }
+            Err(err) => Err(err),
         }
     }
 
@@ -818,7 +3206,8 @@ impl Database {
         self.query(design_name, view_name, options).await
     }
 
-    /// Executes a query against a view.
+    /// Executes a query against a view. The keys, values and included documents of the rows are
+    /// deserialized into `K`, `V` and `T`, which can be any `DeserializeOwned` type.
     ///
     /// Usage:
     /// ```
@@ -861,24 +3250,177 @@ impl Database {
     ///     Ok(())
     /// }
     /// ```    
-    pub async fn query<K: DeserializeOwned, V: DeserializeOwned, T: TypedCouchDocument>(
+    pub async fn query<K: DeserializeOwned, V: DeserializeOwned, T: DeserializeOwned>(
         &self,
         design_name: &str,
         view_name: &str,
+        options: Option<QueryParams>,
+    ) -> CouchResult<ViewCollection<K, V, T>> {
+        self.query_at(self.create_query_view_path(design_name, view_name), options)
+            .await
+    }
+
+    /// Queries a view, or the view of a partition, at the given path
+    pub(crate) async fn query_at<K: DeserializeOwned, V: DeserializeOwned, T: DeserializeOwned>(
+        &self,
+        path: String,
         mut options: Option<QueryParams>,
     ) -> CouchResult<ViewCollection<K, V, T>> {
         if options.is_none() {
             options = Some(QueryParams::default());
         }
+        if let Some(options) = &options {
+            options.validate()?;
+        }
 
-        self._client
+        let response = self
+            ._client
+            .post(path, js!(&options))
+            .send_with_context()
+            .await?
+            .check_status()
+            .await?;
+
+        response_json(response).await
+    }
+
+    /// Builds the index of a view, and waits until it is up to date with the database, so a
+    /// deployment can warm its indexes before routing traffic to them. The build is started through
+    /// an `update=lazy` query, after which the design document info and the `indexer` tasks in
+    /// `_active_tasks` are polled until the index is idle, and a last query confirms it is up to
+    /// date. Returns a `REQUEST_TIMEOUT` error when the index is not built within `timeout`.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::view::{CouchFunc, CouchViews};
+    /// use std::time::Duration;
+    ///
+    /// const TEST_DB: &str = "view_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let couch_func = CouchFunc {
+    ///         map: "function (doc) { emit(doc._id, null); }".to_string(),
+    ///         reduce: None,
+    ///     };
+    ///     db.create_view("warm_design", CouchViews::new("by_id", couch_func)).await?;
+    ///     db.wait_for_view("warm_design", "by_id", Duration::from_secs(60)).await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn wait_for_view(&self, design_name: &str, view_name: &str, timeout: Duration) -> CouchResult<()> {
+        let started = Instant::now();
+
+        // update=lazy responds right away, and builds the index in the background
+        let trigger = QueryParams::default().limit(0).update(UpdateView::Lazy);
+        self.query::<Value, Value, Value>(design_name, view_name, Some(trigger))
+            .await?;
+
+        let design_document = format!("_design/{}", design_name);
+        loop {
+            let design: DesignInfo = self
+                ._client
+                .get(format!("{}/_info", self.create_design_path(design_name)), None)
+                .send_with_context()
+                .await?
+                .check_status()
+                .await?
+                .read_json()
+                .await?;
+            let indexing = self._client.active_tasks().await?.into_iter().any(|task| {
+                task.task_type == "indexer"
+                    && task.runs_on(&self.name)
+                    && task.design_document.as_deref() == Some(design_document.as_str())
+            });
+
+            // Update sequences are opaque, so whether the idle index caught up with the database is
+            // confirmed by a query that updates it first, which responds right away when it is up to date
+            if !design.view_index.updater_running && !indexing {
+                let remaining = timeout.saturating_sub(started.elapsed());
+                let confirm = self
+                    .clone()
+                    .with_request_options(RequestOptions::default().timeout(remaining));
+                match confirm
+                    .query::<Value, Value, Value>(design_name, view_name, Some(QueryParams::default().limit(0)))
+                    .await
+                {
+                    Ok(_) => return Ok(()),
+                    Err(err) if started.elapsed() < timeout => return Err(err),
+                    Err(_) => {}
+                }
+            }
+
+            if started.elapsed() >= timeout {
+                return Err(CouchError::new(
+                    format!("view {}/{} was not built within {:?}", design_name, view_name, timeout),
+                    reqwest::StatusCode::REQUEST_TIMEOUT,
+                ));
+            }
+            sleep(INDEX_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Like `wait_for_view`, for the Mango index with the given name
+    pub async fn wait_for_index(&self, name: &str, timeout: Duration) -> CouchResult<()> {
+        let indexes = self.read_indexes().await?;
+        let ddoc = indexes
+            .indexes
+            .into_iter()
+            .find(|index| index.name == name)
+            .and_then(|index| index.ddoc)
+            .ok_or_else(|| CouchError::new(format!("index {} not found", name), reqwest::StatusCode::NOT_FOUND))?;
+
+        let design_name = ddoc.trim_start_matches("_design/");
+        self.wait_for_view(design_name, name, timeout).await
+    }
+
+    /// Executes a query against a view, returning a stream of its rows. The rows are parsed while
+    /// the response is being received, so very large views can be processed without holding all
+    /// rows in memory. Errors of the request itself are returned before the stream is.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::view::ViewItem;
+    /// use futures_util::stream::TryStreamExt;
+    /// use serde_json::Value;
+    ///
+    /// const TEST_DB: &str = "view_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let mut rows = Box::pin(db.query_stream::<String, Value, Value>("test_design", "funny_guys", None).await?);
+    ///     while let Some(row) = rows.try_next().await? {
+    ///         println!("{} is funny", row.key);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn query_stream<K: DeserializeOwned, V: DeserializeOwned, T: DeserializeOwned>(
+        &self,
+        design_name: &str,
+        view_name: &str,
+        options: Option<QueryParams>,
+    ) -> CouchResult<impl Stream<Item = CouchResult<ViewItem<K, V, T>>>> {
+        let options = options.unwrap_or_default();
+        options.validate()?;
+
+        let response = self
+            ._client
             .post(self.create_query_view_path(design_name, view_name), js!(&options))
-            .send()
+            .send_with_context()
             .await?
-            .error_for_status()?
-            .json()
-            .await
-            .map_err(CouchError::from)
+            .check_status()
+            .await?;
+
+        Ok(response_rows(response))
     }
 
     /// Executes an update function.
@@ -896,14 +3438,157 @@ impl Database {
 
         self._client
             .put(self.create_execute_update_path(design_id, name, document_id), body)
-            .send()
+            .send_with_context()
+            .await?
+            .check_status()
             .await?
-            .error_for_status()?
             .text()
             .await
             .map_err(CouchError::from)
     }
 
+    /// Executes an update function, returning the ID and new revision of the updated document
+    /// along with the body. With a `document_id`, the handler receives that document through a
+    /// `PUT` request; without one, the handler receives `null` through a `POST` request, and
+    /// typically creates a new document.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::design::DesignDocument;
+    /// use serde_json::json;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let design = DesignDocument::new("handlers").update(
+    ///         "create",
+    ///         "function (doc, req) { return [{_id: req.uuid, body: JSON.parse(req.body)}, 'created']; }",
+    ///     );
+    ///     db.put_design(design).await?;
+    ///
+    ///     let response = db.execute_update_detailed("handlers", "create", None, Some(json!({"a": 1}))).await?;
+    ///     assert!(response.new_rev.is_some());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn execute_update_detailed(
+        &self,
+        design_id: &str,
+        name: &str,
+        document_id: Option<&str>,
+        body: Option<Value>,
+    ) -> CouchResult<UpdateResponse> {
+        let body = match body {
+            Some(v) => to_string(&v)?,
+            None => String::default(),
+        };
+
+        let request = match document_id {
+            Some(document_id) => self
+                ._client
+                .put(self.create_execute_update_path(design_id, name, document_id), body),
+            None => self
+                ._client
+                .post(self.create_execute_update_without_id_path(design_id, name), body),
+        };
+
+        let response = request.send_with_context().await?.check_status().await?;
+        UpdateResponse::read(response).await
+    }
+
+    /// Executes a show function, for the given document or, when `document_id` is `None`, for no
+    /// document. The `params` are passed as the query string. The rendered response is returned
+    /// whatever its status, as show functions set the status themselves.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::design::DesignDocument;
+    /// use serde_json::json;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let design = DesignDocument::new("render")
+    ///         .show("hello", "function (doc, req) { return 'Hello ' + doc.name + req.query.suffix; }");
+    ///     db.put_design(design).await?;
+    ///     db.upsert(json!({"_id": "john", "name": "John"})).await?;
+    ///
+    ///     let params = vec![("suffix".to_string(), "!".to_string())].into_iter().collect();
+    ///     let response = db.execute_show("render", "hello", Some("john"), Some(params)).await?;
+    ///     assert_eq!(response.body, "Hello John!");
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn execute_show(
+        &self,
+        design_id: &str,
+        name: &str,
+        document_id: Option<&str>,
+        params: Option<HashMap<String, String>>,
+    ) -> CouchResult<RenderedResponse> {
+        let response = self
+            ._client
+            .get(self.create_execute_show_path(design_id, name, document_id), params)
+            .send_with_context()
+            .await?;
+
+        RenderedResponse::read(response).await
+    }
+
+    /// Executes a list function over the rows of a view in the same design document. The `params`
+    /// are passed as the query string, and hold the view query parameters, such as `limit`, or a
+    /// JSON encoded `key`. Like `execute_show`, the rendered response is returned whatever its status.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use couch_rs::types::design::DesignDocument;
+    /// use couch_rs::types::view::MapFunction;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let design = DesignDocument::new("render")
+    ///         .view("ids", MapFunction::new().emit("doc._id", "null").into())
+    ///         .list("csv", "function (head, req) { var row; while (row = getRow()) { send(row.id + '\\n'); } }");
+    ///     db.put_design(design).await?;
+    ///
+    ///     let params = vec![("limit".to_string(), "10".to_string())].into_iter().collect();
+    ///     let response = db.execute_list("render", "csv", "ids", Some(params)).await?;
+    ///     println!("{}", response.body);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn execute_list(
+        &self,
+        design_id: &str,
+        name: &str,
+        view_name: &str,
+        params: Option<HashMap<String, String>>,
+    ) -> CouchResult<RenderedResponse> {
+        let response = self
+            ._client
+            .get(self.create_execute_list_path(design_id, name, view_name), params)
+            .send_with_context()
+            .await?;
+
+        RenderedResponse::read(response).await
+    }
+
     /// Removes a document from the database. Returns success in a `bool`
     /// Usage:
     /// ```
@@ -941,27 +3626,133 @@ impl Database {
         is_ok(request).await
     }
 
+    /// Removes a document from the database. Unlike `remove`, the outcome is returned as a `Result`:
+    /// the revision of the tombstone on success, or an error holding the HTTP status, which tells a
+    /// missing document (404) apart from a conflict (409) or missing permissions (401/403).
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use serde_json::Value;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     if let Ok(doc) = db.get::<Value>("123").await {
+    ///         match db.remove_checked(doc).await {
+    ///             Ok(deleted) => println!("deleted as {}", deleted.rev),
+    ///             Err(err) if err.is_conflict() => println!("modified in the meantime"),
+    ///             Err(err) => return Err(err),
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn remove_checked<T: TypedCouchDocument>(&self, doc: T) -> CouchResult<DocumentDeletedResult> {
+        self.remove_with_options(doc, &WriteOptions::default()).await
+    }
+
+    /// Deletes a document, like `remove_checked`, with the given options, such as a write quorum
+    pub async fn remove_with_options<T: TypedCouchDocument>(
+        &self,
+        doc: T,
+        options: &WriteOptions,
+    ) -> CouchResult<DocumentDeletedResult> {
+        let id = doc.get_id().into_owned();
+        let mut params = options.to_query();
+        params.insert(s!("rev"), doc.get_rev().into_owned());
+
+        let response = self
+            ._client
+            .delete(self.create_document_path(&id), Some(params))
+            .send_with_context()
+            .await?;
+        let status = response.status();
+        let data: DocumentCreatedResponse = response.read_json().await?;
+
+        match data.ok {
+            Some(true) if status.is_success() => Ok(DocumentDeletedResult {
+                id: data.id.unwrap_or(id),
+                rev: data.rev.unwrap_or_default(),
+            }),
+            _ => {
+                let error = data.error.unwrap_or_else(|| s!("unspecified error"));
+                let reason = data.reason.unwrap_or_else(|| error.clone());
+                Err(CouchError::from_document_write(Some(id), status, &error, reason))
+            }
+        }
+    }
+
+    /// Removes documents in bulk, in a single `_bulk_docs` request. The documents need both their
+    /// `_id` and `_rev`. Deletions that failed, for example because of a conflict, are available
+    /// through `failures()` on the result.
+    ///
+    /// Usage:
+    /// ```
+    /// use couch_rs::error::CouchResult;
+    /// use serde_json::Value;
+    ///
+    /// const TEST_DB: &str = "test_db";
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> CouchResult<()> {
+    ///     let client = couch_rs::Client::new_local_test()?;
+    ///     let db = client.db(TEST_DB).await?;
+    ///
+    ///     let docs = db.get_bulk::<Value>(vec!["john".to_string(), "jane".to_string()]).await?;
+    ///     let result = db.bulk_remove(docs.rows).await?;
+    ///     for err in result.failures() {
+    ///         println!("could not delete {:?}: {}", err.id(), err);
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    ///```
+    pub async fn bulk_remove<T: TypedCouchDocument>(&self, docs: Vec<T>) -> CouchResult<BulkDeleteResult> {
+        if docs.is_empty() {
+            return Ok(BulkDeleteResult::new(vec![]));
+        }
+
+        let tombstones: Vec<Value> = docs
+            .iter()
+            .map(|doc| tombstone(&doc.get_id(), &doc.get_rev()))
+            .collect();
+
+        Ok(BulkDeleteResult::new(self.bulk_docs(tombstones).await?))
+    }
+
     /// Inserts an index in a naive way, if it already exists, will throw an
     /// `Err`
     pub async fn insert_index(&self, name: &str, spec: IndexFields) -> CouchResult<DesignCreated> {
+        self.post_index(name, spec, None).await
+    }
+
+    async fn post_index(&self, name: &str, spec: IndexFields, ddoc: Option<&str>) -> CouchResult<DesignCreated> {
+        let mut body = json!({
+            "name": name,
+            "index": spec
+        });
+
+        if let Some(ddoc) = ddoc {
+            body["ddoc"] = json!(ddoc);
+        }
+
         let response = self
             ._client
-            .post(
-                self.create_raw_path("_index"),
-                js!(json!({
-                    "name": name,
-                    "index": spec
-                })),
-            )
-            .send()
+            .post(self.create_raw_path("_index"), js!(body))
+            .send_with_context()
             .await?;
 
         let status = response.status();
-        let data: DesignCreated = response.json().await?;
+        let data: DesignCreated = response.read_json().await?;
 
-        if data.error.is_some() {
-            let err = data.error.unwrap_or_else(|| s!("unspecified error"));
-            Err(CouchError::new(err, status))
+        if let Some(error) = data.error {
+            let reason = data.reason.unwrap_or_else(|| error.clone());
+            Err(CouchError::from_couch(data.id, status, &error, reason))
         } else {
             Ok(data)
         }
@@ -971,41 +3762,83 @@ impl Database {
     pub async fn read_indexes(&self) -> CouchResult<DatabaseIndexList> {
         self._client
             .get(self.create_raw_path("_index"), None)
-            .send()
+            .send_with_context()
             .await?
-            .json()
+            .read_json()
             .await
-            .map_err(CouchError::from)
     }
 
     /// Method to ensure an index is created on the database with the following
     /// spec. Returns `true` when we created a new one, or `false` when the
-    /// index was already existing.
+    /// index was already existing with an equivalent definition.
+    /// When an index with the same name exists, but with different fields or a different partial
+    /// filter, a `CONFLICT` error is returned. Use `upsert_index` to replace it instead.
     pub async fn ensure_index(&self, name: &str, spec: IndexFields) -> CouchResult<bool> {
+        match self.find_index_by_name(name, &spec).await? {
+            IndexState::Missing => self.create_index(name, spec, None).await,
+            IndexState::Current => Ok(false),
+            IndexState::Stale(_) => Err(CouchError::new(
+                format!("index {} already exists with a different definition", name),
+                reqwest::StatusCode::CONFLICT,
+            )),
+        }
+    }
+
+    /// Like `ensure_index`, but an existing index with the same name and a different definition
+    /// is replaced. The new definition is written to the design document that holds the stale
+    /// index, so the replacement happens in a single write.
+    /// Returns `true` when the index was created or replaced, `false` when it was already up to date.
+    pub async fn upsert_index(&self, name: &str, spec: IndexFields) -> CouchResult<bool> {
+        match self.find_index_by_name(name, &spec).await? {
+            IndexState::Missing => self.create_index(name, spec, None).await,
+            IndexState::Current => Ok(false),
+            IndexState::Stale(ddoc) => self.create_index(name, spec, ddoc.as_deref()).await,
+        }
+    }
+
+    async fn find_index_by_name(&self, name: &str, spec: &IndexFields) -> CouchResult<IndexState> {
         let db_indexes = self.read_indexes().await?;
+        let mut state = IndexState::Missing;
 
         // We look for our index
-        for i in db_indexes.indexes.into_iter() {
-            if i.name == name {
+        for i in db_indexes.indexes.into_iter().filter(|i| i.name == name) {
+            if i.index_type == "json" && i.def.is_equivalent(spec) {
                 // Found? Ok let's return
-                return Ok(false);
+                return Ok(IndexState::Current);
             }
+
+            state = IndexState::Stale(i.ddoc);
         }
 
-        // Let's create it then
-        let result: DesignCreated = self.insert_index(name, spec).await?;
+        Ok(state)
+    }
+
+    async fn create_index(&self, name: &str, spec: IndexFields, ddoc: Option<&str>) -> CouchResult<bool> {
+        let result: DesignCreated = self.post_index(name, spec, ddoc).await?;
         match result.error {
-            Some(e) => Err(CouchError {
-                id: result.id,
-                status: reqwest::StatusCode::INTERNAL_SERVER_ERROR,
-                message: e,
-            }),
+            Some(error) => {
+                let reason = result.reason.unwrap_or_else(|| error.clone());
+                Err(CouchError::from_error(result.id, &error, reason))
+            }
             // Created and alright
             None => Ok(true),
         }
     }
 }
 
+/// ID of the local document holding a checkpoint
+fn checkpoint_id(checkpoint: &str) -> String {
+    format!("_local/{}", checkpoint)
+}
+
+/// State of a named index, compared to the requested definition
+enum IndexState {
+    Missing,
+    Current,
+    /// An index with the same name, but a different definition, exists in the given design document
+    Stale(Option<DocumentId>),
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1018,15 +3851,68 @@ mod tests {
         assert_eq!(p, "testdb/123");
         let p = db.create_document_path("1+3");
         assert_eq!(p, "testdb/1%2B3");
+        let p = db.create_document_path("a b?c#d%e/f");
+        assert_eq!(p, "testdb/a%20b%3Fc%23d%25e%2Ff");
+        let p = db.create_document_path("_design/my view");
+        assert_eq!(p, "testdb/_design/my%20view");
+        let p = db.create_document_path("_local/checkpoint/1");
+        assert_eq!(p, "testdb/_local/checkpoint%2F1");
+        let p = db.create_document_path("café");
+        assert_eq!(p, "testdb/caf%C3%A9");
         let p = db.create_design_path("view1");
         assert_eq!(p, "testdb/_design/view1");
         let p = db.create_query_view_path("design1", "view1");
         assert_eq!(p, "testdb/_design/design1/_view/view1");
         let p = db.create_query_view_path("design+1", "view+1");
         assert_eq!(p, "testdb/_design/design%2B1/_view/view%2B1");
+        let p = db.create_search_path("design+1", "index 1");
+        assert_eq!(p, "testdb/_design/design%2B1/_search/index%201");
+        let p = db.create_nouveau_path("design1", "index1");
+        assert_eq!(p, "testdb/_design/design1/_nouveau/index1");
         let p = db.create_execute_update_path("design1", "update1", "123");
         assert_eq!(p, "testdb/_design/design1/_update/update1/123");
+        let p = db.create_execute_update_without_id_path("design1", "update1");
+        assert_eq!(p, "testdb/_design/design1/_update/update1");
+        let p = db.create_execute_show_path("design1", "show1", Some("1 3"));
+        assert_eq!(p, "testdb/_design/design1/_show/show1/1%203");
+        let p = db.create_execute_show_path("design1", "show1", None);
+        assert_eq!(p, "testdb/_design/design1/_show/show1");
+        let p = db.create_execute_list_path("design1", "list1", "view1");
+        assert_eq!(p, "testdb/_design/design1/_list/list1/view1");
         let p = db.create_compact_path("view1");
         assert_eq!(p, "testdb/_compact/view1");
     }
+
+    #[test]
+    fn test_found_collection_design_docs() {
+        let found = || FindDocuments {
+            docs: vec![json!({"_id": "_design/app"}), json!({"_id": "doc"})],
+            bookmark: None,
+            execution_stats: None,
+            warning: None,
+        };
+        assert_eq!(
+            found_collection::<Value>(found(), false).ids().collect::<Vec<_>>(),
+            vec!["doc"]
+        );
+        assert_eq!(
+            found_collection::<Value>(found(), true).ids().collect::<Vec<_>>(),
+            vec!["_design/app", "doc"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_senders() {
+        let (mut tx, mut rx) = futures_channel::mpsc::channel(1);
+        assert!(tx.send_batch(1).await);
+        assert_eq!(rx.next().await, Some(1));
+        drop(rx);
+        assert!(!tx.send_batch(2).await);
+
+        let (mut tx, mut rx) = tokio::sync::mpsc::channel(1);
+        assert!(tx.send_batch(1).await);
+        assert_eq!(rx.recv().await, Some(1));
+        drop(rx);
+        assert!(!tx.send_batch(2).await);
+    }
 }
@@ -1,33 +1,40 @@
+use crate::error::{CouchError, CouchResult};
+use crate::types::document::{AttachmentStub, Attachments, DocumentId};
+use crate::types::find::ExecutionStats;
+use reqwest::StatusCode;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Map, Value};
 use std::borrow::Cow;
-use std::ops::{Index, IndexMut};
+use std::ops::{Deref, DerefMut, Index, IndexMut};
 
 /// Trait to deal with typed CouchDB documents.
 pub trait TypedCouchDocument: DeserializeOwned + Serialize + Sized {
     /// get the _id field
-    fn get_id(&self) -> Cow<str>;
+    fn get_id(&self) -> Cow<'_, str>;
     /// get the _rev field
-    fn get_rev(&self) -> Cow<str>;
+    fn get_rev(&self) -> Cow<'_, str>;
     /// set the _rev field
     fn set_rev(&mut self, rev: &str);
     /// set the _id field
     fn set_id(&mut self, rev: &str);
     /// merge the _id and _rev from the other document with this one
     fn merge_ids(&mut self, other: &Self);
+    /// value of the `type` field that tells this kind of document apart from others in the same
+    /// database; set it with `#[couch(doc_type = "...")]` when deriving `CouchDocument`
+    fn doc_type() -> Option<&'static str> {
+        None
+    }
 }
 
 /// Allows dealing with _id and _rev fields in untyped (Value) documents
 impl TypedCouchDocument for Value {
-    fn get_id(&self) -> Cow<str> {
-        let id: String = json_extr!(self["_id"]);
-        Cow::from(id)
+    fn get_id(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self["_id"].as_str().unwrap_or_default())
     }
 
-    fn get_rev(&self) -> Cow<str> {
-        let rev: String = json_extr!(self["_rev"]);
-        Cow::from(rev)
+    fn get_rev(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self["_rev"].as_str().unwrap_or_default())
     }
 
     fn set_rev(&mut self, rev: &str) {
@@ -48,6 +55,244 @@ impl TypedCouchDocument for Value {
     }
 }
 
+/// A document holding data of any type, with the metadata CouchDB keeps for it. The data is
+/// flattened into the document on the wire, so `T` needs no `_id` or `_rev` fields of its own,
+/// nor a `TypedCouchDocument` implementation. `Document<T>` dereferences to the data.
+///
+/// ```
+/// use couch_rs::document::{Document, TypedCouchDocument};
+/// use serde::{Deserialize, Serialize};
+/// use serde_json::json;
+///
+/// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// struct Person {
+///     name: String,
+/// }
+///
+/// let doc = Document::new("john", Person { name: "John".to_string() });
+/// assert_eq!(serde_json::to_value(&doc).unwrap(), json!({"_id": "john", "name": "John"}));
+///
+/// let stored: Document<Person> =
+///     serde_json::from_value(json!({"_id": "john", "_rev": "2-b", "_conflicts": ["2-a"], "name": "John"})).unwrap();
+/// assert_eq!(stored.get_rev(), "2-b");
+/// assert_eq!(stored.name, "John");
+/// ```
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+pub struct Document<T> {
+    #[serde(rename = "_id", default, skip_serializing_if = "String::is_empty")]
+    pub id: DocumentId,
+    #[serde(rename = "_rev", default, skip_serializing_if = "String::is_empty")]
+    pub rev: String,
+    /// Whether this is the tombstone of a deleted document; saving a document marked as deleted
+    /// deletes it
+    #[serde(rename = "_deleted", default, skip_serializing_if = "is_false")]
+    pub deleted: bool,
+    /// Stubs of the attachments, when the document was read with them
+    #[serde(rename = "_attachments", default, skip_serializing_if = "Option::is_none")]
+    pub attachments: Option<Attachments>,
+    /// Conflicting revisions, when the document was read with `conflicts`
+    #[serde(rename = "_conflicts", default, skip_serializing_if = "Option::is_none")]
+    pub conflicts: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub data: T,
+}
+
+impl<T> Document<T> {
+    /// A new document with the given ID, or with an ID chosen by CouchDB when it is empty
+    pub fn new(id: &str, data: T) -> Document<T> {
+        Document {
+            id: id.to_string(),
+            rev: String::new(),
+            deleted: false,
+            attachments: None,
+            conflicts: None,
+            data,
+        }
+    }
+
+    pub fn into_data(self) -> T {
+        self.data
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted
+    }
+
+    /// Marks the document as deleted, so saving it deletes it, like `Database::remove`, while the
+    /// data is kept in the tombstone
+    pub fn mark_deleted(&mut self) {
+        self.deleted = true;
+    }
+
+    /// The stubs of the attachments; none when the document was read without them
+    pub fn attachments(&self) -> impl Iterator<Item = (&String, &AttachmentStub)> {
+        self.attachments.iter().flatten()
+    }
+
+    pub fn attachment(&self, name: &str) -> Option<&AttachmentStub> {
+        self.attachments.as_ref()?.get(name)
+    }
+
+    /// Removes an attachment, which deletes it when the document is saved. Returns its stub, if the
+    /// document had it.
+    pub fn remove_attachment(&mut self, name: &str) -> Option<AttachmentStub> {
+        self.attachments.as_mut()?.remove(name)
+    }
+
+    /// The conflicting revisions, when the document was read with `conflicts`
+    pub fn conflicts(&self) -> &[String] {
+        self.conflicts.as_deref().unwrap_or_default()
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        !self.conflicts().is_empty()
+    }
+
+    /// Forgets the conflicting revisions, which CouchDB does not accept in a document that is saved;
+    /// remove the losing revisions with `Database::remove` to resolve the conflict
+    pub fn clear_conflicts(&mut self) -> Vec<String> {
+        self.conflicts.take().unwrap_or_default()
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Builds a `Document<Value>`; see `Document::builder`
+#[derive(Debug, Clone, Default)]
+pub struct DocumentBuilder {
+    id: DocumentId,
+    rev: String,
+    fields: Map<String, Value>,
+    attachments: Attachments,
+    error: Option<CouchError>,
+}
+
+impl DocumentBuilder {
+    /// The ID of the document; CouchDB chooses one when it is not set
+    pub fn id(mut self, id: &str) -> Self {
+        self.id = id.to_string();
+        self
+    }
+
+    /// The revision of the document, to update an existing document
+    pub fn rev(mut self, rev: &str) -> Self {
+        self.rev = rev.to_string();
+        self
+    }
+
+    /// Sets a field. Fields starting with an underscore are reserved by CouchDB, and fail the build.
+    pub fn field<V: Serialize>(mut self, name: &str, value: V) -> Self {
+        if name.starts_with('_') {
+            self.fail(format!("Bad special document member: {}", name));
+        }
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                self.fields.insert(name.to_string(), value);
+            }
+            Err(err) => {
+                self.error.get_or_insert_with(|| CouchError::from(err));
+            }
+        }
+        self
+    }
+
+    /// Adds an attachment, which is saved along with the document
+    pub fn attachment(mut self, name: &str, content_type: &str, content: &[u8]) -> Self {
+        self.attachments
+            .insert(name.to_string(), AttachmentStub::inline(content_type, content));
+        self
+    }
+
+    /// The document, or the first problem found while building it
+    pub fn build(mut self) -> CouchResult<Document<Value>> {
+        let reserved = ["_design/", "_local/"];
+        if self.id.starts_with('_') && !reserved.iter().any(|prefix| self.id.starts_with(prefix)) {
+            self.fail(s!("Only reserved document ids may start with underscore."));
+        }
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        Ok(Document {
+            id: self.id,
+            rev: self.rev,
+            deleted: false,
+            attachments: Some(self.attachments).filter(|attachments| !attachments.is_empty()),
+            conflicts: None,
+            data: Value::Object(self.fields),
+        })
+    }
+
+    /// Records a problem with the document, like the `doc_validation` error of CouchDB
+    fn fail(&mut self, reason: String) {
+        self.error
+            .get_or_insert_with(|| CouchError::from_couch(None, StatusCode::BAD_REQUEST, "doc_validation", reason));
+    }
+}
+
+impl Document<Value> {
+    /// Builds a document field by field, checking that no field is reserved by CouchDB
+    ///
+    /// ```
+    /// use couch_rs::document::Document;
+    /// use serde_json::json;
+    ///
+    /// let doc = Document::builder()
+    ///     .id("john")
+    ///     .field("name", "John")
+    ///     .field("age", 42)
+    ///     .attachment("notes.txt", "text/plain", b"likes tea")
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(doc["age"], json!(42));
+    ///
+    /// assert!(Document::builder().field("_secret", true).build().unwrap_err().is_bad_request());
+    /// ```
+    pub fn builder() -> DocumentBuilder {
+        DocumentBuilder::default()
+    }
+}
+
+impl<T> Deref for Document<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data
+    }
+}
+
+impl<T> DerefMut for Document<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> TypedCouchDocument for Document<T> {
+    fn get_id(&self) -> Cow<'_, str> {
+        Cow::from(&self.id)
+    }
+
+    fn get_rev(&self) -> Cow<'_, str> {
+        Cow::from(&self.rev)
+    }
+
+    fn set_rev(&mut self, rev: &str) {
+        self.rev = rev.to_string();
+    }
+
+    fn set_id(&mut self, id: &str) {
+        self.id = id.to_string();
+    }
+
+    fn merge_ids(&mut self, other: &Self) {
+        self.set_id(&other.get_id());
+        self.set_rev(&other.get_rev());
+    }
+}
+
 /// Memory-optimized, iterable document collection, mostly returned in calls
 /// that involve multiple documents results Can target a specific index through
 /// implementation of `Index` and `IndexMut`
@@ -57,6 +302,13 @@ pub struct DocumentCollection<T: TypedCouchDocument> {
     pub rows: Vec<T>,
     pub total_rows: u32,
     pub bookmark: Option<String>,
+    /// Execution statistics; only available for Mango queries run with `execution_stats(true)`
+    pub execution_stats: Option<ExecutionStats>,
+    /// Warning returned by a Mango query, for example when no matching index was found
+    pub warning: Option<String>,
+    /// Sequence of the database the documents were read at; only returned by `_all_docs` queries
+    /// with `update_seq(true)`. A changes feed started from this sequence continues exactly after them.
+    pub update_seq: Option<String>,
 }
 
 impl<T: TypedCouchDocument> Default for DocumentCollection<T> {
@@ -66,6 +318,9 @@ impl<T: TypedCouchDocument> Default for DocumentCollection<T> {
             rows: vec![],
             total_rows: 0,
             bookmark: None,
+            execution_stats: None,
+            warning: None,
+            update_seq: None,
         }
     }
 }
@@ -76,6 +331,8 @@ pub struct AllDocsResponse<T: TypedCouchDocument> {
     pub total_rows: Option<u32>,
     pub offset: Option<u32>,
     pub rows: Vec<DocResponse<T>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_seq: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
@@ -88,34 +345,58 @@ pub struct DocResponse<T: TypedCouchDocument> {
     pub doc: Option<T>,
 }
 
+impl<T: TypedCouchDocument> DocResponse<T> {
+    /// Returns the document of the row. Rows requested without `include_docs` only hold the id and
+    /// revision, which are returned as a document with just `_id` and `_rev`, when `T` can be
+    /// deserialized from those. Returns `None` for errors, deleted documents and design documents.
+    pub fn into_doc(self) -> Option<T> {
+        self.into_doc_with(false)
+    }
+
+    /// Returns the document of the row, like `into_doc`, keeping design documents when
+    /// `include_design_docs` is set
+    pub(crate) fn into_doc_with(self, include_design_docs: bool) -> Option<T> {
+        if self.error.is_some() {
+            // remove errors
+            return None;
+        }
+
+        let doc = match self.doc {
+            Some(doc) => doc,
+            None => {
+                let value = self.value?;
+                if value.deleted == Some(true) {
+                    return None;
+                }
+                serde_json::from_value(json!({"_id": self.id?, "_rev": value.rev})).ok()?
+            }
+        };
+
+        // Remove _design documents
+        Some(doc).filter(|doc| include_design_docs || !doc.get_id().starts_with('_'))
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct DocResponseValue {
     pub rev: String,
+    /// Set for the deleted documents requested through `keys`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<bool>,
 }
 
 impl<T: TypedCouchDocument> DocumentCollection<T> {
     pub fn new(doc: AllDocsResponse<T>) -> DocumentCollection<T> {
+        Self::from_all_docs(doc, false)
+    }
+
+    /// Collects the documents of an `_all_docs` response, keeping design documents when
+    /// `include_design_docs` is set
+    pub(crate) fn from_all_docs(doc: AllDocsResponse<T>, include_design_docs: bool) -> DocumentCollection<T> {
         let rows = doc.rows;
         let items: Vec<T> = rows
             .into_iter()
-            .filter_map(|d| {
-                if d.error.is_some() {
-                    // remove errors
-                    None
-                } else {
-                    // Remove _design documents
-                    if let Some(doc) = d.doc {
-                        if doc.get_id().starts_with('_') {
-                            None
-                        } else {
-                            Some(doc)
-                        }
-                    } else {
-                        // no documents retrieved
-                        None
-                    }
-                }
-            })
+            .filter_map(|row| row.into_doc_with(include_design_docs))
             .collect();
 
         DocumentCollection {
@@ -123,6 +404,9 @@ impl<T: TypedCouchDocument> DocumentCollection<T> {
             total_rows: items.len() as u32,
             rows: items,
             bookmark: Option::None,
+            execution_stats: None,
+            warning: None,
+            update_seq: doc.update_seq,
         }
     }
 
@@ -133,6 +417,9 @@ impl<T: TypedCouchDocument> DocumentCollection<T> {
             total_rows: len,
             rows: docs,
             bookmark,
+            execution_stats: None,
+            warning: None,
+            update_seq: None,
         }
     }
 
@@ -147,6 +434,9 @@ impl<T: TypedCouchDocument> DocumentCollection<T> {
                 .filter_map(|d| serde_json::from_value::<T>(d).ok())
                 .collect(),
             bookmark,
+            execution_stats: None,
+            warning: None,
+            update_seq: None,
         }
     }
 
@@ -154,13 +444,55 @@ impl<T: TypedCouchDocument> DocumentCollection<T> {
     pub fn get_data(&self) -> &Vec<T> {
         &self.rows
     }
+
+    /// Returns the documents, without copying them
+    pub fn into_rows(self) -> Vec<T> {
+        self.rows
+    }
+
+    /// Converts the documents into another type, such as a struct for documents read as `Value`.
+    /// Fails on the first document that does not fit the type.
+    pub fn into_typed<U: DeserializeOwned>(self) -> CouchResult<Vec<U>> {
+        self.rows
+            .into_iter()
+            .map(|row| Ok(serde_json::from_value(serde_json::to_value(row)?)?))
+            .collect()
+    }
+
+    /// The IDs of the documents, in order
+    pub fn ids(&self) -> impl Iterator<Item = Cow<'_, str>> {
+        self.rows.iter().map(|row| row.get_id())
+    }
+
+    /// The revisions of the documents, in order
+    pub fn revs(&self) -> impl Iterator<Item = Cow<'_, str>> {
+        self.rows.iter().map(|row| row.get_rev())
+    }
+}
+
+impl<T: TypedCouchDocument> IntoIterator for DocumentCollection<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.rows.into_iter()
+    }
+}
+
+impl<'a, T: TypedCouchDocument> IntoIterator for &'a DocumentCollection<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.rows.iter()
+    }
 }
 
 impl<T: TypedCouchDocument> Index<usize> for DocumentCollection<T> {
     type Output = T;
 
     fn index(&self, index: usize) -> &T {
-        &self.rows.get(index).unwrap()
+        self.rows.get(index).unwrap()
     }
 }
 
@@ -196,4 +528,166 @@ mod tests {
         assert_eq!(id, "1");
         assert_eq!(rev, "2");
     }
+    #[derive(Serialize, Deserialize, CouchDocument, Default)]
+    #[couch(doc_type = "user")]
+    struct User {
+        #[serde(rename = "_id", skip_serializing_if = "String::is_empty")]
+        id: String,
+        #[serde(rename = "_rev", skip_serializing_if = "String::is_empty")]
+        rev: String,
+        name: String,
+    }
+
+    #[test]
+    fn test_derive_renamed_fields() {
+        let mut user = User::default();
+        user.set_id("john");
+        user.set_rev("1-abc");
+
+        assert_eq!(user.id, "john");
+        assert_eq!(user.get_rev(), "1-abc");
+        assert_eq!(User::doc_type(), Some("user"));
+        assert_eq!(serde_json::Value::doc_type(), None);
+    }
+
+    #[test]
+    fn test_collection_without_docs() {
+        use super::{AllDocsResponse, DocumentCollection};
+        use serde_json::{json, Value};
+
+        let response: AllDocsResponse<Value> = serde_json::from_value(json!({
+            "total_rows": 4,
+            "offset": 0,
+            "rows": [
+                {"id": "a", "key": "a", "value": {"rev": "1-a"}},
+                {"id": "_design/b", "key": "_design/b", "value": {"rev": "1-b"}},
+                {"id": "c", "key": "c", "value": {"rev": "2-c", "deleted": true}, "doc": null},
+                {"key": "d", "error": "not_found"}
+            ]
+        }))
+        .unwrap();
+
+        let collection = DocumentCollection::new(response.clone());
+        assert_eq!(collection.rows, vec![json!({"_id": "a", "_rev": "1-a"})]);
+        assert_eq!(collection.total_rows, 1);
+
+        let collection = DocumentCollection::from_all_docs(response, true);
+        assert_eq!(
+            collection.rows,
+            vec![
+                json!({"_id": "a", "_rev": "1-a"}),
+                json!({"_id": "_design/b", "_rev": "1-b"})
+            ]
+        );
+    }
+
+    #[test]
+    fn test_document_wrapper() {
+        use super::Document;
+        use serde_json::json;
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Reading {
+            value: f64,
+        }
+
+        let stored = json!({
+            "_id": "r1",
+            "_rev": "1-a",
+            "_attachments": {"raw.csv": {"content_type": "text/csv", "digest": "md5-x", "length": 3, "stub": true}},
+            "value": 2.5
+        });
+        let mut doc: Document<Reading> = serde_json::from_value(stored.clone()).unwrap();
+        assert_eq!(doc.value, 2.5);
+        assert_eq!(doc.attachments.as_ref().unwrap()["raw.csv"].length, Some(3));
+        assert_eq!(doc.conflicts, None);
+        assert_eq!(serde_json::to_value(&doc).unwrap(), stored);
+
+        assert!(!doc.is_deleted() && !doc.has_conflicts());
+        assert_eq!(doc.attachments().count(), 1);
+        assert_eq!(doc.remove_attachment("raw.csv").unwrap().content_type, "text/csv");
+        assert!(doc.attachment("raw.csv").is_none());
+
+        let mut conflicted: Document<Reading> =
+            serde_json::from_value(json!({"_id": "r1", "_rev": "2-b", "_conflicts": ["2-a"], "value": 1.0})).unwrap();
+        assert_eq!(conflicted.conflicts(), &[s!("2-a")]);
+        assert_eq!(conflicted.clear_conflicts(), vec![s!("2-a")]);
+        conflicted.mark_deleted();
+        assert_eq!(
+            serde_json::to_value(&conflicted).unwrap(),
+            json!({"_id": "r1", "_rev": "2-b", "_deleted": true, "value": 1.0})
+        );
+
+        doc.value = 3.0;
+        doc.set_rev("2-b");
+        assert_eq!(doc.get_rev(), "2-b");
+        assert_eq!(doc.into_data(), Reading { value: 3.0 });
+    }
+
+    #[test]
+    fn test_document_builder() {
+        use super::Document;
+        use serde_json::json;
+
+        let doc = Document::builder()
+            .id("john")
+            .field("name", "John")
+            .field("tags", vec!["a", "b"])
+            .attachment("hello.txt", "text/plain", b"hello")
+            .build()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(&doc).unwrap(),
+            json!({
+                "_id": "john",
+                "_attachments": {"hello.txt": {"content_type": "text/plain", "data": "aGVsbG8="}},
+                "name": "John",
+                "tags": ["a", "b"]
+            })
+        );
+
+        let err = Document::builder().field("_rev", "1-a").build().unwrap_err();
+        assert_eq!(err.error(), "doc_validation");
+        assert!(Document::builder().id("_users").build().is_err());
+        assert!(Document::builder().id("_design/app").build().is_ok());
+    }
+
+    #[test]
+    fn test_collection_into_iter() {
+        use super::DocumentCollection;
+        use serde_json::{json, Value};
+        use std::borrow::Cow;
+
+        let doc = json!({"_id": "a", "_rev": "1-a"});
+        assert!(matches!(doc.get_id(), Cow::Borrowed("a")));
+        assert_eq!(json!({"_id": 1}).get_id(), "");
+
+        let collection: DocumentCollection<Value> = DocumentCollection::new_from_documents(vec![doc.clone()], None);
+        let ids: Vec<_> = (&collection).into_iter().map(|d| d.get_id()).collect();
+        assert_eq!(ids, vec!["a"]);
+        assert_eq!(collection.into_iter().collect::<Vec<_>>(), vec![doc]);
+    }
+
+    #[test]
+    fn test_collection_into_typed() {
+        use super::DocumentCollection;
+        use serde_json::{json, Value};
+
+        let collection: DocumentCollection<Value> = DocumentCollection::new_from_documents(
+            vec![
+                json!({"_id": "a", "_rev": "1-a", "thing": "x"}),
+                json!({"_id": "b", "_rev": "2-b", "thing": "y"}),
+            ],
+            None,
+        );
+        assert_eq!(collection.ids().collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(collection.revs().collect::<Vec<_>>(), vec!["1-a", "2-b"]);
+
+        let docs: Vec<TestDocument> = collection.clone().into_typed().unwrap();
+        assert_eq!(docs[1]._id, "b");
+        assert_eq!(docs[1]._rev, "2-b");
+
+        let err = collection.into_typed::<Vec<String>>().unwrap_err();
+        assert!(matches!(err, crate::error::CouchError::Json { .. }));
+    }
 }
@@ -0,0 +1,308 @@
+use crate::error::{CouchError, CouchResult};
+use crate::validation::ValidationHook;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+
+/// Encrypts and decrypts field values with named keys, for example with AES-GCM. The provider is
+/// responsible for the cipher, including any nonce, which is typically prepended to the ciphertext.
+/// Values are always decrypted with the key they were encrypted with, so keys can be rotated by
+/// changing the current key, while old keys are kept for decryption.
+///
+/// The associated data identifies the field: it is the document `_id` and the path of the field,
+/// separated by a NUL byte. Authenticate it along with the ciphertext, as the associated data of an
+/// AEAD cipher such as AES-GCM, so a value copied into another field or document fails to decrypt.
+pub trait KeyProvider: Send + Sync {
+    /// Id of the key new values are encrypted with
+    fn current_key_id(&self) -> String;
+    /// Encrypts the plaintext with the given key, authenticating the associated data
+    fn encrypt(&self, key_id: &str, plaintext: &[u8], associated_data: &[u8]) -> CouchResult<Vec<u8>>;
+    /// Decrypts the ciphertext with the given key, returning an error when it was not encrypted with
+    /// the same associated data
+    fn decrypt(&self, key_id: &str, ciphertext: &[u8], associated_data: &[u8]) -> CouchResult<Vec<u8>>;
+}
+
+/// An encrypted field value, as it is stored in the document
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptedField {
+    /// Id of the key the value was encrypted with
+    pub key_id: String,
+    /// The encrypted JSON value, base64 encoded
+    pub ciphertext: String,
+}
+
+/// Client-side encryption of selected document fields. The values of the fields, which can be any
+/// JSON value, are replaced by an `EncryptedField` holding the ciphertext and the id of the key.
+/// Fields are named by their path, with dots separating nested fields, such as `address.street`.
+/// Missing and null fields are left alone. The ciphertext is bound to the `_id` of the document and
+/// the path of the field, so documents need an `_id` to be encrypted.
+///
+/// Decrypting a field that holds plaintext is an error, unless `allow_plaintext` is set, for example
+/// to read the documents that were written before the field was encrypted.
+///
+/// Use it with `Repository::encryption` to encrypt on `save` and decrypt on `get`, `find` and `all`.
+/// As a `ValidationHook`, it encrypts every document written by a `Database`. Like the changes of
+/// other hooks, the encrypted values are written back into the returned document when its type can
+/// hold them, as a `Value` can. The `Database` does not decrypt what it reads, so read encrypted
+/// documents through a `Repository` with the same encryption, or as raw values passed to `decrypt`.
+/// Encrypted fields cannot be queried or indexed.
+///
+/// ```
+/// use couch_rs::encryption::{FieldEncryption, KeyProvider};
+/// use couch_rs::error::CouchResult;
+/// use serde_json::json;
+///
+/// // Do not use this in production; use a real cipher, such as AES-GCM
+/// struct Reverse;
+///
+/// impl KeyProvider for Reverse {
+///     fn current_key_id(&self) -> String {
+///         "reverse-1".to_string()
+///     }
+///
+///     fn encrypt(&self, _key_id: &str, plaintext: &[u8], _associated_data: &[u8]) -> CouchResult<Vec<u8>> {
+///         Ok(plaintext.iter().rev().cloned().collect())
+///     }
+///
+///     fn decrypt(&self, _key_id: &str, ciphertext: &[u8], _associated_data: &[u8]) -> CouchResult<Vec<u8>> {
+///         Ok(ciphertext.iter().rev().cloned().collect())
+///     }
+/// }
+///
+/// let encryption = FieldEncryption::new(Reverse, &["ssn", "address.street"]);
+/// let mut doc = json!({"_id": "john", "ssn": "123", "address": {"street": "Main", "city": "Springfield"}});
+///
+/// encryption.encrypt(&mut doc).unwrap();
+/// assert_eq!(doc["ssn"]["key_id"], "reverse-1");
+/// assert_eq!(doc["address"]["city"], "Springfield");
+///
+/// encryption.decrypt(&mut doc).unwrap();
+/// assert_eq!(doc, json!({"_id": "john", "ssn": "123", "address": {"street": "Main", "city": "Springfield"}}));
+/// ```
+#[derive(Clone)]
+pub struct FieldEncryption {
+    fields: Vec<String>,
+    provider: Arc<dyn KeyProvider>,
+    allow_plaintext: bool,
+}
+
+impl FieldEncryption {
+    pub fn new<P: KeyProvider + 'static>(provider: P, fields: &[&str]) -> Self {
+        FieldEncryption {
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+            provider: Arc::new(provider),
+            allow_plaintext: false,
+        }
+    }
+
+    /// Leaves fields that hold plaintext as they are when decrypting, instead of returning an error
+    pub fn allow_plaintext(mut self, allow_plaintext: bool) -> Self {
+        self.allow_plaintext = allow_plaintext;
+        self
+    }
+
+    /// The paths of the encrypted fields
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    /// Encrypts the configured fields of the document with the current key. Fields that are already
+    /// encrypted are left alone.
+    pub fn encrypt(&self, doc: &mut Value) -> CouchResult<()> {
+        let key_id = self.provider.current_key_id();
+        let id = doc_id(doc);
+        for field in &self.fields {
+            let value = match field_mut(doc, field) {
+                Some(value) if !value.is_null() && as_encrypted(value).is_none() => value,
+                _ => continue,
+            };
+
+            let id = id.as_deref().ok_or_else(|| {
+                invalid_field(field, "documents need an _id to be encrypted", StatusCode::BAD_REQUEST)
+            })?;
+            let plaintext = serde_json::to_vec(value)?;
+            let ciphertext = self
+                .provider
+                .encrypt(&key_id, &plaintext, &associated_data(id, field))?;
+            *value = serde_json::to_value(EncryptedField {
+                key_id: key_id.clone(),
+                ciphertext: base64::encode(&ciphertext),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Decrypts the configured fields of the document, with the keys they were encrypted with
+    pub fn decrypt(&self, doc: &mut Value) -> CouchResult<()> {
+        let id = doc_id(doc).unwrap_or_default();
+        for field in &self.fields {
+            let (value, encrypted) = match field_mut(doc, field) {
+                Some(value) if value.is_null() => continue,
+                Some(value) => match as_encrypted(value) {
+                    Some(encrypted) => (value, encrypted),
+                    None if self.allow_plaintext => continue,
+                    None => {
+                        return Err(invalid_field(field, "not encrypted", StatusCode::UNPROCESSABLE_ENTITY));
+                    }
+                },
+                None => continue,
+            };
+
+            let ciphertext = base64::decode(&encrypted.ciphertext)
+                .map_err(|err| invalid_field(field, &err.to_string(), StatusCode::UNPROCESSABLE_ENTITY))?;
+            let plaintext = self
+                .provider
+                .decrypt(&encrypted.key_id, &ciphertext, &associated_data(&id, field))?;
+            *value = serde_json::from_slice(&plaintext)?;
+        }
+        Ok(())
+    }
+}
+
+impl ValidationHook for FieldEncryption {
+    fn validate(&self, doc: &mut Value) -> CouchResult<()> {
+        self.encrypt(doc)
+    }
+}
+
+impl fmt::Debug for FieldEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FieldEncryption")
+            .field("fields", &self.fields)
+            .field("allow_plaintext", &self.allow_plaintext)
+            .finish()
+    }
+}
+
+fn as_encrypted(value: &Value) -> Option<EncryptedField> {
+    if value.is_object() {
+        serde_json::from_value(value.clone()).ok()
+    } else {
+        None
+    }
+}
+
+fn field_mut<'a>(doc: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    path.split('.').try_fold(doc, |value, name| value.get_mut(name))
+}
+
+fn doc_id(doc: &Value) -> Option<String> {
+    doc["_id"].as_str().filter(|id| !id.is_empty()).map(String::from)
+}
+
+/// The `_id` and the path of the field, which the ciphertext of the field is bound to
+fn associated_data(id: &str, field: &str) -> Vec<u8> {
+    [id.as_bytes(), &[0], field.as_bytes()].concat()
+}
+
+fn invalid_field(field: &str, problem: &str, status: StatusCode) -> CouchError {
+    CouchError::new(format!("invalid encrypted field {}: {}", field, problem), status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Xors with the key, and prefixes the associated data as a stand-in for an authentication tag
+    struct Xor(u8);
+
+    impl KeyProvider for Xor {
+        fn current_key_id(&self) -> String {
+            format!("xor-{}", self.0)
+        }
+
+        fn encrypt(&self, _key_id: &str, plaintext: &[u8], associated_data: &[u8]) -> CouchResult<Vec<u8>> {
+            let mut ciphertext = vec![associated_data.len() as u8];
+            ciphertext.extend_from_slice(associated_data);
+            ciphertext.extend(plaintext.iter().map(|b| b ^ self.0));
+            Ok(ciphertext)
+        }
+
+        fn decrypt(&self, key_id: &str, ciphertext: &[u8], associated_data: &[u8]) -> CouchResult<Vec<u8>> {
+            let key = match key_id.strip_prefix("xor-").and_then(|key| key.parse::<u8>().ok()) {
+                Some(key) => key,
+                None => return Err(CouchError::new(s!("unknown key"), StatusCode::FORBIDDEN)),
+            };
+            let (tag, encrypted) = ciphertext[1..].split_at(ciphertext[0] as usize);
+            if tag != associated_data {
+                return Err(CouchError::new(s!("authentication failed"), StatusCode::FORBIDDEN));
+            }
+            Ok(encrypted.iter().map(|b| b ^ key).collect())
+        }
+    }
+
+    #[test]
+    fn test_encrypt_fields() {
+        let encryption = FieldEncryption::new(Xor(7), &["ssn", "card.number", "missing.field"]);
+        let plain =
+            json!({"_id": "john", "ssn": {"country": "US", "id": 123}, "card": {"number": "4111"}, "name": null});
+        let mut doc = plain.clone();
+
+        encryption.encrypt(&mut doc).unwrap();
+        let encrypted = doc.clone();
+        assert_eq!(doc["ssn"]["key_id"], "xor-7");
+        assert!(doc["card"]["number"]["ciphertext"].is_string());
+        assert!(doc["name"].is_null());
+
+        encryption.encrypt(&mut doc).unwrap();
+        assert_eq!(doc, encrypted);
+
+        let rotated = FieldEncryption::new(Xor(9), &["ssn", "card.number"]);
+        rotated.decrypt(&mut doc).unwrap();
+        assert_eq!(doc, plain);
+
+        let mut doc = json!({"_id": "john", "ssn": {"key_id": "other", "ciphertext": "AA=="}});
+        assert_eq!(
+            encryption.decrypt(&mut doc).unwrap_err().status(),
+            StatusCode::FORBIDDEN
+        );
+
+        let mut doc = json!({"ssn": "123"});
+        assert_eq!(
+            encryption.encrypt(&mut doc).unwrap_err().status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn test_ciphertext_is_bound_to_the_document_and_field() {
+        let encryption = FieldEncryption::new(Xor(7), &["ssn", "pin"]);
+        let mut doc = json!({"_id": "john", "ssn": "123", "pin": "0000"});
+        encryption.encrypt(&mut doc).unwrap();
+
+        let mut swapped = doc.clone();
+        swapped["pin"] = doc["ssn"].clone();
+        assert_eq!(
+            encryption.decrypt(&mut swapped).unwrap_err().status(),
+            StatusCode::FORBIDDEN
+        );
+
+        let mut copied = doc.clone();
+        copied["_id"] = json!("jane");
+        assert_eq!(
+            encryption.decrypt(&mut copied).unwrap_err().status(),
+            StatusCode::FORBIDDEN
+        );
+
+        encryption.decrypt(&mut doc).unwrap();
+        assert_eq!(doc, json!({"_id": "john", "ssn": "123", "pin": "0000"}));
+    }
+
+    #[test]
+    fn test_decrypt_plaintext() {
+        let mut doc = json!({"_id": "john", "ssn": "123", "pin": null});
+
+        let err = FieldEncryption::new(Xor(7), &["ssn", "pin"])
+            .decrypt(&mut doc.clone())
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let lenient = FieldEncryption::new(Xor(7), &["ssn", "pin"]).allow_plaintext(true);
+        lenient.decrypt(&mut doc).unwrap();
+        assert_eq!(doc, json!({"_id": "john", "ssn": "123", "pin": null}));
+    }
+}
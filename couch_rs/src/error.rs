@@ -1,45 +1,460 @@
+use crate::client::REQUEST_ID_HEADER;
+use reqwest::header::HeaderMap;
+use reqwest::{Request, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
 use std::error;
 use std::fmt;
+use std::sync::Arc;
 
-// Define our error types. These may be customized for our error handling cases.
-// Now we will be able to write our own errors, defer to an underlying error
-// implementation, or do something in between.
+/// Errors returned by CouchDB, or raised while talking to it. Error responses of CouchDB are parsed
+/// into the variant of their status code, keeping the `error` and `reason` of the response body.
+/// Some (bulk) transactions return the id of the document that failed as part of the error.
+///
+/// Errors of requests carry an `ErrorContext` with the method and path of the request, and errors
+/// caused by `reqwest` or `serde_json` are available through `std::error::Error::source`.
 #[derive(Debug, Clone)]
-pub struct CouchError {
-    /// Some (bulk) transaction might return an id as part of the error
-    pub id: Option<String>,
-    /// HTTP Status Code
-    pub status: reqwest::StatusCode,
-    /// Detailed error message
-    pub message: String,
+pub enum CouchError {
+    /// The document, database or design document does not exist (404)
+    NotFound {
+        id: Option<String>,
+        reason: String,
+        context: Option<Box<ErrorContext>>,
+    },
+    /// The document was updated by someone else; fetch its latest revision and retry (409)
+    Conflict {
+        id: Option<String>,
+        reason: String,
+        context: Option<Box<ErrorContext>>,
+    },
+    /// The credentials are missing or wrong (401)
+    Unauthorized {
+        id: Option<String>,
+        reason: String,
+        context: Option<Box<ErrorContext>>,
+    },
+    /// The request is not allowed, for example by a `validate_doc_update` function (403)
+    Forbidden {
+        id: Option<String>,
+        reason: String,
+        context: Option<Box<ErrorContext>>,
+    },
+    /// A `validate_doc_update` function of a design document rejected a document write, by throwing
+    /// `{forbidden: reason}` (403) or `{unauthorized: reason}` (401). The reason is the message of
+    /// the function, which is meant to be shown to the user. Writes of users without access to the
+    /// database fail with the same error and status, and are reported as this variant too.
+    ValidationFailed {
+        id: Option<String>,
+        status: StatusCode,
+        reason: String,
+        context: Option<Box<ErrorContext>>,
+    },
+    /// The request is invalid; `error` is the kind of problem reported by CouchDB (400)
+    BadRequest {
+        id: Option<String>,
+        error: String,
+        reason: String,
+        context: Option<Box<ErrorContext>>,
+    },
+    /// The request could not be sent, or the response could not be received
+    Transport {
+        source: Arc<reqwest::Error>,
+        context: Option<Box<ErrorContext>>,
+    },
+    /// A document or response could not be serialized or deserialized
+    Json {
+        source: Arc<serde_json::Error>,
+        context: Option<Box<ErrorContext>>,
+    },
+    /// Any other error, with the status code that describes it best
+    Other {
+        id: Option<String>,
+        status: StatusCode,
+        error: String,
+        reason: String,
+        context: Option<Box<ErrorContext>>,
+    },
 }
 
 pub type CouchResult<T> = Result<T, CouchError>;
 
+/// The request that failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// HTTP method of the request, such as `POST`
+    pub operation: String,
+    /// Path of the request, such as `/db/_find`
+    pub path: String,
+    /// The `X-Request-ID` header of the request, to find it in the logs of proxies
+    pub request_id: Option<String>,
+    /// The `X-Couch-Request-ID` header of the response, which identifies the request in the log of
+    /// CouchDB; `None` when no response was received
+    pub couch_request_id: Option<String>,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.operation, self.path)
+    }
+}
+
+/// The body of a CouchDB error response
+#[derive(Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    error: String,
+    #[serde(default)]
+    reason: String,
+}
+
 impl CouchError {
-    pub fn new(message: String, status: reqwest::StatusCode) -> CouchError {
-        CouchError {
-            id: None,
-            message,
-            status,
+    pub fn new(message: String, status: StatusCode) -> CouchError {
+        CouchError::new_with_id(None, message, status)
+    }
+
+    pub fn new_with_id(id: Option<String>, message: String, status: StatusCode) -> CouchError {
+        CouchError::from_couch(id, status, &error_name(status), message)
+    }
+
+    /// Creates the error for a CouchDB error response, from its status code and the `error` and
+    /// `reason` of its body
+    pub fn from_couch(id: Option<String>, status: StatusCode, error: &str, reason: String) -> CouchError {
+        let context = None;
+        match status {
+            StatusCode::NOT_FOUND => CouchError::NotFound { id, reason, context },
+            StatusCode::CONFLICT => CouchError::Conflict { id, reason, context },
+            StatusCode::UNAUTHORIZED => CouchError::Unauthorized { id, reason, context },
+            StatusCode::FORBIDDEN => CouchError::Forbidden { id, reason, context },
+            StatusCode::BAD_REQUEST => CouchError::BadRequest {
+                id,
+                error: error.to_string(),
+                reason,
+                context,
+            },
+            status => CouchError::Other {
+                id,
+                status,
+                error: error.to_string(),
+                reason,
+                context,
+            },
+        }
+    }
+
+    /// Creates the error for a failed row of a bulk response, which only holds the `error` and
+    /// `reason`, and not a status code
+    pub fn from_error(id: Option<String>, error: &str, reason: String) -> CouchError {
+        CouchError::from_couch(id, error_status(error), error, reason)
+    }
+
+    /// Creates the error for a failed document write, like `from_couch`. A `validate_doc_update`
+    /// function rejects a document with the `forbidden` error and a 403 status, or the `unauthorized`
+    /// error and a 401 status, which become `ValidationFailed`. CouchDB refuses a user without access
+    /// to the database with the same error and status, so such writes are `ValidationFailed` too.
+    pub(crate) fn from_document_write(
+        id: Option<String>,
+        status: StatusCode,
+        error: &str,
+        reason: String,
+    ) -> CouchError {
+        match (status, error) {
+            (StatusCode::FORBIDDEN, "forbidden") | (StatusCode::UNAUTHORIZED, "unauthorized") => {
+                CouchError::ValidationFailed {
+                    id,
+                    status,
+                    reason,
+                    context: None,
+                }
+            }
+            _ => CouchError::from_couch(id, status, error, reason),
+        }
+    }
+
+    /// Creates the error for a failed row of a bulk write, like `from_error`; see
+    /// `from_document_write`
+    pub(crate) fn from_document_write_error(id: Option<String>, error: &str, reason: String) -> CouchError {
+        CouchError::from_document_write(id, error_status(error), error, reason)
+    }
+
+    /// Reads the error of a failed response, parsing the `error` and `reason` of its body
+    pub(crate) async fn from_response(response: Response) -> CouchError {
+        let status = response.status();
+        let context = ErrorContext::of(&response);
+        let body = match response.bytes().await {
+            Ok(body) => body,
+            Err(err) => return CouchError::from(err).with_context(context),
+        };
+
+        let err = match serde_json::from_slice::<ErrorBody>(&body) {
+            Ok(body) if !body.error.is_empty() => CouchError::from_couch(body.id, status, &body.error, body.reason),
+            _ => {
+                let reason = String::from_utf8_lossy(&body).trim().to_string();
+                CouchError::from_couch(None, status, &error_name(status), reason)
+            }
+        };
+        err.with_context(context)
+    }
+
+    /// HTTP status code of the error. Errors that did not come with a response are reported as
+    /// `NOT_IMPLEMENTED`.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            CouchError::NotFound { .. } => StatusCode::NOT_FOUND,
+            CouchError::Conflict { .. } => StatusCode::CONFLICT,
+            CouchError::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            CouchError::Forbidden { .. } => StatusCode::FORBIDDEN,
+            CouchError::ValidationFailed { status, .. } => *status,
+            CouchError::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            CouchError::Transport { source, .. } => source.status().unwrap_or(StatusCode::NOT_IMPLEMENTED),
+            CouchError::Json { .. } => StatusCode::NOT_IMPLEMENTED,
+            CouchError::Other { status, .. } => *status,
+        }
+    }
+
+    /// Id of the document the error is about, when known
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            CouchError::NotFound { id, .. }
+            | CouchError::Conflict { id, .. }
+            | CouchError::Unauthorized { id, .. }
+            | CouchError::Forbidden { id, .. }
+            | CouchError::ValidationFailed { id, .. }
+            | CouchError::BadRequest { id, .. }
+            | CouchError::Other { id, .. } => id.as_deref(),
+            CouchError::Transport { .. } | CouchError::Json { .. } => None,
+        }
+    }
+
+    /// Sets the id of the document the error is about, unless it is already known
+    pub fn with_id(mut self, doc_id: Option<String>) -> Self {
+        match &mut self {
+            CouchError::NotFound { id, .. }
+            | CouchError::Conflict { id, .. }
+            | CouchError::Unauthorized { id, .. }
+            | CouchError::Forbidden { id, .. }
+            | CouchError::ValidationFailed { id, .. }
+            | CouchError::BadRequest { id, .. }
+            | CouchError::Other { id, .. } => {
+                if id.is_none() {
+                    *id = doc_id;
+                }
+            }
+            CouchError::Transport { .. } | CouchError::Json { .. } => {}
+        }
+        self
+    }
+
+    /// The request that failed, when the error came from a request
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            CouchError::NotFound { context, .. }
+            | CouchError::Conflict { context, .. }
+            | CouchError::Unauthorized { context, .. }
+            | CouchError::Forbidden { context, .. }
+            | CouchError::ValidationFailed { context, .. }
+            | CouchError::BadRequest { context, .. }
+            | CouchError::Transport { context, .. }
+            | CouchError::Json { context, .. }
+            | CouchError::Other { context, .. } => context.as_deref(),
         }
     }
 
-    pub fn new_with_id(id: Option<String>, message: String, status: reqwest::StatusCode) -> CouchError {
-        CouchError { id, message, status }
+    /// Sets the request that failed, unless it is already known
+    pub(crate) fn with_context(mut self, request: Option<ErrorContext>) -> Self {
+        match &mut self {
+            CouchError::NotFound { context, .. }
+            | CouchError::Conflict { context, .. }
+            | CouchError::Unauthorized { context, .. }
+            | CouchError::Forbidden { context, .. }
+            | CouchError::ValidationFailed { context, .. }
+            | CouchError::BadRequest { context, .. }
+            | CouchError::Transport { context, .. }
+            | CouchError::Json { context, .. }
+            | CouchError::Other { context, .. } => {
+                if context.is_none() {
+                    *context = request.map(Box::new);
+                }
+            }
+        }
+        self
+    }
+
+    /// The kind of error, as named by CouchDB, such as `not_found` or `conflict`
+    pub fn error(&self) -> String {
+        match self {
+            CouchError::BadRequest { error, .. } | CouchError::Other { error, .. } => error.clone(),
+            _ => error_name(self.status()),
+        }
+    }
+
+    /// Detailed error message; the `reason` of CouchDB error responses
+    pub fn message(&self) -> String {
+        match self {
+            CouchError::NotFound { reason, .. }
+            | CouchError::Conflict { reason, .. }
+            | CouchError::Unauthorized { reason, .. }
+            | CouchError::Forbidden { reason, .. }
+            | CouchError::ValidationFailed { reason, .. }
+            | CouchError::BadRequest { reason, .. }
+            | CouchError::Other { reason, .. } => reason.clone(),
+            CouchError::Transport { source, .. } => source.to_string(),
+            CouchError::Json { source, .. } => source.to_string(),
+        }
     }
 
     pub fn is_not_found(&self) -> bool {
-        self.status == reqwest::StatusCode::NOT_FOUND
+        self.status() == StatusCode::NOT_FOUND
+    }
+
+    pub fn is_conflict(&self) -> bool {
+        self.status() == StatusCode::CONFLICT
+    }
+
+    /// Whether the request lacked credentials, including validation failures with a 401 status
+    pub fn is_unauthorized(&self) -> bool {
+        self.status() == StatusCode::UNAUTHORIZED
+    }
+
+    /// Whether the request was not allowed, including validation failures with a 403 status
+    pub fn is_forbidden(&self) -> bool {
+        self.status() == StatusCode::FORBIDDEN
+    }
+
+    pub fn is_validation_failure(&self) -> bool {
+        matches!(self, CouchError::ValidationFailed { .. })
+    }
+
+    /// The message of the `validate_doc_update` function that rejected the document, if any
+    pub fn validation_message(&self) -> Option<&str> {
+        match self {
+            CouchError::ValidationFailed { reason, .. } => Some(reason),
+            _ => None,
+        }
+    }
+
+    pub fn is_bad_request(&self) -> bool {
+        matches!(self, CouchError::BadRequest { .. })
+    }
+}
+
+/// The value of a header, when it is present and valid UTF-8
+pub(crate) fn header_string(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Status code of a CouchDB error name, such as 404 for `not_found`
+fn error_status(error: &str) -> StatusCode {
+    match error {
+        "not_found" => StatusCode::NOT_FOUND,
+        "conflict" => StatusCode::CONFLICT,
+        "unauthorized" => StatusCode::UNAUTHORIZED,
+        "forbidden" => StatusCode::FORBIDDEN,
+        "bad_request" => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// CouchDB style name of a status code, such as `not_found` for 404
+fn error_name(status: StatusCode) -> String {
+    status
+        .canonical_reason()
+        .unwrap_or("unknown_error")
+        .to_lowercase()
+        .replace(' ', "_")
+}
+
+impl ErrorContext {
+    fn new(request: &Request) -> ErrorContext {
+        ErrorContext {
+            operation: request.method().to_string(),
+            path: request.url().path().to_string(),
+            request_id: header_string(request.headers(), REQUEST_ID_HEADER),
+            couch_request_id: None,
+        }
+    }
+
+    /// The request of a response sent with `send_with_context`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn of(response: &Response) -> Option<ErrorContext> {
+        let mut context = response.extensions().get::<ErrorContext>().cloned()?;
+        context.couch_request_id = header_string(response.headers(), crate::client::COUCH_REQUEST_ID_HEADER);
+        Some(context)
+    }
+
+    /// The request of a response; responses of the wasm client do not keep it
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn of(_response: &Response) -> Option<ErrorContext> {
+        None
+    }
+}
+
+/// Sends a request, like `RequestBuilder::send`, remembering the request in the errors it causes
+/// and in the extensions of its response
+pub(crate) trait SendWithContext {
+    async fn send_with_context(self) -> CouchResult<Response>;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SendWithContext for RequestBuilder {
+    async fn send_with_context(self) -> CouchResult<Response> {
+        let (client, request) = self.build_split();
+        let request = request?;
+        let context = ErrorContext::new(&request);
+
+        match client.execute(request).await {
+            Ok(mut response) => {
+                response.extensions_mut().insert(context);
+                Ok(response)
+            }
+            Err(err) => Err(CouchError::from(err).with_context(Some(context))),
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl SendWithContext for RequestBuilder {
+    async fn send_with_context(self) -> CouchResult<Response> {
+        // the wasm client can not be taken from the builder, so the request is built from a copy
+        let context = self
+            .try_clone()
+            .and_then(|request| request.build().ok())
+            .map(|request| ErrorContext::new(&request));
+
+        self.send()
+            .await
+            .map_err(|err| CouchError::from(err).with_context(context))
+    }
+}
+
+/// Turns error responses into a `CouchError`, like `Response::error_for_status`, but keeping the
+/// `error` and `reason` of the response body
+pub(crate) trait CheckStatus: Sized {
+    async fn check_status(self) -> CouchResult<Self>;
+}
+
+impl CheckStatus for Response {
+    async fn check_status(self) -> CouchResult<Self> {
+        if self.status().is_client_error() || self.status().is_server_error() {
+            Err(CouchError::from_response(self).await)
+        } else {
+            Ok(self)
+        }
     }
 }
 
 impl fmt::Display for CouchError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if let Some(id) = &self.id {
-            write!(f, "{} -> {}: {}", id, self.status, self.message)
+        if let Some(context) = self.context() {
+            write!(f, "{} failed: ", context)?;
+        }
+        if let Some(id) = self.id() {
+            write!(f, "{} -> {}: {}", id, self.status(), self.message())
         } else {
-            write!(f, "{}: {}", self.status, self.message)
+            write!(f, "{}: {}", self.status(), self.message())
         }
     }
 }
@@ -47,37 +462,115 @@ impl fmt::Display for CouchError {
 // This is important for other errors to wrap this one.
 impl error::Error for CouchError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        // Generic error, underlying cause isn't tracked.
-        None
+        match self {
+            CouchError::Transport { source, .. } => Some(source.as_ref()),
+            CouchError::Json { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
     }
 }
 
+/// Errors of `reqwest` are kept as the `source()` of a `Transport` error, including the status
+/// errors of `Response::error_for_status`, whose status is returned by `status()`
 impl std::convert::From<reqwest::Error> for CouchError {
     fn from(err: reqwest::Error) -> Self {
-        CouchError {
-            id: None,
-            status: err.status().unwrap_or(reqwest::StatusCode::NOT_IMPLEMENTED),
-            message: err.to_string(),
+        CouchError::Transport {
+            source: Arc::new(err),
+            context: None,
         }
     }
 }
 
 impl std::convert::From<serde_json::Error> for CouchError {
     fn from(err: serde_json::Error) -> Self {
-        CouchError {
-            id: None,
-            status: reqwest::StatusCode::NOT_IMPLEMENTED,
-            message: err.to_string(),
+        CouchError::Json {
+            source: Arc::new(err),
+            context: None,
         }
     }
 }
 
 impl std::convert::From<url::ParseError> for CouchError {
     fn from(err: url::ParseError) -> Self {
-        CouchError {
+        CouchError::Other {
             id: None,
-            status: reqwest::StatusCode::NOT_IMPLEMENTED,
-            message: err.to_string(),
+            status: StatusCode::NOT_IMPLEMENTED,
+            error: s!("invalid_url"),
+            reason: err.to_string(),
+            context: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation_failure() {
+        let err = CouchError::from_document_write(None, StatusCode::FORBIDDEN, "forbidden", s!("Title is required"))
+            .with_id(Some(s!("post-1")));
+        assert!(err.is_validation_failure());
+        assert!(err.is_forbidden());
+        assert_eq!(err.validation_message(), Some("Title is required"));
+        assert_eq!(err.error(), "forbidden");
+        assert_eq!(err.to_string(), "post-1 -> 403 Forbidden: Title is required");
+
+        let err = CouchError::from_document_write_error(None, "unauthorized", s!("Only authors may edit"));
+        assert!(err.is_unauthorized());
+        assert_eq!(err.validation_message(), Some("Only authors may edit"));
+
+        // the error and status classify the failure, whatever the wording of the reason
+        let err = CouchError::from_document_write(None, StatusCode::FORBIDDEN, "forbidden", s!("Accès refusé"));
+        assert_eq!(err.validation_message(), Some("Accès refusé"));
+        let err = CouchError::from_document_write(None, StatusCode::FORBIDDEN, "not_allowed", s!("Title is required"));
+        assert!(matches!(err, CouchError::Forbidden { .. }));
+        let err = CouchError::from_document_write(None, StatusCode::UNAUTHORIZED, "forbidden", s!("Title is required"));
+        assert!(matches!(err, CouchError::Unauthorized { .. }));
+        let err = CouchError::from_document_write_error(None, "conflict", s!("Document update conflict."));
+        assert!(err.is_conflict());
+
+        // errors of other requests are not validation failures
+        let err = CouchError::from_couch(None, StatusCode::FORBIDDEN, "forbidden", s!("Title is required"));
+        assert!(!err.is_validation_failure());
+    }
+
+    #[test]
+    fn test_error_variants() {
+        let err = CouchError::new(s!("missing"), StatusCode::NOT_FOUND);
+        assert!(err.is_not_found());
+        assert_eq!(err.error(), "not_found");
+        assert_eq!(err.to_string(), "404 Not Found: missing");
+
+        let err = CouchError::from_couch(None, StatusCode::BAD_REQUEST, "query_parse_error", s!("bad key"));
+        assert!(matches!(&err, CouchError::BadRequest { error, .. } if error == "query_parse_error"));
+        assert_eq!(err.error(), "query_parse_error");
+
+        let err = CouchError::from_error(Some(s!("a")), "forbidden", s!("no way")).with_id(Some(s!("b")));
+        assert!(err.is_forbidden());
+        assert_eq!(err.id(), Some("a"));
+        assert_eq!(err.to_string(), "a -> 403 Forbidden: no way");
+
+        let err = CouchError::new_with_id(None, s!("gone"), StatusCode::GONE).with_id(Some(s!("c")));
+        assert_eq!(err.status(), StatusCode::GONE);
+        assert_eq!(err.error(), "gone");
+        assert_eq!(err.id(), Some("c"));
+
+        let err: CouchError = serde_json::from_str::<u32>("x").unwrap_err().into();
+        assert!(matches!(err, CouchError::Json { .. }));
+        assert!(error::Error::source(&err).is_some());
+
+        let context = ErrorContext {
+            operation: s!("POST"),
+            path: s!("/db/_find"),
+            request_id: Some(s!("a1b2")),
+            couch_request_id: None,
+        };
+        let err = CouchError::new(s!("invalid selector"), StatusCode::BAD_REQUEST).with_context(Some(context.clone()));
+        assert_eq!(err.context(), Some(&context));
+        assert_eq!(
+            err.to_string(),
+            "POST /db/_find failed: 400 Bad Request: invalid selector"
+        );
+    }
+}
@@ -116,6 +116,10 @@ pub use couch_rs_derive::*;
 
 pub use std::borrow::Cow;
 
+// Used by the `mango!` macro
+#[doc(hidden)]
+pub use serde_json;
+
 /// Macros that the crate exports to facilitate most of the
 /// doc-to-json-to-string-related tasks
 #[allow(unused_macros)]
@@ -177,25 +181,57 @@ mod macros {
     /// Url encode path segments
     macro_rules! url_encode {
         ($id:ident) => {{
-            url::form_urlencoded::byte_serialize($id.as_bytes()).collect::<String>()
+            percent_encoding::utf8_percent_encode($id, crate::PATH_SEGMENT).to_string()
         }};
     }
 }
 
+/// Characters that are percent-encoded in a path segment: all but the unreserved characters of RFC 3986
+const PATH_SEGMENT: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Synchronous versions of `Client` and `Database`.
+#[cfg(all(feature = "blocking", not(target_arch = "wasm32")))]
+pub mod blocking;
 mod client;
 /// Database operations on a CouchDB Database.
 pub mod database;
 /// Document model to support CouchDB document operations.
 pub mod document;
+/// Client-side encryption of document fields.
+pub mod encryption;
 /// Error wrappers for the HTTP status codes returned by CouchDB.
 pub mod error;
+/// In-memory CouchDB server for tests.
+#[cfg(all(feature = "mock", not(target_arch = "wasm32")))]
+pub mod mock;
 /// Trait that provides methods that can be used to switch between abstract Document and
 /// concrete Model implementors (such as your custom data models)
 pub mod model;
+/// Iteration of views and queries in pages.
+pub mod pager;
+/// Queries scoped to a partition of a partitioned database.
+pub mod partition;
+/// Typed access to the documents of a single type.
+pub mod repository;
+mod runtime;
+/// Reporting of slow requests.
+pub mod slow_requests;
+mod stream;
+/// Harness for integration tests against CouchDB.
+#[cfg(all(feature = "testing", not(target_arch = "wasm32")))]
+pub mod testing;
 /// Data types to support CouchDB operations.
 pub mod types;
+/// Hooks that check documents before they are written.
+pub mod validation;
+/// Buffered writer that stores documents in bulk.
+pub mod writer;
 
-pub use client::Client;
+pub use client::{Client, RequestOptions, ResponseCompression};
 
 #[allow(unused_mut, unused_variables)]
 #[cfg(test)]
@@ -219,8 +255,12 @@ mod couch_rs_tests {
     mod client_tests {
         use crate::client::Client;
         use crate::couch_rs_tests::TestDoc;
+        use crate::types::user::User;
+        use futures_util::future::join_all;
         use reqwest::StatusCode;
-        use serde_json::json;
+        use serde_json::{json, Value};
+        use std::collections::HashSet;
+        use std::slice;
 
         #[tokio::test]
         async fn should_check_couchdbs_status() {
@@ -236,7 +276,7 @@ mod couch_rs_tests {
             let dbw = client.db("should_create_test_db").await;
             assert!(dbw.is_ok());
 
-            let _ = client.destroy_db("should_create_test_db");
+            let _ = client.destroy_db("should_create_test_db").await;
         }
 
         #[tokio::test]
@@ -256,7 +296,7 @@ mod couch_rs_tests {
             assert!(client.exists(dbname).await.is_ok());
             let info = client.get_info(dbname).await.expect("can not get db info");
             assert_eq!(info.db_name, dbname);
-            let _ = client.destroy_db(dbname);
+            let _ = client.destroy_db(dbname).await;
         }
 
         #[tokio::test]
@@ -277,7 +317,7 @@ mod couch_rs_tests {
             let mut doc = ndoc_result.unwrap();
             assert_eq!(doc["thing"], json!(true));
 
-            let _ = client.destroy_db("should_create_a_document");
+            let _ = client.destroy_db("should_create_a_document").await;
         }
 
         #[tokio::test]
@@ -302,7 +342,7 @@ mod couch_rs_tests {
             assert!(!doc._id.is_empty());
             assert!(doc._rev.starts_with("1-"));
 
-            let _ = client.destroy_db("should_create_a_typed_document");
+            let _ = client.destroy_db("should_create_a_typed_document").await;
         }
 
         #[tokio::test]
@@ -336,9 +376,9 @@ mod couch_rs_tests {
 
             let second_result = docs.next().unwrap();
             assert!(second_result.is_err());
-            assert_eq!(second_result.err().unwrap().status, StatusCode::CONFLICT);
+            assert_eq!(second_result.err().unwrap().status(), StatusCode::CONFLICT);
 
-            let _ = client.destroy_db(dbname);
+            let _ = client.destroy_db(dbname).await;
         }
 
         #[tokio::test]
@@ -348,17 +388,118 @@ mod couch_rs_tests {
 
             assert!(client.destroy_db("should_destroy_the_db").await.unwrap());
         }
+
+        #[tokio::test]
+        async fn should_rename_a_database_through_replication() {
+            use crate::types::system::{RenameOptions, RenameProgress};
+            use std::time::Duration;
+
+            let client = Client::new_local_test().unwrap();
+            let (old, new) = ("should_rename_a_database", "should_rename_a_database_renamed");
+            let _ = client.make_db("_replicator").await;
+            let _ = client.destroy_db(old).await;
+            let _ = client.destroy_db(new).await;
+            let db = client.db(old).await.unwrap();
+            db.create(json!({"_id": "a", "thing": true})).await.unwrap();
+            db.create(json!({"_id": "_design/app"})).await.unwrap();
+
+            // without credentials, the replicator can not read the database, and the rename gives up
+            let anonymous = RenameOptions::default().timeout(Duration::from_secs(5));
+            let err = client.rename_db(old, new, &anonymous, |_| {}).await.unwrap_err();
+            assert!(!err.is_not_found());
+            assert_eq!(client.get_info(old).await.unwrap().doc_count, 2);
+            let _ = client.destroy_db(new).await;
+
+            let options = RenameOptions::default()
+                .credentials("admin", "password")
+                .timeout(Duration::from_secs(60));
+            let mut steps = vec![];
+            let renamed = client
+                .rename_db(old, new, &options, |step| steps.push(step.clone()))
+                .await
+                .unwrap();
+            assert_eq!(renamed.name(), new);
+            assert_eq!(steps.last(), Some(&RenameProgress::Deleted));
+            assert!(steps.contains(&RenameProgress::Verified { doc_count: 2 }));
+            assert!(client.get_info(old).await.unwrap_err().is_not_found());
+            assert!(renamed.get::<Value>("_design/app").await.is_ok());
+            assert_eq!(renamed.get::<Value>("a").await.unwrap()["thing"], json!(true));
+
+            // the replication document is removed, credentials included
+            let replicator = client.db("_replicator").await.unwrap();
+            let target = json!(format!("http://localhost:5984/{}", new));
+            let replications = replicator.get_all::<Value>().await.unwrap();
+            assert!(replications.rows.iter().all(|doc| doc["target"]["url"] != target));
+
+            assert!(client.destroy_db(new).await.unwrap());
+        }
+
+        #[tokio::test]
+        async fn should_rotate_passwords_and_roles_of_users() {
+            let client = Client::new_local_test().unwrap();
+            let users = client.db("_users").await.unwrap();
+            let name = "should_rotate_passwords_and_roles_of_users";
+            if let Ok(stale) = users.get::<Value>(&User::id_for(name)).await {
+                assert!(users.remove(stale).await);
+            }
+
+            client.create_user(name, "secret", vec![]).await.unwrap();
+
+            // concurrent updates of the same user conflict, and are retried on the latest revision
+            let roles = ["reader".to_string(), "writer".to_string(), "admin".to_string()];
+            let updates = join_all(
+                roles
+                    .iter()
+                    .map(|role| client.add_user_roles(name, slice::from_ref(role))),
+            )
+            .await;
+            assert!(updates.iter().all(Result::is_ok));
+            let user = client.get_user(name).await.unwrap().unwrap();
+            assert!(user.rev.starts_with("4-"));
+            assert_eq!(user.roles.iter().collect::<HashSet<_>>(), roles.iter().collect());
+
+            let user = client.remove_user_roles(name, &roles[2..]).await.unwrap();
+            assert_eq!(user.roles.len(), 2);
+
+            // CouchDB hashes the new password, and only accepts that one afterwards
+            client.set_user_password(name, "rotated").await.unwrap();
+            let rotated = Client::new("http://localhost:5984", name, "rotated").unwrap();
+            let own = rotated.get_user(name).await.unwrap().unwrap();
+            assert_eq!(own.password, None);
+            assert!(own.other.contains_key("derived_key"));
+            assert_eq!(own.roles.len(), 2);
+            let old = Client::new("http://localhost:5984", name, "secret").unwrap();
+            assert!(old.get_user(name).await.unwrap_err().is_unauthorized());
+
+            let user = users.get::<Value>(&User::id_for(name)).await.unwrap();
+            assert!(users.remove(user).await);
+        }
     }
 
     mod database_tests {
+        use crate as couch_rs;
         use crate::client::Client;
+        use crate::couch_rs_tests::TestDoc;
         use crate::database::Database;
         use crate::document::{DocumentCollection, TypedCouchDocument};
         use crate::types;
-        use crate::types::find::FindQuery;
-        use crate::types::query::{QueriesParams, QueryParams};
-        use crate::types::view::{CouchFunc, CouchViews};
+        use crate::types::changes::{ChangesOptions, ChangesStyle};
+        use crate::types::design::{DesignDeployment, DesignDocument};
+        use crate::types::document::{BulkImportOptions, DocumentId, RetryPolicy};
+        use crate::types::find::{FindQuery, SortDirection};
+        use crate::types::merge::{ArrayMerge, MergePolicy};
+        use crate::types::nouveau::{NouveauIndex, NouveauQuery};
+        use crate::types::patch::PatchOp;
+        use crate::types::query::{Freshness, QueriesParams, QueryParams};
+        use crate::types::view::{
+            BuiltinReduce, CouchFunc, CouchViews, MapFunction, RawViewCollection, ViewCollection, ViewItem,
+        };
+        use crate::writer::BulkWriterOptions;
+        use crate::CouchDocument;
+        use reqwest::StatusCode;
+        use serde::{Deserialize, Serialize};
         use serde_json::{json, Value};
+        use std::collections::{HashMap, HashSet};
         use tokio::sync::mpsc;
         use tokio::sync::mpsc::{Receiver, Sender};
 
@@ -452,6 +593,38 @@ mod couch_rs_tests {
             teardown(client, dbname).await;
         }
 
+        #[tokio::test]
+        async fn should_return_the_write_result() {
+            use crate::types::document::WriteOptions;
+
+            let dbname = "should_return_the_write_result";
+            let (client, db, _doc) = setup(dbname).await;
+
+            let (doc, written) = db
+                .create_with_result(json!({"thing": "new"}), &WriteOptions::default())
+                .await
+                .unwrap();
+            assert_eq!(written.status, StatusCode::CREATED);
+            assert!(written.is_durable());
+            assert_eq!(written.id, doc.get_id());
+            assert_eq!(written.rev.as_deref(), Some(doc.get_rev().as_ref()));
+
+            let (saved, written) = db.save_with_result(doc, &WriteOptions::default()).await.unwrap();
+            assert!(written.is_durable());
+            assert!(saved.get_rev().starts_with("2-"));
+
+            // in batch mode, CouchDB only acknowledges the write, without a revision
+            let (_, written) = db
+                .save_with_result(saved, &WriteOptions::default().batch(true))
+                .await
+                .unwrap();
+            assert_eq!(written.status, StatusCode::ACCEPTED);
+            assert!(!written.is_durable());
+            assert_eq!(written.rev, None);
+
+            teardown(client, dbname).await;
+        }
+
         #[tokio::test]
         async fn should_remove_a_document() {
             let (client, db, doc) = setup("should_remove_a_document").await;
@@ -460,6 +633,45 @@ mod couch_rs_tests {
             teardown(client, "should_remove_a_document").await;
         }
 
+        #[tokio::test]
+        async fn should_remove_a_document_with_details() {
+            let dbname = "should_remove_a_document_with_details";
+            let (client, db, doc) = setup(dbname).await;
+
+            let stale = doc.clone();
+            let deleted = db.remove_checked(doc).await.unwrap();
+            assert!(deleted.rev.starts_with('2'));
+
+            let err = db.remove_checked(stale).await.unwrap_err();
+            assert!(err.is_conflict());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_compact_database() {
+            let dbname = "should_compact_database";
+            let (client, db, _doc) = setup(dbname).await;
+
+            assert!(db.try_compact().await.is_ok());
+            assert!(db.try_compact_views().await.is_ok());
+            let missing = crate::database::Database::new("should_compact_database_missing".to_string(), client.clone());
+            assert!(missing.try_compact().await.unwrap_err().is_not_found());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_check_whether_a_document_exists() {
+            let dbname = "should_check_whether_a_document_exists";
+            let (client, db, doc) = setup(dbname).await;
+
+            assert!(db.try_exists(&doc.get_id()).await.unwrap());
+            assert!(!db.try_exists("missing").await.unwrap());
+
+            teardown(client, dbname).await;
+        }
+
         #[tokio::test]
         async fn should_get_a_single_document() {
             let (client, ..) = setup("should_get_a_single_document").await;
@@ -482,6 +694,24 @@ mod couch_rs_tests {
             teardown(client, "should_get_a_document_with_a_space_in_id").await;
         }
 
+        #[tokio::test]
+        async fn should_handle_special_characters_in_ids() {
+            let dbname = "should_handle_special_characters_in_ids";
+            let (client, db, _) = setup(dbname).await;
+
+            for id in &["a+b", "what?", "hash#1", "100%", "a/b", "café", "_design/with space"] {
+                let doc = db.create(json!({ "_id": id })).await.unwrap();
+                assert_eq!(doc.get_id(), *id);
+                assert!(db.try_exists(id).await.unwrap());
+
+                let doc: Value = db.get(id).await.unwrap();
+                assert_eq!(doc.get_id(), *id);
+                db.remove_checked(doc).await.unwrap();
+            }
+
+            teardown(client, dbname).await;
+        }
+
         async fn setup_create_indexes(dbname: &str) -> (Client, Database, Value) {
             let (client, db, doc) = setup(dbname).await;
 
@@ -524,6 +754,28 @@ mod couch_rs_tests {
             teardown(client, "should_ensure_index_in_db").await;
         }
 
+        #[tokio::test]
+        async fn should_detect_and_replace_stale_index() {
+            let dbname = "should_detect_and_replace_stale_index";
+            let (client, db, _) = setup_create_indexes(dbname).await;
+
+            let same = types::index::IndexFields::new(vec![types::find::SortSpec::Simple(s!("thing"))]);
+            assert!(!db.ensure_index("thing-index", same).await.unwrap());
+
+            let changed = types::index::IndexFields::new(vec![types::find::SortSpec::Simple(s!("other"))]);
+            let err = db.ensure_index("thing-index", changed.clone()).await.unwrap_err();
+            assert_eq!(err.status(), reqwest::StatusCode::CONFLICT);
+
+            assert!(db.upsert_index("thing-index", changed.clone()).await.unwrap());
+            assert!(!db.ensure_index("thing-index", changed).await.unwrap());
+
+            let index_list = db.read_indexes().await.unwrap();
+            let named: Vec<_> = index_list.indexes.iter().filter(|i| i.name == "thing-index").collect();
+            assert_eq!(named.len(), 1);
+
+            teardown(client, dbname).await;
+        }
+
         #[tokio::test]
         async fn should_find_documents_in_db() {
             let (client, db, doc) = setup_create_indexes("should_find_documents_in_db").await;
@@ -547,203 +799,2242 @@ mod couch_rs_tests {
         }
 
         #[tokio::test]
-        async fn should_bulk_get_a_document() {
-            let (client, db, doc) = setup("should_bulk_get_a_document").await;
-            let id = doc.get_id().into_owned();
+        async fn should_explain_a_query() {
+            let dbname = "should_explain_a_query";
+            let (client, db, _) = setup_create_indexes(dbname).await;
 
-            let collection = db.get_bulk_raw(vec![id]).await.unwrap();
-            assert_eq!(collection.rows.len(), 1);
-            assert!(db.remove(doc).await);
+            let indexed = db.explain(&FindQuery::new(json!({"thing": true}))).await.unwrap();
+            assert_eq!(indexed.index.name, "thing-index");
+            assert!(!indexed.is_full_scan());
 
-            teardown(client, "should_bulk_get_a_document").await;
+            let unindexed = db.explain(&FindQuery::new(json!({"other": true}))).await.unwrap();
+            assert!(unindexed.is_full_scan());
+
+            teardown(client, dbname).await;
         }
 
         #[tokio::test]
-        async fn should_bulk_get_invalid_documents() {
-            let (client, db, doc) = setup("should_bulk_get_invalid_documents").await;
-            let id = doc.get_id().into_owned();
-            let invalid_id = "does_not_exist".to_string();
+        async fn should_advise_and_create_indexes() {
+            let dbname = "should_advise_and_create_indexes";
+            let (client, db, _) = setup(dbname).await;
+
+            let query = FindQuery::new(json!({"thing": true}));
+            let advice = db.advise_index(&query).await.unwrap();
+            assert!(advice.needs_index);
+            assert!(advice.explain.unwrap().is_full_scan());
+            assert_eq!(advice.suggested.unwrap().suggested_name(), "advised_thing");
+
+            // CouchDB refuses to sort without an index, so there is nothing to explain
+            let sorted = FindQuery::new(json!({"thing": true})).sort_by("thing", SortDirection::Desc);
+            let advice = db.advise_index(&sorted).await.unwrap();
+            assert!(advice.needs_index);
+            assert!(advice.explain.is_none());
+
+            let advice = db.ensure_advised_index(&sorted).await.unwrap();
+            assert!(advice.created);
+            let advice = db.advise_index(&sorted).await.unwrap();
+            assert!(!advice.needs_index);
+            assert_eq!(advice.explain.unwrap().index.name, "advised_thing");
+            assert_eq!(db.find_raw(&sorted).await.unwrap().rows.len(), 1);
 
-            let collection = db.get_bulk_raw(vec![id, invalid_id]).await.unwrap();
-            assert_eq!(collection.rows.len(), 1);
-            assert!(db.remove(doc).await);
+            teardown(client, dbname).await;
+        }
 
-            teardown(client, "should_bulk_get_invalid_documents").await;
+        #[tokio::test]
+        async fn should_return_execution_stats() {
+            let dbname = "should_return_execution_stats";
+            let (client, db, _) = setup(dbname).await;
+
+            let query = FindQuery::new(json!({"thing": true})).execution_stats(true);
+            let documents = db.find_raw(&query).await.unwrap();
+            let stats = documents.execution_stats.expect("execution stats should be returned");
+            assert_eq!(stats.results_returned, 1);
+            assert!(stats.total_docs_examined >= 1);
+
+            let documents = db.find_raw(&FindQuery::new(json!({"thing": true}))).await.unwrap();
+            assert!(documents.execution_stats.is_none());
+
+            teardown(client, dbname).await;
         }
 
         #[tokio::test]
-        async fn should_get_all_documents_with_keys() {
-            let (client, db, doc) = setup("should_get_all_documents_with_keys").await;
-            let id = doc.get_id().into_owned();
+        async fn should_return_find_warnings() {
+            let dbname = "should_return_find_warnings";
+            let (client, db, _) = setup_create_indexes(dbname).await;
 
-            let params = QueryParams::from_keys(vec![id]);
+            let unindexed = db.find_raw(&FindQuery::new(json!({"other": true}))).await.unwrap();
+            assert!(unindexed.warning.is_some());
 
-            let collection = db.get_all_params_raw(Some(params)).await.unwrap();
-            assert_eq!(collection.rows.len(), 1);
-            assert!(db.remove(doc).await);
+            let indexed = db.find_raw(&FindQuery::new(json!({"thing": true}))).await.unwrap();
+            assert!(indexed.warning.is_none());
 
-            teardown(client, "should_get_all_documents_with_keys").await;
+            teardown(client, dbname).await;
         }
 
         #[tokio::test]
-        async fn should_query_documents_with_keys() {
-            let db_name = "should_query_documents_with_keys";
-            let (client, db, doc) = setup(db_name).await;
-            let id = doc.get_id().into_owned();
-            let view_name = "testViewAll";
-            db.create_view(
-                view_name,
-                CouchViews::new(
-                    view_name,
-                    CouchFunc {
-                        map: r#"function(doc) {{
-                                    emit(doc._id, null);
-                            }}"#
-                        .to_string(),
-                        reduce: None,
-                    },
-                ),
-            )
-            .await
-            .unwrap();
-            let ndoc = db
-                .create(json!({
-                    "thing": true
-                }))
+        async fn should_find_one_document() {
+            let dbname = "should_find_one_document";
+            let (client, db, docs) = setup_multiple(dbname, 2).await;
+
+            let found = db
+                .find_one::<Value>(&FindQuery::new(json!({"thing": true})))
                 .await
                 .unwrap();
-            let ndoc_id = ndoc.get_id().into_owned();
-            let single_view_name = "testViewSingle";
-            db.create_view(
-                single_view_name,
-                CouchViews::new(
-                    single_view_name,
-                    CouchFunc {
-                        map: format!(
-                            r#"function(doc) {{
-                                    if(doc._id === "{}") {{
-                                        emit(doc._id, null);
-                                    }}
-                            }}"#,
-                            ndoc_id
-                        )
-                        .to_string(),
-                        reduce: None,
-                    },
-                ),
-            )
-            .await
-            .unwrap();
+            assert!(found.is_some());
 
-            // executing 'all' view querying with keys containing 1 key should result in 1 and 0 entries, respectively
-            assert_eq!(
-                db.query_raw(view_name, view_name, Some(QueryParams::from_keys(vec![id.clone()])))
-                    .await
-                    .unwrap()
-                    .rows
-                    .len(),
-                1
-            );
-            assert_eq!(
-                db.query_raw(
-                    single_view_name,
-                    single_view_name,
-                    Some(QueryParams::from_keys(vec![id])),
-                )
+            let missing = db
+                .find_one::<Value>(&FindQuery::new(json!({"thing": false})))
                 .await
-                .unwrap()
-                .rows
-                .len(),
-                0
-            );
+                .unwrap();
+            assert!(missing.is_none());
 
-            assert!(db.remove(ndoc).await);
-            assert!(db.remove(doc).await);
+            teardown(client, dbname).await;
+        }
 
-            teardown(client, db_name).await;
+        #[tokio::test]
+        async fn should_count_documents() {
+            let dbname = "should_count_documents";
+            let (client, db, docs) = setup_multiple(dbname, 3).await;
+            db.create(json!({"thing": false})).await.unwrap();
+
+            assert_eq!(db.count(&json!({"thing": true})).await.unwrap(), 3);
+            assert_eq!(db.count(&json!({"thing": false})).await.unwrap(), 1);
+            assert_eq!(db.count(&json!({"other": true})).await.unwrap(), 0);
+
+            teardown(client, dbname).await;
         }
 
         #[tokio::test]
-        async fn should_query_documents_with_key() {
-            let db_name = "should_query_documents_with_key";
-            let (client, db, doc) = setup(db_name).await;
-            let id = doc.get_id().into_owned();
-            let view_name = "testViewAll";
-            db.create_view(
-                view_name,
-                CouchViews::new(
-                    view_name,
-                    CouchFunc {
-                        map: r#"function(doc) {{
-                                    emit(doc._id, null);
-                            }}"#
-                        .to_string(),
-                        reduce: None,
-                    },
-                ),
-            )
-            .await
-            .unwrap();
-            let ndoc = db
-                .create(json!({
-                    "thing": true
-                }))
+        async fn should_update_documents_by_query() {
+            let dbname = "should_update_documents_by_query";
+            let (client, db, docs) = setup_multiple(dbname, 3).await;
+
+            let query = FindQuery::new(json!({"thing": true})).limit(2);
+            let results = db
+                .update_by_query(&query, |mut doc: Value| {
+                    doc["migrated"] = json!(true);
+                    Some(doc)
+                })
                 .await
                 .unwrap();
-            let ndoc_id = ndoc.get_id().into_owned();
-            let single_view_name = "testViewSingle";
-            db.create_view(
-                single_view_name,
-                CouchViews::new(
-                    single_view_name,
-                    CouchFunc {
-                        map: format!(
-                            r#"function(doc) {{
-                                    if(doc._id === "{}") {{
-                                        emit(doc._id, null);
-                                    }}
-                            }}"#,
-                            ndoc_id
-                        )
-                        .to_string(),
-                        reduce: None,
-                    },
-                ),
-            )
-            .await
-            .unwrap();
-
-            // executing 'all' view querying with a specific key should result in 1 and 0 entries, respectively
-            let mut one_key = QueryParams::default();
-            one_key.key = Some(doc.get_id().into_owned());
 
-            assert_eq!(
-                db.query_raw(view_name, view_name, Some(one_key.clone()))
-                    .await
-                    .unwrap()
-                    .rows
-                    .len(),
-                1
-            );
-            assert_eq!(
-                db.query_raw(single_view_name, single_view_name, Some(one_key))
-                    .await
-                    .unwrap()
-                    .rows
-                    .len(),
-                0
-            );
+            assert_eq!(results.len(), 3);
+            assert!(results.iter().all(|r| r.is_ok()));
+            assert_eq!(db.count(&json!({"migrated": true})).await.unwrap(), 3);
 
-            assert!(db.remove(ndoc).await);
-            assert!(db.remove(doc).await);
+            let results = db
+                .update_by_query(&FindQuery::new(json!({"thing": true})), |_doc: Value| None)
+                .await
+                .unwrap();
+            assert!(results.is_empty());
 
-            teardown(client, db_name).await;
+            teardown(client, dbname).await;
         }
 
         #[tokio::test]
-        async fn should_query_documents_with_defaultparams() {
-            let dbname = "should_query_documents_with_defaultparams";
-            let (client, db, doc) = setup(dbname).await;
+        async fn should_delete_documents_by_query() {
+            let dbname = "should_delete_documents_by_query";
+            let (client, db, docs) = setup_multiple(dbname, 3).await;
+            db.create(json!({"thing": false})).await.unwrap();
+
+            let result = db.delete_by_query(&json!({"thing": true})).await.unwrap();
+            assert_eq!(result.deleted, 3);
+            assert_eq!(result.failures().count(), 0);
+            assert_eq!(db.count(&json!({"thing": true})).await.unwrap(), 0);
+            assert_eq!(db.count(&json!({"thing": false})).await.unwrap(), 1);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_bulk_upsert_documents() {
+            let dbname = "should_bulk_upsert_documents";
+            let (client, db, doc) = setup(dbname).await;
+
+            let docs = vec![
+                json!({"_id": doc.get_id(), "thing": false}),
+                json!({"_id": "new_doc", "thing": true}),
+                json!({"thing": true}),
+            ];
+
+            let results = db.bulk_upsert(docs).await.unwrap();
+            assert_eq!(results.len(), 3);
+            assert!(results.iter().all(|r| r.is_ok()));
+
+            let updated: Value = db.get(&doc.get_id()).await.unwrap();
+            assert_eq!(updated["thing"], json!(false));
+            assert!(updated.get_rev().starts_with('2'));
+            assert_eq!(db.count(&json!({"thing": true})).await.unwrap(), 2);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_bulk_remove_documents() {
+            let dbname = "should_bulk_remove_documents";
+            let (client, db, docs) = setup_multiple(dbname, 3).await;
+
+            // a stale revision can not be deleted
+            let stale = docs.last().unwrap().clone();
+            db.save(stale).await.unwrap();
+
+            let result = db.bulk_remove(docs).await.unwrap();
+            assert_eq!(result.deleted, 2);
+            assert_eq!(result.failures().count(), 1);
+            assert_eq!(db.count(&json!({"thing": true})).await.unwrap(), 1);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_bulk_import_documents() {
+            let dbname = "should_bulk_import_documents";
+            let (client, db, _doc) = setup(dbname).await;
+
+            let docs = (0..2500).map(|idx| json!({"_id": format!("bi_{}", idx), "count": idx}));
+            let options = BulkImportOptions::default().chunk_size(1000).concurrency(2);
+            let result = db.bulk_import(docs, options).await.unwrap();
+
+            assert_eq!(result.written, 2500);
+            assert_eq!(result.failed, 0);
+            assert_eq!(result.requests, 3);
+
+            // importing the same ids again conflicts
+            let docs = (0..10).map(|idx| json!({"_id": format!("bi_{}", idx)}));
+            let result = db.bulk_import(docs, BulkImportOptions::default()).await.unwrap();
+            assert_eq!(result.written, 0);
+            assert_eq!(result.failed, 10);
+            assert!(result.errors.iter().all(|e| e.status() == StatusCode::CONFLICT));
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_write_documents_through_bulk_writer() {
+            let dbname = "should_write_documents_through_bulk_writer";
+            let (client, db, _doc) = setup(dbname).await;
+
+            let options = BulkWriterOptions::default().max_docs(10).flush_interval(None);
+            let mut writer = db.bulk_writer::<Value>(options);
+            for idx in 0..25 {
+                writer.write(json!({"thing": true, "count": idx})).await.unwrap();
+            }
+            assert_eq!(writer.buffered(), 5);
+
+            let results = writer.flush().await.unwrap();
+            assert_eq!(results.len(), 25);
+            assert!(results.iter().all(|r| r.is_ok()));
+            assert_eq!(writer.buffered(), 0);
+
+            writer.write(json!({"thing": true})).await.unwrap();
+            assert_eq!(writer.close().await.unwrap().len(), 1);
+            assert_eq!(db.count(&json!({"thing": true})).await.unwrap(), 27);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_keep_metadata_in_typed_documents() {
+            use crate::document::Document;
+
+            #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+            struct Person {
+                name: String,
+                age: u32,
+            }
+
+            let dbname = "should_keep_metadata_in_typed_documents";
+            let (client, db, _doc) = setup(dbname).await;
+
+            let john = Document::new(
+                "",
+                Person {
+                    name: s!("John"),
+                    age: 42,
+                },
+            );
+            let created = db.create(john).await.unwrap();
+            assert!(!created.id.is_empty());
+            assert!(created.rev.starts_with("1-"));
+
+            let mut read: Document<Person> = db.get(&created.id).await.unwrap();
+            assert_eq!(read, created);
+            read.data.age += 1;
+            let saved = db.save(read).await.unwrap();
+            assert!(saved.rev.starts_with("2-"));
+            assert_eq!(saved.age, 43);
+
+            // the metadata is stored as the fields of CouchDB, next to the data
+            let raw = db.get_raw(&saved.id).await.unwrap();
+            assert_eq!(raw["_rev"], json!(saved.rev));
+            assert_eq!(raw["age"], json!(43));
+            assert_eq!(raw.as_object().unwrap().len(), 4);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_expose_attachments_conflicts_and_deletion_on_documents() {
+            use crate::document::Document;
+            use reqwest::Method;
+
+            let dbname = "should_expose_attachments_conflicts_and_deletion_on_documents";
+            let (client, db, _doc) = setup(dbname).await;
+
+            let note = Document::builder()
+                .id("note")
+                .field("text", "hi")
+                .attachment("notes.txt", "text/plain", b"likes tea")
+                .build()
+                .unwrap();
+            db.create(note).await.unwrap();
+            let mut read: Document<Value> = db.get("note").await.unwrap();
+            let stub = read.attachment("notes.txt").unwrap();
+            assert_eq!(stub.stub, Some(true));
+            assert_eq!(stub.length, Some(9));
+            assert!(read.remove_attachment("notes.txt").is_some());
+            let saved = db.save(read).await.unwrap();
+            let read: Document<Value> = db.get("note").await.unwrap();
+            assert_eq!(read.attachments().count(), 0);
+            assert_eq!(read["text"], json!("hi"));
+
+            // a losing revision, stored as given without new_edits, is a conflict of the document
+            let losing = json!({"new_edits": false, "docs": [{"_id": "note", "_rev": "1-aaa", "text": "lost"}]});
+            let response = client
+                .req(Method::POST, format!("{}/_bulk_docs", dbname), None)
+                .json(&losing)
+                .send()
+                .await
+                .unwrap();
+            assert!(response.status().is_success());
+            let query = FindQuery::new(json!({"_id": "note"})).conflicts(true);
+            let mut found = db.find::<Document<Value>>(&query).await.unwrap().rows.remove(0);
+            assert_eq!(found.rev, saved.rev);
+            assert_eq!(found.conflicts(), &[s!("1-aaa")]);
+            assert_eq!(found.clear_conflicts(), vec![s!("1-aaa")]);
+            let mut loser = Document::new("note", json!({}));
+            loser.rev = s!("1-aaa");
+            assert!(db.remove(loser).await);
+            let found = db.find::<Document<Value>>(&query).await.unwrap().rows.remove(0);
+            assert!(!found.has_conflicts());
+
+            let mut deleted = found;
+            deleted.mark_deleted();
+            let tombstone = db.save(deleted).await.unwrap();
+            assert!(tombstone.is_deleted());
+            assert!(db.get::<Value>("note").await.unwrap_err().is_not_found());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_get_bulk_in_chunks() {
+            let dbname = "should_get_bulk_in_chunks";
+            let (client, db, _doc) = setup(dbname).await;
+
+            let ids: Vec<String> = (0..25).map(|idx| format!("gb_{}", idx)).collect();
+            let docs: Vec<Value> = ids.iter().map(|id| json!({"_id": id})).collect();
+            db.bulk_docs(docs).await.unwrap();
+
+            let collection = db.get_bulk_chunked::<Value>(ids.clone(), None, 10, 2).await.unwrap();
+            assert_eq!(collection.total_rows, 25);
+            let found: Vec<String> = collection.rows.iter().map(|d| d.get_id().to_string()).collect();
+            assert_eq!(found, ids);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_upsert_typed_document_over_other_content() {
+            let dbname = "should_upsert_typed_document_over_other_content";
+            let (client, db, doc) = setup(dbname).await;
+
+            // the stored document {"thing": true} does not deserialize into a TestDoc
+            let typed = TestDoc {
+                _id: doc.get_id().to_string(),
+                first_name: "John".to_string(),
+                last_name: "Doe".to_string(),
+                ..Default::default()
+            };
+
+            let saved = db.upsert(typed).await.unwrap();
+            assert!(saved._rev.starts_with('2'));
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_manage_documents_through_repository() {
+            #[derive(Serialize, Deserialize, CouchDocument, Default, Debug)]
+            #[couch(doc_type = "user")]
+            struct User {
+                #[serde(skip_serializing_if = "String::is_empty")]
+                _id: DocumentId,
+                #[serde(skip_serializing_if = "String::is_empty")]
+                _rev: String,
+                name: String,
+            }
+
+            let dbname = "should_manage_documents_through_repository";
+            let (client, db, doc) = setup(dbname).await;
+            let users = db.repository::<User>();
+
+            let john = users
+                .save(User {
+                    name: "John".to_string(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            users
+                .save(User {
+                    name: "Jane".to_string(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+
+            // the document created by setup() is not a user
+            assert_eq!(users.all().await.unwrap().len(), 2);
+            assert!(users.get(&doc.get_id()).await.unwrap_err().is_not_found());
+
+            let found = users.find(json!({"name": "John"})).await.unwrap();
+            assert_eq!(found.len(), 1);
+            assert_eq!(found[0]._id, john._id);
+
+            let stored: Value = db.get(&john._id).await.unwrap();
+            assert_eq!(stored["type"], json!("user"));
+
+            users.delete(john).await.unwrap();
+            assert_eq!(users.all().await.unwrap().len(), 1);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_soft_delete_through_repository() {
+            #[derive(Serialize, Deserialize, CouchDocument, Default, Debug)]
+            #[couch(doc_type = "note")]
+            struct Note {
+                #[serde(skip_serializing_if = "String::is_empty")]
+                _id: DocumentId,
+                #[serde(skip_serializing_if = "String::is_empty")]
+                _rev: String,
+                text: String,
+            }
+
+            let dbname = "should_soft_delete_through_repository";
+            let (client, db, _doc) = setup(dbname).await;
+            let notes = db.repository::<Note>();
+            let note = notes
+                .save(Note {
+                    text: "draft".to_string(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+
+            notes.soft_delete(&note._id).await.unwrap();
+            assert!(notes.all().await.unwrap().is_empty());
+            assert!(notes.get(&note._id).await.unwrap_err().is_not_found());
+
+            let stored: Value = db.get(&note._id).await.unwrap();
+            assert!(stored["deleted_at"].is_u64());
+            assert_eq!(
+                db.repository::<Note>()
+                    .include_soft_deleted()
+                    .all()
+                    .await
+                    .unwrap()
+                    .len(),
+                1
+            );
+
+            let restored = notes.restore(&note._id).await.unwrap();
+            assert_eq!(restored.text, "draft");
+            assert_eq!(notes.find(json!({"text": "draft"})).await.unwrap().len(), 1);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_retry_conflicting_upserts() {
+            let dbname = "should_retry_conflicting_upserts";
+            let (client, db, _doc) = setup(dbname).await;
+
+            let handles: Vec<_> = (0..5)
+                .map(|idx| {
+                    let db = db.clone();
+                    tokio::spawn(async move {
+                        let policy = RetryPolicy::default().max_attempts(20);
+                        db.upsert_with_retry(json!({"_id": "contended", "writer": idx}), policy)
+                            .await
+                    })
+                })
+                .collect();
+
+            for handle in handles {
+                assert!(handle.await.unwrap().is_ok());
+            }
+
+            let doc: Value = db.get("contended").await.unwrap();
+            assert!(doc.get_rev().starts_with('5'));
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_upsert_with_deep_merge() {
+            let dbname = "should_upsert_with_deep_merge";
+            let (client, db, _doc) = setup(dbname).await;
+
+            db.upsert(json!({"_id": "merged", "address": {"city": "Paris", "zip": "75001"}, "tags": ["a"]}))
+                .await
+                .unwrap();
+
+            let policy = MergePolicy::deep().arrays(ArrayMerge::Union).null_removes(true);
+            let update = json!({"_id": "merged", "address": {"city": "Lyon", "zip": null}, "tags": ["a", "b"]});
+            let merged = db.upsert_merge(update, &policy).await.unwrap();
+            assert!(merged.get_rev().starts_with('2'));
+
+            let stored: Value = db.get("merged").await.unwrap();
+            assert_eq!(stored["address"], json!({"city": "Lyon"}));
+            assert_eq!(stored["tags"], json!(["a", "b"]));
+
+            // a missing document is created
+            let created = db
+                .upsert_merge(json!({"_id": "new", "name": "John"}), &MergePolicy::default())
+                .await
+                .unwrap();
+            assert!(created.get_rev().starts_with('1'));
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_patch_document() {
+            let dbname = "should_patch_document";
+            let (client, db, doc) = setup(dbname).await;
+
+            let patched = db
+                .patch(
+                    &doc.get_id(),
+                    &[
+                        PatchOp::test("/thing", json!(true)),
+                        PatchOp::add("/tags", json!(["a"])),
+                        PatchOp::move_to("/thing", "/other"),
+                    ],
+                )
+                .await
+                .unwrap();
+            assert!(patched.get_rev().starts_with('2'));
+
+            let stored: Value = db.get(&doc.get_id()).await.unwrap();
+            assert_eq!(stored["other"], json!(true));
+            assert_eq!(stored["tags"], json!(["a"]));
+            assert!(stored.get("thing").is_none());
+
+            let err = db
+                .patch(&doc.get_id(), &[PatchOp::test("/other", json!(false))])
+                .await
+                .unwrap_err();
+            assert_eq!(err.status(), StatusCode::PRECONDITION_FAILED);
+            assert!(db.patch("missing", &[]).await.unwrap_err().is_not_found());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_merge_patch_document() {
+            let dbname = "should_merge_patch_document";
+            let (client, db, doc) = setup(dbname).await;
+
+            let patched = db
+                .merge_patch(&doc.get_id(), json!({"thing": null, "address": {"city": "Paris"}}))
+                .await
+                .unwrap();
+            assert!(patched.get_rev().starts_with('2'));
+
+            let stored: Value = db.get(&doc.get_id()).await.unwrap();
+            assert!(stored.get("thing").is_none());
+            assert_eq!(stored["address"], json!({"city": "Paris"}));
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_get_document_as_any_type() {
+            #[derive(serde::Deserialize, Debug)]
+            struct Thing {
+                #[serde(rename = "_rev")]
+                rev: String,
+                thing: bool,
+            }
+
+            let dbname = "should_get_document_as_any_type";
+            let (client, db, doc) = setup(dbname).await;
+
+            let thing: Thing = db.get_as(&doc.get_id()).await.unwrap();
+            assert!(thing.thing);
+            assert_eq!(thing.rev, doc.get_rev());
+            assert!(db.get_as::<Thing>("not_there").await.unwrap_err().is_not_found());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_find_documents_as_any_type() {
+            #[derive(serde::Deserialize, Debug)]
+            struct Thing {
+                thing: bool,
+            }
+
+            let dbname = "should_find_documents_as_any_type";
+            let (client, db, _) = setup(dbname).await;
+
+            let query = FindQuery::new(json!({"thing": true})).fields(vec![s!("thing")]);
+            let found = db.find_as::<Thing>(&query).await.unwrap();
+            assert_eq!(found.docs.len(), 1);
+            assert!(found.docs[0].thing);
+            assert!(found.bookmark.is_some());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_bulk_get_a_document() {
+            let (client, db, doc) = setup("should_bulk_get_a_document").await;
+            let id = doc.get_id().into_owned();
+
+            let collection = db.get_bulk_raw(vec![id]).await.unwrap();
+            assert_eq!(collection.rows.len(), 1);
+            assert!(db.remove(doc).await);
+
+            teardown(client, "should_bulk_get_a_document").await;
+        }
+
+        #[tokio::test]
+        async fn should_bulk_get_invalid_documents() {
+            let (client, db, doc) = setup("should_bulk_get_invalid_documents").await;
+            let id = doc.get_id().into_owned();
+            let invalid_id = "does_not_exist".to_string();
+
+            let collection = db.get_bulk_raw(vec![id, invalid_id]).await.unwrap();
+            assert_eq!(collection.rows.len(), 1);
+            assert!(db.remove(doc).await);
+
+            teardown(client, "should_bulk_get_invalid_documents").await;
+        }
+
+        #[tokio::test]
+        async fn should_query_many_design_docs() {
+            let dbname = "should_query_many_design_docs";
+            let (client, db, _doc) = setup(dbname).await;
+            for id in ["_design/car-a", "_design/car-b", "_design/boat-a"] {
+                db.create(json!({ "_id": id })).await.unwrap();
+            }
+
+            let cars = QueryParams::default()
+                .start_key("_design/car")
+                .end_key("_design/car\u{fff0}");
+            let boats = QueryParams::default().key("_design/boat-a").include_docs(true);
+            let all = QueryParams::default();
+            let results = db
+                .query_many_design_docs(QueriesParams::new(vec![cars, boats, all]))
+                .await
+                .unwrap();
+            let ids = |n: usize| {
+                results[n]
+                    .rows
+                    .iter()
+                    .filter_map(|row| row.id.clone())
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(ids(0), vec!["_design/car-a", "_design/car-b"]);
+            assert_eq!(ids(1), vec!["_design/boat-a"]);
+            assert!(results[1].rows[0].doc.is_some());
+            assert_eq!(ids(2), vec!["_design/boat-a", "_design/car-a", "_design/car-b"]);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_list_local_documents() {
+            use crate::types::changes::Seq;
+
+            let dbname = "should_list_local_documents";
+            let (client, db, _doc) = setup(dbname).await;
+            db.save_checkpoint("sync-1", &Seq::from("1-a")).await.unwrap();
+            db.save_checkpoint("sync-2", &Seq::from("2-b")).await.unwrap();
+
+            let local = db.get_local_docs::<Value>(None).await.unwrap();
+            assert_eq!(local.ids().collect::<Vec<_>>(), vec!["_local/sync-1", "_local/sync-2"]);
+            assert_eq!(local[1]["seq"], "2-b");
+
+            let first = QueryParams::default().limit(1).include_docs(false);
+            let local = db.get_local_docs::<Value>(Some(first)).await.unwrap();
+            assert_eq!(local.rows.len(), 1);
+            assert_eq!(local[0].get("seq"), None);
+
+            // local documents are not replicated, and not listed with the other documents
+            assert!(db.remove(local.into_rows().remove(0)).await);
+            assert_eq!(db.get_local_docs::<Value>(None).await.unwrap().rows.len(), 1);
+            assert_eq!(db.get_all::<Value>().await.unwrap().rows.len(), 1);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_get_all_documents_with_keys() {
+            let (client, db, doc) = setup("should_get_all_documents_with_keys").await;
+            let id = doc.get_id().into_owned();
+
+            let params = QueryParams::from_keys(vec![id]);
+
+            let collection = db.get_all_params_raw(Some(params)).await.unwrap();
+            assert_eq!(collection.rows.len(), 1);
+            assert!(db.remove(doc).await);
+
+            teardown(client, "should_get_all_documents_with_keys").await;
+        }
+
+        #[tokio::test]
+        async fn should_include_design_docs_in_all_docs_on_request() {
+            use futures_util::stream::TryStreamExt;
+
+            let dbname = "should_include_design_docs_in_all_docs_on_request";
+            let (client, db, doc) = setup(dbname).await;
+            let design = db.create(json!({"_id": "_design/app", "views": {}})).await.unwrap();
+            let ids = |docs: &DocumentCollection<Value>| docs.ids().map(|id| id.into_owned()).collect::<Vec<_>>();
+
+            let default = db.get_all_params::<Value>(None).await.unwrap();
+            assert_eq!(ids(&default), vec![doc.get_id().into_owned()]);
+            let included = db
+                .get_all_params::<Value>(Some(QueryParams::default().include_design_docs(true)))
+                .await
+                .unwrap();
+            assert!(ids(&included).contains(&design.get_id().into_owned()));
+            assert_eq!(included.rows.len(), 2);
+
+            let streamed: Vec<Value> = db
+                .get_all_stream(Some(QueryParams::default().include_design_docs(true)))
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(streamed.len(), 2);
+
+            let bulk_ids = vec![s!("_design/app"), doc.get_id().into_owned()];
+            let bulk = db.get_bulk_params::<Value>(bulk_ids.clone(), None).await.unwrap();
+            assert_eq!(bulk.rows.len(), 1);
+            let bulk = db
+                .get_bulk_params::<Value>(bulk_ids, Some(QueryParams::default().include_design_docs(true)))
+                .await
+                .unwrap();
+            assert_eq!(bulk.rows.len(), 2);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_wait_for_view_and_index_builds() {
+            let dbname = "should_wait_for_view_and_index_builds";
+            let (client, db, _docs) = setup_multiple(dbname, 100).await;
+            let timeout = std::time::Duration::from_secs(30);
+
+            db.create_view(
+                "warm",
+                CouchViews::new(
+                    "by_id",
+                    CouchFunc {
+                        map: "function(doc) { emit(doc._id, null); }".to_string(),
+                        reduce: None,
+                    },
+                ),
+            )
+            .await
+            .unwrap();
+            db.wait_for_view("warm", "by_id", timeout).await.unwrap();
+
+            db.ensure_index(
+                "thing-index",
+                types::index::IndexFields::new(vec![types::find::SortSpec::Simple(s!("thing"))]),
+            )
+            .await
+            .unwrap();
+            db.wait_for_index("thing-index", timeout).await.unwrap();
+            assert!(db.wait_for_index("missing", timeout).await.unwrap_err().is_not_found());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_manage_design_documents() {
+            let dbname = "should_manage_design_documents";
+            let (client, db, _doc) = setup(dbname).await;
+
+            let design = DesignDocument::new("managed")
+                .view(
+                    "by_thing",
+                    CouchFunc::new("function(doc) { emit(doc.thing, null); }", None),
+                )
+                .update(
+                    "touch",
+                    "function(doc, req) { doc.touched = true; return [doc, 'ok']; }",
+                )
+                .validate_doc_update("function(newDoc) { if (newDoc.forbidden) throw({forbidden: 'no'}); }");
+            let saved = db.put_design(design.clone()).await.unwrap();
+            assert!(saved.rev.starts_with('1'));
+
+            // putting it again replaces the current revision
+            let saved = db
+                .put_design(design.show("detail", "function(doc) { return doc._id; }"))
+                .await
+                .unwrap();
+            assert!(saved.rev.starts_with('2'));
+
+            let stored = db.get_design("managed").await.unwrap();
+            assert_eq!(stored.views, saved.views);
+            assert!(stored.shows.contains_key("detail"));
+
+            let err = db.create(json!({"forbidden": true})).await.unwrap_err();
+            assert_eq!(err.status(), StatusCode::FORBIDDEN);
+            assert_eq!(err.validation_message(), Some("no"));
+
+            db.delete_design("managed").await.unwrap();
+            assert!(db.get_design("managed").await.unwrap_err().is_not_found());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_search_nouveau_indexes_when_enabled() {
+            let dbname = "should_search_nouveau_indexes_when_enabled";
+            let (client, db, _doc) = setup(dbname).await;
+            db.create(json!({"name": "John"})).await.unwrap();
+            db.create(json!({"name": "Jane"})).await.unwrap();
+
+            let index = NouveauIndex::new(
+                "function(doc) { if (doc.name) { index('text', 'name', doc.name, {store: true}); } }",
+            );
+            db.deploy_design(DesignDocument::new("people").nouveau_index("by_name", index))
+                .await
+                .unwrap();
+
+            let query = NouveauQuery::new("name:john").include_docs(true);
+            let found = db.nouveau_search::<Value>("people", "by_name", &query).await;
+            if client.supports_nouveau().await.unwrap() {
+                let found = found.unwrap();
+                assert_eq!(found.total_hits, 1);
+                assert_eq!(found.hits[0].fields["name"], json!("John"));
+                assert_eq!(found.hits[0].doc.as_ref().unwrap()["name"], json!("John"));
+            } else {
+                assert_eq!(found.unwrap_err().status(), StatusCode::NOT_IMPLEMENTED);
+            }
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_query_views_with_builtin_reducers() {
+            let dbname = "should_query_views_with_builtin_reducers";
+            let (client, db, _docs) = setup_multiple(dbname, 4).await;
+
+            let mut views = CouchViews::new(
+                "count",
+                MapFunction::new()
+                    .when("doc.thing")
+                    .emit("doc.thing", "1")
+                    .reduce(BuiltinReduce::Count),
+            );
+            views.add(
+                "stats",
+                MapFunction::new().emit_field("thing", "1").reduce(BuiltinReduce::Stats),
+            );
+            db.create_view("reducers", views).await.unwrap();
+
+            let count: RawViewCollection<Value, u64> = db.query("reducers", "count", None).await.unwrap();
+            assert_eq!(count.rows[0].value, 4);
+
+            let stats: RawViewCollection<Value, Value> = db.query("reducers", "stats", None).await.unwrap();
+            assert_eq!(stats.rows[0].value["sum"], json!(4));
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_execute_show_function() {
+            let dbname = "should_execute_show_function";
+            let (client, db, doc) = setup(dbname).await;
+
+            let design = DesignDocument::new("render").show(
+                "thing",
+                "function(doc, req) { return {code: doc ? 200 : 404, headers: {'X-Thing': 'yes'}, \
+                 body: doc ? String(doc.thing) + req.query.suffix : 'none'}; }",
+            );
+            db.put_design(design).await.unwrap();
+
+            let mut params = HashMap::new();
+            params.insert(s!("suffix"), s!("!"));
+            let response = db
+                .execute_show("render", "thing", Some(&doc.get_id()), Some(params))
+                .await
+                .unwrap();
+            assert_eq!(response.status, StatusCode::OK);
+            assert_eq!(response.headers["X-Thing"], "yes");
+            assert_eq!(response.body, "true!");
+
+            let response = db.execute_show("render", "thing", None, None).await.unwrap();
+            assert_eq!(response.status, StatusCode::NOT_FOUND);
+            assert_eq!(response.body, "none");
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_execute_list_function() {
+            let dbname = "should_execute_list_function";
+            let (client, db, _docs) = setup_multiple(dbname, 3).await;
+
+            let design = DesignDocument::new("render")
+                .view(
+                    "things",
+                    MapFunction::new().when("doc.thing").emit("doc._id", "null").into(),
+                )
+                .list(
+                    "count",
+                    "function(head, req) { start({headers: {'Content-Type': 'text/plain'}}); \
+                     var n = 0; while (getRow()) { n++; } send(String(n)); }",
+                );
+            db.put_design(design).await.unwrap();
+
+            let mut params = HashMap::new();
+            params.insert(s!("limit"), s!("2"));
+            let response = db
+                .execute_list("render", "count", "things", Some(params))
+                .await
+                .unwrap();
+            assert_eq!(response.status, StatusCode::OK);
+            assert_eq!(response.content_type(), Some("text/plain"));
+            assert_eq!(response.body, "2");
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_execute_update_handlers_with_and_without_id() {
+            let dbname = "should_execute_update_handlers_with_and_without_id";
+            let (client, db, doc) = setup(dbname).await;
+
+            let design = DesignDocument::new("handlers").update(
+                "stamp",
+                "function(doc, req) { if (!doc) { doc = {_id: req.uuid}; } doc.stamped = true; return [doc, 'stamped']; }",
+            );
+            db.put_design(design).await.unwrap();
+
+            let response = db
+                .execute_update_detailed("handlers", "stamp", Some(&doc.get_id()), None)
+                .await
+                .unwrap();
+            assert_eq!(response.body, "stamped");
+            assert_eq!(response.id.as_deref(), Some(doc.get_id().as_ref()));
+            assert!(response.new_rev.unwrap().starts_with('2'));
+
+            let response = db
+                .execute_update_detailed("handlers", "stamp", None, None)
+                .await
+                .unwrap();
+            let created: Value = db.get(&response.id.unwrap()).await.unwrap();
+            assert_eq!(created["stamped"], json!(true));
+            assert_eq!(created.get_rev(), response.new_rev.unwrap());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_deploy_design_documents_only_when_changed() {
+            let dbname = "should_deploy_design_documents_only_when_changed";
+            let (client, db, _doc) = setup(dbname).await;
+
+            let design = DesignDocument::new("deployed").view("ids", MapFunction::new().emit("doc._id", "null").into());
+            assert_eq!(
+                db.deploy_design(design.clone()).await.unwrap(),
+                DesignDeployment::Created
+            );
+            assert_eq!(
+                db.deploy_design(design.clone()).await.unwrap(),
+                DesignDeployment::Unchanged
+            );
+
+            let changed = design.view("things", MapFunction::new().emit_field("thing", "1").into());
+            assert_eq!(db.deploy_design(changed).await.unwrap(), DesignDeployment::Updated);
+            assert!(db.get_design("deployed").await.unwrap().rev.starts_with('2'));
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_query_grouped_counts_per_key_level() {
+            let dbname = "should_query_grouped_counts_per_key_level";
+            let (client, db, _doc) = setup(dbname).await;
+
+            let dates = vec![json!([2020, 1, 5]), json!([2020, 2, 1]), json!([2021, 1, 1])];
+            for date in dates {
+                db.create(json!({ "date": date })).await.unwrap();
+            }
+            let views = CouchViews::new(
+                "by_date",
+                MapFunction::new()
+                    .when("doc.date")
+                    .emit("doc.date", "1")
+                    .reduce(BuiltinReduce::Count),
+            );
+            db.create_view("dates", views).await.unwrap();
+
+            let per_year: RawViewCollection<Value, u64> = db
+                .query("dates", "by_date", Some(QueryParams::default().group_level(1)))
+                .await
+                .unwrap();
+            let counts: Vec<(Value, u64)> = per_year.rows.into_iter().map(|row| (row.key, row.value)).collect();
+            assert_eq!(counts, vec![(json!([2020]), 2), (json!([2021]), 1)]);
+
+            let err = db
+                .query::<Value, Value, Value>(
+                    "dates",
+                    "by_date",
+                    Some(QueryParams::default().group(true).reduce(false)),
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_query_views_with_non_string_keys() {
+            let dbname = "should_query_views_with_non_string_keys";
+            let (client, db, _doc) = setup(dbname).await;
+
+            for score in 1..=5 {
+                db.create(json!({ "score": score, "tags": [score % 2, score] }))
+                    .await
+                    .unwrap();
+            }
+            let mut views = CouchViews::new(
+                "by_score",
+                MapFunction::new().when("doc.score").emit("doc.score", "null").into(),
+            );
+            views.add(
+                "by_tags",
+                MapFunction::new().when("doc.tags").emit("doc.tags", "null").into(),
+            );
+            db.create_view("keys", views).await.unwrap();
+
+            let range: RawViewCollection<u32, Value> = db
+                .query("keys", "by_score", Some(QueryParams::default().start_key(2).end_key(4)))
+                .await
+                .unwrap();
+            assert_eq!(range.rows.iter().map(|row| row.key).collect::<Vec<_>>(), vec![2, 3, 4]);
+
+            let odd: RawViewCollection<Value, Value> = db
+                .query(
+                    "keys",
+                    "by_tags",
+                    Some(QueryParams::default().start_key(json!([1])).end_key(json!([1, {}]))),
+                )
+                .await
+                .unwrap();
+            assert_eq!(odd.rows.len(), 3);
+
+            let exact: RawViewCollection<u32, Value> = db
+                .query("keys", "by_score", Some(QueryParams::from_keys(vec![5, 1])))
+                .await
+                .unwrap();
+            assert_eq!(exact.rows.iter().map(|row| row.key).collect::<Vec<_>>(), vec![5, 1]);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_query_views_by_key_prefix() {
+            let dbname = "should_query_views_by_key_prefix";
+            let (client, db, _doc) = setup(dbname).await;
+
+            let dates = vec![
+                json!([2021, 2, 28]),
+                json!([2021, 3, 1]),
+                json!([2021, 3, 15]),
+                json!([2021, 4, 1]),
+            ];
+            for date in dates {
+                db.create(json!({ "date": date })).await.unwrap();
+            }
+            let views = CouchViews::new(
+                "by_date",
+                MapFunction::new().when("doc.date").emit("doc.date", "null").into(),
+            );
+            db.create_view("dates", views).await.unwrap();
+
+            let march: RawViewCollection<Value, Value> = db
+                .query(
+                    "dates",
+                    "by_date",
+                    Some(QueryParams::default().key_prefix(vec![2021, 3])),
+                )
+                .await
+                .unwrap();
+            let keys: Vec<Value> = march.rows.into_iter().map(|row| row.key).collect();
+            assert_eq!(keys, vec![json!([2021, 3, 1]), json!([2021, 3, 15])]);
+
+            let descending: RawViewCollection<Value, Value> = db
+                .query(
+                    "dates",
+                    "by_date",
+                    Some(QueryParams::default().descending(true).key_prefix(vec![2021, 3])),
+                )
+                .await
+                .unwrap();
+            assert_eq!(descending.rows[0].key, json!([2021, 3, 15]));
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_query_views_into_typed_rows() {
+            #[derive(Deserialize, Debug, PartialEq)]
+            struct Order {
+                customer: String,
+                total: u32,
+            }
+
+            let dbname = "should_query_views_into_typed_rows";
+            let (client, db, _doc) = setup(dbname).await;
+
+            db.create(json!({"customer": "john", "month": [2021, 3], "total": 10}))
+                .await
+                .unwrap();
+            db.create(json!({"customer": "jane", "month": [2021, 4], "total": 20}))
+                .await
+                .unwrap();
+            let views = CouchViews::new(
+                "by_month",
+                MapFunction::new()
+                    .when("doc.month")
+                    .emit("doc.month", "doc.total")
+                    .into(),
+            );
+            db.create_view("orders", views).await.unwrap();
+
+            let orders: ViewCollection<(u32, u32), u32, Order> = db
+                .query("orders", "by_month", Some(QueryParams::default().include_docs(true)))
+                .await
+                .unwrap();
+            assert_eq!(orders.rows[0].key, (2021, 3));
+            assert_eq!(orders.rows[1].value, 20);
+            let customers: Vec<String> = orders.into_docs().into_iter().map(|order| order.customer).collect();
+            assert_eq!(customers, vec!["john", "jane"]);
+
+            let many: Vec<ViewCollection<(u32, u32), u32, Value>> = db
+                .query_many_as(
+                    "orders",
+                    "by_month",
+                    QueriesParams::new(vec![QueryParams::default().key(json!([2021, 4]))]),
+                )
+                .await
+                .unwrap();
+            assert_eq!(many[0].rows[0].value, 20);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_stream_view_rows() {
+            use futures_util::stream::TryStreamExt;
+
+            let dbname = "should_stream_view_rows";
+            let (client, db, _docs) = setup_multiple(dbname, 50).await;
+            let views = CouchViews::new(
+                "things",
+                MapFunction::new().when("doc.thing").emit("doc._id", "doc.thing").into(),
+            );
+            db.create_view("streamed", views).await.unwrap();
+
+            let rows: Vec<ViewItem<String, bool, Value>> = db
+                .query_stream("streamed", "things", Some(QueryParams::default().include_docs(true)))
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(rows.len(), 50);
+            assert!(rows.iter().all(|row| row.value && row.doc.is_some()));
+
+            assert!(db
+                .query_stream::<Value, Value, Value>("streamed", "missing", None)
+                .await
+                .is_err());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_read_stale_indexes() {
+            let dbname = "should_read_stale_indexes";
+            let (client, db, _docs) = setup_multiple(dbname, 3).await;
+            let views = CouchViews::new(
+                "things",
+                MapFunction::new().when("doc.thing").emit("doc._id", "null").into(),
+            );
+            db.create_view("fresh", views).await.unwrap();
+
+            let fresh: RawViewCollection<Value, Value> = db
+                .query(
+                    "fresh",
+                    "things",
+                    Some(QueryParams::default().freshness(Freshness::Fresh)),
+                )
+                .await
+                .unwrap();
+            assert_eq!(fresh.rows.len(), 3);
+
+            // the new document is not in the index, until it is updated after responding
+            db.create(json!({"thing": true})).await.unwrap();
+            let stale: RawViewCollection<Value, Value> = db
+                .query(
+                    "fresh",
+                    "things",
+                    Some(QueryParams::default().freshness(Freshness::UpdateAfter)),
+                )
+                .await
+                .unwrap();
+            assert_eq!(stale.rows.len(), 3);
+
+            let found = db
+                .find_raw(&FindQuery::new(json!({"thing": true})).freshness(Freshness::Stale))
+                .await
+                .unwrap();
+            assert_eq!(found.rows.len(), 4);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_paginate_with_bookmarks() {
+            let dbname = "should_paginate_with_bookmarks";
+            let (client, db, _docs) = setup_multiple(dbname, 12).await;
+
+            let mut paginator = db.find_paginator::<Value>(FindQuery::new(json!({"thing": true})), 5);
+            let mut sizes = vec![];
+            while let Some(page) = paginator.next_page().await.unwrap() {
+                sizes.push(page.rows.len());
+            }
+            assert_eq!(sizes, vec![5, 5, 2]);
+            assert!(!paginator.has_more());
+            assert!(paginator.has_previous());
+
+            let previous = paginator.prev_page().await.unwrap().unwrap();
+            assert_eq!(previous.rows.len(), 5);
+            assert!(paginator.has_more());
+
+            let mut paginator = db.all_docs_paginator::<Value>(None, 4);
+            let first = paginator.next_page().await.unwrap().unwrap();
+            let second = paginator.next_page().await.unwrap().unwrap();
+            assert_eq!(paginator.bookmark(), second.bookmark.as_deref());
+            let again = paginator.prev_page().await.unwrap().unwrap();
+            assert_eq!(first.rows, again.rows);
+            assert!(paginator.prev_page().await.unwrap().is_none());
+
+            let mut pages = 1;
+            while paginator.next_page().await.unwrap().is_some() {
+                pages += 1;
+            }
+            assert_eq!(pages, 3);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_stop_batches_when_receiver_hangs_up() {
+            let dbname = "should_stop_batches_when_receiver_hangs_up";
+            let (client, db, _docs) = setup_multiple(dbname, 7).await;
+
+            let (tx, mut rx) = mpsc::channel(10);
+            let sent = db.find_batched::<Value>(FindQuery::find_all(), tx, 3, 4).await.unwrap();
+            assert_eq!(sent, 6);
+            assert_eq!(rx.recv().await.unwrap().rows.len(), 3);
+
+            let (tx, mut rx) = mpsc::channel(1);
+            let reader = db.clone();
+            let task = tokio::spawn(async move { reader.find_batched::<Value>(FindQuery::find_all(), tx, 2, 0).await });
+            assert_eq!(rx.recv().await.unwrap().rows.len(), 2);
+            drop(rx);
+
+            let err = task.await.unwrap().unwrap_err();
+            assert_eq!(err.status(), StatusCode::GONE);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_scan_all_docs_in_partitions() {
+            use futures_util::stream::TryStreamExt;
+
+            let dbname = "should_scan_all_docs_in_partitions";
+            let (client, db, docs) = setup_multiple(dbname, 20).await;
+
+            let batches: Vec<DocumentCollection<Value>> =
+                db.scan_all_docs(4, 3).await.unwrap().try_collect().await.unwrap();
+            let ids: HashSet<String> = batches
+                .into_iter()
+                .flat_map(|batch| batch.rows)
+                .map(|doc| doc["_id"].as_str().unwrap().to_string())
+                .collect();
+            assert_eq!(ids.len(), docs.len());
+
+            // more partitions than documents
+            let batches: Vec<DocumentCollection<Value>> =
+                db.scan_all_docs(50, 100).await.unwrap().try_collect().await.unwrap();
+            assert_eq!(batches.iter().map(|batch| batch.rows.len()).sum::<usize>(), 20);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_stream_all_docs() {
+            use futures_util::stream::TryStreamExt;
+
+            let dbname = "should_stream_all_docs";
+            let (client, db, docs) = setup_multiple(dbname, 15).await;
+            db.create_view(
+                "streamed",
+                CouchViews::new("things", MapFunction::new().emit("doc._id", "null").into()),
+            )
+            .await
+            .unwrap();
+
+            // the design document is skipped
+            let streamed: Vec<Value> = db.get_all_stream(None).await.unwrap().try_collect().await.unwrap();
+            assert_eq!(streamed.len(), docs.len());
+            assert!(streamed.iter().all(|doc| doc["thing"] == json!(true)));
+
+            let limited: Vec<Value> = db
+                .get_all_stream(Some(QueryParams::default().limit(5)))
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(limited.len(), 5);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_get_ids_and_revs_without_docs() {
+            let dbname = "should_get_ids_and_revs_without_docs";
+            let (client, db, docs) = setup_multiple(dbname, 3).await;
+
+            let all = db
+                .get_all_params_raw(Some(QueryParams::default().include_docs(false)))
+                .await
+                .unwrap();
+            assert_eq!(all.rows.len(), 3);
+            assert!(all
+                .rows
+                .iter()
+                .all(|doc| doc.get("thing").is_none() && doc["_rev"].is_string()));
+
+            let ids = docs
+                .iter()
+                .map(|doc| doc["_id"].as_str().unwrap().to_string())
+                .collect();
+            let bulk = db
+                .get_bulk_params::<Value>(ids, Some(QueryParams::default().include_docs(false)))
+                .await
+                .unwrap();
+            assert_eq!(bulk.rows[0]["_rev"], docs[0]["_rev"]);
+            assert!(bulk.rows[0].get("thing").is_none());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_return_update_seq() {
+            let dbname = "should_return_update_seq";
+            let (client, db, _docs) = setup_multiple(dbname, 2).await;
+            let info = client.get_info(dbname).await.unwrap();
+
+            let all = db
+                .get_all_params_raw(Some(QueryParams::default().update_seq(true)))
+                .await
+                .unwrap();
+            assert_eq!(all.update_seq, Some(info.update_seq));
+
+            db.create_view(
+                "seq",
+                CouchViews::new("things", MapFunction::new().emit("doc._id", "null").into()),
+            )
+            .await
+            .unwrap();
+            let view: RawViewCollection<Value, Value> = db
+                .query("seq", "things", Some(QueryParams::default().update_seq(true)))
+                .await
+                .unwrap();
+            assert!(view.update_seq.is_some());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_read_changes_with_feed_options() {
+            use std::time::{Duration, Instant};
+
+            let dbname = "should_read_changes_with_feed_options";
+            let (client, db, doc) = setup(dbname).await;
+            let second = db.create(json!({"thing": false})).await.unwrap();
+
+            let all = db.changes(&ChangesOptions::default().include_docs(true)).await.unwrap();
+            assert_eq!(all.results.len(), 2);
+            assert_eq!(all.pending, Some(0));
+            assert!(all.results.iter().all(|change| change.doc.is_some()));
+
+            let page = db.changes(&ChangesOptions::default().limit(1)).await.unwrap();
+            assert_eq!(page.results.len(), 1);
+            assert_eq!(page.pending, Some(1));
+            let rest = db
+                .changes(&ChangesOptions::default().since(page.last_seq))
+                .await
+                .unwrap();
+            assert_eq!(rest.results.len(), 1);
+            assert_ne!(rest.results[0].id, page.results[0].id);
+
+            let options = ChangesOptions::default().doc_ids(vec![doc.get_id().to_string()]);
+            let by_id = db.changes(&options).await.unwrap();
+            assert_eq!(by_id.results.len(), 1);
+            assert_eq!(by_id.results[0].id, doc.get_id());
+            let options = ChangesOptions::default().selector(json!({"thing": false}));
+            let selected = db.changes(&options).await.unwrap();
+            assert_eq!(selected.results.len(), 1);
+            assert_eq!(selected.results[0].id, second.get_id());
+
+            let sampled = db.changes(&ChangesOptions::default().seq_interval(2)).await.unwrap();
+            assert_eq!(sampled.results.len(), 2);
+
+            // a longpoll from now returns nothing when the timeout passes without changes
+            let started = Instant::now();
+            let options = ChangesOptions::default()
+                .since_now()
+                .longpoll(true)
+                .timeout(Duration::from_millis(500));
+            assert!(db.changes(&options).await.unwrap().results.is_empty());
+            assert!(started.elapsed() >= Duration::from_millis(500));
+
+            // with a heartbeat, it waits for the next change
+            let writer = db.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                writer.create(json!({"thing": "later"})).await.unwrap();
+            });
+            let options = ChangesOptions::default()
+                .since_now()
+                .longpoll(true)
+                .heartbeat(Duration::from_millis(100))
+                .include_docs(true);
+            let later = db.changes(&options).await.unwrap();
+            assert_eq!(later.results.len(), 1);
+            assert_eq!(later.results[0].doc.as_ref().unwrap()["thing"], json!("later"));
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_list_all_leaves_in_all_docs_style_changes() {
+            use reqwest::Method;
+
+            let dbname = "should_list_all_leaves_in_all_docs_style_changes";
+            let (client, db, doc) = setup(dbname).await;
+
+            // without new_edits, the revisions are stored as given, branching the revision tree
+            let leaves = json!({"new_edits": false, "docs": [
+                {"_id": "conflicted", "_rev": "1-aaa", "value": 1},
+                {"_id": "conflicted", "_rev": "1-bbb", "value": 2},
+                {"_id": "conflicted", "_rev": "1-ccc", "_deleted": true},
+                {"_id": "deleted_conflict", "_rev": "1-aaa", "value": 1},
+                {"_id": "deleted_conflict", "_rev": "1-bbb", "_deleted": true}
+            ]});
+            let response = client
+                .req(Method::POST, format!("{}/_bulk_docs", dbname), None)
+                .json(&leaves)
+                .send()
+                .await
+                .unwrap();
+            assert!(response.status().is_success());
+
+            let options = ChangesOptions::default().style(ChangesStyle::AllDocs);
+            let changes = db.changes(&options).await.unwrap();
+            let change = |id: &str| changes.results.iter().find(|change| change.id == id).unwrap();
+            assert!(!change(doc.get_id().as_ref()).has_multiple_leaves());
+            let mut revs = change("conflicted").revs();
+            revs.sort_unstable();
+            assert_eq!(revs, vec!["1-aaa", "1-bbb", "1-ccc"]);
+            assert!(change("deleted_conflict").has_multiple_leaves());
+
+            let main_only = db.changes(&ChangesOptions::default()).await.unwrap();
+            assert!(main_only.results.iter().all(|change| !change.has_multiple_leaves()));
+
+            // a deleted leaf is not a conflict of the document
+            let mut query = HashMap::new();
+            query.insert(s!("conflicts"), s!("true"));
+            let stored: Value = client
+                .req(Method::GET, format!("{}/deleted_conflict", dbname), Some(query))
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            assert!(stored.get("_conflicts").is_none());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_resume_changes_from_checkpoints() {
+            let dbname = "should_resume_changes_from_checkpoints";
+            let (client, db, _doc) = setup(dbname).await;
+            assert_eq!(db.get_checkpoint("indexer").await.unwrap(), None);
+
+            let first = db.changes(&ChangesOptions::default()).await.unwrap();
+            db.save_checkpoint("indexer", &first.last_seq).await.unwrap();
+            assert_eq!(
+                db.get_checkpoint("indexer").await.unwrap(),
+                Some(first.last_seq.clone())
+            );
+
+            let later = db.create(json!({"thing": "later"})).await.unwrap();
+            let since = db.get_checkpoint("indexer").await.unwrap().unwrap();
+            let resumed = db.changes(&ChangesOptions::default().since(since)).await.unwrap();
+            assert_eq!(resumed.results.len(), 1);
+            assert_eq!(resumed.results[0].id, later.get_id());
+            assert_ne!(resumed.last_seq, first.last_seq);
+
+            // saving again replaces the checkpoint, and it is not part of the changes
+            db.save_checkpoint("indexer", &resumed.last_seq).await.unwrap();
+            assert_eq!(db.get_checkpoint("indexer").await.unwrap(), Some(resumed.last_seq));
+            let all = db.changes(&ChangesOptions::default()).await.unwrap();
+            assert_eq!(all.results.len(), 2);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_export_and_restore_with_attachments() {
+            use crate::types::export::ExportOptions;
+
+            let dbname = "should_export_and_restore_with_attachments";
+            let restored = "should_export_and_restore_with_attachments_restored";
+            let (client, db, doc) = setup(dbname).await;
+            let _ = client.destroy_db(restored).await;
+            db.create(json!({
+                "_id": "with_attachment",
+                "_attachments": {
+                    "notes.txt": {"content_type": "text/plain", "data": base64::encode("likes tea")}
+                }
+            }))
+            .await
+            .unwrap();
+            db.create(json!({"_id": "_design/app"})).await.unwrap();
+
+            let mut export = vec![];
+            let options = ExportOptions::default().attachments(true);
+            let summary = db.export(&mut export, &options).await.unwrap();
+            assert_eq!((summary.doc_count, summary.attachment_count), (3, 1));
+
+            let target = client.db(restored).await.unwrap();
+            let summary = target.restore(export.as_slice()).await.unwrap();
+            assert_eq!(summary.doc_count, 3);
+            assert!(summary.failures.is_empty());
+            // the revisions are kept, so restoring again changes nothing
+            assert!(target.restore(export.as_slice()).await.unwrap().failures.is_empty());
+
+            let with_content = QueryParams::default().include_design_docs(true).attachments(true);
+            let copied = target.get_all_params_raw(Some(with_content.clone())).await.unwrap();
+            let original = db.get_all_params_raw(Some(with_content)).await.unwrap();
+            assert_eq!(copied.rows, original.rows);
+            assert!(copied.rows.iter().any(|copy| copy["_rev"] == doc["_rev"]));
+
+            // without attachments, the documents are exported without them
+            let mut export = vec![];
+            let summary = db.export(&mut export, &ExportOptions::default()).await.unwrap();
+            assert_eq!((summary.doc_count, summary.attachment_count), (3, 0));
+            assert!(!String::from_utf8(export).unwrap().contains("_attachments"));
+
+            teardown(client.clone(), restored).await;
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_export_a_snapshot_while_the_database_is_written() {
+            use crate::types::changes::ChangesOptions;
+            use crate::types::export::ExportOptions;
+            use std::sync::atomic::{AtomicBool, Ordering};
+            use std::sync::Arc;
+
+            let dbname = "should_export_a_snapshot_while_the_database_is_written";
+            let restored = "should_export_a_snapshot_while_the_database_is_written_restored";
+            let (client, db, _doc) = setup_multiple(dbname, 50).await;
+            let _ = client.destroy_db(restored).await;
+
+            // update and delete documents for as long as the export runs
+            let done = Arc::new(AtomicBool::new(false));
+            let writer = {
+                let (db, done) = (db.clone(), done.clone());
+                tokio::spawn(async move {
+                    let mut written = 0;
+                    while !done.load(Ordering::SeqCst) {
+                        for mut doc in db.get_all::<Value>().await.unwrap().into_rows().into_iter().take(5) {
+                            doc["thing"] = json!(written);
+                            if written % 3 == 0 {
+                                db.remove(doc).await;
+                            } else {
+                                let _ = db.save(doc).await;
+                            }
+                            db.create(json!({ "thing": written })).await.unwrap();
+                            written += 1;
+                        }
+                    }
+                })
+            };
+
+            let mut export = vec![];
+            let options = ExportOptions::default().snapshot(true);
+            let summary = db.export(&mut export, &options).await.unwrap();
+            done.store(true, Ordering::SeqCst);
+            writer.await.unwrap();
+            let seq = summary.seq.expect("a snapshot has a sequence");
+
+            // a document is only exported again when it changed after it was read
+            let lines: Vec<(String, String)> = export
+                .split(|byte| *byte == b'\n')
+                .filter(|line| !line.is_empty())
+                .map(|line| serde_json::from_slice::<Value>(line).unwrap())
+                .map(|doc| {
+                    (
+                        doc["_id"].as_str().unwrap().to_string(),
+                        doc["_rev"].as_str().unwrap().to_string(),
+                    )
+                })
+                .collect();
+            let ids: HashSet<&String> = lines.iter().map(|(id, _)| id).collect();
+            let unique: HashSet<&(String, String)> = lines.iter().collect();
+            assert_eq!(unique.len(), lines.len());
+            assert_eq!(ids.len() as u64, summary.doc_count);
+            assert_eq!(lines.len() as u64, summary.doc_count + summary.changed_count);
+
+            let target = client.db(restored).await.unwrap();
+            assert!(target.restore(export.as_slice()).await.unwrap().failures.is_empty());
+
+            // the documents that did not change after the snapshot are restored as they are now, and
+            // deleted documents stay deleted
+            let later = db.changes(&ChangesOptions::default().since(seq)).await.unwrap();
+            let changed_later: HashSet<String> = later.results.into_iter().map(|change| change.id).collect();
+            let revs = |docs: Vec<Value>| -> HashMap<String, Value> {
+                docs.into_iter()
+                    .map(|doc| (doc["_id"].as_str().unwrap().to_string(), doc["_rev"].clone()))
+                    .filter(|(id, _)| !changed_later.contains(id))
+                    .collect()
+            };
+            let original = revs(db.get_all::<Value>().await.unwrap().into_rows());
+            let copied = revs(target.get_all::<Value>().await.unwrap().into_rows());
+            assert_eq!(copied, original);
+
+            // the revisions exported again extend the history of the ones exported first
+            let all = FindQuery::new(json!({"_id": {"$gt": null}}))
+                .conflicts(true)
+                .limit(1000);
+            let found = target.find_raw(&all).await.unwrap();
+            assert!(found.rows.iter().all(|doc| doc.get("_conflicts").is_none()));
+
+            teardown(client.clone(), restored).await;
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_get_attachment_encoding_info() {
+            let dbname = "should_get_attachment_encoding_info";
+            let (client, db, _doc) = setup(dbname).await;
+            let text = "compressible ".repeat(100);
+            db.create(json!({
+                "_id": "with_attachment",
+                "_attachments": {
+                    "notes.txt": {"content_type": "text/plain", "data": base64::encode(&text)}
+                }
+            }))
+            .await
+            .unwrap();
+
+            let doc: Value = db
+                .get_with_options(
+                    "with_attachment",
+                    &types::document::GetOptions::default().att_encoding_info(true),
+                )
+                .await
+                .unwrap();
+            let stubs = types::document::attachments(&doc).unwrap();
+            let stub = &stubs["notes.txt"];
+            assert_eq!(stub.length, Some(text.len() as u64));
+            assert_eq!(stub.encoding.as_deref(), Some("gzip"));
+            assert!(stub.stored_length().unwrap() < text.len() as u64);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_read_and_write_with_quorum() {
+            use types::document::{GetOptions, WriteOptions};
+
+            let dbname = "should_read_and_write_with_quorum";
+            let (client, db, _doc) = setup(dbname).await;
+            let quorum = WriteOptions::default().w(1);
+
+            let created = db.create_with_options(json!({"n": 1}), &quorum).await.unwrap();
+            let mut saved = db.save_with_options(created, &quorum).await.unwrap();
+            assert!(saved.get_rev().starts_with('2'));
+
+            let id = saved.get_id().into_owned();
+            let read: Value = db.get_with_options(&id, &GetOptions::default().r(1)).await.unwrap();
+            assert_eq!(read["_rev"], saved["_rev"]);
+
+            saved["n"] = json!(2);
+            let deleted = db.remove_with_options(saved, &quorum).await.unwrap();
+            assert!(deleted.rev.starts_with('3'));
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_get_doc_count() {
+            let dbname = "should_get_doc_count";
+            let (client, db, docs) = setup_multiple(dbname, 4).await;
+            assert_eq!(db.doc_count().await.unwrap(), 4);
+
+            db.remove_checked(docs[0].clone()).await.unwrap();
+            assert_eq!(db.doc_count().await.unwrap(), 3);
+            assert_eq!(db.info().await.unwrap().doc_del_count, 1);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_get_and_set_purged_infos_limit() {
+            let dbname = "should_get_and_set_purged_infos_limit";
+            let (client, db, _doc) = setup(dbname).await;
+
+            assert_eq!(db.purged_infos_limit().await.unwrap(), 1000);
+            db.set_purged_infos_limit(5000).await.unwrap();
+            assert_eq!(db.purged_infos_limit().await.unwrap(), 5000);
+            assert!(db.set_purged_infos_limit(0).await.unwrap_err().is_bad_request());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_sweep_expired_documents() {
+            use std::time::Duration;
+            use types::expiry::{expires_in, ExpiryOptions};
+
+            let dbname = "should_sweep_expired_documents";
+            let (client, db, _doc) = setup(dbname).await;
+            let options = ExpiryOptions::default().batch_size(2);
+            assert!(db.ensure_expiry_index(&options).await.unwrap());
+
+            for _ in 0..5 {
+                db.create(json!({"expires_at": 1})).await.unwrap();
+            }
+            let kept = db
+                .create(json!({"expires_at": expires_in(Duration::from_secs(3600))}))
+                .await
+                .unwrap();
+
+            let swept = db.run_expiry_sweep(&options).await.unwrap();
+            assert_eq!(swept.deleted, 5);
+            assert!(db.get_raw(kept["_id"].as_str().unwrap()).await.is_ok());
+
+            let expired = db.create(json!({"expires_at": 1})).await.unwrap();
+            let purged = db.run_expiry_sweep(&options.clone().purge(true)).await.unwrap();
+            assert_eq!(purged.deleted, 1);
+            assert!(!db.try_exists(expired["_id"].as_str().unwrap()).await.unwrap());
+            assert_eq!(db.info().await.unwrap().doc_del_count, 5);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_validate_before_writes() {
+            let dbname = "should_validate_before_writes";
+            let (client, db, _doc) = setup(dbname).await;
+            let db = db
+                .with_validation_hook(|doc: &mut Value| match doc["thing"].as_bool() {
+                    Some(_) => Ok(()),
+                    None => Err(crate::error::CouchError::new(
+                        s!("thing is required"),
+                        reqwest::StatusCode::BAD_REQUEST,
+                    )),
+                })
+                .with_validation_hook(|doc: &mut Value| {
+                    doc["checked"] = json!(true);
+                    Ok(())
+                });
+
+            let doc = db.create(json!({"thing": true})).await.unwrap();
+            assert_eq!(doc["checked"], true);
+            let stored: Value = db.get(doc["_id"].as_str().unwrap()).await.unwrap();
+            assert_eq!(stored["_rev"], doc["_rev"]);
+            let err = db.save(json!({"_id": "invalid"})).await.unwrap_err();
+            assert_eq!(err.id(), Some("invalid"));
+            assert!(db.bulk_docs(vec![json!({"thing": false}), json!({})]).await.is_err());
+            assert_eq!(db.doc_count().await.unwrap(), 2);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_report_the_failed_request_in_errors() {
+            let dbname = "should_report_the_failed_request_in_errors";
+            let (client, db, _doc) = setup(dbname).await;
+
+            let err = db.get::<Value>("missing").await.unwrap_err();
+            assert!(err.is_not_found());
+            let context = err.context().unwrap();
+            assert_eq!(context.operation, "GET");
+            assert_eq!(context.path, format!("/{}/missing", dbname));
+            assert!(context.couch_request_id.is_some());
+            assert!(err
+                .to_string()
+                .starts_with(&format!("GET /{}/missing failed: ", dbname)));
+
+            let query = FindQuery::new(json!({"thing": {"$unknown": true}}));
+            let err = db.find::<Value>(&query).await.unwrap_err();
+            assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+            assert_eq!(err.context().unwrap().to_string(), format!("POST /{}/_find", dbname));
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_correlate_request_ids() {
+            use crate::client::RequestOptions;
+            use crate::slow_requests::SlowRequest;
+            use std::sync::{Arc, Mutex};
+            use std::time::Duration;
+
+            let dbname = "should_correlate_request_ids";
+            let (mut client, _db, _doc) = setup(dbname).await;
+            let slow = Arc::new(Mutex::new(Vec::<SlowRequest>::new()));
+            let recorded = slow.clone();
+            client.set_slow_request_hook(Duration::from_secs(0), move |request: &SlowRequest| {
+                recorded.lock().unwrap().push(request.clone())
+            });
+            let db = client.db(dbname).await.unwrap();
+
+            // every request gets an id of its own, and CouchDB answers with its own id
+            let first = db.get::<Value>("missing").await.unwrap_err();
+            let second = db.get::<Value>("missing").await.unwrap_err();
+            let (first, second) = (first.context().unwrap(), second.context().unwrap());
+            assert!(first.request_id.is_some());
+            assert_ne!(first.request_id, second.request_id);
+            assert!(first.couch_request_id.is_some());
+            assert_ne!(first.couch_request_id, second.couch_request_id);
+
+            // an incoming id is passed on, and the slow request reports both ids
+            let options = RequestOptions::default().header("X-Request-ID", "incoming-42").unwrap();
+            let err = db
+                .clone()
+                .with_request_options(options)
+                .get::<Value>("missing")
+                .await
+                .unwrap_err();
+            let context = err.context().unwrap();
+            assert_eq!(context.request_id.as_deref(), Some("incoming-42"));
+            let reported = slow.lock().unwrap().last().cloned().unwrap();
+            assert_eq!(reported.request_id.as_deref(), Some("incoming-42"));
+            assert_eq!(reported.couch_request_id, context.couch_request_id);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_run_migrations() {
+            use types::migration::Migrations;
+
+            let dbname = "should_run_migrations";
+            let (client, db, doc) = setup(dbname).await;
+            let migrations = Migrations::new().migration(|doc: &mut Value| {
+                doc["migrated"] = json!(true);
+                Ok(())
+            });
+            let repository = db.repository::<Value>().migrations(migrations);
+            let old = db.create(json!({"name": "old"})).await.unwrap();
+
+            let read = repository
+                .clone()
+                .migrate_on_read()
+                .get(old["_id"].as_str().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(read["migrated"], true);
+            assert_eq!(read["schema_version"], 1);
+            assert!(repository.get(doc["_id"].as_str().unwrap()).await.unwrap()["migrated"].is_null());
+
+            let created = repository.save(json!({"name": "new"})).await.unwrap();
+            let stored: Value = db.get(created["_id"].as_str().unwrap()).await.unwrap();
+            assert_eq!(stored["schema_version"], 1);
+
+            let mut batches = 0;
+            let progress = repository.run_migrations(1, |_| batches += 1).await.unwrap();
+            assert_eq!(progress.scanned, 2);
+            assert_eq!(progress.migrated, 2);
+            assert!(progress.failures.is_empty());
+            assert_eq!(batches, 3);
+
+            let stored: Value = db.get(old["_id"].as_str().unwrap()).await.unwrap();
+            assert_eq!(stored["migrated"], true);
+            assert_eq!(repository.run_migrations(10, |_| {}).await.unwrap().scanned, 0);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_encrypt_fields_through_repository() {
+            use crate::encryption::{FieldEncryption, KeyProvider};
+            use crate::error::CouchResult;
+
+            struct Xor;
+
+            impl KeyProvider for Xor {
+                fn current_key_id(&self) -> String {
+                    s!("xor")
+                }
+
+                fn encrypt(&self, _key_id: &str, plaintext: &[u8], _associated_data: &[u8]) -> CouchResult<Vec<u8>> {
+                    Ok(plaintext.iter().map(|b| b ^ 0x5a).collect())
+                }
+
+                fn decrypt(&self, _key_id: &str, ciphertext: &[u8], _associated_data: &[u8]) -> CouchResult<Vec<u8>> {
+                    Ok(ciphertext.iter().map(|b| b ^ 0x5a).collect())
+                }
+            }
+
+            let dbname = "should_encrypt_fields_through_repository";
+            let (client, db, _doc) = setup(dbname).await;
+            let repository = db.repository::<Value>().encryption(FieldEncryption::new(Xor, &["ssn"]));
+
+            let saved = repository
+                .save(json!({"_id": "john", "name": "John", "ssn": "123-45"}))
+                .await
+                .unwrap();
+            let id = saved["_id"].as_str().unwrap();
+            assert_eq!(saved["ssn"], "123-45");
+
+            let stored: Value = db.get(id).await.unwrap();
+            assert_eq!(stored["name"], "John");
+            assert_eq!(stored["ssn"]["key_id"], "xor");
+            assert_ne!(stored["ssn"]["ciphertext"], "123-45");
+
+            assert_eq!(repository.get(id).await.unwrap()["ssn"], "123-45");
+            let found = repository.find(json!({"name": "John"})).await.unwrap();
+            assert_eq!(found[0]["ssn"], "123-45");
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_compress_large_requests() {
+            let dbname = "should_compress_large_requests";
+            let mut client = Client::new_local_test().unwrap();
+            client.set_request_compression(Some(256));
+            let db = client.db(dbname).await.unwrap();
+
+            let docs: Vec<Value> = (0..50).map(|i| json!({"_id": format!("doc-{}", i), "n": i})).collect();
+            let results = db.bulk_docs(docs).await.unwrap();
+            assert!(results.iter().all(|r| r.is_ok()));
+            assert_eq!(db.doc_count().await.unwrap(), 50);
+
+            let small = db.create(json!({"n": 50})).await.unwrap();
+            assert_eq!(small["n"], 50);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_read_without_response_compression() {
+            use futures_util::stream::TryStreamExt;
+
+            let dbname = "should_read_without_response_compression";
+            let mut client = Client::new_local_test().unwrap();
+            client
+                .set_response_compression(crate::ResponseCompression::Off)
+                .unwrap();
+            let db = client.db(dbname).await.unwrap();
+
+            let docs: Vec<Value> = (0..20).map(|i| json!({"_id": format!("doc-{}", i)})).collect();
+            db.bulk_docs(docs).await.unwrap();
+
+            let all = db.get_all_raw().await.unwrap();
+            assert_eq!(all.rows.len(), 20);
+            let streamed: Vec<DocumentCollection<Value>> = db.all_docs_stream(7).try_collect().await.unwrap();
+            assert_eq!(streamed.len(), 3);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_return_structured_errors() {
+            use crate::error::CouchError;
+
+            let dbname = "should_return_structured_errors";
+            let (client, db, doc) = setup(dbname).await;
+
+            match db.get::<Value>("missing").await {
+                Err(CouchError::NotFound { reason, .. }) => assert_eq!(reason, "missing"),
+                other => panic!("expected not found, got {:?}", other),
+            }
+
+            let mut stale = doc.clone();
+            stale["_rev"] = json!("1-00000000000000000000000000000000");
+            let err = db.save(stale).await.unwrap_err();
+            assert!(err.is_conflict());
+            assert_eq!(err.error(), "conflict");
+
+            let err = db.query_raw("missing", "view", None).await.unwrap_err();
+            assert!(err.is_not_found());
+            assert!(!err.message().is_empty());
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_stream_batches() {
+            use futures_util::stream::{StreamExt, TryStreamExt};
+
+            let dbname = "should_stream_batches";
+            let (client, db, _docs) = setup_multiple(dbname, 9).await;
+
+            let batches: Vec<DocumentCollection<Value>> = db
+                .find_stream(FindQuery::new(json!({"thing": true})), 4)
+                .try_collect()
+                .await
+                .unwrap();
+            let sizes: Vec<usize> = batches.iter().map(|batch| batch.rows.len()).collect();
+            assert_eq!(sizes, vec![4, 4, 1]);
+
+            let first = db.all_docs_stream::<Value>(4).take(1).collect::<Vec<_>>().await;
+            assert_eq!(first.len(), 1);
+            assert!(first[0].is_ok());
+
+            let docs: Vec<Value> = db.all_docs_paginator(None, 2).documents().try_collect().await.unwrap();
+            assert_eq!(docs.len(), 9);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_page_through_view() {
+            use futures_util::stream::TryStreamExt;
+
+            let dbname = "should_page_through_view";
+            let (client, db, _docs) = setup_multiple(dbname, 25).await;
+            // every row has the same key, so only the document ids tell the pages apart
+            let views = CouchViews::new(
+                "things",
+                MapFunction::new().when("doc.thing").emit("doc.thing", "null").into(),
+            );
+            db.create_view("paged", views).await.unwrap();
+
+            let mut pager = db.view_pager::<bool, Value, Value>("paged", "things", None, 10);
+            let mut sizes = vec![];
+            let mut ids = HashSet::new();
+            while let Some(page) = pager.next_page().await.unwrap() {
+                sizes.push(page.len());
+                ids.extend(page.into_iter().filter_map(|row| row.id));
+            }
+            assert_eq!(sizes, vec![10, 10, 5]);
+            assert_eq!(ids.len(), 25);
+
+            let rows: Vec<ViewItem<bool, Value, Value>> = db
+                .view_pager("paged", "things", Some(QueryParams::default().descending(true)), 7)
+                .rows()
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(rows.len(), 25);
+
+            teardown(client, dbname).await;
+        }
+
+        #[tokio::test]
+        async fn should_query_documents_with_keys() {
+            let db_name = "should_query_documents_with_keys";
+            let (client, db, doc) = setup(db_name).await;
+            let id = doc.get_id().into_owned();
+            let view_name = "testViewAll";
+            db.create_view(
+                view_name,
+                CouchViews::new(
+                    view_name,
+                    CouchFunc {
+                        map: r#"function(doc) {{
+                                    emit(doc._id, null);
+                            }}"#
+                        .to_string(),
+                        reduce: None,
+                    },
+                ),
+            )
+            .await
+            .unwrap();
+            let ndoc = db
+                .create(json!({
+                    "thing": true
+                }))
+                .await
+                .unwrap();
+            let ndoc_id = ndoc.get_id().into_owned();
+            let single_view_name = "testViewSingle";
+            db.create_view(
+                single_view_name,
+                CouchViews::new(
+                    single_view_name,
+                    CouchFunc {
+                        map: format!(
+                            r#"function(doc) {{
+                                    if(doc._id === "{}") {{
+                                        emit(doc._id, null);
+                                    }}
+                            }}"#,
+                            ndoc_id
+                        )
+                        .to_string(),
+                        reduce: None,
+                    },
+                ),
+            )
+            .await
+            .unwrap();
+
+            // executing 'all' view querying with keys containing 1 key should result in 1 and 0 entries, respectively
+            assert_eq!(
+                db.query_raw(view_name, view_name, Some(QueryParams::from_keys(vec![id.clone()])))
+                    .await
+                    .unwrap()
+                    .rows
+                    .len(),
+                1
+            );
+            assert_eq!(
+                db.query_raw(
+                    single_view_name,
+                    single_view_name,
+                    Some(QueryParams::from_keys(vec![id])),
+                )
+                .await
+                .unwrap()
+                .rows
+                .len(),
+                0
+            );
+
+            assert!(db.remove(ndoc).await);
+            assert!(db.remove(doc).await);
+
+            teardown(client, db_name).await;
+        }
+
+        #[tokio::test]
+        async fn should_query_documents_with_key() {
+            let db_name = "should_query_documents_with_key";
+            let (client, db, doc) = setup(db_name).await;
+            let id = doc.get_id().into_owned();
+            let view_name = "testViewAll";
+            db.create_view(
+                view_name,
+                CouchViews::new(
+                    view_name,
+                    CouchFunc {
+                        map: r#"function(doc) {{
+                                    emit(doc._id, null);
+                            }}"#
+                        .to_string(),
+                        reduce: None,
+                    },
+                ),
+            )
+            .await
+            .unwrap();
+            let ndoc = db
+                .create(json!({
+                    "thing": true
+                }))
+                .await
+                .unwrap();
+            let ndoc_id = ndoc.get_id().into_owned();
+            let single_view_name = "testViewSingle";
+            db.create_view(
+                single_view_name,
+                CouchViews::new(
+                    single_view_name,
+                    CouchFunc {
+                        map: format!(
+                            r#"function(doc) {{
+                                    if(doc._id === "{}") {{
+                                        emit(doc._id, null);
+                                    }}
+                            }}"#,
+                            ndoc_id
+                        )
+                        .to_string(),
+                        reduce: None,
+                    },
+                ),
+            )
+            .await
+            .unwrap();
+
+            // executing 'all' view querying with a specific key should result in 1 and 0 entries, respectively
+            let one_key = QueryParams {
+                key: Some(doc.get_id().into_owned().into()),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                db.query_raw(view_name, view_name, Some(one_key.clone()))
+                    .await
+                    .unwrap()
+                    .rows
+                    .len(),
+                1
+            );
+            assert_eq!(
+                db.query_raw(single_view_name, single_view_name, Some(one_key))
+                    .await
+                    .unwrap()
+                    .rows
+                    .len(),
+                0
+            );
+
+            assert!(db.remove(ndoc).await);
+            assert!(db.remove(doc).await);
+
+            teardown(client, db_name).await;
+        }
+
+        #[tokio::test]
+        async fn should_query_documents_with_defaultparams() {
+            let dbname = "should_query_documents_with_defaultparams";
+            let (client, db, doc) = setup(dbname).await;
             let id = doc.get_id().into_owned();
             let view_name = "testViewAll";
             db.create_view(
@@ -830,27 +3121,31 @@ mod couch_rs_tests {
         async fn should_get_many_all_documents_with_keys() {
             let dbname = "should_get_many_all_documents_with_keys";
             let (client, db, docs) = setup_multiple(dbname, 4).await;
-            let doc = docs.get(0).unwrap();
+            let doc = docs.first().unwrap();
 
-            let mut params1 = QueryParams::default();
-            params1.key = Some(doc.get_id().into_owned());
-            let mut params2 = QueryParams::default();
-            params2.include_docs = Some(true);
-            let mut params3 = QueryParams::default();
+            let params1 = QueryParams {
+                key: Some(doc.get_id().into_owned().into()),
+                ..Default::default()
+            };
+            let params2 = QueryParams {
+                include_docs: Some(true),
+                ..Default::default()
+            };
+            let params3 = QueryParams::default();
 
             let params = vec![params1, params2, params3];
             let collections = db.query_many_all_docs(QueriesParams::new(params)).await.unwrap();
 
             assert_eq!(collections.len(), 3);
-            assert_eq!(collections.get(0).unwrap().rows.len(), 1);
+            assert_eq!(collections.first().unwrap().rows.len(), 1);
             // first result has no docs and only 1 row
-            assert!(collections.get(0).unwrap().rows.get(0).unwrap().doc.is_none());
+            assert!(collections.first().unwrap().rows.first().unwrap().doc.is_none());
             // second result has 4 rows with docs
             assert_eq!(collections.get(1).unwrap().rows.len(), 4);
-            assert!(collections.get(1).unwrap().rows.get(0).unwrap().doc.is_some());
+            assert!(collections.get(1).unwrap().rows.first().unwrap().doc.is_some());
             // third result has 4 rows without docs
             assert_eq!(collections.get(2).unwrap().rows.len(), 4);
-            assert!(collections.get(2).unwrap().rows.get(0).unwrap().doc.is_none());
+            assert!(collections.get(2).unwrap().rows.first().unwrap().doc.is_none());
 
             for doc in docs.into_iter() {
                 assert!(db.remove(doc).await);
@@ -863,7 +3158,7 @@ mod couch_rs_tests {
         async fn should_handle_null_view_keys() {
             let dbname = "should_handle_null_view_keys";
             let (client, db, docs) = setup_multiple(dbname, 4).await;
-            let doc = docs.get(0).unwrap();
+            let doc = docs.first().unwrap();
             let count_by_id = r#"function (doc) {
                                         emit(doc._id, null);
                                     }"#;
@@ -925,4 +3220,36 @@ mod couch_rs_tests {
             teardown(client, "should_bulk_insert_and_get_many_docs").await;
         }
     }
+
+    #[cfg(feature = "blocking")]
+    mod blocking_tests {
+        use crate::blocking::Client;
+        use crate::couch_rs_tests::TestDoc;
+        use crate::types::find::FindQuery;
+        use serde_json::json;
+
+        #[test]
+        fn should_save_and_find_documents_without_async() {
+            let dbname = "should_save_and_find_documents_without_async";
+            let client = Client::new_local_test().unwrap();
+            let db = client.db(dbname).unwrap();
+
+            let doc = db
+                .create(TestDoc {
+                    first_name: s!("John"),
+                    last_name: s!("Doe"),
+                    ..Default::default()
+                })
+                .unwrap();
+            let saved: TestDoc = db.get(&doc._id).unwrap();
+            assert_eq!(saved.last_name, "Doe");
+
+            let found = db
+                .find::<TestDoc>(&FindQuery::new(json!({"last_name": "Doe"})))
+                .unwrap();
+            assert_eq!(found.rows.len(), 1);
+
+            assert!(client.destroy_db(dbname).unwrap());
+        }
+    }
 }
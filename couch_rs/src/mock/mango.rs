@@ -0,0 +1,338 @@
+//! Mango selectors and CouchDB collation, evaluated against the documents of the mock server
+
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+
+/// Compares two values in the order CouchDB sorts keys: null, false, true, numbers, strings,
+/// arrays, then objects. Strings compare by code point, rather than by ICU collation.
+pub(crate) fn collate(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            let (a, b) = (a.as_f64().unwrap_or_default(), b.as_f64().unwrap_or_default());
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        }
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| collate(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (Value::Object(a), Value::Object(b)) => a
+            .iter()
+            .zip(b)
+            .map(|((ka, va), (kb, vb))| ka.cmp(kb).then_with(|| collate(va, vb)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(false) => 1,
+        Value::Bool(true) => 2,
+        Value::Number(_) => 3,
+        Value::String(_) => 4,
+        Value::Array(_) => 5,
+        Value::Object(_) => 6,
+    }
+}
+
+/// Looks up a dotted field path, such as `address.city`, in a document
+pub(crate) fn field<'a>(doc: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(doc, |value, name| value.as_object()?.get(name))
+}
+
+/// Whether the document matches the selector. Fails for malformed selectors and for the operators
+/// the mock does not support, such as `$regex`.
+pub(crate) fn matches(doc: &Value, selector: &Value) -> Result<bool, String> {
+    match_value(Some(doc), selector)
+}
+
+fn match_value(value: Option<&Value>, selector: &Value) -> Result<bool, String> {
+    let conditions = match selector {
+        Value::Object(conditions) => conditions,
+        // a plain value is shorthand for $eq
+        _ => return Ok(value.is_some_and(|value| collate(value, selector).is_eq())),
+    };
+
+    for (key, condition) in conditions {
+        let matched = if key.starts_with('$') {
+            match_operator(value, key, condition)?
+        } else {
+            match_value(value.and_then(|value| field(value, key)), condition)?
+        };
+        if !matched {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn match_operator(value: Option<&Value>, operator: &str, argument: &Value) -> Result<bool, String> {
+    match operator {
+        "$and" => all_of(value, argument),
+        "$or" => any_of(value, argument),
+        "$nor" => any_of(value, argument).map(|matched| !matched),
+        "$not" => match_value(value, argument).map(|matched| !matched),
+        "$exists" => match argument {
+            Value::Bool(exists) => Ok(value.is_some() == *exists),
+            _ => Err(s!("$exists takes a boolean")),
+        },
+        // all other operators only match fields that exist
+        _ => match value {
+            Some(value) => match_field_operator(value, operator, argument),
+            None => Ok(false),
+        },
+    }
+}
+
+fn match_field_operator(value: &Value, operator: &str, argument: &Value) -> Result<bool, String> {
+    let ordering = || collate(value, argument);
+    let matched = match operator {
+        "$eq" => ordering().is_eq(),
+        "$ne" => ordering().is_ne(),
+        "$gt" => ordering().is_gt(),
+        "$gte" => ordering().is_ge(),
+        "$lt" => ordering().is_lt(),
+        "$lte" => ordering().is_le(),
+        "$in" => is_in(value, array_argument(operator, argument)?),
+        "$nin" => !is_in(value, array_argument(operator, argument)?),
+        "$type" => match argument.as_str() {
+            Some(name) => type_name(value) == name,
+            None => return Err(s!("$type takes a string")),
+        },
+        "$size" => match argument.as_u64() {
+            Some(size) => value.as_array().is_some_and(|items| items.len() as u64 == size),
+            None => return Err(s!("$size takes an integer")),
+        },
+        "$mod" => match argument.as_array().map(|args| (args.first(), args.get(1), args.len())) {
+            Some((Some(divisor), Some(remainder), 2)) => match (divisor.as_i64(), remainder.as_i64()) {
+                (Some(divisor), Some(remainder)) if divisor != 0 => {
+                    value.as_i64().is_some_and(|value| value % divisor == remainder)
+                }
+                _ => return Err(s!("$mod takes a non-zero divisor and a remainder")),
+            },
+            _ => return Err(s!("$mod takes a non-zero divisor and a remainder")),
+        },
+        "$beginsWith" => match argument.as_str() {
+            Some(prefix) => value.as_str().is_some_and(|value| value.starts_with(prefix)),
+            None => return Err(s!("$beginsWith takes a string")),
+        },
+        "$all" => {
+            let required = array_argument(operator, argument)?;
+            value.as_array().is_some_and(|items| {
+                required
+                    .iter()
+                    .all(|wanted| items.iter().any(|item| collate(item, wanted).is_eq()))
+            })
+        }
+        "$elemMatch" => match value.as_array() {
+            Some(items) => items.iter().try_fold(false, |found, item| {
+                Ok::<_, String>(found || match_value(Some(item), argument)?)
+            })?,
+            None => false,
+        },
+        "$allMatch" => match value.as_array() {
+            Some(items) if !items.is_empty() => items.iter().try_fold(true, |all, item| {
+                Ok::<_, String>(all && match_value(Some(item), argument)?)
+            })?,
+            _ => false,
+        },
+        "$keyMapMatch" => match value.as_object() {
+            Some(map) => map.keys().try_fold(false, |found, key| {
+                Ok::<_, String>(found || match_value(Some(&Value::from(key.as_str())), argument)?)
+            })?,
+            None => false,
+        },
+        _ => return Err(format!("The mock server does not support the {} operator", operator)),
+    };
+    Ok(matched)
+}
+
+fn all_of(value: Option<&Value>, argument: &Value) -> Result<bool, String> {
+    for selector in array_argument("$and", argument)? {
+        if !match_value(value, selector)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn any_of(value: Option<&Value>, argument: &Value) -> Result<bool, String> {
+    for selector in array_argument("$or", argument)? {
+        if match_value(value, selector)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn array_argument<'a>(operator: &str, argument: &'a Value) -> Result<&'a Vec<Value>, String> {
+    argument
+        .as_array()
+        .ok_or_else(|| format!("{} takes an array", operator))
+}
+
+/// `$in` matches the value itself, or any of its items when it is an array
+fn is_in(value: &Value, candidates: &[Value]) -> bool {
+    let items = value.as_array().map(|items| items.iter()).into_iter().flatten();
+    std::iter::once(value)
+        .chain(items)
+        .any(|item| candidates.iter().any(|candidate| collate(item, candidate).is_eq()))
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Sorts documents by the fields of a Mango `sort`, given as `"field"` or `{"field": "desc"}`
+pub(crate) fn sort(docs: &mut [Value], sort: &[Value]) -> Result<(), String> {
+    let mut keys = vec![];
+    for spec in sort {
+        match spec {
+            Value::String(field) => keys.push((field.clone(), false)),
+            Value::Object(spec) if spec.len() == 1 => {
+                for (field, direction) in spec {
+                    let descending = match direction.as_str() {
+                        Some("asc") => false,
+                        Some("desc") => true,
+                        _ => return Err(format!("Invalid sort direction for {}", field)),
+                    };
+                    keys.push((field.clone(), descending));
+                }
+            }
+            _ => return Err(s!("Invalid sort field")),
+        }
+    }
+
+    docs.sort_by(|a, b| {
+        keys.iter()
+            .map(|(path, descending)| {
+                // documents without the field come first, as if the field held a value below null
+                let ordering = match (field(a, path), field(b, path)) {
+                    (Some(a), Some(b)) => collate(a, b),
+                    (a, b) => a.is_some().cmp(&b.is_some()),
+                };
+                if *descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+    Ok(())
+}
+
+/// Keeps only the given fields of a document, as the `fields` of a Mango query do
+pub(crate) fn project(doc: &Value, fields: &[Value]) -> Value {
+    let mut projected = Map::new();
+    for path in fields.iter().filter_map(Value::as_str) {
+        if let Some(value) = field(doc, path) {
+            insert_path(&mut projected, path, value.clone());
+        }
+    }
+    Value::Object(projected)
+}
+
+fn insert_path(target: &mut Map<String, Value>, path: &str, value: Value) {
+    match path.split_once('.') {
+        Some((name, rest)) => {
+            let child = target
+                .entry(name.to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(child) = child {
+                insert_path(child, rest, value);
+            }
+        }
+        None => {
+            target.insert(path.to_string(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_collate() {
+        let mut values = vec![
+            json!({"a": 1}),
+            json!(["a"]),
+            json!("b"),
+            json!("a"),
+            json!(10),
+            json!(2.5),
+            json!(true),
+            json!(false),
+            json!(null),
+        ];
+        values.sort_by(collate);
+        assert_eq!(
+            values,
+            vec![
+                json!(null),
+                json!(false),
+                json!(true),
+                json!(2.5),
+                json!(10),
+                json!("a"),
+                json!("b"),
+                json!(["a"]),
+                json!({"a": 1})
+            ]
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let doc = json!({"name": "john", "age": 42, "tags": ["a", "b"], "address": {"city": "Oslo"}});
+
+        assert!(matches(&doc, &json!({})).unwrap());
+        assert!(matches(&doc, &json!({"name": "john"})).unwrap());
+        assert!(!matches(&doc, &json!({"name": "jane"})).unwrap());
+        assert!(matches(&doc, &json!({"age": {"$gt": 40, "$lte": 42}})).unwrap());
+        assert!(matches(&doc, &json!({"address.city": "Oslo"})).unwrap());
+        assert!(matches(&doc, &json!({"address": {"city": {"$in": ["Oslo", "Bergen"]}}})).unwrap());
+        assert!(matches(&doc, &json!({"tags": {"$in": ["b"]}, "missing": {"$exists": false}})).unwrap());
+        assert!(matches(&doc, &json!({"tags": {"$all": ["a", "b"], "$size": 2}})).unwrap());
+        assert!(matches(&doc, &json!({"$or": [{"name": "jane"}, {"age": {"$mod": [2, 0]}}]})).unwrap());
+        assert!(!matches(&doc, &json!({"$not": {"name": "john"}})).unwrap());
+        assert!(!matches(&doc, &json!({"missing": {"$ne": 1}})).unwrap());
+        assert!(matches(&doc, &json!({"tags": {"$elemMatch": {"$eq": "b"}}})).unwrap());
+        assert!(matches(&doc, &json!({"name": {"$type": "string", "$beginsWith": "jo"}})).unwrap());
+        assert!(matches(&doc, &json!({"name": {"$regex": "^j"}})).is_err());
+    }
+
+    #[test]
+    fn test_sort_and_project() {
+        let mut docs = vec![
+            json!({"_id": "a", "n": 2}),
+            json!({"_id": "b"}),
+            json!({"_id": "c", "n": 3}),
+        ];
+        sort(&mut docs, &[json!({"n": "desc"})]).unwrap();
+        let ids: Vec<_> = docs.iter().map(|doc| doc["_id"].clone()).collect();
+        assert_eq!(ids, vec![json!("c"), json!("a"), json!("b")]);
+
+        let doc = json!({"_id": "a", "address": {"city": "Oslo", "zip": "0150"}});
+        assert_eq!(
+            project(&doc, &[json!("_id"), json!("address.city")]),
+            json!({"_id": "a", "address": {"city": "Oslo"}})
+        );
+    }
+}
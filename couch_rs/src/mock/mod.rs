@@ -0,0 +1,811 @@
+//! An in-memory stand-in for CouchDB, so unit tests do not need a running server.
+//!
+//! `MockCouch` serves the CouchDB HTTP API from memory on a local port, and the `Client` and
+//! `Database` of this crate talk to it as they would to CouchDB. It keeps documents and their
+//! revisions, and answers `_all_docs`, `_design_docs`, `_bulk_docs`, `_changes`, `_purge`, Mango
+//! queries and indexes. Views are computed by map functions written in Rust, registered with
+//! `define_view`, and reduced with the built-in reduce functions of their design documents.
+//!
+//! The mock is no replacement for testing against CouchDB: it keeps only the latest revision of
+//! documents, compares strings by code point rather than by ICU collation, reads every document
+//! for each Mango query, does not wait for changes in long polling feeds, and does not support
+//! attachments, JavaScript, or the `$regex` operator. Requests it does not support fail with `501 Not Implemented`.
+//!
+//! Usage:
+//! ```
+//! use couch_rs::error::CouchResult;
+//! use couch_rs::mock::MockCouch;
+//! use serde_json::{json, Value};
+//!
+//! #[tokio::main]
+//! async fn main() -> CouchResult<()> {
+//!     let couch = MockCouch::start().await;
+//!     couch.define_view("users", "by_name", "by_name", |doc| vec![(doc["name"].clone(), json!(1))]);
+//!
+//!     let db = couch.client()?.db("users").await?;
+//!     db.create(json!({"_id": "john", "name": "John"})).await?;
+//!
+//!     let john: Value = db.get("john").await?;
+//!     assert_eq!(john["name"], "John");
+//!
+//!     let view = db.query::<Value, Value, Value>("by_name", "by_name", None).await?;
+//!     assert_eq!(view.rows[0].key, "John");
+//!     Ok(())
+//! }
+//! ```
+
+mod mango;
+mod routes;
+mod store;
+
+use crate::error::CouchResult;
+use crate::Client;
+use flate2::read::GzDecoder;
+use hyper::header::{HeaderValue, CONTENT_ENCODING, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::io::Read;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use store::{Failure, Store};
+use tokio::task::JoinHandle;
+
+/// A CouchDB server that keeps its databases in memory, for tests. The server stops when the
+/// `MockCouch` is dropped.
+pub struct MockCouch {
+    address: SocketAddr,
+    store: Arc<Mutex<Store>>,
+    server: JoinHandle<()>,
+}
+
+impl MockCouch {
+    /// Starts a server on a free local port. It runs on the tokio runtime of the caller.
+    pub async fn start() -> MockCouch {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).expect("could not bind the mock server to a local port");
+        let address = listener
+            .local_addr()
+            .expect("could not read the address of the mock server");
+        listener
+            .set_nonblocking(true)
+            .expect("could not configure the socket of the mock server");
+
+        let store = Arc::new(Mutex::new(Store::default()));
+        let shared = store.clone();
+        let make_service = make_service_fn(move |_| {
+            let store = shared.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |request| handle(store.clone(), request))) }
+        });
+        let server = Server::from_tcp(listener)
+            .expect("could not start the mock server")
+            .serve(make_service);
+
+        MockCouch {
+            address,
+            store,
+            server: tokio::spawn(async move {
+                let _ = server.await;
+            }),
+        }
+    }
+
+    /// The URL of the server, such as `http://127.0.0.1:49152`
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// Returns a client for the server. The mock does not check credentials.
+    pub fn client(&self) -> CouchResult<Client> {
+        Client::new_no_auth(&self.url())
+    }
+
+    /// Defines the map function of a view, which the mock runs in place of the JavaScript of the
+    /// design document. The function returns the key and value of every row to emit for a document.
+    /// The view can be queried without a design document; when the design document defines a
+    /// built-in reduce function for the view, such as `_count`, queries are reduced with it.
+    pub fn define_view<F>(&self, db: &str, design: &str, view: &str, map: F)
+    where
+        F: Fn(&Value) -> Vec<(Value, Value)> + Send + Sync + 'static,
+    {
+        let name = (db.to_string(), design.to_string(), view.to_string());
+        self.store().views.insert(name, Arc::new(map));
+    }
+
+    /// Removes all databases, keeping the view definitions
+    pub fn reset(&self) {
+        self.store().databases.clear();
+    }
+
+    fn store(&self) -> MutexGuard<'_, Store> {
+        lock(&self.store)
+    }
+}
+
+impl Drop for MockCouch {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+fn lock(store: &Mutex<Store>) -> MutexGuard<'_, Store> {
+    // a panicking request leaves the store usable
+    store.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+async fn handle(store: Arc<Mutex<Store>>, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let (parts, body) = request.into_parts();
+    let outcome = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => read_body(parts.headers.get(CONTENT_ENCODING), &bytes).and_then(|body| {
+            let uri = &parts.uri;
+            routes::route(&mut lock(&store), &parts.method, uri.path(), uri.query(), body)
+        }),
+        Err(err) => Err(Failure::bad_request(err.to_string())),
+    };
+
+    let (status, body) = match outcome {
+        Ok(response) => response,
+        Err(failure) => (
+            failure.status,
+            json!({"error": failure.error, "reason": failure.reason}),
+        ),
+    };
+    let body = if parts.method == Method::HEAD {
+        Body::empty()
+    } else {
+        Body::from(body.to_string())
+    };
+
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    // CouchDB identifies every request in its log, with an ID it returns
+    static REQUESTS: AtomicU64 = AtomicU64::new(0);
+    let request_id = format!("{:010x}", REQUESTS.fetch_add(1, Ordering::Relaxed));
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    if let Ok(request_id) = HeaderValue::from_str(&request_id) {
+        headers.insert("x-couch-request-id", request_id);
+    }
+    Ok(response)
+}
+
+/// Parses the JSON body of a request, which the client may have compressed; an empty body is null
+fn read_body(encoding: Option<&HeaderValue>, bytes: &[u8]) -> Result<Value, Failure> {
+    let mut decompressed = vec![];
+    let bytes = if encoding.is_some_and(|encoding| encoding == "gzip") {
+        GzDecoder::new(bytes)
+            .read_to_end(&mut decompressed)
+            .map_err(|err| Failure::bad_request(format!("invalid gzip body: {}", err)))?;
+        &decompressed
+    } else {
+        bytes
+    };
+
+    if bytes.is_empty() {
+        return Ok(Value::Null);
+    }
+    serde_json::from_slice(bytes).map_err(|_| Failure::bad_request("invalid UTF-8 JSON"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::document::{Document, DocumentCollection, TypedCouchDocument};
+    use crate::encryption::{FieldEncryption, KeyProvider};
+    use crate::error::{CouchError, CouchResult};
+    use crate::types::changes::{ChangesOptions, Seq};
+    use crate::types::document::WriteOptions;
+    use crate::types::find::{FindQuery, SortDirection};
+    use crate::types::nouveau::NouveauQuery;
+    use crate::types::query::{QueriesParams, QueryParams};
+    use crate::types::system::{RenameOptions, RenameProgress};
+    use crate::types::view::{BuiltinReduce, CouchFunc, CouchViews};
+    #[cfg(feature = "brotli")]
+    use crate::ResponseCompression;
+    use futures_util::TryStreamExt;
+    use hyper::StatusCode;
+    #[cfg(feature = "brotli")]
+    use reqwest::Method;
+    use serde::{Deserialize, Serialize};
+    use std::time::Duration;
+    use tokio::task::JoinHandle;
+
+    /// Starts a server with an empty database
+    async fn setup(dbname: &str) -> (MockCouch, Database) {
+        setup_with(dbname, vec![]).await
+    }
+
+    /// Starts a server with a database holding the given documents
+    async fn setup_with(dbname: &str, docs: Vec<Value>) -> (MockCouch, Database) {
+        let couch = MockCouch::start().await;
+        let db = couch.client().unwrap().db(dbname).await.unwrap();
+        if !docs.is_empty() {
+            assert!(db.bulk_docs(docs).await.unwrap().iter().all(Result::is_ok));
+        }
+        (couch, db)
+    }
+
+    /// A server standing in for a proxy in front of CouchDB, answering every request with `respond`.
+    /// It stops when dropped.
+    struct Proxy {
+        url: String,
+        server: JoinHandle<()>,
+    }
+
+    impl Proxy {
+        fn start<F>(respond: F) -> Proxy
+        where
+            F: Fn(Request<Body>) -> Response<Body> + Clone + Send + Sync + 'static,
+        {
+            let make_service = make_service_fn(move |_| {
+                let respond = respond.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| {
+                        let response = respond(request);
+                        async move { Ok::<_, Infallible>(response) }
+                    }))
+                }
+            });
+            let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+            Proxy {
+                url: format!("http://{}", server.local_addr()),
+                server: tokio::spawn(async move {
+                    let _ = server.await;
+                }),
+            }
+        }
+    }
+
+    impl Drop for Proxy {
+        fn drop(&mut self) {
+            self.server.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_documents_and_revisions() {
+        let (couch, db) = setup("docs").await;
+        let client = couch.client().unwrap();
+        assert_eq!(client.list_dbs().await.unwrap(), vec![s!("docs")]);
+
+        let created = db.create(json!({"_id": "a", "n": 1})).await.unwrap();
+        assert!(created["_rev"].as_str().unwrap().starts_with("1-"));
+        assert!(db.exists("a").await);
+
+        let mut doc: Value = db.get("a").await.unwrap();
+        doc["n"] = json!(2);
+        let saved = db.save(doc.clone()).await.unwrap();
+        assert!(saved["_rev"].as_str().unwrap().starts_with("2-"));
+
+        // saving the stale revision again conflicts
+        let err = db.save(doc).await.unwrap_err();
+        assert!(matches!(err, CouchError::Conflict { .. }));
+        assert_eq!(err.context().unwrap().operation, "PUT");
+
+        assert_eq!(db.purged_infos_limit().await.unwrap(), 1000);
+        db.set_purged_infos_limit(5000).await.unwrap();
+        assert_eq!(db.purged_infos_limit().await.unwrap(), 5000);
+        assert!(db.set_purged_infos_limit(0).await.unwrap_err().is_bad_request());
+
+        let note = db.create(Document::new("note", json!({"text": "hi"}))).await.unwrap();
+        let mut read: Document<Value> = db.get("note").await.unwrap();
+        assert_eq!((read.get_rev(), &read["text"]), (note.get_rev(), &json!("hi")));
+        read.mark_deleted();
+        db.save(read).await.unwrap();
+        assert!(db.get::<Value>("note").await.unwrap_err().is_not_found());
+
+        let (doc, written) = db
+            .create_with_result(json!({"_id": "b"}), &WriteOptions::default())
+            .await
+            .unwrap();
+        assert!(written.is_durable());
+        assert_eq!(written.rev.as_deref(), doc["_rev"].as_str());
+        let (_, written) = db
+            .save_with_result(doc, &WriteOptions::default().batch(true))
+            .await
+            .unwrap();
+        assert!(!written.is_durable());
+        assert_eq!((written.status, written.rev), (StatusCode::ACCEPTED, None));
+
+        assert!(db.remove(saved).await);
+        let err = db.get::<Value>("a").await.unwrap_err();
+        assert!(matches!(err, CouchError::NotFound { .. }));
+        let context = err.context().unwrap();
+        assert!(context.request_id.is_some());
+        assert_eq!(context.couch_request_id.as_ref().map(String::len), Some(10));
+        assert_eq!(db.info().await.unwrap().doc_del_count, 2);
+
+        client.destroy_db("docs").await.unwrap();
+        assert!(client.list_dbs().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_bulk_docs_and_all_docs() {
+        let (_couch, db) = setup("bulk").await;
+
+        let docs = (0..5).map(|n| json!({"_id": format!("doc{}", n), "n": n})).collect();
+        let results = db.bulk_docs(docs).await.unwrap();
+        assert!(results.iter().all(Result::is_ok));
+
+        let all = db.get_all::<Value>().await.unwrap();
+        assert_eq!(all.rows.len(), 5);
+
+        let params = QueryParams::default().start_key(json!("doc1")).end_key(json!("doc3"));
+        let page = db.get_all_params::<Value>(Some(params)).await.unwrap();
+        let ids: Vec<_> = page.rows.iter().map(|doc| doc["_id"].clone()).collect();
+        assert_eq!(ids, vec![json!("doc1"), json!("doc2"), json!("doc3")]);
+
+        let bulk = db.get_bulk::<Value>(vec![s!("doc4"), s!("missing")]).await.unwrap();
+        assert_eq!(bulk.rows.len(), 1);
+
+        let ids = vec![s!("doc3"), s!("missing"), s!("doc0"), s!("doc4")];
+        let results = db.get_many_concurrent::<Value>(ids, 2).await;
+        assert!(results[1].as_ref().unwrap_err().is_not_found());
+        let found: Vec<_> = results.iter().flatten().map(|doc| doc["n"].clone()).collect();
+        assert_eq!(found, vec![json!(3), json!(0), json!(4)]);
+    }
+
+    #[tokio::test]
+    async fn test_scan_all_docs() {
+        let docs = (0..40).map(|n| json!({"_id": format!("doc{:02}", n)})).collect();
+        let (couch, db) = setup_with("scan", docs).await;
+
+        let batches: Vec<DocumentCollection<Value>> =
+            db.scan_all_docs(4, 3).await.unwrap().try_collect().await.unwrap();
+        let mut ids: Vec<_> = batches
+            .iter()
+            .flat_map(|batch| batch.ids())
+            .map(|id| id.into_owned())
+            .collect();
+        ids.sort();
+        assert_eq!(ids, (0..40).map(|n| format!("doc{:02}", n)).collect::<Vec<_>>());
+
+        let empty = couch.client().unwrap().db("scan_empty").await.unwrap();
+        let batches: Vec<DocumentCollection<Value>> =
+            empty.scan_all_docs(4, 3).await.unwrap().try_collect().await.unwrap();
+        assert!(batches.iter().all(|batch| batch.rows.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_repository_soft_deletes() {
+        let docs = vec![
+            json!({"_id": "live"}),
+            json!({"_id": "restored", "deleted_at": null}),
+            json!({"_id": "deleted", "deleted_at": 1_600_000_000}),
+        ];
+        let (_couch, db) = setup_with("notes", docs).await;
+
+        let notes = db.repository::<Value>();
+        let mut ids: Vec<_> = notes
+            .all()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|doc| doc["_id"].clone())
+            .collect();
+        ids.sort_by_key(|id| id.to_string());
+        assert_eq!(ids, vec![json!("live"), json!("restored")]);
+        assert!(notes.get("restored").await.is_ok());
+        assert!(notes.get("deleted").await.unwrap_err().is_not_found());
+
+        assert_eq!(
+            db.repository::<Value>()
+                .include_soft_deleted()
+                .all()
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validation_hooks_write_back_to_the_caller_document() {
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        struct Counter {
+            count: u32,
+            #[serde(default)]
+            updated_at: Option<u64>,
+        }
+
+        let (_couch, db) = setup("hooks").await;
+        let stamped = db.clone().with_validation_hook(|doc: &mut Value| {
+            doc["updated_at"] = json!(42);
+            Ok(())
+        });
+        let counter = |count| Counter {
+            count,
+            updated_at: None,
+        };
+
+        let saved = stamped.save(Document::new("first", counter(1))).await.unwrap();
+        assert_eq!(saved.data.updated_at, Some(42));
+        assert!(saved.rev.starts_with("1-"));
+        let created = stamped.create(Document::new("second", counter(2))).await.unwrap();
+        assert_eq!((created.id.as_str(), created.data.updated_at), ("second", Some(42)));
+
+        // a body that no longer deserializes into the type of the caller leaves its document as it was
+        let stringified = db.with_validation_hook(|doc: &mut Value| {
+            doc["count"] = json!(doc["count"].to_string());
+            Ok(())
+        });
+        let saved = stringified.save(Document::new("third", counter(3))).await.unwrap();
+        assert_eq!(saved.data, counter(3));
+        assert!(saved.rev.starts_with("1-"));
+
+        let stored: Value = stringified.get("third").await.unwrap();
+        assert_eq!(stored["count"], "3");
+    }
+
+    #[tokio::test]
+    async fn test_field_encryption_hook() {
+        struct Rot(u8);
+
+        impl KeyProvider for Rot {
+            fn current_key_id(&self) -> String {
+                format!("rot-{}", self.0)
+            }
+
+            fn encrypt(&self, _key_id: &str, plaintext: &[u8], _associated_data: &[u8]) -> CouchResult<Vec<u8>> {
+                Ok(plaintext.iter().map(|byte| byte.wrapping_add(self.0)).collect())
+            }
+
+            fn decrypt(&self, _key_id: &str, ciphertext: &[u8], _associated_data: &[u8]) -> CouchResult<Vec<u8>> {
+                Ok(ciphertext.iter().map(|byte| byte.wrapping_sub(self.0)).collect())
+            }
+        }
+
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        struct Patient {
+            name: String,
+            ssn: String,
+        }
+
+        let (_couch, db) = setup("patients").await;
+        let encryption = FieldEncryption::new(Rot(3), &["ssn"]);
+        let db = db.with_validation_hook(encryption.clone());
+
+        let patient = Patient {
+            name: s!("John"),
+            ssn: s!("123-45-6789"),
+        };
+        let saved = db.save(Document::new("john", patient.clone())).await.unwrap();
+        assert_eq!(saved.data, patient);
+
+        let stored: Value = db.get("john").await.unwrap();
+        assert_eq!(stored["ssn"]["key_id"], "rot-3");
+        assert_ne!(stored["ssn"]["ciphertext"], json!(patient.ssn));
+
+        let patients = db.repository::<Document<Patient>>().encryption(encryption);
+        assert_eq!(patients.get("john").await.unwrap().data, patient);
+    }
+
+    #[tokio::test]
+    async fn test_reqwest_status_errors_keep_their_source() {
+        let couch = MockCouch::start().await;
+        let response = reqwest::get(format!("{}/missing/doc", couch.url())).await.unwrap();
+        let err = CouchError::from(response.error_for_status().unwrap_err());
+
+        assert!(err.is_not_found());
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+        let source = std::error::Error::source(&err).unwrap();
+        assert!(source.downcast_ref::<reqwest::Error>().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_find_reports_non_json_error_pages() {
+        // a proxy that answers every request with an HTML error page
+        let proxy = Proxy::start(|_| {
+            Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .header(CONTENT_TYPE, "text/html")
+                .body(Body::from("<html><body>502 Bad Gateway</body></html>"))
+                .unwrap()
+        });
+
+        let db = Database::new(s!("users"), Client::new_no_auth(&proxy.url).unwrap());
+        let err = db.find::<Value>(&FindQuery::find_all()).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_GATEWAY);
+        assert!(matches!(err, CouchError::Other { .. }));
+        assert_eq!(err.message(), "<html><body>502 Bad Gateway</body></html>");
+        assert_eq!(err.context().unwrap().path, "/users/_find");
+    }
+
+    #[cfg(feature = "brotli")]
+    #[tokio::test]
+    async fn test_response_compression_negotiation() {
+        // a proxy that answers with the encodings the client accepts
+        let proxy = Proxy::start(|request| {
+            let accepted = request.headers()[hyper::header::ACCEPT_ENCODING].to_str().unwrap();
+            Response::new(Body::from(json!({ "accepted": accepted }).to_string()))
+        });
+
+        let mut client = Client::new_no_auth(&proxy.url).unwrap();
+        for (compression, accepted) in [
+            (ResponseCompression::Gzip, "gzip"),
+            (ResponseCompression::Brotli, "gzip, br"),
+            (ResponseCompression::Off, "identity"),
+        ] {
+            client.set_response_compression(compression).unwrap();
+            let response = client.req(Method::GET, s!("db"), None).send().await.unwrap();
+            let response: Value = response.json().await.unwrap();
+            assert_eq!(response["accepted"], accepted);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_design_docs_queries() {
+        let ids = ["_design/car-a", "_design/car-b", "_design/boat-a", "car-1"];
+        let (_couch, db) = setup_with("designs", ids.iter().map(|id| json!({ "_id": id })).collect()).await;
+
+        let cars = QueryParams::default()
+            .start_key("_design/car")
+            .end_key("_design/car\u{fff0}");
+        let all = QueryParams::default();
+        let results = db
+            .query_many_design_docs(QueriesParams::new(vec![cars, all]))
+            .await
+            .unwrap();
+        let ids = |n: usize| results[n].rows.iter().map(|row| row.key.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(0), vec![json!("_design/car-a"), json!("_design/car-b")]);
+        assert_eq!(ids(1).len(), 3);
+        assert_eq!(results[1].total_rows, Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_find() {
+        let docs = ["john", "jane", "bob"]
+            .iter()
+            .enumerate()
+            .map(|(age, name)| json!({"_id": name, "name": name, "age": 30 + age}))
+            .collect();
+        let (_couch, db) = setup_with("find", docs).await;
+
+        let query = FindQuery::new(json!({"age": {"$gte": 31}})).sort_by("age", SortDirection::Desc);
+        let found = db.find::<Value>(&query).await.unwrap();
+        let names: Vec<_> = found.rows.iter().map(|doc| doc["name"].clone()).collect();
+        assert_eq!(names, vec![json!("bob"), json!("jane")]);
+        assert!(found.warning.is_some());
+
+        let invalid = FindQuery::new(json!({"name": {"$regex": "^j"}}));
+        assert!(matches!(
+            db.find::<Value>(&invalid).await,
+            Err(CouchError::BadRequest { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_index_advice() {
+        let (_couch, db) = setup("advice").await;
+        let query = FindQuery::new(json!({"type": "user", "age": {"$gt": 21}}));
+
+        let advice = db.advise_index(&query).await.unwrap();
+        assert!(advice.needs_index);
+        assert!(advice.explain.unwrap().is_full_scan());
+        let suggested = advice.suggested.unwrap();
+        assert_eq!(suggested.suggested_name(), "advised_type_age");
+
+        let advice = db.ensure_advised_index(&query).await.unwrap();
+        assert!(advice.created);
+        let advice = db.advise_index(&query).await.unwrap();
+        assert!(!advice.needs_index);
+        assert_eq!(advice.explain.unwrap().index.name, "advised_type_age");
+        assert_eq!(advice.suggested, None);
+    }
+
+    #[tokio::test]
+    async fn test_nouveau_unavailable() {
+        let couch = MockCouch::start().await;
+        let client = couch.client().unwrap();
+        assert!(!client.supports_nouveau().await.unwrap());
+
+        let db = client.db("nouveau").await.unwrap();
+        let err = db
+            .nouveau_search::<Value>("users", "by_name", &NouveauQuery::new("*:*"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::NOT_IMPLEMENTED);
+        assert!(err.to_string().contains("not enabled"));
+    }
+
+    #[tokio::test]
+    async fn test_local_docs() {
+        let (_couch, db) = setup_with("local", vec![json!({"_id": "a"})]).await;
+        db.save_checkpoint("sync-1", &Seq::from("1-a")).await.unwrap();
+        db.save_checkpoint("sync-2", &Seq::from("2-b")).await.unwrap();
+
+        let local = db.get_local_docs::<Value>(None).await.unwrap();
+        assert_eq!(local.ids().collect::<Vec<_>>(), vec!["_local/sync-1", "_local/sync-2"]);
+        assert_eq!(local[1]["seq"], "2-b");
+
+        let first = QueryParams::default().limit(1).include_docs(false);
+        let local = db.get_local_docs::<Value>(Some(first)).await.unwrap();
+        assert_eq!(local.rows.len(), 1);
+        assert_eq!(local[0].get("seq"), None);
+
+        assert!(db.remove(local.into_rows().remove(0)).await);
+        assert_eq!(db.get_local_docs::<Value>(None).await.unwrap().rows.len(), 1);
+        assert_eq!(db.get_all::<Value>().await.unwrap().rows.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rename_db() {
+        let couch = MockCouch::start().await;
+        let client = couch.client().unwrap();
+        client.make_db("_replicator").await.unwrap();
+        let db = client.db("old").await.unwrap();
+        db.create(json!({"_id": "a"})).await.unwrap();
+        db.create(json!({"_id": "_design/app"})).await.unwrap();
+        db.save_checkpoint("sync", &Seq::from("1")).await.unwrap();
+
+        let mut steps = vec![];
+        let options = RenameOptions::default().credentials("admin", "secret");
+        let renamed = client
+            .rename_db("old", "new", &options, |step| steps.push(step.clone()))
+            .await
+            .unwrap();
+        assert_eq!(renamed.name(), "new");
+        assert_eq!(
+            steps,
+            vec![
+                RenameProgress::Created {
+                    q: 1,
+                    n: 1,
+                    partitioned: false
+                },
+                RenameProgress::Replicating {
+                    docs_written: 2,
+                    doc_count: 2
+                },
+                RenameProgress::Verified { doc_count: 2 },
+                RenameProgress::Deleted,
+            ]
+        );
+        assert_eq!(client.list_dbs().await.unwrap(), vec![s!("_replicator"), s!("new")]);
+        assert!(renamed.get::<Value>("_design/app").await.is_ok());
+        assert_eq!(renamed.get_checkpoint("sync").await.unwrap(), None);
+        assert!(couch
+            .client()
+            .unwrap()
+            .db("_replicator")
+            .await
+            .unwrap()
+            .get_all::<Value>()
+            .await
+            .unwrap()
+            .rows
+            .is_empty());
+
+        // the target must not exist yet
+        client.make_db("other").await.unwrap();
+        let err = client.rename_db("new", "other", &options, |_| {}).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::PRECONDITION_FAILED);
+        assert!(client.get_info("new").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_partition() {
+        let docs = vec![
+            json!({"_id": "a:1", "n": 1}),
+            json!({"_id": "a:2", "n": 2}),
+            json!({"_id": "b:1", "n": 1}),
+        ];
+        let (_couch, db) = setup_with("partitioned", docs).await;
+
+        let partition = db.partition("a");
+        let all = partition.get_all_params::<Value>(None).await.unwrap();
+        assert_eq!(all.rows.len(), 2);
+        let found = partition.find::<Value>(&FindQuery::new(json!({"n": 1}))).await.unwrap();
+        assert_eq!(found.rows.len(), 1);
+        assert_eq!(found.rows[0]["_id"], "a:1");
+    }
+
+    #[tokio::test]
+    async fn test_changes() {
+        let docs = (0..3).map(|n| json!({"_id": format!("doc{}", n), "n": n})).collect();
+        let (_couch, db) = setup_with("changes", docs).await;
+        let doc0: Value = db.get("doc0").await.unwrap();
+        db.remove(doc0).await;
+
+        let changes = db.changes(&ChangesOptions::default()).await.unwrap();
+        let ids: Vec<_> = changes.results.iter().map(|change| change.id.as_str()).collect();
+        assert_eq!(ids, vec!["doc1", "doc2", "doc0"]);
+        assert!(changes.results[2].deleted);
+
+        let page = db.changes(&ChangesOptions::default().limit(1)).await.unwrap();
+        assert_eq!(page.pending, Some(2));
+        let options = ChangesOptions::default()
+            .since(page.last_seq)
+            .include_docs(true)
+            .heartbeat(Duration::from_secs(1))
+            .selector(json!({"n": {"$gt": 1}}));
+        let filtered = db.changes(&options).await.unwrap();
+        assert_eq!(filtered.results.len(), 1);
+        assert_eq!(filtered.results[0].doc.as_ref().unwrap()["n"], 2);
+
+        assert_eq!(db.get_checkpoint("reader").await.unwrap(), None);
+        db.save_checkpoint("reader", &filtered.last_seq).await.unwrap();
+        db.save_checkpoint("reader", &filtered.last_seq).await.unwrap();
+        assert_eq!(db.get_checkpoint("reader").await.unwrap(), Some(filtered.last_seq));
+
+        let now = ChangesOptions::default().since_now();
+        assert!(db.changes(&now).await.unwrap().results.is_empty());
+
+        let stream = db.changes_stream(ChangesOptions::default().doc_ids(vec![s!("doc2"), s!("doc3")]));
+        let mut stream = Box::pin(stream);
+        assert_eq!(stream.try_next().await.unwrap().unwrap().id, "doc2");
+        db.create(json!({"_id": "doc3"})).await.unwrap();
+        assert_eq!(stream.try_next().await.unwrap().unwrap().id, "doc3");
+    }
+
+    #[tokio::test]
+    async fn test_views() {
+        let docs = vec![
+            json!({"type": "a", "amount": 1}),
+            json!({"type": "b", "amount": 2}),
+            json!({"type": "a", "amount": 3}),
+        ];
+        let (couch, db) = setup_with("views", docs).await;
+        couch.define_view("views", "stats", "by_type", |doc| {
+            vec![(doc["type"].clone(), doc["amount"].clone())]
+        });
+
+        let rows = db.query::<Value, Value, Value>("stats", "by_type", None).await.unwrap();
+        let keys: Vec<_> = rows.rows.iter().map(|row| row.key.clone()).collect();
+        assert_eq!(keys, vec![json!("a"), json!("a"), json!("b")]);
+
+        let views = CouchViews::new(
+            "by_type",
+            CouchFunc::with_builtin_reduce("function (doc) { emit(doc.type, doc.amount); }", BuiltinReduce::Sum),
+        );
+        db.create_view("stats", views).await.unwrap();
+
+        let grouped = db
+            .query::<Value, Value, Value>("stats", "by_type", Some(QueryParams::default().group(true)))
+            .await
+            .unwrap();
+        let sums: Vec<_> = grouped
+            .rows
+            .iter()
+            .map(|row| (row.key.clone(), row.value.clone()))
+            .collect();
+        assert_eq!(sums, vec![(json!("a"), json!(4)), (json!("b"), json!(2))]);
+
+        let missing = db.query::<Value, Value, Value>("stats", "missing", None).await;
+        assert!(matches!(missing, Err(CouchError::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_users() {
+        let couch = MockCouch::start().await;
+        let client = couch.client().unwrap();
+        client.make_db("_users").await.unwrap();
+        assert_eq!(client.get_user("john").await.unwrap(), None);
+
+        let created = client.create_user("john", "secret", vec![s!("reader")]).await.unwrap();
+        assert!(created.rev.starts_with("1-"));
+        assert_eq!(created.password, None);
+
+        client
+            .add_user_roles("john", &[s!("writer"), s!("reader")])
+            .await
+            .unwrap();
+        let updated = client.remove_user_roles("john", &[s!("reader")]).await.unwrap();
+        assert_eq!(updated.roles, vec![s!("writer")]);
+
+        client.set_user_password("john", "rotated").await.unwrap();
+        let john = client.get_user("john").await.unwrap().unwrap();
+        assert!(john.rev.starts_with("4-"));
+        assert_eq!(john.roles, vec![s!("writer")]);
+
+        let missing = client.add_user_roles("jane", &[s!("reader")]).await;
+        assert!(matches!(missing, Err(CouchError::NotFound { .. })));
+        assert!(client
+            .create_user("john", "again", vec![])
+            .await
+            .unwrap_err()
+            .is_conflict());
+    }
+}
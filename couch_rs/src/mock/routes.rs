@@ -0,0 +1,235 @@
+//! Maps the requests of the CouchDB HTTP API onto the in-memory store
+
+use super::store::{is_system_database, Failure, MockDatabase, Store};
+use hyper::{Method, StatusCode};
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// Status and body of a successful request
+pub(crate) type Outcome = Result<(StatusCode, Value), Failure>;
+
+pub(crate) fn route(store: &mut Store, method: &Method, path: &str, query: Option<&str>, body: Value) -> Outcome {
+    let segments: Vec<String> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let query: HashMap<String, String> = url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .into_owned()
+        .collect();
+    let method = method.as_str();
+
+    match (method, segments.as_slice()) {
+        ("GET", []) => ok(json!({
+            "couchdb": "Welcome",
+            "version": "3.3.3",
+            "vendor": {"name": "couch_rs mock"}
+        })),
+        ("GET", ["_up"]) => ok(json!({"status": "ok"})),
+        ("GET", ["_all_dbs"]) => ok(json!(store.databases.keys().collect::<Vec<_>>())),
+        ("GET", ["_active_tasks"]) => ok(json!([])),
+        ("GET", ["_scheduler", "docs", "_replicator", id]) => ok(store.replicate(id)?),
+        (_, [endpoint, ..]) if endpoint.starts_with('_') && !is_system_database(endpoint) => {
+            Err(unsupported(method, path))
+        }
+
+        ("HEAD", [db]) | ("GET", [db]) => {
+            let info = store.database(db)?.info(db);
+            ok(info)
+        }
+        ("PUT", [db]) => {
+            store.create_database(db)?;
+            created(json!({"ok": true}))
+        }
+        ("DELETE", [db]) => {
+            store.delete_database(db)?;
+            ok(json!({"ok": true}))
+        }
+        ("POST", [db]) => {
+            let database = store.database(db)?;
+            let id = match body["_id"].as_str() {
+                Some(id) => id.to_string(),
+                None => database.new_id(),
+            };
+            let rev = database.write_doc(&id, object(body)?)?;
+            written(&query, &id, &rev)
+        }
+
+        ("POST", [db, "_bulk_docs"]) => {
+            let docs = body["docs"]
+                .as_array()
+                .ok_or_else(|| Failure::bad_request("POST body must include `docs` parameter."))?;
+            created(store.database(db)?.bulk_docs(docs))
+        }
+        ("GET", [db, "_all_docs"]) => ok(store.database(db)?.all_docs(&query_params(&query))),
+        ("POST", [db, "_all_docs"]) => ok(store.database(db)?.all_docs(&body)),
+        ("POST", [db, "_all_docs", "queries"]) => {
+            let database = store.database(db)?;
+            queries(&body, |params| Ok(database.all_docs(params)))
+        }
+        ("GET", [db, "_design_docs"]) => ok(store.database(db)?.design_docs(&query_params(&query))),
+        ("POST", [db, "_design_docs"]) => ok(store.database(db)?.design_docs(&body)),
+        ("POST", [db, "_design_docs", "queries"]) => {
+            let database = store.database(db)?;
+            queries(&body, |params| Ok(database.design_docs(params)))
+        }
+        ("GET", [db, "_local_docs"]) => ok(store.database(db)?.local_docs(&query_params(&query))),
+        ("POST", [db, "_local_docs"]) => ok(store.database(db)?.local_docs(&body)),
+        ("GET", [db, "_changes"]) => ok(store.database(db)?.changes(&query_params(&query), &Value::Null)?),
+        ("POST", [db, "_changes"]) => ok(store.database(db)?.changes(&query_params(&query), &body)?),
+        ("POST", [db, "_find"]) => ok(store.database(db)?.find(&body)?),
+        ("POST", [db, "_partition", partition, "_find"]) => {
+            let mut query = body;
+            query["selector"] = json!({"$and": [
+                query["selector"].take(),
+                {"_id": {"$beginsWith": format!("{}:", partition)}}
+            ]});
+            ok(store.database(db)?.find(&query)?)
+        }
+        ("POST", [db, "_partition", partition, "_all_docs"]) => {
+            // the documents of a partition are the range of IDs that start with its name
+            let mut params = body;
+            params["start_key"] = json!(format!("{}:", partition));
+            params["end_key"] = json!(format!("{}:\u{fff0}", partition));
+            ok(store.database(db)?.all_docs(&params))
+        }
+        ("POST", [db, "_explain"]) => ok(store.database(db)?.explain(db, &body)?),
+        ("GET", [db, "_index"]) => ok(store.database(db)?.indexes()),
+        ("POST", [db, "_index"]) => ok(store.database(db)?.create_index(&body)?),
+        ("POST", [db, "_purge"]) => {
+            let revs = object(body)?;
+            created(store.database(db)?.purge(&revs))
+        }
+        ("GET", [db, "_purged_infos_limit"]) => ok(json!(store.database(db)?.purged_infos_limit())),
+        ("PUT", [db, "_purged_infos_limit"]) => {
+            store.database(db)?.set_purged_infos_limit(&body)?;
+            ok(json!({"ok": true}))
+        }
+        ("POST", [db, "_compact"]) | ("POST", [db, "_compact", _]) | ("POST", [db, "_view_cleanup"]) => {
+            store.database(db)?;
+            Ok((StatusCode::ACCEPTED, json!({"ok": true})))
+        }
+
+        ("GET", [db, "_design", design, "_info"]) => ok(store.database(db)?.design_info(design)?),
+        ("GET", [db, "_design", design, "_view", view]) => query_view(store, db, design, view, &query_params(&query)),
+        ("POST", [db, "_design", design, "_view", view]) if body.get("queries").is_some() => queries(&body, |params| {
+            query_view(store, db, design, view, params).map(|(_, rows)| rows)
+        }),
+        ("POST", [db, "_design", design, "_view", view]) => query_view(store, db, design, view, &body),
+        ("POST", [db, "_design", design, "_view", view, "queries"]) => queries(&body, |params| {
+            query_view(store, db, design, view, params).map(|(_, rows)| rows)
+        }),
+
+        (_, [db, "_design", design]) => document(
+            store.database(db)?,
+            method,
+            &format!("_design/{}", design),
+            &query,
+            body,
+        ),
+        (_, [db, "_local", name]) => document(store.database(db)?, method, &format!("_local/{}", name), &query, body),
+        (_, [db, id]) if !id.starts_with('_') => document(store.database(db)?, method, id, &query, body),
+        _ => Err(unsupported(method, path)),
+    }
+}
+
+fn document(
+    database: &mut MockDatabase,
+    method: &str,
+    id: &str,
+    query: &HashMap<String, String>,
+    body: Value,
+) -> Outcome {
+    let rev = query.get("rev").map(String::as_str);
+    match method {
+        "GET" | "HEAD" => ok(database.get_doc(id, rev)?),
+        "PUT" => {
+            let mut doc = object(body)?;
+            if let Some(rev) = rev {
+                doc.entry("_rev").or_insert_with(|| Value::from(rev));
+            }
+            let rev = database.write_doc(id, doc)?;
+            written(query, id, &rev)
+        }
+        "DELETE" => {
+            let rev = database.delete_doc(id, rev)?;
+            ok(json!({"ok": true, "id": id, "rev": rev}))
+        }
+        _ => Err(Failure::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method_not_allowed",
+            "Only DELETE,GET,HEAD,PUT allowed",
+        )),
+    }
+}
+
+fn query_view(store: &Store, db: &str, design: &str, view: &str, params: &Value) -> Outcome {
+    let database = store
+        .databases
+        .get(db)
+        .ok_or_else(|| Failure::not_found("Database does not exist."))?;
+    let reduce = database.reduce_function(design, view);
+    let map = store
+        .views
+        .get(&(db.to_string(), design.to_string(), view.to_string()))
+        .ok_or_else(|| match database.get_doc(&format!("_design/{}", design), None) {
+            Ok(doc) if doc["views"].get(view).is_some() => Failure::not_implemented(format!(
+                "The mock server does not run JavaScript; define the map function of {}/{} with MockCouch::define_view",
+                design, view
+            )),
+            _ => Failure::not_found("missing_named_view"),
+        })?;
+    ok(database.query_view(map, reduce.as_deref(), params)?)
+}
+
+/// Runs each of the `queries` of a multiple query request
+fn queries(body: &Value, mut run: impl FnMut(&Value) -> Result<Value, Failure>) -> Outcome {
+    let queries = body["queries"]
+        .as_array()
+        .ok_or_else(|| Failure::bad_request("Missing required key: queries"))?;
+    let results = queries.iter().map(&mut run).collect::<Result<Vec<_>, _>>()?;
+    ok(json!({ "results": results }))
+}
+
+/// Query parameters hold JSON values, such as `"key"` or `true`; values that are not valid JSON are
+/// taken as strings
+fn query_params(query: &HashMap<String, String>) -> Value {
+    let params: Map<String, Value> = query
+        .iter()
+        .map(|(name, value)| {
+            let value = serde_json::from_str(value).unwrap_or_else(|_| Value::from(value.as_str()));
+            (name.clone(), value)
+        })
+        .collect();
+    Value::Object(params)
+}
+
+fn object(body: Value) -> Result<Map<String, Value>, Failure> {
+    match body {
+        Value::Object(doc) => Ok(doc),
+        _ => Err(Failure::bad_request("Document must be a JSON object")),
+    }
+}
+
+fn unsupported(method: &str, path: &str) -> Failure {
+    Failure::not_implemented(format!("The mock server does not support {} {}", method, path))
+}
+
+fn ok(body: Value) -> Outcome {
+    Ok((StatusCode::OK, body))
+}
+
+fn created(body: Value) -> Outcome {
+    Ok((StatusCode::CREATED, body))
+}
+
+/// The response to a document write; in batch mode, CouchDB only accepts the write, without a revision
+fn written(query: &HashMap<String, String>, id: &str, rev: &str) -> Outcome {
+    if query.get("batch").map(String::as_str) == Some("ok") {
+        Ok((StatusCode::ACCEPTED, json!({"ok": true, "id": id})))
+    } else {
+        created(json!({"ok": true, "id": id, "rev": rev}))
+    }
+}
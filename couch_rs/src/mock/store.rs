@@ -0,0 +1,925 @@
+//! In-memory databases of the mock server: documents and their revisions, Mango indexes, and the
+//! views computed by the map functions registered with the server
+
+use super::mango::{self, collate};
+use crate::types::index::SelectorFields;
+use hyper::StatusCode;
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Map, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// Map function of a view; returns the key and value of every row to emit for a document
+pub(crate) type MapFunction = Arc<dyn Fn(&Value) -> Vec<(Value, Value)> + Send + Sync>;
+
+/// Members starting with an underscore that documents may hold
+const SPECIAL_MEMBERS: &[&str] = &[
+    "_attachments",
+    "_conflicts",
+    "_deleted_conflicts",
+    "_local_seq",
+    "_revisions",
+    "_revs_info",
+];
+
+const DEFAULT_FIND_LIMIT: usize = 25;
+
+const DEFAULT_PURGED_INFOS_LIMIT: u64 = 1000;
+
+/// Databases that may be created although their name starts with an underscore
+const SYSTEM_DATABASES: &[&str] = &["_users", "_replicator", "_global_changes"];
+
+/// Row of a view: its key, the id of the document that emitted it, and its value
+type ViewRow = (Value, Value, Value);
+
+/// A request that failed, rendered as a CouchDB error response
+#[derive(Debug)]
+pub(crate) struct Failure {
+    pub(crate) status: StatusCode,
+    pub(crate) error: &'static str,
+    pub(crate) reason: String,
+}
+
+impl Failure {
+    pub(crate) fn new(status: StatusCode, error: &'static str, reason: impl Into<String>) -> Failure {
+        Failure {
+            status,
+            error,
+            reason: reason.into(),
+        }
+    }
+
+    pub(crate) fn not_found(reason: impl Into<String>) -> Failure {
+        Failure::new(StatusCode::NOT_FOUND, "not_found", reason)
+    }
+
+    pub(crate) fn bad_request(reason: impl Into<String>) -> Failure {
+        Failure::new(StatusCode::BAD_REQUEST, "bad_request", reason)
+    }
+
+    pub(crate) fn not_implemented(reason: impl Into<String>) -> Failure {
+        Failure::new(StatusCode::NOT_IMPLEMENTED, "not_implemented", reason)
+    }
+
+    fn conflict() -> Failure {
+        Failure::new(StatusCode::CONFLICT, "conflict", "Document update conflict.")
+    }
+}
+
+/// The databases of the mock server, and the map functions of their views
+#[derive(Default)]
+pub(crate) struct Store {
+    pub(crate) databases: BTreeMap<String, MockDatabase>,
+    /// Map functions by database, design document and view name
+    pub(crate) views: HashMap<(String, String, String), MapFunction>,
+}
+
+impl Store {
+    pub(crate) fn database(&mut self, name: &str) -> Result<&mut MockDatabase, Failure> {
+        self.databases
+            .get_mut(name)
+            .ok_or_else(|| Failure::not_found("Database does not exist."))
+    }
+
+    pub(crate) fn create_database(&mut self, name: &str) -> Result<(), Failure> {
+        let valid = is_system_database(name)
+            || name.starts_with(|c: char| c.is_ascii_lowercase())
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "_$()+-/".contains(c));
+        if !valid {
+            return Err(Failure::new(
+                StatusCode::BAD_REQUEST,
+                "illegal_database_name",
+                format!("Name: '{}'. Only lowercase characters (a-z), digits (0-9), and any of the characters _, $, (, ), +, -, and / are allowed. Must begin with a letter.", name),
+            ));
+        }
+        if self.databases.contains_key(name) {
+            return Err(Failure::new(
+                StatusCode::PRECONDITION_FAILED,
+                "file_exists",
+                "The database could not be created, the file already exists.",
+            ));
+        }
+
+        self.databases.insert(name.to_string(), MockDatabase::default());
+        Ok(())
+    }
+
+    pub(crate) fn delete_database(&mut self, name: &str) -> Result<(), Failure> {
+        self.databases
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| Failure::not_found("Database does not exist."))
+    }
+
+    /// The scheduler state of the replication defined by a document of the `_replicator` database.
+    /// The mock replicates at once, when the state is read, so replications are always completed,
+    /// or failed when a database does not exist.
+    pub(crate) fn replicate(&mut self, id: &str) -> Result<Value, Failure> {
+        let replication = self.database("_replicator")?.get_doc(id, None)?;
+        let mut state = json!({"database": "_replicator", "doc_id": id});
+        match self.copy_docs(&replication["source"], &replication["target"]) {
+            Ok(docs_written) => {
+                state["state"] = json!("completed");
+                state["info"] =
+                    json!({"docs_read": docs_written, "docs_written": docs_written, "doc_write_failures": 0});
+            }
+            Err(failure) => {
+                state["state"] = json!("failed");
+                state["info"] = json!({"error": format!("{}: {}", failure.error, failure.reason)});
+            }
+        }
+        Ok(state)
+    }
+
+    /// Copies the latest revision of the documents of one database to another, except local
+    /// documents, returning the number of documents written
+    fn copy_docs(&mut self, source: &Value, target: &Value) -> Result<u64, Failure> {
+        let docs: Vec<(String, StoredDoc)> = self
+            .database(&endpoint_database(source)?)?
+            .docs
+            .iter()
+            .filter(|(id, _)| !id.starts_with("_local/"))
+            .map(|(id, doc)| (id.clone(), doc.clone()))
+            .collect();
+
+        let target = self.database(&endpoint_database(target)?)?;
+        let mut docs_written = 0;
+        for (id, doc) in docs {
+            if target.docs.get(&id).map(|existing| &existing.rev) != Some(&doc.rev) {
+                target.update_seq += 1;
+                let seq = target.update_seq;
+                target.docs.insert(id, StoredDoc { seq, ..doc });
+                docs_written += 1;
+            }
+        }
+        Ok(docs_written)
+    }
+}
+
+/// Latest revision of a document; older revisions are not kept
+#[derive(Clone)]
+struct StoredDoc {
+    rev: String,
+    deleted: bool,
+    /// Update sequence of the latest change of the document
+    seq: u64,
+    /// Members of the document, without `_id` and `_rev`
+    body: Map<String, Value>,
+}
+
+impl StoredDoc {
+    fn generation(&self) -> u64 {
+        revision_generation(&self.rev)
+    }
+
+    fn to_json(&self, id: &str) -> Value {
+        let mut doc = Map::new();
+        doc.insert(s!("_id"), Value::from(id));
+        doc.insert(s!("_rev"), Value::from(self.rev.as_str()));
+        if self.deleted {
+            doc.insert(s!("_deleted"), Value::Bool(true));
+        }
+        doc.extend(self.body.clone());
+        Value::Object(doc)
+    }
+}
+
+/// A database of the mock server
+#[derive(Default)]
+pub(crate) struct MockDatabase {
+    docs: BTreeMap<String, StoredDoc>,
+    update_seq: u64,
+    purge_seq: u64,
+    ids_generated: u64,
+    /// Mango indexes, as listed by `GET /{db}/_index`
+    indexes: Vec<Value>,
+    /// `None` until it is set, when the default of CouchDB applies
+    purged_infos_limit: Option<u64>,
+}
+
+impl MockDatabase {
+    pub(crate) fn purged_infos_limit(&self) -> u64 {
+        self.purged_infos_limit.unwrap_or(DEFAULT_PURGED_INFOS_LIMIT)
+    }
+
+    pub(crate) fn set_purged_infos_limit(&mut self, limit: &Value) -> Result<(), Failure> {
+        match limit.as_u64() {
+            Some(limit) if limit > 0 => {
+                self.purged_infos_limit = Some(limit);
+                Ok(())
+            }
+            _ => Err(Failure::bad_request("`purged_infos_limit` must be positive integer")),
+        }
+    }
+
+    pub(crate) fn info(&self, name: &str) -> Value {
+        let doc_count = self.visible_docs().count();
+        let doc_del_count = self.docs.values().filter(|doc| doc.deleted).count();
+        let size: usize = self
+            .docs
+            .values()
+            .map(|doc| serde_json::to_string(&doc.body).map_or(0, |body| body.len()))
+            .sum();
+
+        json!({
+            "cluster": {"n": 1, "q": 1, "r": 1, "w": 1},
+            "compact_running": false,
+            "db_name": name,
+            "disk_format_version": 8,
+            "doc_count": doc_count,
+            "doc_del_count": doc_del_count,
+            "instance_start_time": "0",
+            "purge_seq": format!("{}-mock", self.purge_seq),
+            "sizes": {"active": size, "external": size, "file": size},
+            "update_seq": self.update_seq(),
+            "props": {}
+        })
+    }
+
+    pub(crate) fn update_seq(&self) -> String {
+        format!("{}-mock", self.update_seq)
+    }
+
+    /// Documents that are neither deleted nor local, by id
+    fn visible_docs(&self) -> impl Iterator<Item = (&String, &StoredDoc)> {
+        self.docs
+            .iter()
+            .filter(|(id, doc)| !doc.deleted && !id.starts_with("_local/"))
+    }
+
+    /// Documents that queries and views read: the visible documents, without design documents
+    fn data_docs(&self) -> impl Iterator<Item = Value> + '_ {
+        self.visible_docs()
+            .filter(|(id, _)| !id.starts_with("_design/"))
+            .map(|(id, doc)| doc.to_json(id))
+    }
+
+    pub(crate) fn get_doc(&self, id: &str, rev: Option<&str>) -> Result<Value, Failure> {
+        let doc = self.docs.get(id).ok_or_else(|| Failure::not_found("missing"))?;
+        match rev {
+            Some(rev) if rev != doc.rev => Err(Failure::not_found("missing")),
+            None if doc.deleted => Err(Failure::not_found("deleted")),
+            _ => Ok(doc.to_json(id)),
+        }
+    }
+
+    pub(crate) fn current_rev(&self, id: &str) -> Option<&str> {
+        self.docs.get(id).filter(|doc| !doc.deleted).map(|doc| doc.rev.as_str())
+    }
+
+    /// Stores a new revision of a document, and returns it. The `_rev` of the document must be the
+    /// current revision, unless the document is new or deleted; `_deleted` deletes it.
+    pub(crate) fn write_doc(&mut self, id: &str, mut doc: Map<String, Value>) -> Result<String, Failure> {
+        if id.is_empty() {
+            return Err(Failure::bad_request("Document id must not be empty"));
+        }
+        if id.starts_with('_') && !id.starts_with("_design/") && !id.starts_with("_local/") {
+            return Err(Failure::bad_request(
+                "Only reserved document ids may start with underscore.",
+            ));
+        }
+
+        doc.remove("_id");
+        let rev = doc.remove("_rev");
+        let rev = rev.as_ref().and_then(Value::as_str);
+        let deleted = doc.remove("_deleted") == Some(Value::Bool(true));
+        if let Some(member) = doc
+            .keys()
+            .find(|key| key.starts_with('_') && !SPECIAL_MEMBERS.contains(&key.as_str()))
+        {
+            return Err(Failure::new(
+                StatusCode::BAD_REQUEST,
+                "doc_validation",
+                format!("Bad special document member: {}", member),
+            ));
+        }
+
+        let generation = match self.docs.get(id) {
+            Some(existing) if !existing.deleted && rev != Some(existing.rev.as_str()) => {
+                return Err(Failure::conflict())
+            }
+            Some(existing) if existing.deleted && rev.is_some() && rev != Some(existing.rev.as_str()) => {
+                return Err(Failure::conflict())
+            }
+            None if rev.is_some() => return Err(Failure::conflict()),
+            Some(existing) => existing.generation() + 1,
+            None => 1,
+        };
+
+        let new_rev = if id.starts_with("_local/") {
+            format!("0-{}", generation)
+        } else {
+            self.update_seq += 1;
+            new_revision(generation, id, rev, deleted, &doc)
+        };
+        let stored = StoredDoc {
+            rev: new_rev.clone(),
+            deleted,
+            seq: self.update_seq,
+            body: if deleted { Map::new() } else { doc },
+        };
+        self.docs.insert(id.to_string(), stored);
+        Ok(new_rev)
+    }
+
+    pub(crate) fn delete_doc(&mut self, id: &str, rev: Option<&str>) -> Result<String, Failure> {
+        if self.current_rev(id).is_none() {
+            return Err(Failure::not_found(if self.docs.contains_key(id) {
+                "deleted"
+            } else {
+                "missing"
+            }));
+        }
+        let mut tombstone = Map::new();
+        tombstone.insert(s!("_deleted"), Value::Bool(true));
+        if let Some(rev) = rev {
+            tombstone.insert(s!("_rev"), Value::from(rev));
+        }
+        self.write_doc(id, tombstone)
+    }
+
+    /// Writes the documents of a `_bulk_docs` request; documents without an `_id` get a new one
+    pub(crate) fn bulk_docs(&mut self, docs: &[Value]) -> Value {
+        let mut results = vec![];
+        for doc in docs {
+            let doc = match doc.as_object() {
+                Some(doc) => doc.clone(),
+                None => {
+                    results.push(json!({"error": "bad_request", "reason": "Document must be a JSON object"}));
+                    continue;
+                }
+            };
+            let id = match doc.get("_id").and_then(Value::as_str) {
+                Some(id) => id.to_string(),
+                None => self.new_id(),
+            };
+            results.push(match self.write_doc(&id, doc) {
+                Ok(rev) => json!({"ok": true, "id": id, "rev": rev}),
+                Err(failure) => json!({"id": id, "error": failure.error, "reason": failure.reason}),
+            });
+        }
+        Value::Array(results)
+    }
+
+    /// Id for a document created without one. CouchDB generates random ids; the mock numbers them.
+    pub(crate) fn new_id(&mut self) -> String {
+        self.ids_generated += 1;
+        format!("{:032x}", self.ids_generated)
+    }
+
+    /// The changes feed. The mock has no long polling: a `longpoll` feed returns at once, like a
+    /// `normal` one. Filters written in JavaScript are not supported.
+    pub(crate) fn changes(&self, params: &Value, body: &Value) -> Result<Value, Failure> {
+        let since = match &params["since"] {
+            Value::String(since) if since == "now" => self.update_seq,
+            since => sequence_number(since),
+        };
+        let include_docs = params["include_docs"].as_bool() == Some(true);
+        let matches = |id: &str, doc: &StoredDoc| -> Result<bool, Failure> {
+            Ok(match params["filter"].as_str() {
+                None => true,
+                Some("_doc_ids") => body["doc_ids"]
+                    .as_array()
+                    .is_some_and(|ids| ids.iter().any(|doc_id| doc_id == id)),
+                Some("_selector") => {
+                    !doc.deleted && mango::matches(&doc.to_json(id), &body["selector"]).map_err(invalid_query)?
+                }
+                Some("_design") => id.starts_with("_design/"),
+                Some(filter) => {
+                    return Err(Failure::not_implemented(format!(
+                        "The mock server does not run JavaScript filter {}",
+                        filter
+                    )))
+                }
+            })
+        };
+
+        let mut changed = vec![];
+        for (id, doc) in &self.docs {
+            if doc.seq > since && !id.starts_with("_local/") && matches(id, doc)? {
+                changed.push((id, doc));
+            }
+        }
+        changed.sort_by_key(|(_, doc)| doc.seq);
+        if params["descending"].as_bool() == Some(true) {
+            changed.reverse();
+        }
+
+        let limit = params["limit"].as_u64().map_or(usize::MAX, |limit| limit as usize);
+        let pending = changed.len().saturating_sub(limit);
+        let results: Vec<Value> = changed
+            .into_iter()
+            .take(limit)
+            .map(|(id, doc)| {
+                let mut change = json!({
+                    "seq": format!("{}-mock", doc.seq),
+                    "id": id,
+                    "changes": [{"rev": doc.rev}]
+                });
+                if doc.deleted {
+                    change["deleted"] = Value::Bool(true);
+                }
+                if include_docs {
+                    change["doc"] = doc.to_json(id);
+                }
+                change
+            })
+            .collect();
+
+        let last_seq = match results.last() {
+            Some(last) if pending > 0 => last["seq"].clone(),
+            _ => Value::from(self.update_seq()),
+        };
+        Ok(json!({"results": results, "last_seq": last_seq, "pending": pending}))
+    }
+
+    /// Removes the given revisions without leaving a tombstone
+    pub(crate) fn purge(&mut self, revs: &Map<String, Value>) -> Value {
+        let mut purged = Map::new();
+        for (id, revs) in revs {
+            let revs: Vec<&str> = revs
+                .as_array()
+                .map(|revs| revs.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default();
+            let removed = match self.docs.get(id) {
+                Some(doc) if revs.contains(&doc.rev.as_str()) => self.docs.remove(id).map(|doc| doc.rev),
+                _ => None,
+            };
+            purged.insert(id.clone(), json!(removed.into_iter().collect::<Vec<_>>()));
+        }
+        self.purge_seq += 1;
+        json!({"purge_seq": null, "purged": purged})
+    }
+
+    pub(crate) fn all_docs(&self, params: &Value) -> Value {
+        self.list_docs(params, |id| !id.starts_with("_local/"))
+    }
+
+    pub(crate) fn design_docs(&self, params: &Value) -> Value {
+        self.list_docs(params, |id| id.starts_with("_design/"))
+    }
+
+    pub(crate) fn local_docs(&self, params: &Value) -> Value {
+        self.list_docs(params, |id| id.starts_with("_local/"))
+    }
+
+    /// The `_all_docs` rows of the documents whose ID is `listed`
+    fn list_docs(&self, params: &Value, listed: impl Fn(&str) -> bool) -> Value {
+        let include_docs = params["include_docs"].as_bool().unwrap_or(false);
+        let row = |id: &str, doc: &StoredDoc| {
+            let mut row = json!({"id": id, "key": id, "value": {"rev": doc.rev}});
+            if doc.deleted {
+                row["value"]["deleted"] = Value::Bool(true);
+                row["doc"] = Value::Null;
+            } else if include_docs {
+                row["doc"] = doc.to_json(id);
+            }
+            row
+        };
+
+        let listed_docs = || self.docs.iter().filter(|(id, doc)| !doc.deleted && listed(id));
+        let total_rows = listed_docs().count();
+        let (offset, rows) = match params["keys"].as_array() {
+            Some(keys) => {
+                let rows: Vec<Value> = keys
+                    .iter()
+                    .map(|key| {
+                        let found = key
+                            .as_str()
+                            .and_then(|id| self.docs.get_key_value(id))
+                            .filter(|(id, _)| listed(id));
+                        match found {
+                            Some((id, doc)) => row(id, doc),
+                            None => json!({"key": key, "error": "not_found"}),
+                        }
+                    })
+                    .collect();
+                (0, rows)
+            }
+            None => {
+                let range = KeyRange::new(params);
+                let mut ids: Vec<(Value, &String, &StoredDoc)> = listed_docs()
+                    .map(|(id, doc)| (Value::from(id.as_str()), id, doc))
+                    .collect();
+                if range.descending {
+                    ids.reverse();
+                }
+                let (offset, selected) = range.select(&ids, |(key, _, _)| key);
+                (offset, selected.iter().map(|(_, id, doc)| row(id, doc)).collect())
+            }
+        };
+
+        let mut response = json!({"total_rows": total_rows, "offset": offset, "rows": rows});
+        if params["update_seq"].as_bool() == Some(true) {
+            response["update_seq"] = Value::from(self.update_seq());
+        }
+        response
+    }
+
+    pub(crate) fn find(&self, query: &Value) -> Result<Value, Failure> {
+        let selector = query
+            .get("selector")
+            .filter(|selector| selector.is_object())
+            .ok_or_else(|| Failure::bad_request("Missing required key: selector"))?;
+
+        let mut docs = vec![];
+        for doc in self.data_docs() {
+            if mango::matches(&doc, selector).map_err(invalid_query)? {
+                docs.push(doc);
+            }
+        }
+        if let Some(sort) = query["sort"].as_array() {
+            mango::sort(&mut docs, sort).map_err(invalid_query)?;
+        }
+
+        let examined = docs.len();
+        let start = query["bookmark"]
+            .as_str()
+            .and_then(|bookmark| bookmark.parse::<usize>().ok())
+            .unwrap_or(0)
+            + query["skip"].as_u64().unwrap_or(0) as usize;
+        let limit = query["limit"]
+            .as_u64()
+            .map_or(DEFAULT_FIND_LIMIT, |limit| limit as usize);
+        let fields = query["fields"].as_array().filter(|fields| !fields.is_empty());
+        let docs: Vec<Value> = docs
+            .into_iter()
+            .skip(start)
+            .take(limit)
+            .map(|doc| match fields {
+                Some(fields) => mango::project(&doc, fields),
+                None => doc,
+            })
+            .collect();
+
+        // the bookmark of the mock is the position after the last document returned
+        let mut response = json!({"docs": docs, "bookmark": (start + docs.len()).to_string()});
+        if self.index_for(selector).is_none() {
+            response["warning"] = json!("No matching index found, create an index to optimize query time.");
+        }
+        if query["execution_stats"].as_bool() == Some(true) {
+            response["execution_stats"] = json!({
+                "total_keys_examined": 0,
+                "total_docs_examined": examined,
+                "total_quorum_docs_examined": 0,
+                "results_returned": docs.len(),
+                "execution_time_ms": 0.0
+            });
+        }
+        Ok(response)
+    }
+
+    /// The mock reads all documents for every query, but reports queries as CouchDB would: a query
+    /// uses an index when its selector compares all fields of the index
+    fn index_for(&self, selector: &Value) -> Option<&Value> {
+        let selected = SelectorFields::of(selector);
+        self.indexes.iter().find(|index| {
+            let specs = index["def"]["fields"].as_array().into_iter().flatten();
+            specs
+                .flat_map(|spec| spec.as_object().into_iter().flat_map(Map::keys))
+                .all(|field| selected.equal.contains(field) || selected.range.contains(field))
+        })
+    }
+
+    pub(crate) fn explain(&self, name: &str, query: &Value) -> Result<Value, Failure> {
+        if !query["selector"].is_object() {
+            return Err(Failure::bad_request("Missing required key: selector"));
+        }
+        Ok(json!({
+            "dbname": name,
+            "index": self.index_for(&query["selector"]).cloned().unwrap_or_else(all_docs_index),
+            "selector": query["selector"],
+            "opts": {},
+            "limit": query["limit"].as_u64().unwrap_or(DEFAULT_FIND_LIMIT as u64),
+            "skip": query["skip"].as_u64().unwrap_or(0),
+            "fields": query["fields"].as_array().map_or(json!("all_fields"), |fields| json!(fields)),
+        }))
+    }
+
+    pub(crate) fn indexes(&self) -> Value {
+        let indexes: Vec<Value> = std::iter::once(all_docs_index())
+            .chain(self.indexes.iter().cloned())
+            .collect();
+        json!({"total_rows": indexes.len(), "indexes": indexes})
+    }
+
+    pub(crate) fn create_index(&mut self, request: &Value) -> Result<Value, Failure> {
+        let fields = request["index"]["fields"]
+            .as_array()
+            .ok_or_else(|| Failure::bad_request("Missing required key: fields"))?;
+        let fields: Vec<Value> = fields
+            .iter()
+            .map(|spec| match spec {
+                Value::String(field) => Ok(json!({ field: "asc" })),
+                Value::Object(_) => Ok(spec.clone()),
+                _ => Err(Failure::bad_request("Invalid sort field")),
+            })
+            .collect::<Result<_, _>>()?;
+        let mut def = json!({ "fields": fields });
+        if let Some(filter) = request["index"].get("partial_filter_selector") {
+            def["partial_filter_selector"] = filter.clone();
+        }
+
+        let digest = format!("{:016x}", hash_of(&def.to_string()));
+        let name = request["name"].as_str().map_or(digest.clone(), str::to_string);
+        let ddoc = match request["ddoc"].as_str() {
+            Some(ddoc) if ddoc.starts_with("_design/") => ddoc.to_string(),
+            Some(ddoc) => format!("_design/{}", ddoc),
+            None => format!("_design/{}", digest),
+        };
+        let index = json!({"ddoc": ddoc, "name": name, "type": "json", "def": def});
+
+        let existing = self
+            .indexes
+            .iter()
+            .position(|index| index["ddoc"] == ddoc.as_str() && index["name"] == name.as_str());
+        let result = match existing {
+            Some(position) if self.indexes[position] == index => "exists",
+            Some(position) => {
+                self.indexes[position] = index;
+                "created"
+            }
+            None => {
+                self.indexes.push(index);
+                "created"
+            }
+        };
+        Ok(json!({"result": result, "id": ddoc, "name": name}))
+    }
+
+    pub(crate) fn design_info(&self, design: &str) -> Result<Value, Failure> {
+        self.current_rev(&format!("_design/{}", design))
+            .ok_or_else(|| Failure::not_found("missing"))?;
+        Ok(json!({
+            "name": design,
+            "view_index": {
+                "updater_running": false,
+                "update_seq": self.update_seq,
+                "signature": format!("{:032x}", hash_of(&design))
+            }
+        }))
+    }
+
+    /// The reduce function of a view, as defined in its design document
+    pub(crate) fn reduce_function(&self, design: &str, view: &str) -> Option<String> {
+        let id = format!("_design/{}", design);
+        self.current_rev(&id)?;
+        let design = &self.docs[&id].body;
+        design
+            .get("views")?
+            .get(view)?
+            .get("reduce")?
+            .as_str()
+            .map(str::to_string)
+    }
+
+    /// Runs the map function over all documents, and answers a view query. The view is reduced
+    /// with one of the built-in reduce functions, when it has one and `reduce` is not disabled.
+    pub(crate) fn query_view(&self, map: &MapFunction, reduce: Option<&str>, params: &Value) -> Result<Value, Failure> {
+        let mut rows: Vec<ViewRow> = vec![];
+        for doc in self.data_docs() {
+            let id = doc["_id"].clone();
+            rows.extend(map(&doc).into_iter().map(|(key, value)| (key, id.clone(), value)));
+        }
+        rows.sort_by(|(ka, ia, _), (kb, ib, _)| collate(ka, kb).then_with(|| collate(ia, ib)));
+
+        let reduce = reduce.filter(|_| params["reduce"].as_bool() != Some(false));
+        if reduce.is_some() && params["include_docs"].as_bool() == Some(true) {
+            return Err(Failure::new(
+                StatusCode::BAD_REQUEST,
+                "query_parse_error",
+                "`include_docs` is invalid for reduce",
+            ));
+        }
+
+        let total_rows = rows.len();
+        let range = KeyRange::new(params);
+        if range.descending {
+            rows.reverse();
+        }
+        let selected: Vec<&ViewRow> = match params["keys"].as_array() {
+            Some(keys) => keys
+                .iter()
+                .flat_map(|key| rows.iter().filter(move |(k, _, _)| collate(k, key).is_eq()))
+                .collect(),
+            None => range.filter(&rows, |(key, _, _)| key).collect(),
+        };
+
+        let reduce = match reduce {
+            Some(reduce) => reduce,
+            None => {
+                let include_docs = params["include_docs"].as_bool() == Some(true);
+                let (offset, page) = range.page(selected);
+                let rows: Vec<Value> = page
+                    .into_iter()
+                    .map(|(key, id, value)| {
+                        let mut row = json!({"id": id, "key": key, "value": value});
+                        if include_docs {
+                            row["doc"] = id
+                                .as_str()
+                                .and_then(|id| self.get_doc(id, None).ok())
+                                .unwrap_or(Value::Null);
+                        }
+                        row
+                    })
+                    .collect();
+                let mut response = json!({"total_rows": total_rows, "offset": offset, "rows": rows});
+                if params["update_seq"].as_bool() == Some(true) {
+                    response["update_seq"] = Value::from(self.update_seq());
+                }
+                return Ok(response);
+            }
+        };
+
+        let group_level = match (params["group"].as_bool(), params["group_level"].as_u64()) {
+            (_, Some(level)) => Some(level as usize),
+            (Some(true), None) => Some(usize::MAX),
+            _ => None,
+        };
+        let mut groups: Vec<(Value, Vec<&ViewRow>)> = vec![];
+        for row in selected {
+            let key = match group_level {
+                Some(level) => match &row.0 {
+                    Value::Array(items) => Value::Array(items.iter().take(level).cloned().collect()),
+                    key => key.clone(),
+                },
+                None => Value::Null,
+            };
+            match groups.last_mut() {
+                Some((group, rows)) if collate(group, &key).is_eq() => rows.push(row),
+                _ => groups.push((key, vec![row])),
+            }
+        }
+
+        let (_, groups) = range.page(groups);
+        let rows = groups
+            .into_iter()
+            .map(|(key, rows)| Ok(json!({"key": key, "value": builtin_reduce(reduce, &rows)?})))
+            .collect::<Result<Vec<_>, Failure>>()?;
+        Ok(json!({ "rows": rows }))
+    }
+}
+
+/// The range of keys, direction, `skip` and `limit` of a view or `_all_docs` query
+struct KeyRange {
+    start: Option<Value>,
+    end: Option<Value>,
+    key: Option<Value>,
+    inclusive_end: bool,
+    descending: bool,
+    skip: usize,
+    limit: Option<usize>,
+}
+
+impl KeyRange {
+    fn new(params: &Value) -> KeyRange {
+        let param = |names: &[&str]| names.iter().find_map(|name| params.get(*name)).cloned();
+        KeyRange {
+            start: param(&["start_key", "startkey"]),
+            end: param(&["end_key", "endkey"]),
+            key: param(&["key"]),
+            inclusive_end: params["inclusive_end"].as_bool().unwrap_or(true),
+            descending: params["descending"].as_bool().unwrap_or(false),
+            skip: params["skip"].as_u64().unwrap_or(0) as usize,
+            limit: params["limit"].as_u64().map(|limit| limit as usize),
+        }
+    }
+
+    /// Whether a key lies in the range; the rows are in the direction of the query
+    fn contains(&self, key: &Value) -> bool {
+        let forward = |ordering: std::cmp::Ordering| if self.descending { ordering.reverse() } else { ordering };
+        let before_start = self
+            .start
+            .as_ref()
+            .is_some_and(|start| forward(collate(key, start)).is_lt());
+        let after_end = self.end.as_ref().is_some_and(|end| {
+            let ordering = forward(collate(key, end));
+            ordering.is_gt() || (!self.inclusive_end && ordering.is_eq())
+        });
+        let other_key = self.key.as_ref().is_some_and(|wanted| collate(key, wanted).is_ne());
+        !before_start && !after_end && !other_key
+    }
+
+    fn filter<'a, T>(&'a self, rows: &'a [T], key: impl Fn(&T) -> &Value + 'a) -> impl Iterator<Item = &'a T> {
+        rows.iter().filter(move |row| self.contains(key(row)))
+    }
+
+    /// Applies `skip` and `limit`, and returns the offset of the first row
+    fn page<T>(&self, rows: Vec<T>) -> (usize, Vec<T>) {
+        let limit = self.limit.unwrap_or(usize::MAX);
+        (self.skip, rows.into_iter().skip(self.skip).take(limit).collect())
+    }
+
+    /// Selects the rows in the range, and applies `skip` and `limit`. The offset counts the rows
+    /// before the first one returned.
+    fn select<'a, T>(&self, rows: &'a [T], key: impl Fn(&T) -> &Value) -> (usize, Vec<&'a T>) {
+        let before = rows.iter().take_while(|row| !self.contains(key(row))).count();
+        let selected: Vec<&T> = rows.iter().filter(|row| self.contains(key(row))).collect();
+        let (skip, page) = self.page(selected);
+        (before + skip, page)
+    }
+}
+
+fn builtin_reduce(reduce: &str, rows: &[&ViewRow]) -> Result<Value, Failure> {
+    let numbers = || {
+        rows.iter()
+            .map(|(_, _, value)| {
+                value.as_f64().ok_or_else(|| {
+                    Failure::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "builtin_reduce_error",
+                        format!("{} function requires that map values be numbers", reduce),
+                    )
+                })
+            })
+            .collect::<Result<Vec<f64>, Failure>>()
+    };
+
+    match reduce {
+        "_count" => Ok(json!(rows.len())),
+        "_sum" => Ok(number(numbers()?.iter().sum())),
+        "_stats" => {
+            let numbers = numbers()?;
+            Ok(json!({
+                "sum": number(numbers.iter().sum()),
+                "count": numbers.len(),
+                "min": number(numbers.iter().cloned().fold(f64::INFINITY, f64::min)),
+                "max": number(numbers.iter().cloned().fold(f64::NEG_INFINITY, f64::max)),
+                "sumsqr": number(numbers.iter().map(|n| n * n).sum()),
+            }))
+        }
+        "_approx_count_distinct" => {
+            let mut keys: Vec<&Value> = rows.iter().map(|(key, _, _)| key).collect();
+            keys.sort_by(|a, b| collate(a, b));
+            keys.dedup_by(|a, b| collate(a, b).is_eq());
+            Ok(json!(keys.len()))
+        }
+        _ => Err(Failure::not_implemented(
+            "The mock server only runs the built-in reduce functions",
+        )),
+    }
+}
+
+/// Renders whole numbers as integers, as CouchDB does
+fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        json!(n as i64)
+    } else {
+        json!(n)
+    }
+}
+
+pub(crate) fn is_system_database(name: &str) -> bool {
+    SYSTEM_DATABASES.contains(&name)
+}
+
+fn all_docs_index() -> Value {
+    json!({"ddoc": null, "name": "_all_docs", "type": "special", "def": {"fields": [{"_id": "asc"}]}})
+}
+
+/// The name of the database at the URL of a replication endpoint, such as `{"url": "http://localhost/db"}`
+fn endpoint_database(endpoint: &Value) -> Result<String, Failure> {
+    let url = endpoint["url"]
+        .as_str()
+        .or_else(|| endpoint.as_str())
+        .unwrap_or_default();
+    let name = url.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+    match percent_decode_str(name).decode_utf8_lossy() {
+        name if name.is_empty() => Err(Failure::bad_request("Replication endpoint has no database")),
+        name => Ok(name.into_owned()),
+    }
+}
+
+fn invalid_query(reason: String) -> Failure {
+    Failure::new(StatusCode::BAD_REQUEST, "invalid_operator", reason)
+}
+
+/// The number of a sequence, such as `"12-mock"` or `12`
+fn sequence_number(seq: &Value) -> u64 {
+    match seq {
+        Value::String(seq) => seq.split('-').next().and_then(|n| n.parse().ok()).unwrap_or(0),
+        seq => seq.as_u64().unwrap_or(0),
+    }
+}
+
+fn revision_generation(rev: &str) -> u64 {
+    rev.split('-').next().and_then(|n| n.parse().ok()).unwrap_or_default()
+}
+
+/// Revisions are derived from the document and its previous revision, so the same edits always
+/// produce the same revisions
+fn new_revision(generation: u64, id: &str, previous: Option<&str>, deleted: bool, doc: &Map<String, Value>) -> String {
+    let body = serde_json::to_string(doc).unwrap_or_default();
+    let digest = hash_of(&(id, previous, deleted, &body));
+    format!("{}-{:016x}{:016x}", generation, digest, hash_of(&(digest, body)))
+}
+
+fn hash_of(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
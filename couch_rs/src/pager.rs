@@ -0,0 +1,542 @@
+use crate::database::{found_collection, Database, DEFAULT_BATCH_SIZE};
+use crate::document::{DocumentCollection, TypedCouchDocument};
+use crate::error::{CouchError, CouchResult};
+use crate::types::find::FindQuery;
+use crate::types::query::QueryParams;
+use crate::types::view::ViewItem;
+use futures_util::stream::{self, Stream, TryStreamExt};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::marker::PhantomData;
+
+/// The rows of one page
+type Page<K, V, T> = Vec<ViewItem<K, V, T>>;
+
+/// Iterates the rows of a view in pages. Every page is requested with one row more than the page
+/// size; that extra row is where the next page starts, through `start_key` and `start_key_doc_id`.
+/// Unlike `skip`, this stays fast deep into the view, and rows that share a key are neither
+/// repeated nor missed.
+///
+/// Usage:
+/// ```
+/// use couch_rs::error::CouchResult;
+/// use futures_util::stream::TryStreamExt;
+/// use serde_json::Value;
+///
+/// const TEST_DB: &str = "view_db";
+///
+/// #[tokio::main]
+/// async fn main() -> CouchResult<()> {
+///     let client = couch_rs::Client::new_local_test()?;
+///     let db = client.db(TEST_DB).await?;
+///
+///     // page by page
+///     let mut pager = db.view_pager::<String, Value, Value>("test_design", "funny_guys", None, 100);
+///     while let Some(page) = pager.next_page().await? {
+///         println!("{} funny guys", page.len());
+///     }
+///
+///     // or row by row
+///     let mut rows = Box::pin(db.view_pager::<String, Value, Value>("test_design", "funny_guys", None, 100).rows());
+///     while let Some(row) = rows.try_next().await? {
+///         println!("{} is funny", row.key);
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct ViewPager<K: DeserializeOwned, V: DeserializeOwned, T: DeserializeOwned> {
+    db: Database,
+    design_name: String,
+    view_name: String,
+    params: QueryParams,
+    page_size: u64,
+    done: bool,
+    _rows: PhantomData<Page<K, V, T>>,
+}
+
+impl<K: DeserializeOwned, V: DeserializeOwned, T: DeserializeOwned> ViewPager<K, V, T> {
+    /// Creates a pager over the rows selected by `params`. The `limit` of the parameters is replaced
+    /// by the page size, a `skip` only applies to the first page, and a `key` is turned into a range.
+    /// A page size of 0 means the default of 1000 rows.
+    pub fn new(db: Database, design_name: &str, view_name: &str, params: Option<QueryParams>, page_size: u64) -> Self {
+        let mut params = params.unwrap_or_default();
+        if let Some(key) = params.key.take() {
+            params.start_key = Some(key.clone());
+            params.end_key = Some(key);
+        }
+
+        ViewPager {
+            db,
+            design_name: design_name.to_string(),
+            view_name: view_name.to_string(),
+            params,
+            page_size: if page_size > 0 { page_size } else { DEFAULT_BATCH_SIZE },
+            done: false,
+            _rows: PhantomData,
+        }
+    }
+
+    /// Returns the next page of rows, or `None` once all rows have been returned
+    pub async fn next_page(&mut self) -> CouchResult<Option<Page<K, V, T>>> {
+        if self.done {
+            return Ok(None);
+        }
+        if !self.params.keys.is_empty() {
+            return Err(multiple_keys());
+        }
+
+        let params = self.params.clone().limit(self.page_size + 1);
+        let mut rows = self
+            .db
+            .query::<Value, Value, Value>(&self.design_name, &self.view_name, Some(params))
+            .await?
+            .rows;
+        self.turn_page(&mut rows);
+
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        rows.into_iter()
+            .map(typed_row)
+            .collect::<CouchResult<Vec<_>>>()
+            .map(Some)
+    }
+
+    /// Whether all rows have been returned
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Turns the pager into a stream of pages
+    pub fn pages(self) -> impl Stream<Item = CouchResult<Page<K, V, T>>> {
+        stream::unfold(self, |mut pager| async move {
+            match pager.next_page().await {
+                Ok(Some(page)) => Some((Ok(page), pager)),
+                Ok(None) => None,
+                Err(err) => {
+                    pager.done = true;
+                    Some((Err(err), pager))
+                }
+            }
+        })
+    }
+
+    /// Turns the pager into a stream of rows. Pages are only requested once their rows are needed.
+    pub fn rows(self) -> impl Stream<Item = CouchResult<ViewItem<K, V, T>>> {
+        self.pages()
+            .map_ok(|page| stream::iter(page.into_iter().map(Ok)))
+            .try_flatten()
+    }
+
+    /// Removes the extra row from a received page, and continues the next page at it
+    fn turn_page(&mut self, rows: &mut Vec<ViewItem<Value, Value, Value>>) {
+        if rows.len() as u64 <= self.page_size {
+            self.done = true;
+            return;
+        }
+
+        if let Some(next) = rows.pop() {
+            self.params.start_key = Some(next.key);
+            self.params.start_key_doc_id = next.id;
+            self.params.skip = None;
+        }
+    }
+}
+
+/// Where the documents of a `Paginator` come from
+#[derive(Debug, Clone)]
+enum PageSource {
+    Find(FindQuery),
+    AllDocs(QueryParams),
+}
+
+/// Pages through the documents found by a Mango query, or through `_all_docs`, forwards and
+/// backwards. The bookmark of every visited page is remembered, so `prev_page` can return to it.
+/// Mango queries continue through the bookmarks returned by `_find`; `_all_docs` continues at the
+/// id of the first document of the next page, which is used as the bookmark.
+///
+/// Usage:
+/// ```
+/// use couch_rs::error::CouchResult;
+/// use couch_rs::types::find::FindQuery;
+/// use serde_json::{json, Value};
+///
+/// const TEST_DB: &str = "test_db";
+///
+/// #[tokio::main]
+/// async fn main() -> CouchResult<()> {
+///     let client = couch_rs::Client::new_local_test()?;
+///     let db = client.db(TEST_DB).await?;
+///     let mut paginator = db.find_paginator::<Value>(FindQuery::new(json!({"last_name": "Doe"})), 20);
+///
+///     while let Some(page) = paginator.next_page().await? {
+///         println!("{} Does", page.rows.len());
+///     }
+///
+///     if let Some(page) = paginator.prev_page().await? {
+///         println!("{} Does on the previous page", page.rows.len());
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct Paginator<T: TypedCouchDocument> {
+    db: Database,
+    source: PageSource,
+    page_size: u64,
+    bookmarks: Vec<Option<String>>,
+    current: Option<usize>,
+    next_bookmark: Option<String>,
+    has_more: bool,
+    _doc: PhantomData<fn() -> T>,
+}
+
+impl<T: TypedCouchDocument> Paginator<T> {
+    /// Creates a paginator over the documents found by the query. The `limit` of the query is
+    /// replaced by the page size, and a `skip` only applies to the first page. A page size of 0
+    /// means the default of 1000 documents.
+    pub fn find(db: Database, query: FindQuery, page_size: u64) -> Self {
+        Paginator::new(db, PageSource::Find(query), page_size)
+    }
+
+    /// Creates a paginator over the documents of the database, in the range of `params`. The
+    /// `limit` of the parameters is replaced by the page size, and a `skip` only applies to the
+    /// first page. A page size of 0 means the default of 1000 documents.
+    pub fn all_docs(db: Database, params: Option<QueryParams>, page_size: u64) -> Self {
+        Paginator::new(db, PageSource::AllDocs(params.unwrap_or_default()), page_size)
+    }
+
+    fn new(db: Database, source: PageSource, page_size: u64) -> Self {
+        Paginator {
+            db,
+            source,
+            page_size: if page_size > 0 { page_size } else { DEFAULT_BATCH_SIZE },
+            bookmarks: vec![],
+            current: None,
+            next_bookmark: None,
+            has_more: true,
+            _doc: PhantomData,
+        }
+    }
+
+    /// Returns the page after the current one, or the first page on the first call. Returns `None`
+    /// when there are no more documents.
+    pub async fn next_page(&mut self) -> CouchResult<Option<DocumentCollection<T>>> {
+        if !self.has_more {
+            return Ok(None);
+        }
+
+        let bookmark = self.current.and(self.next_bookmark.clone());
+        let page = self.fetch(bookmark.clone()).await?;
+        let idx = match self.current {
+            // a page that ends exactly at the last document can only be told apart from a full page
+            // by the empty page after it
+            Some(_) if page.rows.is_empty() => {
+                self.has_more = false;
+                return Ok(None);
+            }
+            Some(current) => current + 1,
+            None => 0,
+        };
+
+        self.bookmarks.truncate(idx);
+        self.bookmarks.push(bookmark);
+        self.current = Some(idx);
+        Ok(Some(page))
+    }
+
+    /// Returns the page before the current one, or `None` on the first page
+    pub async fn prev_page(&mut self) -> CouchResult<Option<DocumentCollection<T>>> {
+        let idx = match self.current {
+            Some(current) if current > 0 => current - 1,
+            _ => return Ok(None),
+        };
+
+        let page = self.fetch(self.bookmarks[idx].clone()).await?;
+        self.current = Some(idx);
+        Ok(Some(page))
+    }
+
+    /// Whether `next_page` may return more documents
+    pub fn has_more(&self) -> bool {
+        self.has_more
+    }
+
+    /// Whether `prev_page` returns a page
+    pub fn has_previous(&self) -> bool {
+        self.current.is_some_and(|current| current > 0)
+    }
+
+    /// The bookmark of the page after the current one
+    pub fn bookmark(&self) -> Option<&str> {
+        self.next_bookmark.as_deref()
+    }
+
+    /// Turns the paginator into a stream of the pages after the current one
+    pub fn pages(self) -> impl Stream<Item = CouchResult<DocumentCollection<T>>> {
+        stream::unfold(self, |mut paginator| async move {
+            match paginator.next_page().await {
+                Ok(Some(page)) => Some((Ok(page), paginator)),
+                Ok(None) => None,
+                Err(err) => {
+                    paginator.has_more = false;
+                    Some((Err(err), paginator))
+                }
+            }
+        })
+    }
+
+    /// Turns the paginator into a stream of the documents after the current page
+    pub fn documents(self) -> impl Stream<Item = CouchResult<T>> {
+        self.pages()
+            .map_ok(|page| stream::iter(page.rows.into_iter().map(Ok)))
+            .try_flatten()
+    }
+
+    /// Requests the page at the bookmark, and remembers where the page after it starts
+    async fn fetch(&mut self, bookmark: Option<String>) -> CouchResult<DocumentCollection<T>> {
+        let (page, next_bookmark, has_more) = match &self.source {
+            PageSource::Find(_) => {
+                let query = self.find_query(bookmark);
+                let found = self.db.find_as::<T>(&query).await?;
+                let full = found.docs.len() as u64 >= self.page_size;
+                let next_bookmark = found.bookmark.clone();
+                (
+                    found_collection(found, query.include_design_docs),
+                    next_bookmark.clone(),
+                    full && next_bookmark.is_some(),
+                )
+            }
+            PageSource::AllDocs(params) => {
+                if !params.keys.is_empty() {
+                    return Err(multiple_keys());
+                }
+
+                let params = self.all_docs_params(bookmark);
+                let include_design_docs = params.include_design_docs;
+                let mut response = self.db.all_docs_response::<T>(params).await?;
+                let next = if response.rows.len() as u64 > self.page_size {
+                    response.rows.pop().and_then(|row| row.id)
+                } else {
+                    None
+                };
+                let mut page = DocumentCollection::from_all_docs(response, include_design_docs);
+                page.bookmark = next.clone();
+                (page, next.clone(), next.is_some())
+            }
+        };
+
+        self.next_bookmark = next_bookmark;
+        self.has_more = has_more;
+        Ok(page)
+    }
+
+    fn find_query(&self, bookmark: Option<String>) -> FindQuery {
+        let mut query = match &self.source {
+            PageSource::Find(query) => query.clone(),
+            PageSource::AllDocs(_) => FindQuery::find_all(),
+        };
+        query.limit = Some(self.page_size);
+        if bookmark.is_some() {
+            query.skip = None;
+        }
+        query.bookmark = bookmark;
+        query
+    }
+
+    /// One more document than the page size is requested; that document starts the next page
+    fn all_docs_params(&self, bookmark: Option<String>) -> QueryParams {
+        let mut params = match &self.source {
+            PageSource::AllDocs(params) => params.clone(),
+            PageSource::Find(_) => QueryParams::default(),
+        };
+        params.limit = Some(self.page_size + 1);
+        if let Some(id) = bookmark {
+            params.start_key = Some(Value::String(id));
+            params.skip = None;
+        }
+        params
+    }
+}
+
+/// Splits the document ids into ranges that end at the given ids, which have to be sorted. The
+/// first range starts at the first document, and the last range ends at the last document.
+pub(crate) fn id_ranges(boundaries: &[String]) -> Vec<QueryParams> {
+    let mut boundaries: Vec<&str> = boundaries.iter().map(String::as_str).collect();
+    boundaries.dedup();
+
+    let mut ranges = vec![];
+    let mut start = None;
+    for end in boundaries.into_iter().map(Some).chain(std::iter::once(None)) {
+        let mut range = QueryParams::default();
+        if let Some(start) = start {
+            range = range.start_key(start);
+        }
+        if let Some(end) = end {
+            range = range.end_key(end).inclusive_end(false);
+        }
+        ranges.push(range);
+        start = end;
+    }
+    ranges
+}
+
+/// Number of characters after the common prefix of two ids that `interpolated_ids` interpolates
+const INTERPOLATED_CHARS: u32 = 4;
+/// Number of Unicode code points, the base of the interpolation
+const CODE_POINTS: u128 = 0x11_0000;
+
+/// Splits the id space between the first and last document id into `partitions` ranges of equal
+/// width, returning the ids between the ranges. Ids are compared by code point, like `_all_docs`
+/// does, so the ranges hold about the same number of documents when the ids are spread evenly,
+/// such as the UUIDs CouchDB generates.
+pub(crate) fn interpolated_ids(first: &str, last: &str, partitions: usize) -> Vec<String> {
+    let prefix: String = first
+        .chars()
+        .zip(last.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a)
+        .collect();
+    let position = |id: &str| {
+        let mut digits = id.chars().skip(prefix.chars().count()).map(u128::from);
+        (0..INTERPOLATED_CHARS).fold(0, |position, _| position * CODE_POINTS + digits.next().unwrap_or(0))
+    };
+    let (low, high) = (position(first), position(last));
+
+    (1..partitions)
+        .map(|partition| {
+            let mut position = low + (high - low) * partition as u128 / partitions as u128;
+            let mut digits = vec![];
+            for _ in 0..INTERPOLATED_CHARS {
+                digits.push((position % CODE_POINTS) as u32);
+                position /= CODE_POINTS;
+            }
+            while digits.first() == Some(&0) {
+                digits.remove(0);
+            }
+            // Surrogates are not characters; the first character after them keeps the order
+            let suffix: String = digits
+                .into_iter()
+                .rev()
+                .map(|digit| char::from_u32(digit).unwrap_or('\u{e000}'))
+                .collect();
+            format!("{}{}", prefix, suffix)
+        })
+        .collect()
+}
+
+fn multiple_keys() -> CouchError {
+    CouchError::new(s!("a query on multiple keys can not be paged"), StatusCode::BAD_REQUEST)
+}
+
+fn typed_row<K, V, T>(row: ViewItem<Value, Value, Value>) -> CouchResult<ViewItem<K, V, T>>
+where
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+    T: DeserializeOwned,
+{
+    Ok(ViewItem {
+        key: serde_json::from_value(row.key)?,
+        value: serde_json::from_value(row.value)?,
+        id: row.id,
+        doc: row.doc.map(serde_json::from_value).transpose()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use serde_json::json;
+
+    fn pager(params: Option<QueryParams>, page_size: u64) -> ViewPager<Value, Value, Value> {
+        let client = Client::new_local_test().unwrap();
+        ViewPager::new(Database::new(s!("pages"), client), "design", "view", params, page_size)
+    }
+
+    fn row(key: Value, id: &str) -> ViewItem<Value, Value, Value> {
+        ViewItem {
+            key,
+            value: Value::Null,
+            id: Some(id.to_string()),
+            doc: None,
+        }
+    }
+
+    #[test]
+    fn test_turn_page() {
+        let mut pager = pager(Some(QueryParams::default().skip(5).start_key("a")), 2);
+
+        let mut rows = vec![row(json!("a"), "1"), row(json!("b"), "2"), row(json!("b"), "3")];
+        pager.turn_page(&mut rows);
+        assert_eq!(rows.len(), 2);
+        assert!(!pager.is_done());
+        assert_eq!(pager.params.start_key, Some(json!("b")));
+        assert_eq!(pager.params.start_key_doc_id.as_deref(), Some("3"));
+        assert_eq!(pager.params.skip, None);
+
+        let mut rows = vec![row(json!("b"), "3"), row(json!("c"), "4")];
+        pager.turn_page(&mut rows);
+        assert_eq!(rows.len(), 2);
+        assert!(pager.is_done());
+    }
+
+    #[test]
+    fn test_paginator_requests() {
+        let client = Client::new_local_test().unwrap();
+        let db = Database::new(s!("pages"), client);
+
+        let query = FindQuery::new(json!({"a": 1})).skip(3);
+        let paginator = Paginator::<Value>::find(db.clone(), query, 10);
+        let first = paginator.find_query(None);
+        assert_eq!((first.limit, first.skip, first.bookmark), (Some(10), Some(3), None));
+        let next = paginator.find_query(Some(s!("g1AAAA")));
+        assert_eq!((next.skip, next.bookmark.as_deref()), (None, Some("g1AAAA")));
+
+        let paginator = Paginator::<Value>::all_docs(db, Some(QueryParams::default().descending(true)), 0);
+        let first = paginator.all_docs_params(None);
+        assert_eq!((first.limit, first.start_key), (Some(DEFAULT_BATCH_SIZE + 1), None));
+        let next = paginator.all_docs_params(Some(s!("doc-42")));
+        assert_eq!(next.start_key, Some(json!("doc-42")));
+        assert_eq!(next.descending, Some(true));
+        assert!(!paginator.has_previous());
+        assert!(paginator.has_more());
+    }
+
+    #[test]
+    fn test_id_ranges() {
+        let ranges = id_ranges(&[s!("f"), s!("f"), s!("p")]);
+        assert_eq!(
+            ranges,
+            vec![
+                QueryParams::default().end_key("f").inclusive_end(false),
+                QueryParams::default().start_key("f").end_key("p").inclusive_end(false),
+                QueryParams::default().start_key("p"),
+            ]
+        );
+        assert_eq!(id_ranges(&[]), vec![QueryParams::default()]);
+    }
+
+    #[test]
+    fn test_interpolated_ids() {
+        assert_eq!(interpolated_ids("a", "e", 4), vec![s!("b"), s!("c"), s!("d")]);
+        assert_eq!(interpolated_ids("car:0", "car:8", 2), vec![s!("car:4")]);
+        assert!(interpolated_ids("a", "e", 1).is_empty());
+
+        let ids = interpolated_ids("00000000", "ffffffff", 16);
+        assert_eq!(ids.len(), 15);
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(ids
+            .iter()
+            .all(|id| id.as_str() > "00000000" && id.as_str() < "ffffffff"));
+    }
+
+    #[test]
+    fn test_key_becomes_range() {
+        let pager = pager(Some(QueryParams::default().key("a")), 0);
+        assert_eq!(pager.params.key, None);
+        assert_eq!(pager.params.start_key, Some(json!("a")));
+        assert_eq!(pager.params.end_key, Some(json!("a")));
+        assert_eq!(pager.page_size, DEFAULT_BATCH_SIZE);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::database::{found_collection, Database};
+use crate::document::{DocumentCollection, TypedCouchDocument};
+use crate::error::CouchResult;
+use crate::types::find::{ExplainResult, FindDocuments, FindQuery};
+use crate::types::query::QueryParams;
+use crate::types::view::ViewCollection;
+use serde::de::DeserializeOwned;
+
+/// A partition of a partitioned database. The documents of a partition have IDs of the form
+/// `{partition}:{key}`, and are stored together, so queries on a single partition only read a
+/// single shard, which is much cheaper than a global query. Create one with `Database::partition`.
+///
+/// Usage:
+/// ```no_run
+/// use couch_rs::error::CouchResult;
+/// use couch_rs::types::find::FindQuery;
+/// use serde_json::{json, Value};
+///
+/// const TEST_DB: &str = "partitioned_db";
+///
+/// #[tokio::main]
+/// async fn main() -> CouchResult<()> {
+///     let client = couch_rs::Client::new_local_test()?;
+///     let db = client.db(TEST_DB).await?;
+///     let sensor = db.partition("sensor-1");
+///
+///     db.create(json!({"_id": sensor.doc_id("reading-1"), "value": 21.5})).await?;
+///
+///     let warm = sensor.find::<Value>(&FindQuery::new(json!({"value": {"$gt": 20}}))).await?;
+///     let all = sensor.get_all_params::<Value>(None).await?;
+///     assert_eq!(warm.rows.len(), all.rows.len());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Partition {
+    db: Database,
+    name: String,
+}
+
+impl Partition {
+    pub(crate) fn new(db: Database, name: &str) -> Partition {
+        Partition {
+            db,
+            name: name.to_string(),
+        }
+    }
+
+    /// The name of the partition
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The ID of the document with the given key in this partition, such as `sensor-1:reading-1`
+    pub fn doc_id(&self, key: &str) -> String {
+        format!("{}:{}", self.name, key)
+    }
+
+    fn create_path(&self, endpoint: &str) -> String {
+        let name = self.name.as_str();
+        format!("{}/_partition/{}/{}", self.db.name(), url_encode!(name), endpoint)
+    }
+
+    /// Finds the documents of the partition matching a Mango query, like `Database::find`
+    pub async fn find<T: TypedCouchDocument>(&self, query: &FindQuery) -> CouchResult<DocumentCollection<T>> {
+        let found = self.find_as::<T>(query).await?;
+        Ok(found_collection(found, query.include_design_docs))
+    }
+
+    /// Finds the documents of the partition matching a Mango query, deserialized into any type,
+    /// like `Database::find_as`
+    pub async fn find_as<T: DeserializeOwned>(&self, query: &FindQuery) -> CouchResult<FindDocuments<T>> {
+        self.db.find_as_at(self.create_path("_find"), query).await
+    }
+
+    /// Shows which index would be used to run a Mango query on the partition
+    pub async fn explain(&self, query: &FindQuery) -> CouchResult<ExplainResult> {
+        self.db.explain_at(self.create_path("_explain"), query).await
+    }
+
+    /// Gets the documents of the partition, like `Database::get_all_params`
+    pub async fn get_all_params<T: TypedCouchDocument>(
+        &self,
+        params: Option<QueryParams>,
+    ) -> CouchResult<DocumentCollection<T>> {
+        let params = params.unwrap_or_default();
+        let include_design_docs = params.include_design_docs;
+        let response = self
+            .db
+            .all_docs_response_at(self.create_path("_all_docs"), params)
+            .await?;
+        Ok(DocumentCollection::from_all_docs(response, include_design_docs))
+    }
+
+    /// Queries a view of a partitioned design document on the partition, like `Database::query`
+    pub async fn query<K: DeserializeOwned, V: DeserializeOwned, T: DeserializeOwned>(
+        &self,
+        design_name: &str,
+        view_name: &str,
+        options: Option<QueryParams>,
+    ) -> CouchResult<ViewCollection<K, V, T>> {
+        let encoded_design = url_encode!(design_name);
+        let encoded_view = url_encode!(view_name);
+        let path = self.create_path(&format!("_design/{}/_view/{}", encoded_design, encoded_view));
+        self.db.query_at(path, options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::Client;
+
+    #[test]
+    fn test_partition_paths() {
+        let db = Database::new(s!("readings"), Client::new_local_test().unwrap());
+        let partition = db.partition("sensor 1");
+        assert_eq!(partition.doc_id("a"), "sensor 1:a");
+        assert_eq!(partition.create_path("_find"), "readings/_partition/sensor%201/_find");
+    }
+}
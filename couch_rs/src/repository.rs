@@ -0,0 +1,441 @@
+use crate::database::{Database, DEFAULT_BATCH_SIZE};
+use crate::document::TypedCouchDocument;
+use crate::encryption::FieldEncryption;
+use crate::error::{CouchError, CouchResult};
+use crate::types::expiry;
+use crate::types::find::FindQuery;
+use crate::types::index::IndexFields;
+use crate::types::migration::{MigrationProgress, Migrations};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::marker::PhantomData;
+
+/// Name of the field that holds the document type, unless configured otherwise
+pub const DEFAULT_TYPE_FIELD: &str = "type";
+
+/// Name of the field that marks a document as soft deleted, unless configured otherwise
+pub const DEFAULT_SOFT_DELETE_FIELD: &str = "deleted_at";
+
+/// Typed access to the documents of a single type in a database. Documents are told apart by a type
+/// field (`type` by default) holding `T::doc_type()`, which can be set with
+/// `#[couch(doc_type = "...")]` when deriving `CouchDocument`. The repository writes this field on
+/// `save`, and only returns documents of its own type.
+///
+/// When `T::doc_type()` is `None`, no type field is written or filtered on.
+///
+/// Documents can be soft deleted: `soft_delete` sets a `deleted_at` field instead of deleting the
+/// document, so it can be restored later. `get`, `find` and `all` leave soft deleted documents out,
+/// unless the repository was created with `include_soft_deleted`. A `deleted_at` of `null` counts
+/// as not deleted. Only the repository filters them: `Database::find` and `get_all` return soft
+/// deleted documents like any other.
+///
+/// Documents can carry a schema version, see `Migrations`. With `migrate_on_read`, documents are
+/// upgraded to the latest version when they are read, and `run_migrations` upgrades all stored
+/// documents of this type. New documents are saved at the latest version.
+///
+/// With `encryption`, the configured fields are encrypted on `save`, and decrypted when read; see
+/// `FieldEncryption`. Encrypted documents need an `_id`, which their ciphertext is bound to.
+///
+/// Usage:
+/// ```
+/// use couch_rs::CouchDocument;
+/// use couch_rs::document::TypedCouchDocument;
+/// use couch_rs::error::CouchResult;
+/// use couch_rs::types::document::DocumentId;
+/// use serde::{Deserialize, Serialize};
+/// use serde_json::json;
+///
+/// const TEST_DB: &str = "test_db";
+///
+/// #[derive(Serialize, Deserialize, CouchDocument, Default)]
+/// #[couch(doc_type = "user")]
+/// pub struct User {
+///     #[serde(skip_serializing_if = "String::is_empty")]
+///     pub _id: DocumentId,
+///     #[serde(skip_serializing_if = "String::is_empty")]
+///     pub _rev: String,
+///     pub name: String,
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> CouchResult<()> {
+///     let client = couch_rs::Client::new_local_test()?;
+///     let db = client.db(TEST_DB).await?;
+///     let users = db.repository::<User>();
+///
+///     let john = users.save(User { name: "John".to_string(), ..Default::default() }).await?;
+///     let found = users.find(json!({"name": "John"})).await?;
+///     assert!(found.iter().any(|user| user._id == john._id));
+///
+///     users.delete(john).await?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Repository<T: TypedCouchDocument> {
+    db: Database,
+    type_field: String,
+    soft_delete_field: String,
+    include_soft_deleted: bool,
+    migrations: Option<Migrations>,
+    migrate_on_read: bool,
+    encryption: Option<FieldEncryption>,
+    _doc: PhantomData<fn() -> T>,
+}
+
+impl<T: TypedCouchDocument> Repository<T> {
+    pub fn new(db: Database) -> Self {
+        Repository {
+            db,
+            type_field: DEFAULT_TYPE_FIELD.to_string(),
+            soft_delete_field: DEFAULT_SOFT_DELETE_FIELD.to_string(),
+            include_soft_deleted: false,
+            migrations: None,
+            migrate_on_read: false,
+            encryption: None,
+            _doc: PhantomData,
+        }
+    }
+
+    /// Uses another field than `type` to hold the document type
+    pub fn type_field(mut self, type_field: &str) -> Self {
+        self.type_field = type_field.to_string();
+        self
+    }
+
+    /// Uses another field than `deleted_at` to mark documents as soft deleted
+    pub fn soft_delete_field(mut self, soft_delete_field: &str) -> Self {
+        self.soft_delete_field = soft_delete_field.to_string();
+        self
+    }
+
+    /// Also returns soft deleted documents from `get`, `find` and `all`
+    pub fn include_soft_deleted(mut self) -> Self {
+        self.include_soft_deleted = true;
+        self
+    }
+
+    /// Sets the migrations of this document type
+    pub fn migrations(mut self, migrations: Migrations) -> Self {
+        self.migrations = Some(migrations);
+        self
+    }
+
+    /// Upgrades outdated documents to the latest schema version when they are read by `get`, `find`
+    /// and `all`. The upgraded documents are only stored when they are saved.
+    pub fn migrate_on_read(mut self) -> Self {
+        self.migrate_on_read = true;
+        self
+    }
+
+    /// Encrypts fields of the documents before they are saved, and decrypts them when they are read
+    pub fn encryption(mut self, encryption: FieldEncryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// Gets a document of this type. Returns a `NOT_FOUND` error when the document has another type,
+    /// or has been soft deleted.
+    pub async fn get(&self, id: &str) -> CouchResult<T> {
+        let doc = self.get_own(id).await?;
+        if self.is_soft_deleted(&doc) && !self.include_soft_deleted {
+            return Err(CouchError::new_with_id(
+                Some(id.to_string()),
+                s!("document is deleted"),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+
+        self.read_stored(doc)
+    }
+
+    async fn get_own(&self, id: &str) -> CouchResult<Value> {
+        let doc: Value = self.db.get(id).await?;
+        if !self.is_own_type(&doc) {
+            return Err(CouchError::new_with_id(
+                Some(id.to_string()),
+                format!("document is not of type {}", T::doc_type().unwrap_or_default()),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+        Ok(doc)
+    }
+
+    /// Creates or updates a document, writing the type field. The returned document holds the new `_rev`.
+    pub async fn save(&self, mut doc: T) -> CouchResult<T> {
+        let mut value = serde_json::to_value(&doc)?;
+        if let (Some(doc_type), Some(object)) = (T::doc_type(), value.as_object_mut()) {
+            object.insert(self.type_field.clone(), Value::from(doc_type));
+        }
+        if let (Some(migrations), Some(object)) = (&self.migrations, value.as_object_mut()) {
+            if doc.get_rev().is_empty() && !object.contains_key(migrations.version_field()) {
+                object.insert(migrations.version_field().to_string(), Value::from(migrations.latest()));
+            }
+        }
+        if let Some(encryption) = &self.encryption {
+            encryption.encrypt(&mut value)?;
+        }
+
+        let saved = if doc.get_id().is_empty() {
+            self.db.create(value).await?
+        } else {
+            self.db.save(value).await?
+        };
+
+        doc.set_id(&saved.get_id());
+        doc.set_rev(&saved.get_rev());
+        Ok(doc)
+    }
+
+    /// Deletes a document; it needs both its `_id` and `_rev`
+    pub async fn delete(&self, doc: T) -> CouchResult<()> {
+        let mut result = self.db.bulk_remove(vec![doc]).await?;
+        match result.results.pop() {
+            Some(Err(err)) => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Marks a document as deleted, by setting the soft delete field to the current time, in seconds
+    /// since the Unix epoch. The document is kept, and can be restored with `restore`.
+    pub async fn soft_delete(&self, id: &str) -> CouchResult<T> {
+        self.get_own(id).await?;
+        let deleted_at = expiry::now();
+        let doc = self
+            .db
+            .merge_patch(id, json!({ &self.soft_delete_field: deleted_at }))
+            .await?;
+        self.read_stored(doc)
+    }
+
+    /// Restores a soft deleted document, by removing the soft delete field
+    pub async fn restore(&self, id: &str) -> CouchResult<T> {
+        self.get_own(id).await?;
+        let doc = self
+            .db
+            .merge_patch(id, json!({ &self.soft_delete_field: null }))
+            .await?;
+        self.read_stored(doc)
+    }
+
+    /// Finds all documents of this type matching the Mango selector
+    pub async fn find(&self, selector: Value) -> CouchResult<Vec<T>> {
+        self.find_all_pages(self.visible_selector(Some(selector))).await
+    }
+
+    /// Returns all documents of this type
+    pub async fn all(&self) -> CouchResult<Vec<T>> {
+        self.find_all_pages(self.visible_selector(None)).await
+    }
+
+    /// Ensures an index exists for the documents of this type. The index name is prefixed with the
+    /// document type, and a partial filter on the type field is added, unless one is provided.
+    pub async fn ensure_index(&self, name: &str, mut spec: IndexFields) -> CouchResult<bool> {
+        let name = match T::doc_type() {
+            Some(doc_type) => {
+                if spec.partial_filter_selector.is_none() {
+                    spec.partial_filter_selector = Some(self.typed_selector(None));
+                }
+                format!("{}-{}", doc_type, name)
+            }
+            None => name.to_string(),
+        };
+
+        self.db.ensure_index(&name, spec).await
+    }
+
+    /// Upgrades all stored documents of this type that are at an older schema version, in batches of
+    /// `batch_size` documents. Soft deleted documents are upgraded too. `on_progress` is called after
+    /// every batch; documents that fail to migrate or save are collected in the progress, and the
+    /// run continues with the next documents.
+    pub async fn run_migrations<F>(&self, batch_size: u64, mut on_progress: F) -> CouchResult<MigrationProgress>
+    where
+        F: FnMut(&MigrationProgress),
+    {
+        let mut progress = MigrationProgress::default();
+        let migrations = match &self.migrations {
+            Some(migrations) => migrations,
+            None => return Ok(progress),
+        };
+
+        let batch_size = batch_size.max(1);
+        let mut query = FindQuery::new(self.typed_selector(Some(migrations.outdated_selector()))).limit(batch_size);
+
+        loop {
+            let found = self.db.find_as::<Value>(&query).await?;
+            let returned = found.docs.len() as u64;
+            progress.scanned += returned;
+
+            let mut upgraded = vec![];
+            for doc in found.docs.into_iter().filter(|d| !d.get_id().starts_with('_')) {
+                match self.upgrade(migrations, doc) {
+                    Ok(Some(doc)) => upgraded.push(doc),
+                    Ok(None) => {}
+                    Err(err) => progress.failures.push(err),
+                }
+            }
+            if !upgraded.is_empty() {
+                for result in self.db.bulk_docs(upgraded).await? {
+                    progress.record(result);
+                }
+            }
+            on_progress(&progress);
+
+            match found.bookmark {
+                Some(bookmark) if returned == batch_size => query.bookmark = Some(bookmark),
+                _ => break,
+            }
+        }
+
+        Ok(progress)
+    }
+
+    /// Migrates a stored document, returning the upgraded document to store, if it changed
+    fn upgrade(&self, migrations: &Migrations, mut doc: Value) -> CouchResult<Option<Value>> {
+        if let Some(encryption) = &self.encryption {
+            encryption.decrypt(&mut doc)?;
+        }
+        if !migrations.migrate(&mut doc)? {
+            return Ok(None);
+        }
+        if let Some(encryption) = &self.encryption {
+            encryption.encrypt(&mut doc)?;
+        }
+        Ok(Some(doc))
+    }
+
+    fn transforms_reads(&self) -> bool {
+        self.encryption.is_some() || (self.migrations.is_some() && self.migrate_on_read)
+    }
+
+    /// Converts a stored document, decrypting it, and upgrading it when migrating on read
+    fn read_stored(&self, mut doc: Value) -> CouchResult<T> {
+        if let Some(encryption) = &self.encryption {
+            encryption.decrypt(&mut doc)?;
+        }
+        if let (Some(migrations), true) = (&self.migrations, self.migrate_on_read) {
+            migrations.migrate(&mut doc)?;
+        }
+        Ok(serde_json::from_value(doc)?)
+    }
+
+    fn is_own_type(&self, doc: &Value) -> bool {
+        match T::doc_type() {
+            Some(doc_type) => doc[&self.type_field] == doc_type,
+            None => true,
+        }
+    }
+
+    fn is_soft_deleted(&self, doc: &Value) -> bool {
+        doc.get(&self.soft_delete_field)
+            .is_some_and(|deleted_at| !deleted_at.is_null())
+    }
+
+    /// The typed selector, leaving out soft deleted documents unless they are included
+    fn visible_selector(&self, selector: Option<Value>) -> Value {
+        let selector = self.typed_selector(selector);
+        if self.include_soft_deleted {
+            return selector;
+        }
+
+        // a null field, as left by restoring through a merge without the field, is not deleted either
+        let not_deleted = json!({ "$or": [
+            { &self.soft_delete_field: { "$exists": false } },
+            { &self.soft_delete_field: null },
+        ]});
+        json!({ "$and": [selector, not_deleted] })
+    }
+
+    fn typed_selector(&self, selector: Option<Value>) -> Value {
+        let type_selector = T::doc_type().map(|doc_type| json!({ &self.type_field: doc_type }));
+
+        match (selector, type_selector) {
+            (Some(selector), Some(type_selector)) => json!({ "$and": [selector, type_selector] }),
+            (Some(selector), None) => selector,
+            (None, Some(type_selector)) => type_selector,
+            (None, None) => json!({ "_id": { "$gt": null } }),
+        }
+    }
+
+    async fn find_all_pages(&self, selector: Value) -> CouchResult<Vec<T>> {
+        let mut query = FindQuery::new(selector).limit(DEFAULT_BATCH_SIZE);
+        let mut docs = vec![];
+
+        loop {
+            // documents are deserialized directly, unless they have to be decrypted or migrated first
+            let (returned, bookmark) = if self.transforms_reads() {
+                let found = self.db.find_as::<Value>(&query).await?;
+                let returned = found.docs.len() as u64;
+                for doc in found.docs.into_iter().filter(|d| !d.get_id().starts_with('_')) {
+                    docs.push(self.read_stored(doc)?);
+                }
+                (returned, found.bookmark)
+            } else {
+                let found = self.db.find_as::<T>(&query).await?;
+                let returned = found.docs.len() as u64;
+                docs.extend(found.docs.into_iter().filter(|d| !d.get_id().starts_with('_')));
+                (returned, found.bookmark)
+            };
+
+            match bookmark {
+                Some(bookmark) if returned == DEFAULT_BATCH_SIZE => query.bookmark = Some(bookmark),
+                _ => break,
+            }
+        }
+
+        Ok(docs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as couch_rs;
+    use crate::client::Client;
+    use couch_rs::CouchDocument;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, CouchDocument)]
+    #[couch(doc_type = "user")]
+    struct User {
+        _id: String,
+        _rev: String,
+    }
+
+    fn users() -> Repository<User> {
+        let client = Client::new_local_test().unwrap();
+        Repository::new(Database::new("users".to_string(), client))
+    }
+
+    #[test]
+    fn test_typed_selector() {
+        let users = users();
+        assert_eq!(users.typed_selector(None), json!({"type": "user"}));
+        assert_eq!(
+            users.typed_selector(Some(json!({"name": "John"}))),
+            json!({"$and": [{"name": "John"}, {"type": "user"}]})
+        );
+
+        assert_eq!(
+            users.visible_selector(None),
+            json!({"$and": [
+                {"type": "user"},
+                {"$or": [{"deleted_at": {"$exists": false}}, {"deleted_at": null}]}
+            ]})
+        );
+        assert_eq!(
+            self::users().include_soft_deleted().visible_selector(None),
+            json!({"type": "user"})
+        );
+        assert!(users.is_soft_deleted(&json!({"deleted_at": 1_600_000_000})));
+        assert!(!users.is_soft_deleted(&json!({"deleted_at": null})));
+
+        let users = users.type_field("kind");
+        assert!(users.is_own_type(&json!({"kind": "user"})));
+        assert!(!users.is_own_type(&json!({"type": "user"})));
+    }
+}
@@ -0,0 +1,227 @@
+//! Timers and clocks. These come from tokio, from threads of their own when the `tokio` feature is
+//! disabled, or from the event loop of the JavaScript host on wasm.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    pub(crate) use std::time::Instant;
+
+    /// A boxed future, which can be sent between threads
+    pub(crate) type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+    /// The current time, in seconds since the Unix epoch
+    pub(crate) fn unix_time() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use native::*;
+
+#[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+pub(crate) use tokio::time::{sleep, Sleep};
+
+/// Timers that do not need a runtime: a single timer thread, started on first use, sleeps until the
+/// earliest deadline and wakes the tasks whose timers elapsed. Tests build it with tokio too.
+#[cfg(all(any(test, not(feature = "tokio")), not(target_arch = "wasm32")))]
+mod thread_timer {
+    use std::cmp::Ordering;
+    use std::collections::BinaryHeap;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+    use std::sync::{Arc, Mutex, OnceLock};
+    use std::task::{Context, Poll, Waker};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[derive(Default)]
+    struct TimerState {
+        elapsed: bool,
+        waker: Option<Waker>,
+    }
+
+    /// A timer waiting in the queue of the timer thread, ordered by its deadline
+    struct Entry {
+        deadline: Instant,
+        state: Arc<Mutex<TimerState>>,
+    }
+
+    impl Entry {
+        fn fire(self) {
+            let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+            state.elapsed = true;
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    impl PartialEq for Entry {
+        fn eq(&self, other: &Self) -> bool {
+            self.deadline == other.deadline
+        }
+    }
+
+    impl Eq for Entry {}
+
+    impl PartialOrd for Entry {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Entry {
+        // reversed, so the heap pops the earliest deadline first
+        fn cmp(&self, other: &Self) -> Ordering {
+            other.deadline.cmp(&self.deadline)
+        }
+    }
+
+    fn timer_thread() -> &'static Sender<Entry> {
+        static TIMERS: OnceLock<Sender<Entry>> = OnceLock::new();
+        TIMERS.get_or_init(|| {
+            let (sender, receiver) = mpsc::channel();
+            thread::Builder::new()
+                .name("couch_rs-timer".to_string())
+                .spawn(move || run_timers(receiver))
+                .expect("could not start the timer thread");
+            sender
+        })
+    }
+
+    fn run_timers(receiver: Receiver<Entry>) {
+        let mut queue = BinaryHeap::new();
+        loop {
+            let now = Instant::now();
+            while queue.peek().is_some_and(|entry: &Entry| entry.deadline <= now) {
+                queue.pop().expect("peeked").fire();
+            }
+
+            let next = match queue.peek() {
+                Some(entry) => receiver.recv_timeout(entry.deadline - now),
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match next {
+                Ok(entry) => queue.push(entry),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+
+    /// Future that completes after a duration
+    pub(crate) struct Sleep(Arc<Mutex<TimerState>>);
+
+    pub(crate) fn sleep(duration: Duration) -> Sleep {
+        let state = Arc::new(Mutex::new(TimerState::default()));
+        let entry = Entry {
+            deadline: Instant::now() + duration,
+            state: state.clone(),
+        };
+        if let Err(mpsc::SendError(entry)) = timer_thread().send(entry) {
+            entry.fire();
+        }
+        Sleep(state)
+    }
+
+    impl Future for Sleep {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            let mut state = self.0.lock().unwrap_or_else(|err| err.into_inner());
+            if state.elapsed {
+                Poll::Ready(())
+            } else {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_timers_elapse_in_order() {
+            let started = Instant::now();
+            let (long, short) = (sleep(Duration::from_millis(500)), sleep(Duration::from_millis(10)));
+            short.await;
+            assert!(started.elapsed() >= Duration::from_millis(10));
+            assert!(!long.0.lock().unwrap().elapsed);
+            long.await;
+            assert!(started.elapsed() >= Duration::from_millis(500));
+        }
+    }
+}
+
+#[cfg(all(not(feature = "tokio"), not(target_arch = "wasm32")))]
+pub(crate) use thread_timer::*;
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use js_sys::{Date, Function, Promise};
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+    use wasm_bindgen::prelude::*;
+    use wasm_bindgen_futures::JsFuture;
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_name = setTimeout)]
+        fn set_timeout(handler: &Function, timeout: i32) -> JsValue;
+    }
+
+    /// A boxed future; futures of the JavaScript host can not be sent between threads
+    pub(crate) type BoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+    /// Future that completes after a duration, through `setTimeout`
+    pub(crate) struct Sleep(JsFuture);
+
+    pub(crate) fn sleep(duration: Duration) -> Sleep {
+        let millis = duration.as_millis().min(i32::MAX as u128) as i32;
+        let promise = Promise::new(&mut |resolve, _| {
+            set_timeout(&resolve, millis);
+        });
+        Sleep(JsFuture::from(promise))
+    }
+
+    impl Future for Sleep {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            Pin::new(&mut self.0).poll(cx).map(|_| ())
+        }
+    }
+
+    /// Point in time, in milliseconds since the epoch; `std::time::Instant` is not available
+    #[derive(Debug, Clone, Copy)]
+    pub(crate) struct Instant(f64);
+
+    impl Instant {
+        pub(crate) fn now() -> Instant {
+            Instant(Date::now())
+        }
+
+        pub(crate) fn elapsed(&self) -> Duration {
+            Duration::from_secs_f64((Date::now() - self.0).max(0.0) / 1000.0)
+        }
+    }
+
+    /// The current time, in seconds since the Unix epoch
+    pub(crate) fn unix_time() -> u64 {
+        (Date::now() / 1000.0) as u64
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) use wasm::*;
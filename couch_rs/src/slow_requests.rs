@@ -0,0 +1,240 @@
+use crate::client::{COUCH_REQUEST_ID_HEADER, REQUEST_ID_HEADER};
+use crate::error::{header_string, CouchResult, SendWithContext};
+use crate::runtime::Instant;
+use flate2::read::GzDecoder;
+use percent_encoding::percent_decode_str;
+use reqwest::header::CONTENT_ENCODING;
+use reqwest::{Request, RequestBuilder, Response, StatusCode};
+use serde_json::Value;
+use std::fmt;
+use std::io::Read;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A request that took at least the threshold set with `Client::set_slow_request_hook`. The time is
+/// measured until the response headers arrive, which for queries is when CouchDB found the first
+/// rows; reading the rest of the response is not included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowRequest {
+    /// HTTP method, such as `POST`
+    pub method: String,
+    /// Path of the request, such as `/users/_find`
+    pub path: String,
+    /// The database the request was sent to, if any
+    pub database: Option<String>,
+    /// The Mango selector of `_find` and `_explain` requests
+    pub selector: Option<Value>,
+    /// The design document and view of view queries, such as `users/by_name`
+    pub view: Option<String>,
+    /// Status of the response; `None` when no response was received
+    pub status: Option<StatusCode>,
+    pub elapsed: Duration,
+    /// The `X-Request-ID` header of the request
+    pub request_id: Option<String>,
+    /// The `X-Couch-Request-ID` header of the response, which identifies the request in the log of
+    /// CouchDB
+    pub couch_request_id: Option<String>,
+}
+
+impl SlowRequest {
+    pub(crate) fn new(request: &Request, response: Option<&Response>, elapsed: Duration) -> SlowRequest {
+        let status = response.map(Response::status);
+        let couch_request_id = response.and_then(|response| header_string(response.headers(), COUCH_REQUEST_ID_HEADER));
+        SlowRequest::with_status(request, status, couch_request_id, elapsed)
+    }
+
+    fn with_status(
+        request: &Request,
+        status: Option<StatusCode>,
+        couch_request_id: Option<String>,
+        elapsed: Duration,
+    ) -> SlowRequest {
+        let path = request.url().path().to_string();
+        let segments: Vec<String> = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+            .collect();
+
+        let database = segments.first().filter(|db| !db.starts_with('_')).cloned();
+        let view = match segments.as_slice() {
+            [_, design, name, view_type, view, ..] if design == "_design" && view_type == "_view" => {
+                Some(format!("{}/{}", name, view))
+            }
+            _ => None,
+        };
+
+        SlowRequest {
+            method: request.method().to_string(),
+            path,
+            database,
+            selector: request_json(request).and_then(|mut body| body.get_mut("selector").map(Value::take)),
+            view,
+            status,
+            elapsed,
+            request_id: header_string(request.headers(), REQUEST_ID_HEADER),
+            couch_request_id,
+        }
+    }
+}
+
+impl fmt::Display for SlowRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} took {:?}", self.method, self.path, self.elapsed)?;
+        if let Some(status) = self.status {
+            write!(f, " ({})", status)?;
+        }
+        if let Some(view) = &self.view {
+            write!(f, ", view {}", view)?;
+        }
+        if let Some(selector) = &self.selector {
+            write!(f, ", selector {}", selector)?;
+        }
+        Ok(())
+    }
+}
+
+/// The JSON body of a request, which the client may have compressed
+fn request_json(request: &Request) -> Option<Value> {
+    let body = request.body()?.as_bytes()?;
+    if request
+        .headers()
+        .get(CONTENT_ENCODING)
+        .is_some_and(|encoding| encoding == "gzip")
+    {
+        let mut decompressed = vec![];
+        GzDecoder::new(body).read_to_end(&mut decompressed).ok()?;
+        serde_json::from_slice(&decompressed).ok()
+    } else {
+        serde_json::from_slice(body).ok()
+    }
+}
+
+/// Receives the requests that took longer than the threshold set with
+/// `Client::set_slow_request_hook`, for example to find Mango queries that lack an index before
+/// they slow down production. `LogSlowRequests` logs them; closures taking a `&SlowRequest` are
+/// hooks too:
+/// ```
+/// use couch_rs::slow_requests::SlowRequest;
+/// use std::time::Duration;
+///
+/// # fn main() -> couch_rs::error::CouchResult<()> {
+/// let mut client = couch_rs::Client::new_local_test()?;
+/// client.set_slow_request_hook(Duration::from_millis(500), |request: &SlowRequest| {
+///     if let Some(selector) = &request.selector {
+///         eprintln!("slow query on {:?}: {}", request.database, selector);
+///     }
+/// });
+/// # Ok(())
+/// # }
+/// ```
+pub trait SlowRequestHook: Send + Sync {
+    fn on_slow_request(&self, request: &SlowRequest);
+}
+
+impl<F> SlowRequestHook for F
+where
+    F: Fn(&SlowRequest) + Send + Sync,
+{
+    fn on_slow_request(&self, request: &SlowRequest) {
+        self(request)
+    }
+}
+
+/// Logs slow requests as warnings, through the `log` crate, with the `couch_rs::slow_requests` target
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogSlowRequests;
+
+impl SlowRequestHook for LogSlowRequests {
+    fn on_slow_request(&self, request: &SlowRequest) {
+        log::warn!(target: "couch_rs::slow_requests", "slow CouchDB request: {}", request);
+    }
+}
+
+/// The slow request hook of a client, with its threshold
+#[derive(Clone)]
+pub(crate) struct SlowRequests {
+    threshold: Duration,
+    hook: Arc<dyn SlowRequestHook>,
+}
+
+impl SlowRequests {
+    pub(crate) fn new(threshold: Duration, hook: Arc<dyn SlowRequestHook>) -> SlowRequests {
+        SlowRequests { threshold, hook }
+    }
+
+    /// Sends the request, and reports it to the hook when it took at least the threshold
+    pub(crate) async fn send(&self, request: RequestBuilder) -> CouchResult<Response> {
+        // the copy shares the body of the request, rather than copying it
+        let copy = request.try_clone().and_then(|copy| copy.build().ok());
+        let started = Instant::now();
+        let result = request.send_with_context().await;
+        let elapsed = started.elapsed();
+
+        if elapsed >= self.threshold {
+            if let Some(copy) = copy {
+                let response = result.as_ref().ok();
+                self.hook.on_slow_request(&SlowRequest::new(&copy, response, elapsed));
+            }
+        }
+        result
+    }
+}
+
+impl fmt::Debug for SlowRequests {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SlowRequests")
+            .field("threshold", &self.threshold)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Client;
+    use serde_json::json;
+
+    #[test]
+    fn test_slow_request() {
+        let mut client = Client::new_local_test().unwrap();
+        let find = json!({"selector": {"type": "user"}, "limit": 10}).to_string();
+        let request = client.post(s!("users/_find"), find.clone()).build().unwrap();
+        let slow = SlowRequest::with_status(
+            &request,
+            Some(StatusCode::OK),
+            Some(s!("c0ffee")),
+            Duration::from_secs(2),
+        );
+        assert_eq!(slow.database.as_deref(), Some("users"));
+        assert_eq!(
+            slow.request_id.as_deref(),
+            request.headers()[REQUEST_ID_HEADER].to_str().ok()
+        );
+        assert_eq!(slow.couch_request_id.as_deref(), Some("c0ffee"));
+        assert_eq!(slow.selector, Some(json!({"type": "user"})));
+        assert_eq!(slow.view, None);
+        assert_eq!(
+            slow.to_string(),
+            r#"POST /users/_find took 2s (200 OK), selector {"type":"user"}"#
+        );
+
+        // compressed bodies are read too
+        client.set_request_compression(Some(1));
+        let request = client.post(s!("users/_find"), find).build().unwrap();
+        let slow = SlowRequest::new(&request, None, Duration::from_secs(2));
+        assert_eq!(slow.selector, Some(json!({"type": "user"})));
+
+        let request = client
+            .post(s!("my%2Fdb/_design/users/_view/by%20name"), s!("{}"))
+            .build()
+            .unwrap();
+        let slow = SlowRequest::new(&request, None, Duration::from_secs(2));
+        assert_eq!(slow.database.as_deref(), Some("my/db"));
+        assert_eq!(slow.view.as_deref(), Some("users/by name"));
+        assert_eq!(slow.selector, None);
+
+        let request = client.get(s!("_all_dbs"), None).build().unwrap();
+        assert_eq!(SlowRequest::new(&request, None, Duration::ZERO).database, None);
+    }
+}
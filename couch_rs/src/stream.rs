@@ -0,0 +1,268 @@
+use crate::error::{CouchError, CouchResult, ErrorContext};
+use crate::runtime::sleep;
+use bytes::Bytes;
+use futures_util::future::{self, Either};
+use futures_util::stream::{self, Stream, TryStreamExt};
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+/// Parses a JSON response body. The responses of the read paths that dominate bulk workloads,
+/// `_all_docs`, `_find` and views, are all parsed through here and `parse_json`, so they share a
+/// single parser.
+pub(crate) async fn response_json<T: DeserializeOwned>(response: Response) -> CouchResult<T> {
+    let context = ErrorContext::of(&response);
+    let body = match response.bytes().await {
+        Ok(body) => body,
+        Err(err) => return Err(CouchError::from(err).with_context(context)),
+    };
+    parse_json(&body).map_err(|err| err.with_context(context))
+}
+
+/// Reads JSON response bodies through `response_json`
+pub(crate) trait JsonBody {
+    async fn read_json<T: DeserializeOwned>(self) -> CouchResult<T>;
+}
+
+impl JsonBody for Response {
+    async fn read_json<T: DeserializeOwned>(self) -> CouchResult<T> {
+        response_json(self).await
+    }
+}
+
+/// Parses a JSON response body like `response_json`, but fails when nothing is received for `idle`.
+/// Feeds that send heartbeats while they wait are read through here, so a dead connection is
+/// noticed. The wasm client receives the body at once, so there a long wait fails too.
+pub(crate) async fn response_json_idle<T: DeserializeOwned>(response: Response, idle: Duration) -> CouchResult<T> {
+    let context = ErrorContext::of(&response);
+    let mut body = ChunkedBody::new(response);
+    let mut received = vec![];
+
+    loop {
+        match future::select(Box::pin(body.chunk()), Box::pin(sleep(idle))).await {
+            Either::Left((Ok(Some(bytes)), _)) => received.extend_from_slice(&bytes),
+            Either::Left((Ok(None), _)) => break,
+            Either::Left((Err(err), _)) => return Err(CouchError::from(err).with_context(context)),
+            Either::Right(_) => {
+                let err = CouchError::new(
+                    format!("nothing received for {:?}, the connection seems dead", idle),
+                    StatusCode::GATEWAY_TIMEOUT,
+                );
+                return Err(err.with_context(context));
+            }
+        }
+    }
+    parse_json(&received).map_err(|err| err.with_context(context))
+}
+
+/// Parses a JSON body, or a single row of one, with serde_json
+#[cfg(not(feature = "simd-json"))]
+pub(crate) fn parse_json<T: DeserializeOwned>(body: &[u8]) -> CouchResult<T> {
+    Ok(serde_json::from_slice(body)?)
+}
+
+/// Parses a JSON body, or a single row of one, with simd-json. It parses in place, so it works on a
+/// copy of the body; errors are reported as serde_json errors, like those of the default parser.
+#[cfg(feature = "simd-json")]
+pub(crate) fn parse_json<T: DeserializeOwned>(body: &[u8]) -> CouchResult<T> {
+    use serde::de::Error;
+
+    let mut body = body.to_vec();
+    simd_json::serde::from_slice(&mut body).map_err(|err| CouchError::from(serde_json::Error::custom(err)))
+}
+
+/// Parses the rows of a view or `_all_docs` response while its body is being received, so the rows
+/// do not have to be buffered all at once. Memory use is bounded by the size of a single row.
+pub(crate) fn response_rows<R: DeserializeOwned>(response: Response) -> impl Stream<Item = CouchResult<R>> {
+    let context = ErrorContext::of(&response);
+    stream::unfold(
+        (Some(ChunkedBody::new(response)), RowSplitter::default()),
+        |(mut response, mut splitter)| async move {
+            loop {
+                if let Some(row) = splitter.next_row() {
+                    let row = parse_json(&row);
+                    return Some((row, (response, splitter)));
+                }
+
+                // the body has been read completely, or reading it failed
+                let body = response.as_mut()?;
+                match body.chunk().await {
+                    Ok(Some(bytes)) => splitter.push(&bytes),
+                    Ok(None) if splitter.is_complete() => return None,
+                    Ok(None) => {
+                        let err = CouchError::new(s!("view response ended unexpectedly"), StatusCode::BAD_GATEWAY);
+                        return Some((Err(err), (None, splitter)));
+                    }
+                    Err(err) => return Some((Err(err.into()), (None, splitter))),
+                }
+            }
+        },
+    )
+    .map_err(move |err| err.with_context(context.clone()))
+}
+
+/// Body of a response, read in the chunks it is received in
+#[cfg(not(target_arch = "wasm32"))]
+struct ChunkedBody(Response);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ChunkedBody {
+    fn new(response: Response) -> Self {
+        ChunkedBody(response)
+    }
+
+    async fn chunk(&mut self) -> reqwest::Result<Option<Bytes>> {
+        self.0.chunk().await
+    }
+}
+
+/// Body of a response of the wasm client, which can only be read at once; the rows are still
+/// parsed one by one
+#[cfg(target_arch = "wasm32")]
+struct ChunkedBody(Option<Response>);
+
+#[cfg(target_arch = "wasm32")]
+impl ChunkedBody {
+    fn new(response: Response) -> Self {
+        ChunkedBody(Some(response))
+    }
+
+    async fn chunk(&mut self) -> reqwest::Result<Option<Bytes>> {
+        match self.0.take() {
+            Some(response) => response.bytes().await.map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Splits the `rows` array of a view or `_all_docs` response, received in arbitrary chunks, into its rows
+#[derive(Default)]
+struct RowSplitter {
+    buf: Vec<u8>,
+    scanned: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    in_rows: bool,
+    seen_rows: bool,
+    row_start: Option<usize>,
+}
+
+impl RowSplitter {
+    fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Whether the whole response has been parsed
+    fn is_complete(&self) -> bool {
+        self.seen_rows && self.depth == 0
+    }
+
+    /// Returns the next complete row, if one has been received
+    fn next_row(&mut self) -> Option<Vec<u8>> {
+        while self.scanned < self.buf.len() {
+            let idx = self.scanned;
+            let byte = self.buf[idx];
+            self.scanned += 1;
+
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if byte == b'\\' {
+                    self.escaped = true;
+                } else if byte == b'"' {
+                    self.in_string = false;
+                }
+                continue;
+            }
+
+            match byte {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => {
+                    self.depth += 1;
+                    // the rows array is the only array in the response object
+                    if self.depth == 2 && byte == b'[' {
+                        self.in_rows = true;
+                        self.seen_rows = true;
+                    } else if self.in_rows && self.depth == 3 {
+                        self.row_start = Some(idx);
+                    }
+                }
+                b'}' | b']' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.depth == 1 {
+                        self.in_rows = false;
+                    }
+                    if let (true, 2, Some(start)) = (self.in_rows, self.depth, self.row_start) {
+                        let row = self.buf[start..=idx].to_vec();
+                        self.buf.drain(..=idx);
+                        self.scanned = 0;
+                        self.row_start = None;
+                        return Some(row);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // nothing that was scanned is needed anymore, unless a row is being received
+        if self.row_start.is_none() {
+            self.buf.clear();
+            self.scanned = 0;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn split(chunks: &[&str]) -> (Vec<Value>, bool) {
+        let mut splitter = RowSplitter::default();
+        let mut rows = vec![];
+        for chunk in chunks {
+            splitter.push(chunk.as_bytes());
+            while let Some(row) = splitter.next_row() {
+                rows.push(serde_json::from_slice(&row).unwrap());
+            }
+        }
+        (rows, splitter.is_complete())
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let row: Value = parse_json(br#"{"id":"a","key":["x",1],"value":{"rev":"1-\u00e9"}}"#).unwrap();
+        assert_eq!(row, json!({"id": "a", "key": ["x", 1], "value": {"rev": "1-\u{e9}"}}));
+
+        let err = parse_json::<Value>(b"<html>").unwrap_err();
+        assert!(matches!(err, CouchError::Json { .. }));
+    }
+
+    #[test]
+    fn test_split_rows() {
+        let body = r#"{"total_rows":2,"offset":0,"rows":[
+{"id":"a","key":["x]}",1],"value":{"nested":"\"{"}},
+{"id":"b","key":"b","value":null}
+]}"#;
+        let expected = vec![
+            json!({"id": "a", "key": ["x]}", 1], "value": {"nested": "\"{"}}),
+            json!({"id": "b", "key": "b", "value": null}),
+        ];
+
+        assert_eq!(split(&[body]), (expected.clone(), true));
+
+        // every possible chunk boundary
+        for at in 1..body.len() {
+            assert_eq!(split(&[&body[..at], &body[at..]]), (expected.clone(), true));
+        }
+    }
+
+    #[test]
+    fn test_split_incomplete() {
+        let (rows, complete) = split(&[r#"{"total_rows":2,"offset":0,"rows":[{"id":"a","key":1,"value":1},{"id""#]);
+        assert_eq!(rows.len(), 1);
+        assert!(!complete);
+    }
+}
@@ -0,0 +1,268 @@
+//! Harness for integration tests against a real CouchDB, for this crate and the crates that use it.
+//!
+//! `CouchServer::start` connects to the server at `COUCHDB_URL`, or starts CouchDB in a Docker
+//! container when it is not set, and removes the container again when the server is dropped.
+//! `temp_db` creates a database with a unique name, which is destroyed when it is dropped, so tests
+//! can run in parallel against the same server.
+//!
+//! The harness reads these environment variables:
+//! - `COUCHDB_URL`: the server to test against, such as `http://localhost:5984`
+//! - `COUCHDB_USER` and `COUCHDB_PASSWORD`: the credentials, `admin` and `password` by default
+//! - `COUCHDB_IMAGE`: the Docker image to start, `couchdb:3` by default
+//!
+//! Usage:
+//! ```no_run
+//! use couch_rs::error::CouchResult;
+//! use couch_rs::testing::CouchServer;
+//! use serde_json::json;
+//!
+//! #[tokio::main]
+//! async fn main() -> CouchResult<()> {
+//!     let couch = CouchServer::start().await?;
+//!     let db = couch.temp_db().await?;
+//!
+//!     db.create(json!({"_id": "john"})).await?;
+//!     assert!(db.exists("john").await);
+//!     Ok(())
+//! }
+//! ```
+
+use crate::database::Database;
+use crate::error::{CouchError, CouchResult};
+use crate::runtime::{sleep, Instant};
+use crate::Client;
+use reqwest::StatusCode;
+use std::env;
+use std::ops::Deref;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_IMAGE: &str = "couchdb:3";
+const DEFAULT_USER: &str = "admin";
+const DEFAULT_PASSWORD: &str = "password";
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+const STARTUP_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Number of the next temporary database of this process
+static TEMP_DBS: AtomicUsize = AtomicUsize::new(0);
+
+/// A CouchDB server to run tests against
+pub struct CouchServer {
+    url: String,
+    username: String,
+    password: String,
+    client: Client,
+    /// The container the server runs in, when it was started by the harness
+    container: Option<Container>,
+}
+
+impl CouchServer {
+    /// Connects to the server at `COUCHDB_URL`, or starts CouchDB in a Docker container when it is
+    /// not set, and waits until the server responds
+    pub async fn start() -> CouchResult<CouchServer> {
+        let username = env_or("COUCHDB_USER", DEFAULT_USER);
+        let password = env_or("COUCHDB_PASSWORD", DEFAULT_PASSWORD);
+        let (url, container) = match env::var("COUCHDB_URL") {
+            Ok(url) => (url, None),
+            Err(_) => {
+                let container = Container::run(&env_or("COUCHDB_IMAGE", DEFAULT_IMAGE), &username, &password)?;
+                (container.url()?, Some(container))
+            }
+        };
+
+        let client = Client::new(&url, &username, &password)?;
+        wait_until_up(&client).await?;
+        if container.is_some() {
+            // a new single node server lacks the system databases, and logs errors until they exist
+            for system_db in &["_users", "_replicator"] {
+                let _ = client.make_db(system_db).await;
+            }
+        }
+
+        Ok(CouchServer {
+            url,
+            username,
+            password,
+            client,
+            container,
+        })
+    }
+
+    /// The URL of the server, such as `http://127.0.0.1:49153`
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// A client for the server, with the credentials of the admin user
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Whether the server runs in a container started by the harness
+    pub fn is_container(&self) -> bool {
+        self.container.is_some()
+    }
+
+    /// Creates a database with a unique name, which is destroyed when it is dropped
+    pub async fn temp_db(&self) -> CouchResult<TempDatabase> {
+        let db = self.client.make_db(&temp_db_name()).await?;
+        Ok(TempDatabase {
+            db,
+            url: self.url.clone(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+        })
+    }
+}
+
+/// A database that is destroyed when it is dropped. Dereferences to the `Database`. Dropping it
+/// blocks until the database is destroyed, so the test ends with the server clean.
+pub struct TempDatabase {
+    db: Database,
+    url: String,
+    username: String,
+    password: String,
+}
+
+impl Deref for TempDatabase {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        &self.db
+    }
+}
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        let (url, username, password) = (self.url.clone(), self.username.clone(), self.password.clone());
+        let name = self.db.name().to_string();
+
+        // Drop can not await, and may run on the runtime of the test: destroy the database from a
+        // runtime and client of its own
+        let _ = thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build();
+            if let (Ok(runtime), Ok(client)) = (runtime, Client::new(&url, &username, &password)) {
+                let _ = runtime.block_on(client.destroy_db(&name));
+            }
+        })
+        .join();
+    }
+}
+
+/// A CouchDB container, which is removed when it is dropped
+struct Container {
+    id: String,
+}
+
+impl Container {
+    fn run(image: &str, username: &str, password: &str) -> CouchResult<Container> {
+        let id = docker(&[
+            "run",
+            "--detach",
+            "--publish",
+            "127.0.0.1::5984",
+            "--env",
+            &format!("COUCHDB_USER={}", username),
+            "--env",
+            &format!("COUCHDB_PASSWORD={}", password),
+            image,
+        ])?;
+        Ok(Container { id })
+    }
+
+    /// The URL of the CouchDB port, which Docker mapped to a free port of the host
+    fn url(&self) -> CouchResult<String> {
+        let ports = docker(&["port", &self.id, "5984/tcp"])?;
+        host_url(&ports).ok_or_else(|| {
+            CouchError::new(
+                format!("could not read the port of container {}: {}", self.id, ports),
+                StatusCode::SERVICE_UNAVAILABLE,
+            )
+        })
+    }
+}
+
+impl Drop for Container {
+    fn drop(&mut self) {
+        let _ = docker(&["rm", "--force", "--volumes", &self.id]);
+    }
+}
+
+/// Runs a docker command, and returns its output
+fn docker(args: &[&str]) -> CouchResult<String> {
+    let unavailable = |message: String| CouchError::new(message, StatusCode::SERVICE_UNAVAILABLE);
+    let output = Command::new("docker")
+        .args(args)
+        .output()
+        .map_err(|err| unavailable(format!("could not run docker: {}", err)))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(unavailable(format!(
+            "docker {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+/// Reads the URL from the output of `docker port`, such as `127.0.0.1:49153`
+fn host_url(ports: &str) -> Option<String> {
+    let address = ports.lines().next()?.trim();
+    address.rsplit_once(':')?.1.parse::<u16>().ok()?;
+    Some(format!("http://{}", address))
+}
+
+async fn wait_until_up(client: &Client) -> CouchResult<()> {
+    let started = Instant::now();
+    loop {
+        match client.check_status().await {
+            Ok(_) => return Ok(()),
+            Err(err) if started.elapsed() >= STARTUP_TIMEOUT => return Err(err),
+            Err(_) => sleep(STARTUP_POLL_INTERVAL).await,
+        }
+    }
+}
+
+fn temp_db_name() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let number = TEMP_DBS.fetch_add(1, Ordering::Relaxed);
+    format!("test_{}_{:x}_{}", std::process::id(), nanos, number)
+}
+
+fn env_or(name: &str, default: &str) -> String {
+    env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_db_name() {
+        let first = temp_db_name();
+        let second = temp_db_name();
+        assert_ne!(first, second);
+        assert!(first.starts_with("test_"));
+        assert!(first
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'));
+    }
+
+    #[test]
+    fn test_host_url() {
+        assert_eq!(host_url("127.0.0.1:49153\n"), Some(s!("http://127.0.0.1:49153")));
+        assert_eq!(
+            host_url("127.0.0.1:49153\n[::1]:49153"),
+            Some(s!("http://127.0.0.1:49153"))
+        );
+        assert_eq!(host_url(""), None);
+        assert_eq!(host_url("no port"), None);
+    }
+}
@@ -0,0 +1,454 @@
+use crate::error::{CouchError, CouchResult};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Number, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+/// An update sequence of a database, such as the `seq` of a change or the `last_seq` of a feed.
+///
+/// Sequences are opaque tokens. Since CouchDB 2.0 they are strings such as `"12-g1AAAA..."`, which
+/// encode a position per shard; the number in front is not ordered across the nodes of a cluster,
+/// and two feeds may return different sequences for the same position. Store and compare them as
+/// strings, hand them back to CouchDB as they are, and never parse them into numbers. The numeric
+/// sequences of CouchDB 1.x are kept as their decimal string.
+///
+/// `Database::save_checkpoint` and `get_checkpoint` store a sequence in a local document, to resume
+/// a feed where it left off.
+///
+/// ```
+/// use couch_rs::types::changes::{ChangesOptions, Seq};
+///
+/// let seq: Seq = serde_json::from_str("\"12-g1AAAA\"").unwrap();
+/// assert_eq!(seq.as_str(), "12-g1AAAA");
+/// assert_eq!(serde_json::to_string(&seq).unwrap(), "\"12-g1AAAA\"");
+///
+/// let options = ChangesOptions::default().since(seq);
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Seq(String);
+
+impl Seq {
+    pub fn new(seq: &str) -> Seq {
+        Seq(seq.to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl fmt::Display for Seq {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for Seq {
+    fn from(seq: String) -> Seq {
+        Seq(seq)
+    }
+}
+
+impl From<&str> for Seq {
+    fn from(seq: &str) -> Seq {
+        Seq::new(seq)
+    }
+}
+
+impl Serialize for Seq {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Seq {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RawSeq {
+            Text(String),
+            Number(Number),
+        }
+
+        Ok(match RawSeq::deserialize(deserializer)? {
+            RawSeq::Text(seq) => Seq(seq),
+            RawSeq::Number(seq) => Seq(seq.to_string()),
+        })
+    }
+}
+
+/// Options of a changes feed. Use the builder paradigm to set them.
+/// See [changes](https://docs.couchdb.org/en/stable/api/database/changes.html) for more details.
+///
+/// ```
+/// use couch_rs::types::changes::ChangesOptions;
+/// use std::time::Duration;
+///
+/// let options = ChangesOptions::default()
+///     .since_now()
+///     .include_docs(true)
+///     .heartbeat(Duration::from_secs(10))
+///     .seq_interval(100);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChangesOptions {
+    /// Sequence to start after: a sequence returned by an earlier feed, or `now`. The feed starts
+    /// at the beginning of the database by default.
+    pub since: Option<Seq>,
+    /// Maximum number of changes returned per request
+    pub limit: Option<u64>,
+    pub descending: Option<bool>,
+    pub include_docs: Option<bool>,
+    pub conflicts: Option<bool>,
+    /// Which revisions each change lists; only the winning revision by default
+    pub style: Option<ChangesStyle>,
+    /// Waits for changes when there are none yet, instead of returning at once
+    pub longpoll: bool,
+    /// Interval at which CouchDB sends an empty line while it waits for changes, so proxies and the
+    /// client can tell a quiet feed from a dead connection. Overrides `timeout`.
+    pub heartbeat: Option<Duration>,
+    /// Time CouchDB waits for changes before it returns an empty result; 60 seconds by default
+    pub timeout: Option<Duration>,
+    /// Only computes the sequence of every n-th change in a cluster, which makes feeds that
+    /// return many changes cheaper; the other changes have a `null` sequence
+    pub seq_interval: Option<u64>,
+    /// Filter function, as `design/filter`, or a built-in filter such as `_design`
+    pub filter: Option<String>,
+    /// Only returns the changes of these documents
+    pub doc_ids: Option<Vec<String>>,
+    /// Only returns the changes of documents matching this Mango selector
+    pub selector: Option<Value>,
+}
+
+impl ChangesOptions {
+    /// Starts the feed after the given sequence, as returned by an earlier feed
+    pub fn since<T: Into<Seq>>(mut self, seq: T) -> Self {
+        self.since = Some(seq.into());
+        self
+    }
+
+    /// Starts the feed at the current end of the database, so only later changes are returned
+    pub fn since_now(mut self) -> Self {
+        self.since = Some(Seq::new("now"));
+        self
+    }
+
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn descending(mut self, descending: bool) -> Self {
+        self.descending = Some(descending);
+        self
+    }
+
+    pub fn include_docs(mut self, include_docs: bool) -> Self {
+        self.include_docs = Some(include_docs);
+        self
+    }
+
+    pub fn conflicts(mut self, conflicts: bool) -> Self {
+        self.conflicts = Some(conflicts);
+        self
+    }
+
+    /// Lists all leaf revisions of each change with `ChangesStyle::AllDocs`, which shows the
+    /// conflicts of the documents
+    pub fn style(mut self, style: ChangesStyle) -> Self {
+        self.style = Some(style);
+        self
+    }
+
+    pub fn longpoll(mut self, longpoll: bool) -> Self {
+        self.longpoll = longpoll;
+        self
+    }
+
+    pub fn heartbeat(mut self, heartbeat: Duration) -> Self {
+        self.heartbeat = Some(heartbeat);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn seq_interval(mut self, seq_interval: u64) -> Self {
+        self.seq_interval = Some(seq_interval);
+        self
+    }
+
+    pub fn filter(mut self, filter: &str) -> Self {
+        self.filter = Some(filter.to_string());
+        self
+    }
+
+    /// Only returns the changes of these documents, through the `_doc_ids` filter
+    pub fn doc_ids(mut self, doc_ids: Vec<String>) -> Self {
+        self.doc_ids = Some(doc_ids);
+        self
+    }
+
+    /// Only returns the changes of documents matching the selector, through the `_selector` filter
+    pub fn selector(mut self, selector: Value) -> Self {
+        self.selector = Some(selector);
+        self
+    }
+
+    /// The options as query string parameters
+    pub(crate) fn to_query(&self) -> HashMap<String, String> {
+        let mut query = HashMap::new();
+        let millis = |duration: Duration| duration.as_millis().to_string();
+
+        query.insert(s!("feed"), s!(if self.longpoll { "longpoll" } else { "normal" }));
+        if let Some(since) = &self.since {
+            query.insert(s!("since"), since.to_string());
+        }
+        if let Some(limit) = self.limit {
+            query.insert(s!("limit"), limit.to_string());
+        }
+        if let Some(descending) = self.descending {
+            query.insert(s!("descending"), descending.to_string());
+        }
+        if let Some(include_docs) = self.include_docs {
+            query.insert(s!("include_docs"), include_docs.to_string());
+        }
+        if let Some(conflicts) = self.conflicts {
+            query.insert(s!("conflicts"), conflicts.to_string());
+        }
+        if let Some(style) = self.style {
+            query.insert(s!("style"), s!(style.as_str()));
+        }
+        if let Some(heartbeat) = self.heartbeat {
+            query.insert(s!("heartbeat"), millis(heartbeat));
+        }
+        if let Some(timeout) = self.timeout {
+            query.insert(s!("timeout"), millis(timeout));
+        }
+        if let Some(seq_interval) = self.seq_interval {
+            query.insert(s!("seq_interval"), seq_interval.to_string());
+        }
+
+        let filter = match (&self.filter, &self.doc_ids, &self.selector) {
+            (Some(filter), _, _) => Some(filter.as_str()),
+            (None, Some(_), _) => Some("_doc_ids"),
+            (None, None, Some(_)) => Some("_selector"),
+            (None, None, None) => None,
+        };
+        if let Some(filter) = filter {
+            query.insert(s!("filter"), filter.to_string());
+        }
+        query
+    }
+
+    /// The body of the request, which holds the document ids or selector to filter on
+    pub(crate) fn to_body(&self) -> Value {
+        let mut body = json!({});
+        if let Some(doc_ids) = &self.doc_ids {
+            body["doc_ids"] = json!(doc_ids);
+        }
+        if let Some(selector) = &self.selector {
+            body["selector"] = selector.clone();
+        }
+        body
+    }
+}
+
+/// Revisions listed per change of a changes feed
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangesStyle {
+    /// Only the winning revision
+    MainOnly,
+    /// All leaf revisions, including conflicts and deleted conflicts
+    AllDocs,
+}
+
+impl ChangesStyle {
+    fn as_str(self) -> &'static str {
+        match self {
+            ChangesStyle::MainOnly => "main_only",
+            ChangesStyle::AllDocs => "all_docs",
+        }
+    }
+}
+
+/// Changes of a database, as returned by a changes feed. The documents included with
+/// `include_docs(true)` are `Value`s by default, or any type with `Database::changes_as`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+pub struct ChangesResponse<T = Value> {
+    pub results: Vec<ChangeEvent<T>>,
+    /// Sequence to continue the feed from
+    pub last_seq: Seq,
+    /// Number of changes after the returned ones
+    pub pending: Option<u64>,
+}
+
+impl ChangesResponse {
+    /// Deserializes the included documents into `T`. The tombstone of a deleted document only holds
+    /// its ID and revision, so when it does not fit `T`, the change of a deleted document has no `doc`.
+    pub(crate) fn into_typed<T: DeserializeOwned>(self) -> CouchResult<ChangesResponse<T>> {
+        let results = self
+            .results
+            .into_iter()
+            .map(ChangeEvent::into_typed)
+            .collect::<CouchResult<Vec<_>>>()?;
+        Ok(ChangesResponse {
+            results,
+            last_seq: self.last_seq,
+            pending: self.pending,
+        })
+    }
+}
+
+/// A change of a single document
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+pub struct ChangeEvent<T = Value> {
+    /// Sequence of the change; `None` for the changes skipped by `seq_interval`
+    pub seq: Option<Seq>,
+    pub id: String,
+    /// The leaf revisions of the document
+    pub changes: Vec<ChangeRev>,
+    /// Whether the document was deleted
+    #[serde(default)]
+    pub deleted: bool,
+    /// The document, when the feed was requested with `include_docs(true)`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc: Option<T>,
+}
+
+impl<T> ChangeEvent<T> {
+    /// The revisions listed in the change: the winning revision, followed by the other leaf
+    /// revisions when the feed was requested with `ChangesStyle::AllDocs`
+    pub fn revs(&self) -> Vec<&str> {
+        self.changes.iter().map(|change| change.rev.as_str()).collect()
+    }
+
+    /// Whether the document has more than one leaf revision, which is only known when the feed
+    /// was requested with `ChangesStyle::AllDocs`. The feed does not tell deleted leaves apart, so
+    /// unlike the `_conflicts` of a document, this includes deleted conflicts; read the document
+    /// with its conflicts to tell whether any are live.
+    pub fn has_multiple_leaves(&self) -> bool {
+        self.changes.len() > 1
+    }
+}
+
+impl ChangeEvent {
+    fn into_typed<T: DeserializeOwned>(self) -> CouchResult<ChangeEvent<T>> {
+        let doc = match self.doc {
+            Some(doc) => match serde_json::from_value(doc) {
+                Ok(doc) => Some(doc),
+                Err(_) if self.deleted => None,
+                Err(err) => return Err(CouchError::from(err)),
+            },
+            None => None,
+        };
+        Ok(ChangeEvent {
+            seq: self.seq,
+            id: self.id,
+            changes: self.changes,
+            deleted: self.deleted,
+            doc,
+        })
+    }
+}
+
+/// A revision listed in a change
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct ChangeRev {
+    pub rev: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_query() {
+        let options = ChangesOptions::default()
+            .since("12-g1AAAA")
+            .limit(10)
+            .longpoll(true)
+            .heartbeat(Duration::from_secs(5))
+            .seq_interval(50)
+            .style(ChangesStyle::AllDocs)
+            .doc_ids(vec![s!("a")]);
+        let query = options.to_query();
+        assert_eq!(query["feed"], "longpoll");
+        assert_eq!(query["since"], "12-g1AAAA");
+        assert_eq!(query["heartbeat"], "5000");
+        assert_eq!(query["seq_interval"], "50");
+        assert_eq!(query["style"], "all_docs");
+        assert_eq!(query["filter"], "_doc_ids");
+        assert_eq!(options.to_body(), json!({"doc_ids": ["a"]}));
+
+        let query = ChangesOptions::default().since("42").since_now().to_query();
+        assert_eq!(query["feed"], "normal");
+        assert_eq!(query["since"], "now");
+        assert!(!query.contains_key("filter"));
+    }
+
+    #[test]
+    fn test_seq() {
+        let changes: ChangesResponse = serde_json::from_value(json!({
+            "results": [
+                {"seq": 7, "id": "a", "changes": [{"rev": "1-x"}]},
+                {"seq": null, "id": "b", "changes": [{"rev": "1-y"}]}
+            ],
+            "last_seq": "8-g1AAAA"
+        }))
+        .unwrap();
+        assert_eq!(changes.results[0].seq, Some(Seq::new("7")));
+        assert_eq!(changes.results[1].seq, None);
+        assert_eq!(json!(changes.last_seq), json!("8-g1AAAA"));
+        assert_eq!(
+            ChangesOptions::default().since(changes.last_seq).to_query()["since"],
+            "8-g1AAAA"
+        );
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Reading {
+        value: f64,
+    }
+
+    #[test]
+    fn test_typed_changes() {
+        let changes: ChangesResponse = serde_json::from_value(json!({
+            "results": [
+                {"seq": "1-a", "id": "r1", "changes": [{"rev": "1-x"}], "doc": {"_id": "r1", "value": 2.5}},
+                {"seq": "2-a", "id": "r2", "changes": [{"rev": "2-y"}], "deleted": true,
+                 "doc": {"_id": "r2", "_rev": "2-y", "_deleted": true}}
+            ],
+            "last_seq": "2-a",
+            "pending": 0
+        }))
+        .unwrap();
+        assert!(!changes.results[0].has_multiple_leaves());
+        let conflicted: ChangeEvent = serde_json::from_value(json!({
+            "seq": "3-a", "id": "r3", "changes": [{"rev": "2-b"}, {"rev": "2-a"}]
+        }))
+        .unwrap();
+        assert!(conflicted.has_multiple_leaves());
+        assert_eq!(conflicted.revs(), vec!["2-b", "2-a"]);
+
+        let typed = changes.clone().into_typed::<Reading>().unwrap();
+        assert_eq!(typed.results[0].doc, Some(Reading { value: 2.5 }));
+        assert_eq!(typed.results[1].doc, None);
+
+        let mut broken = changes;
+        broken.results[0].doc = Some(json!({"_id": "r1"}));
+        let err = broken.into_typed::<Reading>().unwrap_err();
+        assert!(matches!(err, CouchError::Json { .. }));
+    }
+}
@@ -1,4 +1,15 @@
-use serde::{Serialize, Deserialize};
+use crate::document::TypedCouchDocument;
+use crate::error::CouchResult;
+use crate::types::changes::Seq;
+use crate::types::document::DocumentId;
+use crate::types::nouveau::NouveauIndex;
+use crate::types::search::SearchIndex;
+use crate::types::view::CouchFunc;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::collections::HashMap;
 
 /// Design document created abstraction
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
@@ -7,5 +18,318 @@ pub struct DesignCreated {
     pub id: Option<String>,
     pub name: Option<String>,
     pub error: Option<String>,
-    pub reason: Option<String>
-}
\ No newline at end of file
+    pub reason: Option<String>,
+}
+/// Design document information, as returned by `_design/{ddoc}/_info`
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct DesignInfo {
+    pub name: String,
+    pub view_index: ViewIndexInfo,
+}
+
+/// State of the index that holds the views of a design document
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ViewIndexInfo {
+    /// Whether the index is being built
+    #[serde(default)]
+    pub updater_running: bool,
+    /// Database sequence the index is built up to
+    #[serde(default)]
+    pub update_seq: Option<Seq>,
+    pub signature: Option<String>,
+}
+
+/// Response rendered by a show or list function. These functions set the status code and
+/// headers themselves, so the response is returned whatever its status.
+#[derive(Debug, Clone)]
+pub struct RenderedResponse {
+    pub status: reqwest::StatusCode,
+    pub headers: reqwest::header::HeaderMap,
+    pub body: String,
+}
+
+impl RenderedResponse {
+    pub(crate) async fn read(response: reqwest::Response) -> CouchResult<Self> {
+        Ok(RenderedResponse {
+            status: response.status(),
+            headers: response.headers().clone(),
+            body: response.text().await?,
+        })
+    }
+
+    /// The `Content-Type` header, if any
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+    }
+
+    /// Deserializes a JSON body
+    pub fn json<T: DeserializeOwned>(&self) -> CouchResult<T> {
+        Ok(serde_json::from_str(&self.body)?)
+    }
+}
+
+/// Response of an update handler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpdateResponse {
+    /// ID of the updated document, from the `X-Couch-Id` header; `None` when nothing was saved
+    pub id: Option<String>,
+    /// Revision of the updated document, from the `X-Couch-Update-NewRev` header; `None` when nothing was saved
+    pub new_rev: Option<String>,
+    pub body: String,
+}
+
+impl UpdateResponse {
+    pub(crate) async fn read(response: reqwest::Response) -> CouchResult<Self> {
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+
+        Ok(UpdateResponse {
+            id: header("X-Couch-Id"),
+            new_rev: header("X-Couch-Update-NewRev"),
+            body: response.text().await?,
+        })
+    }
+}
+
+/// A complete design document: views, filters, update handlers, show and list functions and
+/// `validate_doc_update`, so design documents can be managed as code. Fields this model does not
+/// know about are kept in `other`. See [design documents](https://docs.couchdb.org/en/stable/ddocs/ddocs.html).
+///
+/// ```
+/// use couch_rs::types::design::DesignDocument;
+/// use couch_rs::types::view::CouchFunc;
+///
+/// let design = DesignDocument::new("users")
+///     .view("by_name", CouchFunc::new("function (doc) { emit(doc.name, null); }", None))
+///     .filter("active", "function (doc, req) { return doc.active; }")
+///     .validate_doc_update("function (newDoc, oldDoc, userCtx) { if (!newDoc.name) throw({forbidden: 'name required'}); }");
+///
+/// assert_eq!(design.id, "_design/users");
+/// assert_eq!(design.name(), "users");
+/// ```
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct DesignDocument {
+    #[serde(rename = "_id")]
+    pub id: DocumentId,
+    #[serde(rename = "_rev", default, skip_serializing_if = "String::is_empty")]
+    pub rev: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Design document options, such as `partitioned` or `local_seq`
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub options: Map<String, Value>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub views: HashMap<String, CouchFunc>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub filters: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub updates: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub shows: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub lists: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validate_doc_update: Option<String>,
+    /// Search indexes, run with `Database::search`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub indexes: HashMap<String, SearchIndex>,
+    /// Nouveau indexes, run with `Database::nouveau_search`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub nouveau: HashMap<String, NouveauIndex>,
+    /// Any other fields of the design document
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+impl DesignDocument {
+    /// Creates an empty JavaScript design document; the `_design/` prefix is added to the name
+    pub fn new(name: &str) -> Self {
+        DesignDocument {
+            id: format!("_design/{}", name.trim_start_matches("_design/")),
+            language: Some("javascript".to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// The name of the design document, without the `_design/` prefix
+    pub fn name(&self) -> &str {
+        self.id.trim_start_matches("_design/")
+    }
+
+    pub fn language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_string());
+        self
+    }
+
+    pub fn option(mut self, name: &str, value: Value) -> Self {
+        self.options.insert(name.to_string(), value);
+        self
+    }
+
+    pub fn view(mut self, name: &str, func: CouchFunc) -> Self {
+        self.views.insert(name.to_string(), func);
+        self
+    }
+
+    pub fn filter(mut self, name: &str, func: &str) -> Self {
+        self.filters.insert(name.to_string(), func.to_string());
+        self
+    }
+
+    pub fn update(mut self, name: &str, func: &str) -> Self {
+        self.updates.insert(name.to_string(), func.to_string());
+        self
+    }
+
+    pub fn show(mut self, name: &str, func: &str) -> Self {
+        self.shows.insert(name.to_string(), func.to_string());
+        self
+    }
+
+    pub fn list(mut self, name: &str, func: &str) -> Self {
+        self.lists.insert(name.to_string(), func.to_string());
+        self
+    }
+
+    pub fn validate_doc_update(mut self, func: &str) -> Self {
+        self.validate_doc_update = Some(func.to_string());
+        self
+    }
+
+    pub fn search_index(mut self, name: &str, index: SearchIndex) -> Self {
+        self.indexes.insert(name.to_string(), index);
+        self
+    }
+
+    pub fn nouveau_index(mut self, name: &str, index: NouveauIndex) -> Self {
+        self.nouveau.insert(name.to_string(), index);
+        self
+    }
+}
+
+impl DesignDocument {
+    /// Whether both design documents have the same functional content; the `_id` and `_rev` are
+    /// not compared, and a missing language is the default JavaScript
+    pub fn same_content(&self, other: &DesignDocument) -> bool {
+        let language = |design: &DesignDocument| design.language.clone().unwrap_or_else(|| "javascript".to_string());
+
+        language(self) == language(other)
+            && self.options == other.options
+            && self.views == other.views
+            && self.filters == other.filters
+            && self.updates == other.updates
+            && self.shows == other.shows
+            && self.lists == other.lists
+            && self.validate_doc_update == other.validate_doc_update
+            && self.indexes == other.indexes
+            && self.nouveau == other.nouveau
+            && self.other == other.other
+    }
+}
+
+/// Outcome of `Database::deploy_design`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DesignDeployment {
+    /// The design document did not exist yet
+    Created,
+    /// The design document was replaced, as its content changed
+    Updated,
+    /// The design document already had this content, so it was not written
+    Unchanged,
+}
+
+impl TypedCouchDocument for DesignDocument {
+    fn get_id(&self) -> Cow<'_, str> {
+        Cow::from(&self.id)
+    }
+
+    fn get_rev(&self) -> Cow<'_, str> {
+        Cow::from(&self.rev)
+    }
+
+    fn set_rev(&mut self, rev: &str) {
+        self.rev = rev.to_string();
+    }
+
+    fn set_id(&mut self, id: &str) {
+        self.id = id.to_string();
+    }
+
+    fn merge_ids(&mut self, other: &Self) {
+        self.set_id(&other.get_id());
+        self.set_rev(&other.get_rev());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_design_document_serde() {
+        let design = DesignDocument::new("_design/users")
+            .view(
+                "by_name",
+                CouchFunc::new("function (doc) { emit(doc.name); }", Some("_count")),
+            )
+            .show("detail", "function (doc, req) { return doc.name; }")
+            .option("partitioned", json!(false));
+
+        let value = serde_json::to_value(&design).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "_id": "_design/users",
+                "language": "javascript",
+                "options": {"partitioned": false},
+                "views": {"by_name": {"map": "function (doc) { emit(doc.name); }", "reduce": "_count"}},
+                "shows": {"detail": "function (doc, req) { return doc.name; }"}
+            })
+        );
+
+        let mut stored = value;
+        stored["_rev"] = json!("1-abc");
+        stored["rewrites"] = json!([]);
+        let read: DesignDocument = serde_json::from_value(stored).unwrap();
+        assert_eq!(read.rev, "1-abc");
+        assert_eq!(read.views, design.views);
+        assert_eq!(read.other["rewrites"], json!([]));
+    }
+
+    #[test]
+    fn test_same_content() {
+        let design = DesignDocument::new("users").filter("active", "function (doc) { return doc.active; }");
+
+        let mut stored = design.clone();
+        stored.rev = "3-abc".to_string();
+        stored.language = None;
+        assert!(design.same_content(&stored));
+
+        let changed = design
+            .clone()
+            .filter("active", "function (doc) { return !doc.active; }");
+        assert!(!design.same_content(&changed));
+    }
+
+    #[test]
+    fn test_view_index_update_seq() {
+        let info: DesignInfo = serde_json::from_value(json!({
+            "name": "warm",
+            "view_index": {"updater_running": false, "update_seq": "42-g1AAAA", "signature": "abc"}
+        }))
+        .unwrap();
+        assert_eq!(info.view_index.update_seq, Some(Seq::new("42-g1AAAA")));
+
+        let info: DesignInfo =
+            serde_json::from_value(json!({"name": "warm", "view_index": {"update_seq": 42}})).unwrap();
+        assert_eq!(info.view_index.update_seq, Some(Seq::new("42")));
+    }
+}
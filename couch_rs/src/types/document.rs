@@ -1,10 +1,23 @@
 use crate::error::{CouchError, CouchResult};
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
 
 /// String that represents a Document ID in CouchDB
 pub type DocumentId = String;
 
+/// Document reference holding only the `_id` and `_rev` fields. Use this to query documents
+/// without retrieving their content.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct DocumentIdRev {
+    #[serde(rename = "_id")]
+    pub id: DocumentId,
+    #[serde(rename = "_rev", default, skip_serializing_if = "String::is_empty")]
+    pub rev: String,
+}
+
 /// DocumentRef<T> is an abstraction over populated/unpopulated data fields
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 #[serde(untagged)]
@@ -40,17 +53,10 @@ pub struct DocumentCreatedDetails {
 impl From<DocumentCreatedResponse> for DocumentCreatedResult {
     fn from(response: DocumentCreatedResponse) -> Self {
         if let Some(error) = response.error {
-            let status_code = match error.as_str() {
-                "forbidden" => StatusCode::FORBIDDEN,
-                "unauthorized" => StatusCode::UNAUTHORIZED,
-                "conflict" => StatusCode::CONFLICT,
-                _ => StatusCode::INTERNAL_SERVER_ERROR,
-            };
-
-            Err(CouchError::new_with_id(
+            Err(CouchError::from_document_write_error(
                 response.id,
+                &error,
                 response.reason.unwrap_or_default(),
-                status_code,
             ))
         } else {
             Ok(DocumentCreatedDetails {
@@ -62,3 +68,474 @@ impl From<DocumentCreatedResponse> for DocumentCreatedResult {
 }
 
 pub type DocumentCreatedResult = CouchResult<DocumentCreatedDetails>;
+
+/// Outcome of writing a single document through `_bulk_docs`
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum BulkDocResult {
+    /// The document was written
+    Ok { id: DocumentId, rev: String },
+    /// The document was modified in the meantime; retry with the current revision
+    Conflict { id: Option<DocumentId> },
+    /// The document was rejected, for example by a validation function
+    Forbidden { id: Option<DocumentId>, reason: String },
+    /// Any other error reported by CouchDB
+    Error {
+        id: Option<DocumentId>,
+        error: String,
+        reason: String,
+    },
+}
+
+impl BulkDocResult {
+    /// Returns the document ID, when known
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            BulkDocResult::Ok { id, .. } => Some(id),
+            BulkDocResult::Conflict { id } | BulkDocResult::Forbidden { id, .. } | BulkDocResult::Error { id, .. } => {
+                id.as_deref()
+            }
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        matches!(self, BulkDocResult::Ok { .. })
+    }
+
+    pub fn is_conflict(&self) -> bool {
+        matches!(self, BulkDocResult::Conflict { .. })
+    }
+}
+
+impl From<DocumentCreatedResponse> for BulkDocResult {
+    fn from(response: DocumentCreatedResponse) -> Self {
+        let id = response.id;
+        match response.error.as_deref() {
+            None => BulkDocResult::Ok {
+                id: id.unwrap_or_default(),
+                rev: response.rev.unwrap_or_default(),
+            },
+            Some("conflict") => BulkDocResult::Conflict { id },
+            Some("forbidden") => BulkDocResult::Forbidden {
+                id,
+                reason: response.reason.unwrap_or_default(),
+            },
+            Some(error) => BulkDocResult::Error {
+                id,
+                error: error.to_string(),
+                reason: response.reason.unwrap_or_default(),
+            },
+        }
+    }
+}
+
+/// Outcome of deleting a single document
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct DocumentDeletedResult {
+    /// Document ID
+    pub id: DocumentId,
+    /// Revision of the tombstone that marks the document as deleted
+    pub rev: String,
+}
+
+/// Outcome of deleting documents in bulk
+#[derive(Debug, Clone)]
+pub struct BulkDeleteResult {
+    /// Number of documents that were deleted
+    pub deleted: u64,
+    /// Result per document, in the order the deletions were sent
+    pub results: Vec<DocumentCreatedResult>,
+}
+
+impl BulkDeleteResult {
+    pub fn new(results: Vec<DocumentCreatedResult>) -> Self {
+        BulkDeleteResult {
+            deleted: results.iter().filter(|r| r.is_ok()).count() as u64,
+            results,
+        }
+    }
+
+    /// Returns the deletions that failed, for example because of a conflict
+    pub fn failures(&self) -> impl Iterator<Item = &CouchError> {
+        self.results.iter().filter_map(|r| r.as_ref().err())
+    }
+}
+
+/// Options for `Database::bulk_import`. Use the builder paradigm to override the defaults.
+#[derive(Debug, Clone)]
+pub struct BulkImportOptions {
+    /// Maximum number of documents per `_bulk_docs` request
+    pub chunk_size: usize,
+    /// Maximum size, in bytes, of the documents in a single `_bulk_docs` request
+    pub max_bytes: usize,
+    /// Number of `_bulk_docs` requests that run concurrently
+    pub concurrency: usize,
+}
+
+impl Default for BulkImportOptions {
+    fn default() -> Self {
+        BulkImportOptions {
+            chunk_size: 1000,
+            max_bytes: 8 * 1024 * 1024,
+            concurrency: 4,
+        }
+    }
+}
+
+impl BulkImportOptions {
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+}
+
+/// Retry policy for conflicting writes. The backoff doubles after every attempt.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: u32,
+    /// Time to wait before the first retry
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(50),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Does not retry at all
+    pub fn no_retry() -> Self {
+        RetryPolicy::default().max_attempts(1)
+    }
+
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Time to wait after the given (1-based) failed attempt
+    pub fn backoff_for(&self, attempt: u32) -> Duration {
+        self.backoff * 2u32.saturating_pow(attempt.saturating_sub(1).min(16))
+    }
+}
+
+/// Options for reading a single document. Use the builder paradigm to set them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GetOptions {
+    /// Adds the `encoding` and `encoded_length` of compressed attachments to their stubs
+    pub att_encoding_info: Option<bool>,
+    /// Read quorum: the number of replicas that have to return the document in a cluster
+    pub r: Option<u32>,
+}
+
+impl GetOptions {
+    pub fn att_encoding_info(mut self, att_encoding_info: bool) -> Self {
+        self.att_encoding_info = Some(att_encoding_info);
+        self
+    }
+
+    pub fn r(mut self, r: u32) -> Self {
+        self.r = Some(r);
+        self
+    }
+
+    /// The options as query string parameters
+    pub(crate) fn to_query(&self) -> HashMap<String, String> {
+        let mut query = HashMap::new();
+        if let Some(att_encoding_info) = self.att_encoding_info {
+            query.insert(s!("att_encoding_info"), att_encoding_info.to_string());
+        }
+        if let Some(r) = self.r {
+            query.insert(s!("r"), r.to_string());
+        }
+        query
+    }
+}
+
+/// Options for writing a single document. Use the builder paradigm to set them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WriteOptions {
+    /// Write quorum: the number of replicas that have to store the document in a cluster, before
+    /// the write is acknowledged
+    pub w: Option<u32>,
+    /// Batch mode: CouchDB acknowledges the write before storing it, and stores it later together
+    /// with other writes. This is faster, but the write is lost when CouchDB stops before storing it,
+    /// and its revision is not returned.
+    pub batch: bool,
+}
+
+impl WriteOptions {
+    pub fn w(mut self, w: u32) -> Self {
+        self.w = Some(w);
+        self
+    }
+
+    pub fn batch(mut self, batch: bool) -> Self {
+        self.batch = batch;
+        self
+    }
+
+    /// The options as query string parameters
+    pub(crate) fn to_query(&self) -> HashMap<String, String> {
+        let mut query = HashMap::new();
+        if let Some(w) = self.w {
+            query.insert(s!("w"), w.to_string());
+        }
+        if self.batch {
+            query.insert(s!("batch"), s!("ok"));
+        }
+        query
+    }
+}
+
+/// Outcome of a single document write, as acknowledged by CouchDB
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteResult {
+    pub id: DocumentId,
+    /// The new revision; `None` for writes in batch mode, which CouchDB stores later
+    pub rev: Option<String>,
+    /// `201 Created` when the write was stored by the write quorum; `202 Accepted` when it was
+    /// stored by fewer replicas than the quorum, or only accepted in batch mode
+    pub status: StatusCode,
+}
+
+impl WriteResult {
+    /// Whether the write was stored by the write quorum, rather than only accepted
+    pub fn is_durable(&self) -> bool {
+        self.status == StatusCode::CREATED
+    }
+}
+
+/// Metadata of an attachment, as listed in the `_attachments` of a document that was read without
+/// its attachment bodies. Add `#[serde(default, rename = "_attachments")] attachments: Attachments`
+/// to a document type to receive them. An inline attachment, created with `inline`, holds its
+/// content instead, to be saved along with the document.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct AttachmentStub {
+    pub content_type: String,
+    /// Digest of the attachment content, such as `md5-...`; empty for inline attachments
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub digest: String,
+    /// Base64 encoded content of an inline attachment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// Length of the attachment content, in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<u64>,
+    /// Revision of the document in which the attachment was last changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revpos: Option<u64>,
+    /// Whether this only describes the attachment, without its content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stub: Option<bool>,
+    /// Compression of the stored attachment, such as `gzip`; only returned with `att_encoding_info`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    /// Length of the stored, compressed attachment; only returned with `att_encoding_info`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoded_length: Option<u64>,
+}
+
+impl AttachmentStub {
+    /// An attachment with its content, which is saved along with the document
+    pub fn inline(content_type: &str, content: &[u8]) -> AttachmentStub {
+        AttachmentStub {
+            content_type: content_type.to_string(),
+            data: Some(base64::encode(content)),
+            ..AttachmentStub::default()
+        }
+    }
+
+    /// Number of bytes the attachment takes in storage: its compressed length, when it is compressed
+    pub fn stored_length(&self) -> Option<u64> {
+        self.encoded_length.or(self.length)
+    }
+}
+
+/// Attachment stubs by attachment name
+pub type Attachments = HashMap<String, AttachmentStub>;
+
+/// Reads the attachment stubs of a raw document; a document without attachments has none
+pub fn attachments(doc: &Value) -> CouchResult<Attachments> {
+    match doc.get("_attachments") {
+        Some(stubs) => Ok(serde_json::from_value(stubs.clone())?),
+        None => Ok(Attachments::new()),
+    }
+}
+
+/// Outcome of `Database::bulk_import`
+#[derive(Debug, Clone, Default)]
+pub struct BulkImportResult {
+    /// Number of documents that were written
+    pub written: u64,
+    /// Number of documents that could not be written
+    pub failed: u64,
+    /// Errors per failed document, or per failed request
+    pub errors: Vec<CouchError>,
+    /// Number of `_bulk_docs` requests sent
+    pub requests: u64,
+    /// Number of bytes sent
+    pub bytes: u64,
+    /// Time it took to run the import
+    pub elapsed: Duration,
+}
+
+impl BulkImportResult {
+    /// Average number of documents written per second
+    pub fn docs_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.written as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// Adds the outcome of a single `_bulk_docs` request of `docs` documents
+    pub(crate) fn record(&mut self, docs: usize, outcome: CouchResult<Vec<DocumentCreatedResult>>) {
+        match outcome {
+            Ok(results) => {
+                for result in results {
+                    match result {
+                        Ok(_) => self.written += 1,
+                        Err(err) => {
+                            self.failed += 1;
+                            self.errors.push(err);
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                self.failed += docs as u64;
+                self.errors.push(err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+    use serde_json::{from_value, json};
+
+    #[test]
+    fn test_attachment_stubs() {
+        let doc = json!({
+            "_id": "report",
+            "_attachments": {
+                "data.csv": {
+                    "content_type": "text/csv",
+                    "digest": "md5-abc",
+                    "length": 1200,
+                    "revpos": 2,
+                    "stub": true,
+                    "encoding": "gzip",
+                    "encoded_length": 300
+                },
+                "logo.png": {"content_type": "image/png", "digest": "md5-def", "length": 500, "revpos": 1, "stub": true}
+            }
+        });
+
+        let stubs = attachments(&doc).unwrap();
+        assert_eq!(stubs["data.csv"].stored_length(), Some(300));
+        assert_eq!(stubs["logo.png"].stored_length(), Some(500));
+        assert_eq!(stubs["logo.png"].encoding, None);
+        assert!(attachments(&json!({"_id": "plain"})).unwrap().is_empty());
+
+        assert_eq!(GetOptions::default().to_query(), HashMap::new());
+        assert_eq!(GetOptions::default().r(2).to_query()["r"], "2");
+        assert_eq!(WriteOptions::default().w(3).to_query()["w"], "3");
+        assert_eq!(WriteOptions::default().batch(true).to_query()["batch"], "ok");
+        assert_eq!(
+            GetOptions::default().att_encoding_info(true).to_query()["att_encoding_info"],
+            "true"
+        );
+    }
+
+    #[test]
+    fn test_bulk_doc_result() {
+        let responses: Vec<DocumentCreatedResponse> = from_value(json!([
+            {"ok": true, "id": "a", "rev": "1-abc"},
+            {"id": "b", "error": "conflict", "reason": "Document update conflict."},
+            {"id": "c", "error": "forbidden", "reason": "invalid"},
+            {"id": "d", "error": "unknown_error", "reason": "oops"}
+        ]))
+        .unwrap();
+        let results: Vec<BulkDocResult> = responses.into_iter().map(BulkDocResult::from).collect();
+
+        assert_eq!(
+            results,
+            vec![
+                BulkDocResult::Ok {
+                    id: "a".to_string(),
+                    rev: "1-abc".to_string()
+                },
+                BulkDocResult::Conflict {
+                    id: Some("b".to_string())
+                },
+                BulkDocResult::Forbidden {
+                    id: Some("c".to_string()),
+                    reason: "invalid".to_string()
+                },
+                BulkDocResult::Error {
+                    id: Some("d".to_string()),
+                    error: "unknown_error".to_string(),
+                    reason: "oops".to_string()
+                },
+            ]
+        );
+        assert!(results[1].is_conflict());
+        assert_eq!(results[2].id(), Some("c"));
+    }
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let policy = RetryPolicy::default().backoff(Duration::from_millis(10));
+        assert_eq!(policy.backoff_for(1), Duration::from_millis(10));
+        assert_eq!(policy.backoff_for(3), Duration::from_millis(40));
+        assert_eq!(RetryPolicy::no_retry().max_attempts, 1);
+    }
+
+    #[test]
+    fn test_bulk_import_result() {
+        let mut result = BulkImportResult::default();
+        result.record(
+            2,
+            Ok(vec![
+                Ok(DocumentCreatedDetails {
+                    id: Some("a".to_string()),
+                    rev: Some("1-abc".to_string()),
+                }),
+                Err(CouchError::new("conflict".to_string(), StatusCode::CONFLICT)),
+            ]),
+        );
+        result.record(
+            3,
+            Err(CouchError::new("too large".to_string(), StatusCode::PAYLOAD_TOO_LARGE)),
+        );
+
+        assert_eq!(result.written, 1);
+        assert_eq!(result.failed, 4);
+        assert_eq!(result.errors.len(), 2);
+    }
+}
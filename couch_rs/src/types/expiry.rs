@@ -0,0 +1,74 @@
+use crate::runtime::unix_time;
+use std::time::Duration;
+
+/// Name of the field that holds the expiry time of a document, unless configured otherwise
+pub const DEFAULT_EXPIRY_FIELD: &str = "expires_at";
+
+/// Options for `Database::run_expiry_sweep`. Use the builder paradigm to override the defaults.
+#[derive(Debug, Clone)]
+pub struct ExpiryOptions {
+    /// Field that holds the expiry time, in seconds since the Unix epoch
+    pub field: String,
+    /// Number of expired documents that are removed per request
+    pub batch_size: u64,
+    /// Purge expired documents, instead of deleting them. Purged documents leave no tombstone, and
+    /// their removal is not replicated.
+    pub purge: bool,
+}
+
+impl Default for ExpiryOptions {
+    fn default() -> Self {
+        ExpiryOptions {
+            field: DEFAULT_EXPIRY_FIELD.to_string(),
+            batch_size: 1000,
+            purge: false,
+        }
+    }
+}
+
+impl ExpiryOptions {
+    pub fn field(mut self, field: &str) -> Self {
+        self.field = field.to_string();
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: u64) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn purge(mut self, purge: bool) -> Self {
+        self.purge = purge;
+        self
+    }
+
+    /// Name of the Mango index on the expiry field
+    pub fn index_name(&self) -> String {
+        format!("expiry-{}", self.field)
+    }
+}
+
+/// The expiry time for a document that expires after `ttl`, in seconds since the Unix epoch
+pub fn expires_in(ttl: Duration) -> u64 {
+    now() + ttl.as_secs()
+}
+
+/// The current time, in seconds since the Unix epoch
+pub(crate) fn now() -> u64 {
+    unix_time()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expiry_options() {
+        let options = ExpiryOptions::default().field("ttl").batch_size(0).purge(true);
+        assert_eq!(options.batch_size, 1);
+        assert_eq!(options.index_name(), "expiry-ttl");
+
+        let expires_at = expires_in(Duration::from_secs(60));
+        assert!(expires_at >= now() + 59 && expires_at <= now() + 60);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::error::{CouchError, CouchResult};
+use crate::types::changes::Seq;
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+
+/// Options for `Database::export`. Use the builder paradigm to override the defaults.
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// Exports the content of attachments, base64 encoded next to their digest, so a restore brings
+    /// them back. Without it, attachments are left out of the export.
+    pub attachments: bool,
+    /// Exports the database as of a single update sequence, even while it is written to; see
+    /// `Database::export`
+    pub snapshot: bool,
+}
+
+impl ExportOptions {
+    pub fn attachments(mut self, attachments: bool) -> Self {
+        self.attachments = attachments;
+        self
+    }
+
+    pub fn snapshot(mut self, snapshot: bool) -> Self {
+        self.snapshot = snapshot;
+        self
+    }
+}
+
+/// Summary of `Database::export`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportSummary {
+    /// Number of exported documents, including design documents. A document exported again by a
+    /// snapshot export is counted once.
+    pub doc_count: u64,
+    /// Number of exported attachments
+    pub attachment_count: u64,
+    /// The update sequence as of which a snapshot export holds the database
+    pub seq: Option<Seq>,
+    /// Number of documents of a snapshot export that changed after they were read, and were exported
+    /// once more at their revision as of `seq`; the later line supersedes the earlier one on restore
+    pub changed_count: u64,
+}
+
+/// Summary of `Database::restore`
+#[derive(Debug, Clone, Default)]
+pub struct RestoreSummary {
+    /// Number of documents read from the export
+    pub doc_count: u64,
+    /// The documents that could not be written, for example design documents when the client is
+    /// not an admin
+    pub failures: Vec<CouchError>,
+}
+
+/// The revisions written by a snapshot export, so the changes made during the export are only
+/// exported when they are not in it yet. Ids and revisions are kept as hashes, which keeps the
+/// memory use small for large databases.
+#[derive(Debug, Default)]
+pub(crate) struct ExportedRevs(HashMap<u64, u64>);
+
+impl ExportedRevs {
+    /// Records the revision exported for a document. Returns whether the document was exported
+    /// before.
+    pub(crate) fn insert(&mut self, id: &str, rev: &str) -> bool {
+        self.0.insert(hash(id), hash(rev)).is_some()
+    }
+
+    pub(crate) fn contains_id(&self, id: &str) -> bool {
+        self.0.contains_key(&hash(id))
+    }
+
+    pub(crate) fn contains(&self, id: &str, rev: &str) -> bool {
+        self.0.get(&hash(id)) == Some(&hash(rev))
+    }
+}
+
+fn hash(value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Writes a document as a line of an export, leaving out its attachments unless `attachments` is
+/// set. Returns the number of exported attachments.
+pub(crate) fn write_doc<W: Write>(writer: &mut W, mut doc: Value, attachments: bool) -> CouchResult<u64> {
+    let exported = match doc.as_object_mut() {
+        Some(fields) if !attachments => {
+            fields.remove("_attachments");
+            0
+        }
+        Some(fields) => fields
+            .get("_attachments")
+            .and_then(Value::as_object)
+            .map_or(0, |stubs| stubs.len() as u64),
+        None => 0,
+    };
+
+    let mut line = serde_json::to_vec(&doc)?;
+    line.push(b'\n');
+    writer.write_all(&line).map_err(|err| io_failed("write", err))?;
+    Ok(exported)
+}
+
+pub(crate) fn io_failed(operation: &str, err: io::Error) -> CouchError {
+    CouchError::new(
+        format!("could not {} the export: {}", operation, err),
+        StatusCode::INTERNAL_SERVER_ERROR,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_write_doc() {
+        let doc = json!({
+            "_id": "a",
+            "_rev": "1-x",
+            "_attachments": {"notes.txt": {"content_type": "text/plain", "digest": "md5-x", "data": "aGk="}}
+        });
+        let mut export = vec![];
+        assert_eq!(write_doc(&mut export, doc.clone(), true).unwrap(), 1);
+        assert_eq!(write_doc(&mut export, doc, false).unwrap(), 0);
+        assert_eq!(
+            write_doc(&mut export, json!({"_id": "b", "_rev": "1-y"}), true).unwrap(),
+            0
+        );
+
+        let lines: Vec<Value> = export
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["_attachments"]["notes.txt"]["data"], "aGk=");
+        assert_eq!(lines[1], json!({"_id": "a", "_rev": "1-x"}));
+    }
+
+    #[test]
+    fn test_exported_revs() {
+        let mut exported = ExportedRevs::default();
+        assert!(!exported.insert("a", "1-x"));
+        assert!(exported.contains("a", "1-x"));
+        assert!(!exported.contains("a", "2-y"));
+        assert!(!exported.contains_id("b"));
+
+        assert!(exported.insert("a", "2-y"));
+        assert!(exported.contains("a", "2-y"));
+        assert!(!exported.contains("a", "1-x"));
+    }
+}
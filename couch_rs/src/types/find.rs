@@ -1,9 +1,13 @@
-use crate::document::TypedCouchDocument;
-use serde::export::Formatter;
+use crate::error::{CouchError, CouchResult};
+use crate::types::index::Index;
+use crate::types::query::Freshness;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::fmt::Formatter;
 
 /// Sort direction abstraction
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
@@ -54,7 +58,7 @@ pub struct FindQuery {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub skip: Option<u64>,
 
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub sort: Vec<SortSpec>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -63,6 +67,9 @@ pub struct FindQuery {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub use_index: Option<IndexSpec>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conflicts: Option<bool>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub r: Option<i32>,
 
@@ -80,32 +87,115 @@ pub struct FindQuery {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub execution_stats: Option<bool>,
+
+    /// Keep design documents in the results of `find`; they are left out by default. Not sent to
+    /// CouchDB.
+    #[serde(skip)]
+    pub include_design_docs: bool,
 }
 
 /// Find result abstraction
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
-#[serde(bound(deserialize = "T: TypedCouchDocument"))]
-pub struct FindResult<T: TypedCouchDocument> {
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+pub struct FindResult<T> {
     pub docs: Option<Vec<T>>,
     pub warning: Option<String>,
     pub error: Option<String>,
     pub reason: Option<String>,
     pub bookmark: Option<String>,
+    pub execution_stats: Option<ExecutionStats>,
+}
+
+/// Documents found by a Mango query, deserialized into any type. Returned by `find_as`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct FindDocuments<T> {
+    pub docs: Vec<T>,
+    /// Bookmark to request the next page of results; `None` when there are no more results
+    pub bookmark: Option<String>,
+    pub warning: Option<String>,
+    pub execution_stats: Option<ExecutionStats>,
+}
+
+/// Execution statistics of a Mango query, returned when the query was run with `execution_stats(true)`
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ExecutionStats {
+    /// Number of index keys examined
+    pub total_keys_examined: u64,
+    /// Number of documents fetched from the database / index
+    pub total_docs_examined: u64,
+    /// Number of documents fetched from the database using an out-of-band document fetch
+    pub total_quorum_docs_examined: u64,
+    /// Number of results returned from the query
+    pub results_returned: u64,
+    /// Total execution time in milliseconds as measured by the database
+    pub execution_time_ms: f64,
 }
 
 //todo: include status on structs
 
-/// Explain result abstraction
+/// Explain result abstraction, describing the plan CouchDB uses to run a Mango query.
+/// See [/db/_explain](https://docs.couchdb.org/en/stable/api/database/find.html#db-explain)
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct ExplainResult {
     pub dbname: String,
-    pub index: IndexSpec,
+    /// The index chosen to run the query. `_all_docs` means a full database scan.
+    pub index: Index,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partitioned: Option<bool>,
     pub selector: Value,
     pub opts: Value,
-    pub limit: u32,
+    pub limit: u64,
     pub skip: u64,
-    pub fields: Vec<String>,
-    pub range: Value,
+    pub fields: ExplainFields,
+    /// Arguments passed to the underlying view, available for json indexes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mrargs: Option<MrArgs>,
+    /// Whether the index covers all requested fields, so no documents need to be read (CouchDB 3.4+)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub covering: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<Value>,
+}
+
+/// Fields returned by an explained query
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+#[serde(untagged)]
+pub enum ExplainFields {
+    /// All fields are returned; CouchDB reports this as "all_fields"
+    All(String),
+    Selected(Vec<String>),
+}
+
+/// View arguments of an explained query
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct MrArgs {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_key: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_key: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direction: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_docs: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub view_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reduce: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub update: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conflicts: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition: Option<Value>,
+}
+
+impl ExplainResult {
+    /// Returns true when no index could be used, and the query requires a full database scan
+    pub fn is_full_scan(&self) -> bool {
+        self.index.name == "_all_docs"
+    }
 }
 
 /// $ne operation
@@ -138,9 +228,9 @@ impl SelectAll {
     }
 }
 
-impl Into<serde_json::Value> for &SelectAll {
-    fn into(self) -> Value {
-        serde_json::to_value(&self).expect("can not convert into json")
+impl From<&SelectAll> for serde_json::Value {
+    fn from(value: &SelectAll) -> Value {
+        serde_json::to_value(value).expect("can not convert into json")
     }
 }
 
@@ -158,6 +248,128 @@ macro_rules! find_all_selector {
     };
 }
 
+/// Builds a `FindQuery` from an inline selector, without nested `json!` blocks. Keys are
+/// identifiers, string literals for dotted paths such as `"address.city"`, or Mango operators;
+/// values are nested selectors, arrays or any serializable expression. Unknown operators fail to
+/// compile; `FindQuery::validate` checks their operands.
+///
+/// ```
+/// use couch_rs::mango;
+/// use serde_json::json;
+///
+/// let min_age = 21;
+/// let query = mango! {
+///     type: "user",
+///     age: { $gt: min_age },
+///     "address.city": { $in: ["Paris", "Oslo"] },
+///     $or: [{ vip: true }, { score: { $gte: 9.5 } }]
+/// }
+/// .limit(10);
+///
+/// assert_eq!(
+///     query.selector,
+///     json!({
+///         "type": "user",
+///         "age": {"$gt": 21},
+///         "address.city": {"$in": ["Paris", "Oslo"]},
+///         "$or": [{"vip": true}, {"score": {"$gte": 9.5}}]
+///     })
+/// );
+/// assert!(query.validate().is_ok());
+/// ```
+///
+/// Unknown operators are rejected:
+///
+/// ```compile_fail
+/// let query = couch_rs::mango! { age: { $greater: 21 } };
+/// ```
+#[macro_export]
+macro_rules! mango {
+    (@value {}) => {
+        $crate::serde_json::Value::Object($crate::serde_json::Map::new())
+    };
+    (@value { $($fields:tt)+ }) => {{
+        let mut fields = $crate::serde_json::Map::new();
+        $crate::mango!(@fields fields; $($fields)+);
+        $crate::serde_json::Value::Object(fields)
+    }};
+    (@value [ $($items:tt)* ]) => {
+        $crate::mango!(@items [] () $($items)*)
+    };
+    (@value $($value:tt)+) => {
+        $crate::serde_json::json!($($value)+)
+    };
+
+    // Munches the `key: value` pairs of an object
+    (@fields $fields:ident;) => {};
+    (@fields $fields:ident; $dollar:tt $operator:ident : $($rest:tt)*) => {
+        $crate::mango!(@field $fields; $crate::mango!(@operator $operator); () $($rest)*);
+    };
+    (@fields $fields:ident; $name:ident : $($rest:tt)*) => {
+        $crate::mango!(@field $fields; stringify!($name); () $($rest)*);
+    };
+    (@fields $fields:ident; $name:literal : $($rest:tt)*) => {
+        $crate::mango!(@field $fields; $name; () $($rest)*);
+    };
+
+    // Collects the tokens of a value, up to the next comma
+    (@field $fields:ident; $key:expr; ($($value:tt)+) , $($rest:tt)*) => {
+        $fields.insert(String::from($key), $crate::mango!(@value $($value)+));
+        $crate::mango!(@fields $fields; $($rest)*);
+    };
+    (@field $fields:ident; $key:expr; ($($value:tt)+)) => {
+        $fields.insert(String::from($key), $crate::mango!(@value $($value)+));
+    };
+    (@field $fields:ident; $key:expr; ($($value:tt)*) $next:tt $($rest:tt)*) => {
+        $crate::mango!(@field $fields; $key; ($($value)* $next) $($rest)*);
+    };
+
+    // Collects the tokens of each item of an array, up to the next comma
+    (@items [$($done:expr,)*] ()) => {
+        $crate::serde_json::Value::Array(vec![$($done,)*])
+    };
+    (@items [$($done:expr,)*] ($($item:tt)+)) => {
+        $crate::serde_json::Value::Array(vec![$($done,)* $crate::mango!(@value $($item)+)])
+    };
+    (@items [$($done:expr,)*] ($($item:tt)+) , $($rest:tt)*) => {
+        $crate::mango!(@items [$($done,)* $crate::mango!(@value $($item)+),] () $($rest)*)
+    };
+    (@items [$($done:expr,)*] ($($item:tt)*) $next:tt $($rest:tt)*) => {
+        $crate::mango!(@items [$($done,)*] ($($item)* $next) $($rest)*)
+    };
+
+    // The Mango operators; any other name is a compile error
+    (@operator and) => { "$and" };
+    (@operator or) => { "$or" };
+    (@operator nor) => { "$nor" };
+    (@operator not) => { "$not" };
+    (@operator eq) => { "$eq" };
+    (@operator ne) => { "$ne" };
+    (@operator lt) => { "$lt" };
+    (@operator lte) => { "$lte" };
+    (@operator gt) => { "$gt" };
+    (@operator gte) => { "$gte" };
+    (@operator in) => { "$in" };
+    (@operator nin) => { "$nin" };
+    (@operator all) => { "$all" };
+    (@operator exists) => { "$exists" };
+    (@operator type) => { "$type" };
+    (@operator size) => { "$size" };
+    (@operator mod) => { "$mod" };
+    (@operator regex) => { "$regex" };
+    (@operator beginsWith) => { "$beginsWith" };
+    (@operator elemMatch) => { "$elemMatch" };
+    (@operator allMatch) => { "$allMatch" };
+    (@operator keyMapMatch) => { "$keyMapMatch" };
+    (@operator $other:ident) => {
+        compile_error!(concat!("unknown Mango operator $", stringify!($other)))
+    };
+
+    ($($selector:tt)*) => {
+        $crate::types::find::FindQuery::new($crate::mango!(@value { $($selector)* }))
+    };
+}
+
 /// Find query. You can use the builder paradigm to construct these parameters easily:
 /// ```
 /// use couch_rs::types::find::FindQuery;
@@ -168,6 +380,61 @@ impl FindQuery {
         query.into()
     }
 
+    /// Creates a FindQuery from the JSON body of a `_find` request, checking it like `validate`.
+    /// Unknown fields and values of the wrong type are `BAD_REQUEST` errors instead of panics.
+    ///
+    /// ```
+    /// use couch_rs::types::find::FindQuery;
+    /// use serde_json::json;
+    ///
+    /// let query = FindQuery::try_from_value(json!({"selector": {"age": {"$gt": 21}}, "limit": 10})).unwrap();
+    /// assert_eq!(query.limit, Some(10));
+    ///
+    /// let err = FindQuery::try_from_value(json!({"selector": {"age": {"$greater": 21}}})).unwrap_err();
+    /// assert_eq!(err.message(), "invalid find query: unknown operator $greater in selector.age");
+    /// ```
+    pub fn try_from_value(query: Value) -> CouchResult<Self> {
+        let fields = query.as_object().ok_or_else(|| invalid_query("it must be an object"))?;
+        if let Some(name) = fields.keys().find(|name| !QUERY_FIELDS.contains(&name.as_str())) {
+            return Err(invalid_query(&format!("unknown field {}", name)));
+        }
+        if !fields.contains_key("selector") {
+            return Err(invalid_query("selector is required"));
+        }
+
+        let query: FindQuery = serde_json::from_value(query).map_err(|err| invalid_query(&err.to_string()))?;
+        query.validate()?;
+        Ok(query)
+    }
+
+    /// Creates a FindQuery from a JSON string, checking it like `try_from_value`
+    pub fn from_json_str(json: &str) -> CouchResult<Self> {
+        let query = serde_json::from_str(json).map_err(|err| invalid_query(&err.to_string()))?;
+        Self::try_from_value(query)
+    }
+
+    /// Checks the selector and sort of the query, so a query CouchDB would reject fails before it
+    /// is sent, with a `BAD_REQUEST` error telling where the problem is. Operators must be known
+    /// Mango operators with operands of the right type, and sort fields must all use one direction.
+    pub fn validate(&self) -> CouchResult<()> {
+        check_selector(&self.selector, "selector").map_err(|problem| invalid_query(&problem))?;
+
+        let mut directions = vec![];
+        for (idx, spec) in self.sort.iter().enumerate() {
+            match spec {
+                SortSpec::Simple(_) => directions.push(SortDirection::Asc),
+                SortSpec::Complex(content) if content.len() == 1 => directions.extend(content.values().cloned()),
+                SortSpec::Complex(_) => {
+                    return Err(invalid_query(&format!("sort[{}] must name exactly one field", idx)));
+                }
+            }
+        }
+        if directions.windows(2).any(|pair| pair[0] != pair[1]) {
+            return Err(invalid_query("all sort fields must use the same direction"));
+        }
+        Ok(())
+    }
+
     // Create a new FindQuery from a valid selector. The selector syntax is documented here:
     // https://docs.couchdb.org/en/latest/api/database/find.html#find-selectors
     pub fn new(selector: Value) -> Self {
@@ -178,12 +445,14 @@ impl FindQuery {
             sort: vec![],
             fields: None,
             use_index: None,
+            conflicts: None,
             r: None,
             bookmark: None,
             update: None,
             stable: None,
             stale: None,
             execution_stats: None,
+            include_design_docs: false,
         }
     }
 
@@ -215,11 +484,36 @@ impl FindQuery {
         self
     }
 
+    /// Appends a field to sort on. All fields in a sort should use the same direction, and an
+    /// index on these fields is required.
+    pub fn sort_by(mut self, field: &str, direction: SortDirection) -> Self {
+        let mut spec = SortSpecContent::new();
+        spec.insert(field.to_string(), direction);
+        self.sort.push(SortSpec::Complex(spec));
+        self
+    }
+
     pub fn use_index(mut self, use_index: IndexSpec) -> Self {
         self.use_index = Some(use_index);
         self
     }
 
+    /// Instructs the query to use an index from the given design document
+    pub fn use_index_ddoc(self, ddoc: &str) -> Self {
+        self.use_index(IndexSpec::DesignDocument(ddoc.to_string()))
+    }
+
+    /// Instructs the query to use the named index from the given design document
+    pub fn use_index_name(self, ddoc: &str, name: &str) -> Self {
+        self.use_index(IndexSpec::IndexName((ddoc.to_string(), name.to_string())))
+    }
+
+    /// Include conflicted revisions in the `_conflicts` field of the returned documents
+    pub fn conflicts(mut self, conflicts: bool) -> Self {
+        self.conflicts = Some(conflicts);
+        self
+    }
+
     pub fn r(mut self, r: i32) -> Self {
         self.r = Some(r);
         self
@@ -245,21 +539,120 @@ impl FindQuery {
         self
     }
 
+    /// Sets `stable` and `update` for the given freshness. `_find` can not update an index after
+    /// responding, so `UpdateAfter` behaves like `Stale`.
+    pub fn freshness(mut self, freshness: Freshness) -> Self {
+        let stale = freshness != Freshness::Fresh;
+        self.stable = Some(stale);
+        self.update = Some(!stale);
+        self
+    }
+
+    /// Sets the legacy `stale` parameter for the given freshness, for servers that do not support
+    /// `stable` and `update`
+    pub fn legacy_freshness(mut self, freshness: Freshness) -> Self {
+        self.stale = freshness.legacy_stale().map(String::from);
+        self
+    }
+
     pub fn execution_stats(mut self, execution_stats: bool) -> Self {
         self.execution_stats = Some(execution_stats);
         self
     }
+
+    /// Keep design documents, and any other document whose `_id` starts with `_`, in the results
+    /// of `find` instead of leaving them out
+    pub fn include_design_docs(mut self, include_design_docs: bool) -> Self {
+        self.include_design_docs = include_design_docs;
+        self
+    }
+}
+
+/// Fields of the body of a `_find` request
+const QUERY_FIELDS: &[&str] = &[
+    "selector",
+    "limit",
+    "skip",
+    "sort",
+    "fields",
+    "use_index",
+    "conflicts",
+    "r",
+    "bookmark",
+    "update",
+    "stable",
+    "stale",
+    "execution_stats",
+];
+
+/// Values of the `$type` operator
+const SELECTOR_TYPES: &[&str] = &["null", "boolean", "number", "string", "array", "object"];
+
+fn invalid_query(problem: &str) -> CouchError {
+    CouchError::new(format!("invalid find query: {}", problem), StatusCode::BAD_REQUEST)
+}
+
+/// Checks the operators of a selector, or of the condition on a field, at the given path
+fn check_selector(selector: &Value, path: &str) -> Result<(), String> {
+    let fields = selector
+        .as_object()
+        .ok_or_else(|| format!("{} must be an object", path))?;
+    for (name, value) in fields {
+        let path = format!("{}.{}", path, name);
+        if name.starts_with('$') {
+            check_operator(name, value, &path)?;
+        } else if value.is_object() {
+            check_selector(value, &path)?;
+        }
+    }
+    Ok(())
+}
+
+fn check_operator(operator: &str, operand: &Value, path: &str) -> Result<(), String> {
+    let valid = match operator {
+        "$and" | "$or" | "$nor" => {
+            let selectors = operand.as_array().ok_or_else(|| format!("{} must be an array", path))?;
+            for (idx, selector) in selectors.iter().enumerate() {
+                check_selector(selector, &format!("{}[{}]", path, idx))?;
+            }
+            true
+        }
+        "$not" | "$elemMatch" | "$allMatch" | "$keyMapMatch" => {
+            check_selector(operand, path)?;
+            true
+        }
+        "$eq" | "$ne" | "$lt" | "$lte" | "$gt" | "$gte" => true,
+        "$in" | "$nin" | "$all" => operand.is_array(),
+        "$exists" => operand.is_boolean(),
+        "$type" => operand.as_str().is_some_and(|name| SELECTOR_TYPES.contains(&name)),
+        "$size" => operand.is_u64(),
+        "$mod" => match operand.as_array().map(Vec::as_slice) {
+            Some([divisor, remainder]) => divisor.as_i64().is_some_and(|d| d != 0) && remainder.is_i64(),
+            _ => false,
+        },
+        "$regex" | "$beginsWith" => operand.is_string(),
+        _ => {
+            let parent = path.rsplit_once('.').map_or(path, |(parent, _)| parent);
+            return Err(format!("unknown operator {} in {}", operator, parent));
+        }
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid operand of {}: {}", path, operand))
+    }
 }
 
-impl Into<serde_json::Value> for FindQuery {
-    fn into(self) -> Value {
-        serde_json::to_value(&self).expect("can not convert into json")
+impl From<FindQuery> for serde_json::Value {
+    fn from(value: FindQuery) -> Value {
+        serde_json::to_value(value).expect("can not convert into json")
     }
 }
 
-impl Into<serde_json::Value> for &FindQuery {
-    fn into(self) -> Value {
-        serde_json::to_value(&self).expect("can not convert into json")
+impl From<&FindQuery> for serde_json::Value {
+    fn from(value: &FindQuery) -> Value {
+        serde_json::to_value(value).expect("can not convert into json")
     }
 }
 
@@ -297,6 +690,39 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_builder_parameters() {
+        let query = FindQuery::new(json!({"year": {"$gt": 2010}}))
+            .fields(vec!["_id".to_string(), "year".to_string()])
+            .sort_by("year", SortDirection::Desc)
+            .use_index_name("year-ddoc", "year-index")
+            .skip(5)
+            .conflicts(true)
+            .stable(true)
+            .update(false);
+
+        assert_eq!(
+            query.as_value(),
+            json!({
+                "selector": {"year": {"$gt": 2010}},
+                "fields": ["_id", "year"],
+                "sort": [{"year": "desc"}],
+                "use_index": ["year-ddoc", "year-index"],
+                "skip": 5,
+                "conflicts": true,
+                "stable": true,
+                "update": false
+            })
+        );
+
+        let query = FindQuery::find_all().use_index_ddoc("year-ddoc");
+        assert_eq!(query.as_value()["use_index"], json!("year-ddoc"));
+
+        let query = FindQuery::find_all().include_design_docs(true);
+        assert!(query.include_design_docs);
+        assert_eq!(query.as_value(), FindQuery::find_all().as_value());
+    }
+
     #[test]
     fn test_default_select_all() {
         let selector = FindQuery::find_all().as_value().to_string();
@@ -319,7 +745,7 @@ mod tests {
         assert_eq!(selector, r#"{"thing":true}"#);
         assert_eq!(query.limit, Some(1));
         assert_eq!(query.sort.len(), 1);
-        let first_sort = query.sort.get(0).unwrap();
+        let first_sort = query.sort.first().unwrap();
         if let SortSpec::Complex(spec) = first_sort {
             assert!(spec.contains_key("thing"));
             let direction = spec.get("thing").unwrap();
@@ -328,4 +754,165 @@ mod tests {
             panic!("unexpected sort spec");
         }
     }
+
+    #[test]
+    fn test_try_from_value() {
+        let query = FindQuery::try_from_value(json!({
+            "selector": {
+                "$or": [{"tags": {"$all": ["a"]}}, {"age": {"$mod": [2, 0], "$exists": true}}],
+                "address": {"city": {"$regex": "^P"}},
+                "scores": {"$elemMatch": {"$gte": 80}}
+            },
+            "sort": [{"age": "desc"}, {"name": "desc"}],
+            "fields": ["_id"]
+        }))
+        .unwrap();
+        assert_eq!(query.fields, Some(vec![s!("_id")]));
+
+        let problem = |query: Value| FindQuery::try_from_value(query).unwrap_err().message();
+        assert_eq!(problem(json!([])), "invalid find query: it must be an object");
+        assert_eq!(problem(json!({"limit": 1})), "invalid find query: selector is required");
+        assert_eq!(
+            problem(json!({"selector": {}, "partition": "a"})),
+            "invalid find query: unknown field partition"
+        );
+        assert!(problem(json!({"selector": {}, "limit": "10"})).starts_with("invalid find query: invalid type"));
+        assert_eq!(
+            problem(json!({"selector": {"$and": [{"a": 1}, 2]}})),
+            "invalid find query: selector.$and[1] must be an object"
+        );
+        assert_eq!(
+            problem(json!({"selector": {"age": {"$type": "int"}}})),
+            r#"invalid find query: invalid operand of selector.age.$type: "int""#
+        );
+        assert_eq!(
+            problem(json!({"selector": {}, "sort": ["name", {"age": "desc"}]})),
+            "invalid find query: all sort fields must use the same direction"
+        );
+        assert_eq!(
+            problem(json!({"selector": {}, "sort": [{"age": "desc", "name": "desc"}]})),
+            "invalid find query: sort[0] must name exactly one field"
+        );
+
+        let err = FindQuery::from_json_str(r#"{"selector": {"name": {"$eq": "john"}}"#).unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert!(FindQuery::from_json_str(r#"{"selector": {"name": "john"}}"#).is_ok());
+    }
+
+    #[test]
+    fn test_mango_macro() {
+        let names = vec!["john", "jane"];
+        let query = mango! {
+            name: { $in: names, $ne: "" },
+            "tags.0": { $exists: true },
+            age: { $mod: [2, 0], $gte: -1 },
+            $not: { deleted: true },
+        };
+        assert_eq!(
+            query.selector,
+            json!({
+                "name": {"$in": ["john", "jane"], "$ne": ""},
+                "tags.0": {"$exists": true},
+                "age": {"$mod": [2, 0], "$gte": -1},
+                "$not": {"deleted": true}
+            })
+        );
+        assert_eq!(mango! {}.selector, json!({}));
+        assert_eq!(mango! { tags: [] }.selector, json!({"tags": []}));
+    }
+
+    #[test]
+    fn test_explain_result() {
+        let explained: ExplainResult = serde_json::from_value(json!({
+            "dbname": "movies",
+            "index": {
+                "ddoc": "_design/0d61d9177426b1e2aa8d0fe732ec6e506f5d443c",
+                "name": "0d61d9177426b1e2aa8d0fe732ec6e506f5d443c",
+                "type": "json",
+                "partitioned": false,
+                "def": {
+                    "fields": [{"year": "asc"}]
+                }
+            },
+            "partitioned": false,
+            "selector": {"year": {"$gt": 2010}},
+            "opts": {"use_index": [], "bookmark": "nil", "limit": 2, "skip": 0},
+            "limit": 2,
+            "skip": 0,
+            "fields": ["_id", "_rev", "year"],
+            "mrargs": {
+                "include_docs": true,
+                "view_type": "map",
+                "reduce": false,
+                "partition": null,
+                "start_key": [2010],
+                "end_key": ["<MAX>"],
+                "direction": "fwd",
+                "stable": false,
+                "update": true,
+                "conflicts": "undefined"
+            },
+            "covering": false
+        }))
+        .unwrap();
+
+        assert!(!explained.is_full_scan());
+        assert_eq!(explained.index.index_type, "json");
+        assert_eq!(explained.covering, Some(false));
+        assert_eq!(explained.mrargs.unwrap().start_key, Some(json!([2010])));
+
+        let all_docs: ExplainResult = serde_json::from_value(json!({
+            "dbname": "movies",
+            "index": {
+                "ddoc": null,
+                "name": "_all_docs",
+                "type": "special",
+                "def": {"fields": [{"_id": "asc"}]}
+            },
+            "selector": {"title": {"$eq": "Alien"}},
+            "opts": {},
+            "limit": 25,
+            "skip": 0,
+            "fields": "all_fields",
+            "mrargs": {"start_key": null, "end_key": "<MAX>", "direction": "fwd"}
+        }))
+        .unwrap();
+
+        assert!(all_docs.is_full_scan());
+        assert_eq!(all_docs.fields, ExplainFields::All("all_fields".to_string()));
+    }
+
+    #[test]
+    fn test_find_result_with_execution_stats() {
+        let result: FindResult<Value> = serde_json::from_value(json!({
+            "docs": [{"_id": "1", "_rev": "1-abc"}],
+            "bookmark": "g1AAAA",
+            "execution_stats": {
+                "total_keys_examined": 0,
+                "total_docs_examined": 200,
+                "total_quorum_docs_examined": 0,
+                "results_returned": 1,
+                "execution_time_ms": 7.2
+            }
+        }))
+        .unwrap();
+
+        let stats = result.execution_stats.unwrap();
+        assert_eq!(stats.total_docs_examined, 200);
+        assert_eq!(stats.results_returned, 1);
+        assert!(stats.execution_time_ms > 7.0);
+    }
+
+    #[test]
+    fn test_freshness() {
+        let query = FindQuery::find_all().freshness(Freshness::UpdateAfter);
+        assert_eq!(query.stable, Some(true));
+        assert_eq!(query.update, Some(false));
+
+        let query = FindQuery::find_all()
+            .freshness(Freshness::Fresh)
+            .legacy_freshness(Freshness::Stale);
+        assert_eq!(query.update, Some(true));
+        assert_eq!(query.stale, Some("ok".to_string()));
+    }
 }
@@ -1,20 +1,157 @@
 use super::*;
-use serde::{Serialize, Deserialize};
-use find::{SortSpec};
-use document::{DocumentId};
+use document::DocumentId;
+use find::{ExplainResult, FindQuery, SortDirection, SortSpec};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 /// Index fields abstraction
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
 pub struct IndexFields {
-    pub fields: Vec<SortSpec>
+    pub fields: Vec<SortSpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partial_filter_selector: Option<Value>,
 }
 
 impl IndexFields {
     pub fn new(fields: Vec<SortSpec>) -> IndexFields {
         IndexFields {
-            fields
+            fields,
+            partial_filter_selector: None,
         }
     }
+
+    /// Only documents matching the selector will be included in the index.
+    pub fn partial_filter_selector(mut self, selector: Value) -> Self {
+        self.partial_filter_selector = Some(selector);
+        self
+    }
+
+    /// Compares two index definitions, ignoring the differences CouchDB introduces when it
+    /// normalizes a definition: `"field"` equals `{"field": "asc"}`, and an empty partial filter
+    /// equals no partial filter.
+    pub fn is_equivalent(&self, other: &IndexFields) -> bool {
+        self.normalized_fields() == other.normalized_fields()
+            && self.normalized_partial_filter() == other.normalized_partial_filter()
+    }
+
+    fn normalized_fields(&self) -> Vec<(String, SortDirection)> {
+        self.fields
+            .iter()
+            .flat_map(|spec| match spec {
+                SortSpec::Simple(field) => vec![(field.clone(), SortDirection::Asc)],
+                SortSpec::Complex(content) => content
+                    .iter()
+                    .map(|(field, direction)| (field.clone(), direction.clone()))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    fn normalized_partial_filter(&self) -> Option<&Value> {
+        self.partial_filter_selector
+            .as_ref()
+            .filter(|selector| !selector.as_object().is_some_and(|o| o.is_empty()))
+    }
+}
+
+impl IndexFields {
+    /// Suggests an index for a Mango query: the fields compared for equality come first, then the
+    /// fields the query sorts on, then the fields compared by range (`$gt`, `$lt`, `$beginsWith`,
+    /// ...). Fields under `$or`, `$nor`, `$not` and the array operators are left out, as a plain
+    /// json index can not select on them. Returns `None` when the query has no such fields.
+    pub fn suggest_for(query: &FindQuery) -> Option<IndexFields> {
+        let selected = SelectorFields::of(&query.selector);
+        let sorted = query.sort.iter().flat_map(|spec| match spec {
+            SortSpec::Simple(field) => vec![field.clone()],
+            SortSpec::Complex(content) => content.keys().cloned().collect(),
+        });
+
+        let mut fields: Vec<String> = vec![];
+        for field in selected.equal.into_iter().chain(sorted).chain(selected.range) {
+            if !fields.contains(&field) {
+                fields.push(field);
+            }
+        }
+
+        if fields.is_empty() {
+            None
+        } else {
+            Some(IndexFields::new(fields.into_iter().map(SortSpec::Simple).collect()))
+        }
+    }
+
+    /// A name for the index, derived from its fields, such as `advised_type_address.city`
+    pub fn suggested_name(&self) -> String {
+        let fields: Vec<String> = self.normalized_fields().into_iter().map(|(field, _)| field).collect();
+        format!("advised_{}", fields.join("_"))
+    }
+}
+
+/// The fields a Mango selector compares, which an index on them can select
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct SelectorFields {
+    /// Fields compared for equality
+    pub(crate) equal: Vec<String>,
+    /// Fields compared by range, and not for equality
+    pub(crate) range: Vec<String>,
+}
+
+impl SelectorFields {
+    pub(crate) fn of(selector: &Value) -> SelectorFields {
+        let mut fields = SelectorFields::default();
+        fields.collect(selector, None);
+        let SelectorFields { equal, mut range } = fields;
+        range.retain(|field| !equal.contains(field));
+        SelectorFields { equal, range }
+    }
+
+    fn collect(&mut self, selector: &Value, field: Option<&str>) {
+        let conditions = match (selector, field) {
+            (Value::Object(conditions), _) if !conditions.is_empty() => conditions,
+            // a plain value is shorthand for $eq
+            (_, Some(field)) => return add(&mut self.equal, field),
+            _ => return,
+        };
+
+        for (key, value) in conditions {
+            match (key.as_str(), field) {
+                ("$and", _) => {
+                    for condition in value.as_array().into_iter().flatten() {
+                        self.collect(condition, field);
+                    }
+                }
+                ("$eq", Some(field)) => add(&mut self.equal, field),
+                ("$gt", Some(field))
+                | ("$gte", Some(field))
+                | ("$lt", Some(field))
+                | ("$lte", Some(field))
+                | ("$beginsWith", Some(field)) => add(&mut self.range, field),
+                (operator, _) if operator.starts_with('$') => {}
+                (name, Some(field)) => self.collect(value, Some(&format!("{}.{}", field, name))),
+                (name, None) => self.collect(value, Some(name)),
+            }
+        }
+    }
+}
+
+fn add(fields: &mut Vec<String>, field: &str) {
+    if !fields.iter().any(|existing| existing == field) {
+        fields.push(field.to_string());
+    }
+}
+
+/// Outcome of `Database::advise_index` and `ensure_advised_index`
+#[derive(PartialEq, Debug, Clone)]
+pub struct IndexAdvice {
+    /// The plan CouchDB chose for the query; `None` when CouchDB rejected the query because no
+    /// index can serve its sort
+    pub explain: Option<ExplainResult>,
+    /// Whether the query reads the whole database, or can not run at all, for lack of an index
+    pub needs_index: bool,
+    /// An index that serves the query, when it needs one
+    pub suggested: Option<IndexFields>,
+    /// Whether `ensure_advised_index` created the suggested index
+    pub created: bool,
 }
 
 /// Index abstraction
@@ -24,12 +161,69 @@ pub struct Index {
     pub name: String,
     #[serde(rename = "type")]
     pub index_type: String,
-    pub def: IndexFields
+    pub def: IndexFields,
 }
 
 /// Database index list abstraction
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct DatabaseIndexList {
     pub total_rows: u32,
-    pub indexes: Vec<Index>
+    pub indexes: Vec<Index>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::selector::Selector;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_equivalent_index_fields() {
+        let mut sort = HashMap::new();
+        sort.insert("thing".to_string(), SortDirection::Asc);
+
+        let simple = IndexFields::new(vec![SortSpec::Simple("thing".to_string())]);
+        let complex = IndexFields::new(vec![SortSpec::Complex(sort)]).partial_filter_selector(json!({}));
+        assert!(simple.is_equivalent(&complex));
+
+        let other_field = IndexFields::new(vec![SortSpec::Simple("other".to_string())]);
+        assert!(!simple.is_equivalent(&other_field));
+
+        let filtered = simple.clone().partial_filter_selector(json!({"thing": {"$eq": true}}));
+        assert!(!simple.is_equivalent(&filtered));
+        assert!(filtered.is_equivalent(&filtered.clone()));
+    }
+
+    #[test]
+    fn test_suggest_for() {
+        let selector = Selector::field("age")
+            .gte(21)
+            .and(Selector::field("type").eq("user"))
+            .and(Selector::field("address.city").eq("Paris"));
+        let query = FindQuery::from(selector).sort_by("name", SortDirection::Desc);
+        let suggested = IndexFields::suggest_for(&query).unwrap();
+        let fields = ["type", "address.city", "name", "age"];
+        assert_eq!(
+            suggested,
+            IndexFields::new(fields.iter().map(|field| SortSpec::Simple(field.to_string())).collect())
+        );
+        assert_eq!(suggested.suggested_name(), "advised_type_address.city_name_age");
+
+        // nested objects are paths, and a field compared both ways is an equality
+        let query = FindQuery::new(json!({
+            "address": {"zip": {"$gt": "1000"}},
+            "age": {"$gt": 1, "$eq": 2},
+            "$or": [{"a": 1}, {"b": 2}],
+            "tags": {"$elemMatch": {"$eq": "x"}}
+        }));
+        let fields = SelectorFields::of(&query.selector);
+        assert_eq!(fields.equal, vec![s!("age")]);
+        assert_eq!(fields.range, vec![s!("address.zip")]);
+
+        assert_eq!(
+            IndexFields::suggest_for(&FindQuery::new(json!({"$or": [{"a": 1}]}))),
+            None
+        );
+    }
 }
@@ -0,0 +1,191 @@
+use serde_json::Value;
+
+/// How arrays are merged by a deep merge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrayMerge {
+    /// The new array replaces the current one
+    Replace,
+    /// The new elements are appended to the current array
+    Append,
+    /// The new elements are appended, unless the current array already holds them
+    Union,
+}
+
+/// Describes how a document is merged into the stored document, for example by `Database::upsert_merge`.
+///
+/// ```
+/// use couch_rs::types::merge::{ArrayMerge, MergePolicy};
+/// use serde_json::json;
+///
+/// let mut current = json!({"name": "John", "address": {"city": "Paris", "zip": "75001"}, "tags": ["a"]});
+/// let policy = MergePolicy::deep().arrays(ArrayMerge::Union).null_removes(true);
+/// policy.merge(&mut current, json!({"address": {"city": "Lyon", "zip": null}, "tags": ["a", "b"]}));
+///
+/// assert_eq!(current, json!({"name": "John", "address": {"city": "Lyon"}, "tags": ["a", "b"]}));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergePolicy {
+    /// Merge nested objects recursively, instead of replacing top level fields
+    pub deep: bool,
+    /// How arrays are merged; only used by a deep merge
+    pub arrays: ArrayMerge,
+    /// A `null` value removes the field, instead of storing `null`
+    pub null_removes: bool,
+}
+
+impl Default for MergePolicy {
+    fn default() -> Self {
+        MergePolicy::shallow()
+    }
+}
+
+impl MergePolicy {
+    /// Replaces the top level fields of the stored document, keeping the fields not provided
+    pub fn shallow() -> Self {
+        MergePolicy {
+            deep: false,
+            arrays: ArrayMerge::Replace,
+            null_removes: false,
+        }
+    }
+
+    /// Merges nested objects recursively
+    pub fn deep() -> Self {
+        MergePolicy {
+            deep: true,
+            ..MergePolicy::shallow()
+        }
+    }
+
+    /// JSON Merge Patch (RFC 7396) semantics: a deep merge where arrays are replaced, and `null` removes the field
+    pub fn merge_patch() -> Self {
+        MergePolicy::deep().null_removes(true)
+    }
+
+    pub fn arrays(mut self, arrays: ArrayMerge) -> Self {
+        self.arrays = arrays;
+        self
+    }
+
+    pub fn null_removes(mut self, null_removes: bool) -> Self {
+        self.null_removes = null_removes;
+        self
+    }
+
+    /// Merges `update` into `target`. When either of them is not an object, `update` replaces `target`.
+    pub fn merge(&self, target: &mut Value, update: Value) {
+        let update = match update {
+            Value::Object(update) if target.is_object() => update,
+            update => {
+                *target = update;
+                return;
+            }
+        };
+
+        if let Some(target) = target.as_object_mut() {
+            for (key, value) in update {
+                if value.is_null() && self.null_removes {
+                    target.remove(&key);
+                } else if self.deep {
+                    match target.get_mut(&key) {
+                        Some(current) => self.merge_nested(current, value),
+                        None => {
+                            target.insert(key, self.without_nulls(value));
+                        }
+                    }
+                } else {
+                    target.insert(key, value);
+                }
+            }
+        }
+    }
+
+    fn merge_nested(&self, current: &mut Value, value: Value) {
+        match (current, value) {
+            (Value::Array(current), Value::Array(values)) => match self.arrays {
+                ArrayMerge::Replace => *current = values,
+                ArrayMerge::Append => current.extend(values),
+                ArrayMerge::Union => {
+                    for value in values {
+                        if !current.contains(&value) {
+                            current.push(value);
+                        }
+                    }
+                }
+            },
+            (current, value) if current.is_object() && value.is_object() => self.merge(current, value),
+            (current, value) => *current = self.without_nulls(value),
+        }
+    }
+
+    /// New objects should not hold the nulls that would have removed a field
+    fn without_nulls(&self, value: Value) -> Value {
+        match value {
+            Value::Object(map) if self.null_removes => Value::Object(
+                map.into_iter()
+                    .filter(|(_, v)| !v.is_null())
+                    .map(|(k, v)| (k, self.without_nulls(v)))
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_shallow_merge() {
+        let mut current = json!({"a": {"x": 1, "y": 2}, "b": 1});
+        MergePolicy::shallow().merge(&mut current, json!({"a": {"x": 3}, "c": null}));
+        assert_eq!(current, json!({"a": {"x": 3}, "b": 1, "c": null}));
+    }
+
+    #[test]
+    fn test_deep_merge() {
+        let mut current = json!({"a": {"x": 1, "y": 2}, "list": [1, 2]});
+        MergePolicy::deep().merge(&mut current, json!({"a": {"x": 3}, "list": [3]}));
+        assert_eq!(current, json!({"a": {"x": 3, "y": 2}, "list": [3]}));
+
+        let mut current = json!({"list": [1, 2]});
+        MergePolicy::deep()
+            .arrays(ArrayMerge::Append)
+            .merge(&mut current, json!({"list": [2, 3]}));
+        assert_eq!(current, json!({"list": [1, 2, 2, 3]}));
+
+        let mut current = json!({"list": [1, 2]});
+        MergePolicy::deep()
+            .arrays(ArrayMerge::Union)
+            .merge(&mut current, json!({"list": [2, 3]}));
+        assert_eq!(current, json!({"list": [1, 2, 3]}));
+    }
+
+    #[test]
+    fn test_null_removes() {
+        let mut current = json!({"a": {"x": 1, "y": 2}, "b": 1});
+        MergePolicy::deep().null_removes(true).merge(
+            &mut current,
+            json!({"a": {"y": null}, "b": null, "c": {"z": null, "w": 1}}),
+        );
+        assert_eq!(current, json!({"a": {"x": 1}, "c": {"w": 1}}));
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        // examples from RFC 7396, appendix A
+        let mut current = json!({"a": "b", "c": {"d": "e", "f": "g"}});
+        MergePolicy::merge_patch().merge(&mut current, json!({"a": "z", "c": {"f": null}}));
+        assert_eq!(current, json!({"a": "z", "c": {"d": "e"}}));
+
+        let mut current = json!({"a": [{"b": "c"}]});
+        MergePolicy::merge_patch().merge(&mut current, json!({"a": [1]}));
+        assert_eq!(current, json!({"a": [1]}));
+
+        let mut current = json!({"e": null});
+        MergePolicy::merge_patch().merge(&mut current, json!({"a": 1}));
+        assert_eq!(current, json!({"e": null, "a": 1}));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::error::{CouchError, CouchResult};
+use crate::types::document::DocumentCreatedResult;
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::Arc;
+
+/// Name of the field that holds the schema version of a document, unless configured otherwise
+pub const DEFAULT_SCHEMA_VERSION_FIELD: &str = "schema_version";
+
+type Migration = Arc<dyn Fn(&mut Value) -> CouchResult<()> + Send + Sync>;
+
+/// The ordered migrations of a document type. A document without a schema version is at version 0,
+/// and the migration added as the n-th one upgrades documents from version n - 1 to version n. The
+/// latest version is the number of migrations.
+///
+/// ```
+/// use couch_rs::types::migration::Migrations;
+/// use serde_json::json;
+///
+/// let migrations = Migrations::new()
+///     .migration(|doc| {
+///         doc["full_name"] = doc["name"].take();
+///         Ok(())
+///     })
+///     .migration(|doc| {
+///         doc["tags"] = json!([]);
+///         Ok(())
+///     });
+///
+/// let mut doc = json!({"_id": "john", "name": "John"});
+/// assert!(migrations.migrate(&mut doc).unwrap());
+/// assert_eq!(doc, json!({"_id": "john", "name": null, "full_name": "John", "tags": [], "schema_version": 2}));
+/// ```
+#[derive(Clone)]
+pub struct Migrations {
+    field: String,
+    steps: Vec<Migration>,
+}
+
+impl Default for Migrations {
+    fn default() -> Self {
+        Migrations {
+            field: DEFAULT_SCHEMA_VERSION_FIELD.to_string(),
+            steps: vec![],
+        }
+    }
+}
+
+impl Migrations {
+    pub fn new() -> Self {
+        Migrations::default()
+    }
+
+    /// Uses another field than `schema_version` to hold the schema version
+    pub fn field(mut self, field: &str) -> Self {
+        self.field = field.to_string();
+        self
+    }
+
+    /// Adds the migration to the next version
+    pub fn migration<F>(mut self, migration: F) -> Self
+    where
+        F: Fn(&mut Value) -> CouchResult<()> + Send + Sync + 'static,
+    {
+        self.steps.push(Arc::new(migration));
+        self
+    }
+
+    /// Name of the field that holds the schema version
+    pub fn version_field(&self) -> &str {
+        &self.field
+    }
+
+    /// The latest schema version, which is the number of migrations
+    pub fn latest(&self) -> u64 {
+        self.steps.len() as u64
+    }
+
+    /// The schema version of the document
+    pub fn version(&self, doc: &Value) -> u64 {
+        doc[&self.field].as_u64().unwrap_or_default()
+    }
+
+    /// Upgrades the document to the latest version, running the migrations it is missing in order.
+    /// Returns whether the document was changed. Documents at a version newer than the latest one are
+    /// left alone.
+    pub fn migrate(&self, doc: &mut Value) -> CouchResult<bool> {
+        let version = self.version(doc);
+        if version >= self.latest() {
+            return Ok(false);
+        }
+
+        for (step, migration) in self.steps.iter().enumerate().skip(version as usize) {
+            migration(doc).map_err(|err| {
+                let id = err.id().or_else(|| doc["_id"].as_str()).map(String::from);
+                let message = format!("migration to version {} failed: {}", step + 1, err.message());
+                CouchError::new_with_id(id, message, err.status())
+            })?;
+        }
+
+        if let Some(object) = doc.as_object_mut() {
+            object.insert(self.field.clone(), Value::from(self.latest()));
+        }
+        Ok(true)
+    }
+
+    /// Mango selector for the documents at a version older than the latest one
+    pub fn outdated_selector(&self) -> Value {
+        json!({
+            "$or": [
+                { &self.field: { "$exists": false } },
+                { &self.field: { "$lt": self.latest() } }
+            ]
+        })
+    }
+}
+
+impl fmt::Debug for Migrations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Migrations")
+            .field("field", &self.field)
+            .field("latest", &self.latest())
+            .finish()
+    }
+}
+
+/// Progress of `Repository::run_migrations`
+#[derive(Debug, Clone, Default)]
+pub struct MigrationProgress {
+    /// Number of outdated documents that were read
+    pub scanned: u64,
+    /// Number of documents that were upgraded and saved
+    pub migrated: u64,
+    /// Documents that could not be migrated or saved, for example because of a conflict
+    pub failures: Vec<CouchError>,
+}
+
+impl MigrationProgress {
+    pub(crate) fn record(&mut self, result: DocumentCreatedResult) {
+        match result {
+            Ok(_) => self.migrated += 1,
+            Err(err) => self.failures.push(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrations() -> Migrations {
+        Migrations::new()
+            .field("v")
+            .migration(|doc| {
+                doc["a"] = json!(1);
+                Ok(())
+            })
+            .migration(|doc| match doc["a"].as_u64() {
+                Some(a) => {
+                    doc["b"] = json!(a + 1);
+                    Ok(())
+                }
+                None => Err(CouchError::new(s!("a is missing"), reqwest::StatusCode::BAD_REQUEST)),
+            })
+    }
+
+    #[test]
+    fn test_migrate() {
+        let migrations = migrations();
+        assert_eq!(migrations.latest(), 2);
+
+        let mut doc = json!({"_id": "x"});
+        assert!(migrations.migrate(&mut doc).unwrap());
+        assert_eq!(doc, json!({"_id": "x", "a": 1, "b": 2, "v": 2}));
+        assert!(!migrations.migrate(&mut doc).unwrap());
+
+        let mut doc = json!({"_id": "y", "v": 1});
+        let err = migrations.migrate(&mut doc).unwrap_err();
+        assert_eq!(err.id(), Some("y"));
+        assert_eq!(err.message(), "migration to version 2 failed: a is missing");
+
+        let mut newer = json!({"v": 3});
+        assert!(!migrations.migrate(&mut newer).unwrap());
+        assert_eq!(
+            migrations.outdated_selector(),
+            json!({"$or": [{"v": {"$exists": false}}, {"v": {"$lt": 2}}]})
+        );
+    }
+}
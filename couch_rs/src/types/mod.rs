@@ -1,7 +1,17 @@
-pub mod view;
+pub mod changes;
 pub mod design;
-pub mod system;
 pub mod document;
+pub mod expiry;
+pub mod export;
 pub mod find;
 pub mod index;
+pub mod merge;
+pub mod migration;
+pub mod nouveau;
+pub mod patch;
 pub mod query;
+pub mod search;
+pub mod selector;
+pub mod system;
+pub mod user;
+pub mod view;
@@ -0,0 +1,244 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// A Nouveau index of a design document, as defined in its `nouveau` field. Nouveau is the Lucene
+/// based full-text search of CouchDB 3.4 and later.
+/// See [nouveau](https://docs.couchdb.org/en/stable/ddocs/nouveau.html).
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct NouveauIndex {
+    /// The analyzer of all fields, `standard` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_analyzer: Option<String>,
+    /// Analyzers of specific fields
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub field_analyzers: HashMap<String, String>,
+    /// JavaScript function that calls `index(type, field, value)` for the fields of a document
+    pub index: String,
+}
+
+impl NouveauIndex {
+    pub fn new(index: &str) -> Self {
+        NouveauIndex {
+            default_analyzer: None,
+            field_analyzers: HashMap::new(),
+            index: index.to_string(),
+        }
+    }
+
+    pub fn default_analyzer(mut self, analyzer: &str) -> Self {
+        self.default_analyzer = Some(analyzer.to_string());
+        self
+    }
+
+    pub fn field_analyzer(mut self, field: &str, analyzer: &str) -> Self {
+        self.field_analyzers.insert(field.to_string(), analyzer.to_string());
+        self
+    }
+}
+
+/// A query on a Nouveau index, written in the Lucene query syntax. Run it with
+/// `Database::nouveau_search`.
+///
+/// ```
+/// use couch_rs::types::nouveau::{NouveauQuery, NouveauRange};
+///
+/// let query = NouveauQuery::new("name:john AND age:[21 TO *]")
+///     .limit(10)
+///     .include_docs(true)
+///     .counts(vec!["city".to_string()])
+///     .range("age", NouveauRange::new("young").max(30.0, false));
+/// ```
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct NouveauQuery {
+    /// The Lucene query, such as `name:john`
+    pub q: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bookmark: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+
+    /// Fields to sort on, such as `-age<double>` for a descending numeric sort
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub sort: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_docs: Option<bool>,
+
+    /// String fields to count the values of, over all results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub counts: Option<Vec<String>>,
+
+    /// Named ranges of numeric fields to count the results in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ranges: Option<HashMap<String, Vec<NouveauRange>>>,
+
+    /// Whether to bring the index up to date before running the query; `true` by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub update: Option<bool>,
+}
+
+impl NouveauQuery {
+    pub fn new(q: &str) -> Self {
+        NouveauQuery {
+            q: q.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Continues after the results of an earlier query, using the bookmark it returned
+    pub fn bookmark(mut self, bookmark: &str) -> Self {
+        self.bookmark = Some(bookmark.to_string());
+        self
+    }
+
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn sort(mut self, sort: Vec<String>) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    pub fn include_docs(mut self, include_docs: bool) -> Self {
+        self.include_docs = Some(include_docs);
+        self
+    }
+
+    pub fn counts(mut self, fields: Vec<String>) -> Self {
+        self.counts = Some(fields);
+        self
+    }
+
+    /// Adds a named range of a numeric field to count the results in
+    pub fn range(mut self, field: &str, range: NouveauRange) -> Self {
+        self.ranges
+            .get_or_insert_with(HashMap::new)
+            .entry(field.to_string())
+            .or_default()
+            .push(range);
+        self
+    }
+
+    pub fn update(mut self, update: bool) -> Self {
+        self.update = Some(update);
+        self
+    }
+}
+
+/// A named range of a numeric field; a range without bounds holds all values
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct NouveauRange {
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_inclusive: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_inclusive: Option<bool>,
+}
+
+impl NouveauRange {
+    pub fn new(label: &str) -> Self {
+        NouveauRange {
+            label: label.to_string(),
+            min: None,
+            min_inclusive: None,
+            max: None,
+            max_inclusive: None,
+        }
+    }
+
+    pub fn min(mut self, min: f64, inclusive: bool) -> Self {
+        self.min = Some(min);
+        self.min_inclusive = Some(inclusive);
+        self
+    }
+
+    pub fn max(mut self, max: f64, inclusive: bool) -> Self {
+        self.max = Some(max);
+        self.max_inclusive = Some(inclusive);
+        self
+    }
+}
+
+/// Results of a Nouveau query
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+pub struct NouveauResult<T> {
+    pub total_hits: u64,
+    /// `EQUAL_TO` when `total_hits` is exact, `GREATER_THAN_OR_EQUAL_TO` when it is a lower bound
+    pub total_hits_relation: String,
+    /// Bookmark to request the next page of results
+    pub bookmark: Option<String>,
+    pub hits: Vec<NouveauHit<T>>,
+    /// Number of results per value of the fields in `counts`
+    pub counts: Option<HashMap<String, HashMap<String, u64>>>,
+    /// Number of results per named range of the fields in `ranges`
+    pub ranges: Option<HashMap<String, HashMap<String, u64>>>,
+}
+
+/// A document found by a Nouveau query
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+pub struct NouveauHit<T> {
+    pub id: String,
+    /// The sort order of the hit, such as its score
+    #[serde(default)]
+    pub order: Vec<Value>,
+    /// The stored fields of the document
+    #[serde(default)]
+    pub fields: Map<String, Value>,
+    /// The document, when the query was run with `include_docs(true)`
+    pub doc: Option<T>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_nouveau_query() {
+        let query = NouveauQuery::new("*:*")
+            .limit(5)
+            .range("price", NouveauRange::new("cheap").min(0.0, true).max(10.0, false))
+            .update(false);
+        assert_eq!(
+            json!(query),
+            json!({
+                "q": "*:*",
+                "limit": 5,
+                "ranges": {"price": [{"label": "cheap", "min": 0.0, "min_inclusive": true, "max": 10.0, "max_inclusive": false}]},
+                "update": false
+            })
+        );
+    }
+
+    #[test]
+    fn test_nouveau_result() {
+        let result: NouveauResult<Value> = serde_json::from_value(json!({
+            "total_hits_relation": "EQUAL_TO",
+            "total_hits": 1,
+            "ranges": null,
+            "counts": {"city": {"Paris": 1}},
+            "bookmark": "W3siQHR5cGUi",
+            "hits": [{
+                "order": [{"@type": "float", "value": 1.0}],
+                "id": "john",
+                "fields": {"name": "john"}
+            }]
+        }))
+        .unwrap();
+        assert_eq!(result.hits[0].id, "john");
+        assert_eq!(result.hits[0].doc, None);
+        assert_eq!(result.counts.unwrap()["city"]["Paris"], 1);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::error::{CouchError, CouchResult};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A JSON Patch (RFC 6902) operation. Paths are JSON Pointers (RFC 6901), such as `/address/city`.
+/// Serializes as, and deserializes from, the RFC format: `{"op": "add", "path": "/a", "value": 1}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    /// Adds a member to an object, or inserts an element into an array (`-` appends)
+    Add { path: String, value: Value },
+    /// Removes an existing value
+    Remove { path: String },
+    /// Replaces an existing value
+    Replace { path: String, value: Value },
+    /// Moves the value at `from` to `path`
+    Move { from: String, path: String },
+    /// Fails the patch, unless the value at `path` equals `value`
+    Test { path: String, value: Value },
+}
+
+impl PatchOp {
+    pub fn add(path: &str, value: Value) -> Self {
+        PatchOp::Add {
+            path: path.to_string(),
+            value,
+        }
+    }
+
+    pub fn remove(path: &str) -> Self {
+        PatchOp::Remove { path: path.to_string() }
+    }
+
+    pub fn replace(path: &str, value: Value) -> Self {
+        PatchOp::Replace {
+            path: path.to_string(),
+            value,
+        }
+    }
+
+    pub fn move_to(from: &str, path: &str) -> Self {
+        PatchOp::Move {
+            from: from.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    pub fn test(path: &str, value: Value) -> Self {
+        PatchOp::Test {
+            path: path.to_string(),
+            value,
+        }
+    }
+}
+
+/// Applies the operations to the document. The patch is atomic: when an operation fails, the
+/// document is left untouched. Invalid paths are `BAD_REQUEST` errors; a failing `test` operation is
+/// a `PRECONDITION_FAILED` error.
+///
+/// ```
+/// use couch_rs::types::patch::{apply_patch, PatchOp};
+/// use serde_json::json;
+///
+/// let mut doc = json!({"name": "John", "tags": ["a"]});
+/// apply_patch(&mut doc, &[
+///     PatchOp::test("/name", json!("John")),
+///     PatchOp::add("/tags/-", json!("b")),
+///     PatchOp::move_to("/name", "/first_name"),
+/// ]).unwrap();
+///
+/// assert_eq!(doc, json!({"first_name": "John", "tags": ["a", "b"]}));
+/// ```
+pub fn apply_patch(doc: &mut Value, ops: &[PatchOp]) -> CouchResult<()> {
+    let mut patched = doc.clone();
+    for op in ops {
+        match op {
+            PatchOp::Add { path, value } => add(&mut patched, path, value.clone())?,
+            PatchOp::Remove { path } => {
+                remove(&mut patched, path)?;
+            }
+            PatchOp::Replace { path, value } => match patched.pointer_mut(path) {
+                Some(current) => *current = value.clone(),
+                None => return Err(invalid_path(path)),
+            },
+            PatchOp::Move { from, path } => {
+                if path.starts_with(&format!("{}/", from)) {
+                    return Err(invalid_path(path));
+                }
+                let value = remove(&mut patched, from)?;
+                add(&mut patched, path, value)?;
+            }
+            PatchOp::Test { path, value } => {
+                if patched.pointer(path) != Some(value) {
+                    return Err(CouchError::new(
+                        format!("test failed for path {}", path),
+                        StatusCode::PRECONDITION_FAILED,
+                    ));
+                }
+            }
+        }
+    }
+
+    *doc = patched;
+    Ok(())
+}
+
+fn add(doc: &mut Value, path: &str, value: Value) -> CouchResult<()> {
+    if path.is_empty() {
+        *doc = value;
+        return Ok(());
+    }
+
+    let (parent, key) = split_path(path)?;
+    match doc.pointer_mut(parent) {
+        Some(Value::Object(object)) => {
+            object.insert(key, value);
+        }
+        Some(Value::Array(array)) if key == "-" => array.push(value),
+        Some(Value::Array(array)) => match key.parse::<usize>() {
+            Ok(idx) if idx <= array.len() => array.insert(idx, value),
+            _ => return Err(invalid_path(path)),
+        },
+        _ => return Err(invalid_path(path)),
+    }
+    Ok(())
+}
+
+fn remove(doc: &mut Value, path: &str) -> CouchResult<Value> {
+    let (parent, key) = split_path(path)?;
+    let removed = match doc.pointer_mut(parent) {
+        Some(Value::Object(object)) => object.remove(&key),
+        Some(Value::Array(array)) => match key.parse::<usize>() {
+            Ok(idx) if idx < array.len() => Some(array.remove(idx)),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    removed.ok_or_else(|| invalid_path(path))
+}
+
+/// Splits a pointer into the pointer to its parent and the unescaped last reference token
+fn split_path(path: &str) -> CouchResult<(&str, String)> {
+    match path.rfind('/') {
+        Some(idx) if path.starts_with('/') => {
+            let key = path[idx + 1..].replace("~1", "/").replace("~0", "~");
+            Ok((&path[..idx], key))
+        }
+        _ => Err(invalid_path(path)),
+    }
+}
+
+fn invalid_path(path: &str) -> CouchError {
+    CouchError::new(format!("invalid patch path {}", path), StatusCode::BAD_REQUEST)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_patch_ops() {
+        let mut doc = json!({"a": {"b": 1}, "list": [1, 2], "x/y": true});
+        apply_patch(
+            &mut doc,
+            &[
+                PatchOp::add("/a/c", json!(2)),
+                PatchOp::add("/list/0", json!(0)),
+                PatchOp::replace("/a/b", json!(3)),
+                PatchOp::remove("/x~1y"),
+                PatchOp::move_to("/list", "/a/list"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(doc, json!({"a": {"b": 3, "c": 2, "list": [0, 1, 2]}}));
+    }
+
+    #[test]
+    fn test_patch_is_atomic() {
+        let mut doc = json!({"a": 1});
+        let err = apply_patch(&mut doc, &[PatchOp::add("/b", json!(2)), PatchOp::remove("/missing")]).unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(doc, json!({"a": 1}));
+
+        let err = apply_patch(&mut doc, &[PatchOp::test("/a", json!(2))]).unwrap_err();
+        assert_eq!(err.status(), StatusCode::PRECONDITION_FAILED);
+
+        assert!(apply_patch(&mut doc, &[PatchOp::move_to("/a", "/a/b")]).is_err());
+    }
+
+    #[test]
+    fn test_patch_op_serde() {
+        let ops: Vec<PatchOp> = serde_json::from_value(json!([
+            {"op": "add", "path": "/a", "value": 1},
+            {"op": "move", "from": "/a", "path": "/b"}
+        ]))
+        .unwrap();
+        assert_eq!(ops, vec![PatchOp::add("/a", json!(1)), PatchOp::move_to("/a", "/b")]);
+    }
+}
@@ -1,11 +1,13 @@
-use crate::document::TypedCouchDocument;
+use crate::error::{CouchError, CouchResult};
 use crate::types::view::ViewCollection;
+use reqwest::StatusCode;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct QueriesParams {
-    queries: Vec<QueryParams>,
+    pub(crate) queries: Vec<QueryParams>,
 }
 
 impl QueriesParams {
@@ -15,8 +17,8 @@ impl QueriesParams {
 }
 
 #[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
-#[serde(bound(deserialize = "T: TypedCouchDocument"))]
-pub struct QueriesCollection<K: DeserializeOwned, V: DeserializeOwned, T: TypedCouchDocument> {
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+pub struct QueriesCollection<K: DeserializeOwned, V: DeserializeOwned, T: DeserializeOwned> {
     pub results: Vec<ViewCollection<K, V, T>>,
 }
 
@@ -31,13 +33,39 @@ pub enum UpdateView {
     Lazy,
 }
 
+/// How up to date the index must be when reading from a view or Mango index. Latency-sensitive
+/// reads can opt out of waiting for the index to be refreshed.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Freshness {
+    /// The index is updated before responding; the default
+    Fresh,
+    /// The index is read as it is, from stable shards, without updating it.
+    /// Equivalent to the legacy `stale=ok`.
+    Stale,
+    /// Like `Stale`, but the index is updated after responding; Mango queries do not support this,
+    /// and behave like `Stale`. Equivalent to the legacy `stale=update_after`.
+    UpdateAfter,
+}
+
+impl Freshness {
+    /// The value of the legacy `stale` parameter, for CouchDB 1.x servers
+    pub fn legacy_stale(&self) -> Option<&'static str> {
+        match self {
+            Freshness::Fresh => None,
+            Freshness::Stale => Some("ok"),
+            Freshness::UpdateAfter => Some("update_after"),
+        }
+    }
+}
+
 /// Query parameters. You can use the builder paradigm to construct these parameters easily:
 /// [views.html](https://docs.couchdb.org/en/stable/api/ddoc/views.html)
 /// ```
 /// use couch_rs::types::query::QueryParams;
 /// let _qp = QueryParams::default().group(true).conflicts(false).start_key("1");
+/// let _validated = QueryParams::builder().descending(true).limit(50).build().unwrap();
 /// ```
-#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct QueryParams {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub conflicts: Option<bool>,
@@ -46,7 +74,7 @@ pub struct QueryParams {
     pub descending: Option<bool>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub end_key: Option<String>,
+    pub end_key: Option<Value>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub end_key_doc_id: Option<String>,
@@ -70,10 +98,10 @@ pub struct QueryParams {
     pub inclusive_end: Option<bool>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub key: Option<String>,
+    pub key: Option<Value>,
 
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    pub keys: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keys: Vec<Value>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub limit: Option<u64>,
@@ -94,7 +122,7 @@ pub struct QueryParams {
     pub stale: Option<String>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub start_key: Option<String>,
+    pub start_key: Option<Value>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub start_key_doc_id: Option<String>,
@@ -104,10 +132,17 @@ pub struct QueryParams {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub update_seq: Option<bool>,
+
+    /// Keep design documents in the documents returned from `_all_docs`; they are left out by
+    /// default. Not sent to CouchDB.
+    #[serde(skip)]
+    pub include_design_docs: bool,
 }
 
-impl Default for QueryParams {
-    fn default() -> Self {
+impl QueryParams {
+    /// Creates parameters that only return the rows with the given keys. Keys can be any JSON
+    /// value, such as strings, numbers or arrays.
+    pub fn from_keys<K: Into<Value>>(keys: Vec<K>) -> Self {
         QueryParams {
             conflicts: None,
             descending: None,
@@ -120,7 +155,7 @@ impl Default for QueryParams {
             att_encoding_info: None,
             inclusive_end: None,
             key: None,
-            keys: vec![],
+            keys: keys.into_iter().map(Into::into).collect(),
             limit: None,
             reduce: None,
             skip: None,
@@ -131,35 +166,46 @@ impl Default for QueryParams {
             start_key_doc_id: None,
             update: None,
             update_seq: None,
+            include_design_docs: false,
         }
     }
-}
 
-impl QueryParams {
-    pub fn from_keys(keys: Vec<String>) -> Self {
-        QueryParams {
-            conflicts: None,
-            descending: None,
-            end_key: None,
-            end_key_doc_id: None,
-            group: None,
-            group_level: None,
-            include_docs: None,
-            attachments: None,
-            att_encoding_info: None,
-            inclusive_end: None,
-            key: None,
-            keys,
-            limit: None,
-            reduce: None,
-            skip: None,
-            sorted: None,
-            stable: None,
-            stale: None,
-            start_key: None,
-            start_key_doc_id: None,
-            update: None,
-            update_seq: None,
+    /// Starts building parameters, which are validated by `build`.
+    /// ```
+    /// use couch_rs::types::query::QueryParams;
+    ///
+    /// let params = QueryParams::builder().descending(true).limit(50).include_docs(true).build().unwrap();
+    /// assert_eq!(params.limit, Some(50));
+    ///
+    /// assert!(QueryParams::builder().group(true).include_docs(true).build().is_err());
+    /// assert!(QueryParams::builder().key("a").start_key("a").build().is_err());
+    /// ```
+    pub fn builder() -> Self {
+        QueryParams::default()
+    }
+
+    /// Finishes building parameters, returning a `BAD_REQUEST` error for mutually exclusive options.
+    /// Besides the checks of `validate`, which `query` runs on every request, this rejects options
+    /// that CouchDB ignores rather than rejects: `key` with a range, a document id bound without its
+    /// key, and `stale` with `stable` or `update`.
+    pub fn build(self) -> CouchResult<Self> {
+        self.validate()?;
+
+        let problem = if self.key.is_some() && (self.start_key.is_some() || self.end_key.is_some()) {
+            Some("key can not be combined with start_key or end_key")
+        } else if self.start_key_doc_id.is_some() && self.start_key.is_none() {
+            Some("start_key_doc_id requires start_key")
+        } else if self.end_key_doc_id.is_some() && self.end_key.is_none() {
+            Some("end_key_doc_id requires end_key")
+        } else if self.stale.is_some() && (self.stable.is_some() || self.update.is_some()) {
+            Some("stale can not be combined with stable or update")
+        } else {
+            None
+        };
+
+        match problem {
+            Some(problem) => Err(invalid_params(problem)),
+            None => Ok(self),
         }
     }
 
@@ -173,16 +219,20 @@ impl QueryParams {
         self
     }
 
-    pub fn end_key(mut self, end_key: &str) -> Self {
-        self.end_key = Some(end_key.to_string());
+    /// Stops returning rows at this key; any JSON value, such as `"b"`, `42` or `json!([2021, 12])`
+    pub fn end_key<K: Into<Value>>(mut self, end_key: K) -> Self {
+        self.end_key = Some(end_key.into());
         self
     }
 
+    /// Groups the reduce results by their exact key. Only valid for views with a reduce function.
     pub fn group(mut self, group: bool) -> Self {
         self.group = Some(group);
         self
     }
 
+    /// Groups the reduce results by the first `group_level` elements of their array keys, for
+    /// example per year with `group_level(1)` on `[year, month, day]` keys. Implies `group`.
     pub fn group_level(mut self, group_level: u32) -> Self {
         self.group_level = Some(group_level);
         self
@@ -208,13 +258,15 @@ impl QueryParams {
         self
     }
 
-    pub fn key(mut self, key: &str) -> Self {
-        self.key = Some(key.to_string());
+    /// Only returns the rows with this key; any JSON value
+    pub fn key<K: Into<Value>>(mut self, key: K) -> Self {
+        self.key = Some(key.into());
         self
     }
 
-    pub fn keys(mut self, keys: Vec<String>) -> Self {
-        self.keys = keys;
+    /// Only returns the rows with these keys; any JSON values
+    pub fn keys<K: Into<Value>>(mut self, keys: Vec<K>) -> Self {
+        self.keys = keys.into_iter().map(Into::into).collect();
         self
     }
 
@@ -223,6 +275,7 @@ impl QueryParams {
         self
     }
 
+    /// Whether to run the reduce function of the view; `false` returns the rows emitted by the map function
     pub fn reduce(mut self, reduce: bool) -> Self {
         self.reduce = Some(reduce);
         self
@@ -243,8 +296,37 @@ impl QueryParams {
         self
     }
 
-    pub fn start_key(mut self, start_key: &str) -> Self {
-        self.start_key = Some(start_key.to_string());
+    /// Starts returning rows at this key; any JSON value, such as `"a"`, `42` or `json!([2021, 1])`
+    pub fn start_key<K: Into<Value>>(mut self, start_key: K) -> Self {
+        self.start_key = Some(start_key.into());
+        self
+    }
+
+    /// Legacy form of `stable` and `update`, for CouchDB 1.x servers: `ok` or `update_after`
+    pub fn stale(mut self, stale: &str) -> Self {
+        self.stale = Some(stale.to_string());
+        self
+    }
+
+    /// Sets `stable` and `update` for the given freshness, replacing a `legacy_freshness`
+    pub fn freshness(mut self, freshness: Freshness) -> Self {
+        let (stable, update) = match freshness {
+            Freshness::Fresh => (false, UpdateView::True),
+            Freshness::Stale => (true, UpdateView::False),
+            Freshness::UpdateAfter => (true, UpdateView::Lazy),
+        };
+        self.stable = Some(stable);
+        self.update = Some(update);
+        self.stale = None;
+        self
+    }
+
+    /// Sets the legacy `stale` parameter for the given freshness, for CouchDB 1.x servers, which
+    /// do not support `stable` and `update`, replacing a `freshness`
+    pub fn legacy_freshness(mut self, freshness: Freshness) -> Self {
+        self.stale = freshness.legacy_stale().map(String::from);
+        self.stable = None;
+        self.update = None;
         self
     }
 
@@ -258,15 +340,112 @@ impl QueryParams {
         self
     }
 
+    /// Returns the sequence of the database the rows were read at, as the `update_seq` of the result
     pub fn update_seq(mut self, update_seq: bool) -> Self {
         self.update_seq = Some(update_seq);
         self
     }
+
+    /// Keep design documents, and any other document whose `_id` starts with `_`, in the documents
+    /// returned by `get_all_params`, `get_all_stream` and `get_bulk_params`
+    pub fn include_design_docs(mut self, include_design_docs: bool) -> Self {
+        self.include_design_docs = include_design_docs;
+        self
+    }
+
+    /// Only returns the rows whose array key starts with `prefix`, for example all days of a
+    /// month with `key_prefix(vec![2021, 3])` on `[year, month, day]` keys. Honours `descending`,
+    /// so call it after setting the direction.
+    pub fn key_prefix<K: Into<Value>>(mut self, prefix: Vec<K>) -> Self {
+        let prefix = prefix.into_iter().fold(ComplexKey::new(), ComplexKey::then);
+        let (low, high) = (Value::from(prefix.clone()), prefix.high());
+
+        if self.descending == Some(true) {
+            self.start_key = Some(high);
+            self.end_key = Some(low);
+        } else {
+            self.start_key = Some(low);
+            self.end_key = Some(high);
+        }
+        self
+    }
+
+    /// Checks for combinations of parameters that CouchDB rejects, so they fail before a request
+    /// is sent, with a `BAD_REQUEST` error explaining the problem
+    pub fn validate(&self) -> CouchResult<()> {
+        let grouped = self.group == Some(true) || self.group_level.is_some();
+        let problem = if grouped && self.reduce == Some(false) {
+            Some("group and group_level require reduce")
+        } else if self.group == Some(false) && self.group_level.is_some() {
+            Some("group_level can not be combined with group=false")
+        } else if grouped && self.include_docs == Some(true) {
+            Some("include_docs can not be combined with grouping")
+        } else if !self.keys.is_empty() && (self.key.is_some() || self.start_key.is_some() || self.end_key.is_some()) {
+            Some("keys can not be combined with key, start_key or end_key")
+        } else {
+            None
+        };
+
+        match problem {
+            Some(problem) => Err(invalid_params(problem)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn invalid_params(problem: &str) -> CouchError {
+    CouchError::new(
+        format!("invalid query parameters: {}", problem),
+        StatusCode::BAD_REQUEST,
+    )
+}
+
+/// Builds a composite (array) view key, such as `[year, month, id]`. CouchDB sorts arrays element
+/// by element, and shorter arrays before longer arrays with the same prefix; `high` appends `{}`,
+/// which sorts after all other values, to close a range.
+///
+/// ```
+/// use couch_rs::types::query::{ComplexKey, QueryParams};
+/// use serde_json::json;
+///
+/// // all orders of March 2021, from the first of the month
+/// let params = QueryParams::default()
+///     .start_key(ComplexKey::new().then(2021).then(3).then(1))
+///     .end_key(ComplexKey::new().then(2021).then(3).high());
+///
+/// assert_eq!(params.start_key, Some(json!([2021, 3, 1])));
+/// assert_eq!(params.end_key, Some(json!([2021, 3, {}])));
+/// ```
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct ComplexKey(Vec<Value>);
+
+impl ComplexKey {
+    pub fn new() -> Self {
+        ComplexKey::default()
+    }
+
+    /// Appends the next element of the key
+    pub fn then<V: Into<Value>>(mut self, part: V) -> Self {
+        self.0.push(part.into());
+        self
+    }
+
+    /// The key followed by `{}`: sorts after all keys that start with this key
+    pub fn high(self) -> Value {
+        self.then(Value::Object(Default::default())).into()
+    }
+}
+
+impl From<ComplexKey> for Value {
+    fn from(key: ComplexKey) -> Self {
+        Value::Array(key.0)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
 
     #[test]
     fn test_query_params_builder_paradigm() {
@@ -276,8 +455,96 @@ mod tests {
             .start_key("1")
             .update(UpdateView::Lazy);
         assert_eq!(qp.group, Some(true));
-        assert_eq!(qp.start_key, Some("1".to_string()));
+        assert_eq!(qp.start_key, Some(Value::from("1")));
         let str_val = serde_json::to_string(&qp).expect("can not convert to string");
         assert!(str_val.contains(r#""update":"lazy""#))
     }
+
+    #[test]
+    fn test_query_params_validation() {
+        assert!(QueryParams::default().group_level(2).validate().is_ok());
+        assert!(QueryParams::default().group(true).reduce(true).validate().is_ok());
+        assert!(QueryParams::default()
+            .reduce(false)
+            .include_docs(true)
+            .validate()
+            .is_ok());
+
+        let invalid = vec![
+            QueryParams::default().group(true).reduce(false),
+            QueryParams::default().group(false).group_level(1),
+            QueryParams::default().group_level(1).include_docs(true),
+            QueryParams::from_keys(vec!["a".to_string()]).start_key("a"),
+        ];
+        for params in invalid {
+            assert_eq!(params.validate().unwrap_err().status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[test]
+    fn test_query_params_build() {
+        let params = QueryParams::builder()
+            .start_key("a")
+            .start_key_doc_id("doc")
+            .limit(10)
+            .build()
+            .unwrap();
+        assert_eq!(
+            params,
+            QueryParams::default().start_key("a").start_key_doc_id("doc").limit(10)
+        );
+
+        // accepted by validate, so queries that were sent before keep working
+        let strict = vec![
+            QueryParams::default().key("a").end_key("b"),
+            QueryParams::default().start_key_doc_id("a"),
+            QueryParams::default().stale("ok").stable(true),
+        ];
+        for params in strict {
+            assert!(params.validate().is_ok());
+            assert_eq!(params.build().unwrap_err().status(), StatusCode::BAD_REQUEST);
+        }
+
+        let params = QueryParams::builder()
+            .freshness(Freshness::Stale)
+            .legacy_freshness(Freshness::UpdateAfter)
+            .build()
+            .unwrap();
+        assert_eq!((params.stable, params.stale.as_deref()), (None, Some("update_after")));
+    }
+
+    #[test]
+    fn test_query_params_json_keys() {
+        let qp = QueryParams::default()
+            .start_key(json!([2020, 1]))
+            .end_key(json!([2020, {}]));
+        let str_val = serde_json::to_string(&qp).unwrap();
+        assert_eq!(str_val, r#"{"end_key":[2020,{}],"start_key":[2020,1]}"#);
+
+        let qp = QueryParams::from_keys(vec![1, 2]).key(3);
+        assert_eq!(qp.keys, vec![json!(1), json!(2)]);
+        assert_eq!(qp.key, Some(json!(3)));
+    }
+
+    #[test]
+    fn test_key_prefix() {
+        let qp = QueryParams::default().key_prefix(vec![2021, 3]);
+        assert_eq!(qp.start_key, Some(json!([2021, 3])));
+        assert_eq!(qp.end_key, Some(json!([2021, 3, {}])));
+
+        let qp = QueryParams::default().descending(true).key_prefix(vec!["user"]);
+        assert_eq!(qp.start_key, Some(json!(["user", {}])));
+        assert_eq!(qp.end_key, Some(json!(["user"])));
+    }
+
+    #[test]
+    fn test_freshness() {
+        let qp = QueryParams::default().freshness(Freshness::UpdateAfter);
+        assert_eq!(qp.stable, Some(true));
+        assert_eq!(qp.update, Some(UpdateView::Lazy));
+
+        let qp = QueryParams::default().legacy_freshness(Freshness::Stale);
+        assert_eq!(serde_json::to_string(&qp).unwrap(), r#"{"stale":"ok"}"#);
+        assert_eq!(QueryParams::default().legacy_freshness(Freshness::Fresh).stale, None);
+    }
 }
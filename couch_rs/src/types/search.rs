@@ -0,0 +1,252 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// A search index of a design document, as defined in its `indexes` field. Search indexes are
+/// Lucene indexes, available on Cloudant and on CouchDB with Clouseau.
+/// See [search](https://docs.couchdb.org/en/stable/ddocs/search.html).
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SearchIndex {
+    /// The analyzer, such as `"standard"`, or an object for per field analyzers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub analyzer: Option<Value>,
+    /// JavaScript function that calls `index(field, value)` for the fields of a document
+    pub index: String,
+}
+
+impl SearchIndex {
+    pub fn new(index: &str) -> Self {
+        SearchIndex {
+            analyzer: None,
+            index: index.to_string(),
+        }
+    }
+
+    pub fn analyzer(mut self, analyzer: Value) -> Self {
+        self.analyzer = Some(analyzer);
+        self
+    }
+}
+
+/// A query on a search index, written in the Lucene query syntax. Run it with `Database::search`.
+///
+/// ```
+/// use couch_rs::types::search::SearchQuery;
+/// use serde_json::json;
+///
+/// let query = SearchQuery::new("name:john AND age:[21 TO Infinity]")
+///     .limit(10)
+///     .include_docs(true)
+///     .counts(vec!["city".to_string()])
+///     .range("age", "young", "[0 TO 30}")
+///     .highlight_fields(vec!["bio".to_string()]);
+///
+/// assert_eq!(json!(query)["ranges"], json!({"age": {"young": "[0 TO 30}"}}));
+/// ```
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct SearchQuery {
+    /// The Lucene query, such as `name:john`
+    pub query: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bookmark: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+
+    /// Fields to sort on, such as `-age<number>` for a descending numeric sort
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub sort: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_docs: Option<bool>,
+
+    /// Stored fields to return; all stored fields are returned by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_fields: Option<Vec<String>>,
+
+    /// Fields to count the values of, over all results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub counts: Option<Vec<String>>,
+
+    /// Named ranges per field to count the results in, such as `{"age": {"young": "[0 TO 30}"}}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ranges: Option<HashMap<String, HashMap<String, String>>>,
+
+    /// Restricts the results to the given values of faceted fields, as `[field, value]` pairs
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub drilldown: Vec<(String, String)>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight_fields: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight_pre_tag: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight_post_tag: Option<String>,
+
+    /// Number of fragments returned per highlighted field
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight_number: Option<u32>,
+
+    /// Number of characters per highlighted fragment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight_size: Option<u32>,
+}
+
+impl SearchQuery {
+    pub fn new(query: &str) -> Self {
+        SearchQuery {
+            query: query.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Continues after the results of an earlier query, using the bookmark it returned
+    pub fn bookmark(mut self, bookmark: &str) -> Self {
+        self.bookmark = Some(bookmark.to_string());
+        self
+    }
+
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn sort(mut self, sort: Vec<String>) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    pub fn include_docs(mut self, include_docs: bool) -> Self {
+        self.include_docs = Some(include_docs);
+        self
+    }
+
+    pub fn include_fields(mut self, fields: Vec<String>) -> Self {
+        self.include_fields = Some(fields);
+        self
+    }
+
+    pub fn counts(mut self, fields: Vec<String>) -> Self {
+        self.counts = Some(fields);
+        self
+    }
+
+    /// Adds a named range of a field to count the results in, such as `[0 TO 30}`
+    pub fn range(mut self, field: &str, name: &str, range: &str) -> Self {
+        self.ranges
+            .get_or_insert_with(HashMap::new)
+            .entry(field.to_string())
+            .or_default()
+            .insert(name.to_string(), range.to_string());
+        self
+    }
+
+    pub fn drilldown(mut self, field: &str, value: &str) -> Self {
+        self.drilldown.push((field.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn highlight_fields(mut self, fields: Vec<String>) -> Self {
+        self.highlight_fields = Some(fields);
+        self
+    }
+
+    /// Tags that surround the highlighted terms; `<em>` and `</em>` by default
+    pub fn highlight_tags(mut self, pre_tag: &str, post_tag: &str) -> Self {
+        self.highlight_pre_tag = Some(pre_tag.to_string());
+        self.highlight_post_tag = Some(post_tag.to_string());
+        self
+    }
+
+    pub fn highlight_number(mut self, number: u32) -> Self {
+        self.highlight_number = Some(number);
+        self
+    }
+
+    pub fn highlight_size(mut self, size: u32) -> Self {
+        self.highlight_size = Some(size);
+        self
+    }
+}
+
+/// Results of a search query
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+pub struct SearchResult<T> {
+    pub total_rows: u64,
+    /// Bookmark to request the next page of results
+    pub bookmark: Option<String>,
+    pub rows: Vec<SearchRow<T>>,
+    /// Number of results per value of the fields in `counts`
+    pub counts: Option<HashMap<String, HashMap<String, u64>>>,
+    /// Number of results per named range of the fields in `ranges`
+    pub ranges: Option<HashMap<String, HashMap<String, u64>>>,
+}
+
+/// A document found by a search query
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+pub struct SearchRow<T> {
+    pub id: String,
+    /// The sort order of the row, such as its score
+    #[serde(default)]
+    pub order: Vec<Value>,
+    /// The stored fields of the document
+    #[serde(default)]
+    pub fields: Map<String, Value>,
+    /// The document, when the query was run with `include_docs(true)`
+    pub doc: Option<T>,
+    /// Highlighted fragments per field, when the query sets `highlight_fields`
+    pub highlights: Option<HashMap<String, Vec<String>>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_search_query() {
+        let query = SearchQuery::new("name:j*")
+            .bookmark("g1A")
+            .sort(vec![s!("-age<number>")])
+            .drilldown("city", "Paris")
+            .highlight_tags("<b>", "</b>");
+        assert_eq!(
+            json!(query),
+            json!({
+                "query": "name:j*",
+                "bookmark": "g1A",
+                "sort": ["-age<number>"],
+                "drilldown": [["city", "Paris"]],
+                "highlight_pre_tag": "<b>",
+                "highlight_post_tag": "</b>"
+            })
+        );
+    }
+
+    #[test]
+    fn test_search_result() {
+        let result: SearchResult<Value> = serde_json::from_value(json!({
+            "total_rows": 1,
+            "bookmark": "g1A",
+            "rows": [{
+                "id": "john",
+                "order": [1.2, 0],
+                "fields": {"name": "john"},
+                "doc": {"_id": "john", "_rev": "1-a", "name": "john"},
+                "highlights": {"bio": ["<em>john</em> lives"]}
+            }],
+            "counts": {"city": {"Paris": 1}}
+        }))
+        .unwrap();
+        assert_eq!(result.rows[0].fields["name"], "john");
+        assert_eq!(result.rows[0].highlights.as_ref().unwrap()["bio"].len(), 1);
+        assert_eq!(result.counts.unwrap()["city"]["Paris"], 1);
+        assert_eq!(result.ranges, None);
+    }
+}
@@ -0,0 +1,264 @@
+use crate::types::find::FindQuery;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt::{Display, Formatter};
+
+/// Mango selector. Use the builder paradigm to construct selectors without hand-writing the
+/// nested json. The selector syntax is documented here:
+/// [find-selectors](https://docs.couchdb.org/en/stable/api/database/find.html#find-selectors)
+///
+/// ```
+/// use couch_rs::types::find::FindQuery;
+/// use couch_rs::types::selector::Selector;
+///
+/// let selector = Selector::field("age").gt(21).and(Selector::field("type").eq("user"));
+/// assert_eq!(selector.to_string(), r#"{"$and":[{"age":{"$gt":21}},{"type":{"$eq":"user"}}]}"#);
+///
+/// let _query = FindQuery::from(selector).limit(10);
+/// ```
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(transparent)]
+pub struct Selector(Value);
+
+/// Builds a condition on a single field; the field may be a dotted path into nested objects,
+/// for example `address.city`.
+#[derive(Debug, Clone)]
+pub struct FieldSelector {
+    field: String,
+}
+
+impl Selector {
+    /// Starts a condition on the given field
+    pub fn field(name: &str) -> FieldSelector {
+        FieldSelector {
+            field: name.to_string(),
+        }
+    }
+
+    /// Matches all documents
+    pub fn all() -> Self {
+        Selector::field("_id").gt(Value::Null)
+    }
+
+    /// Matches when both this and the other selector match
+    pub fn and(self, other: Selector) -> Self {
+        self.combine("$and", other)
+    }
+
+    /// Matches when this or the other selector matches
+    pub fn or(self, other: Selector) -> Self {
+        self.combine("$or", other)
+    }
+
+    /// Matches when none of the selectors match
+    pub fn nor(selectors: Vec<Selector>) -> Self {
+        Selector::operator("$nor", Value::Array(selectors.into_iter().map(Value::from).collect()))
+    }
+
+    /// Matches when the given selector does not match
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(selector: Selector) -> Self {
+        Selector::operator("$not", selector.0)
+    }
+
+    pub fn as_value(&self) -> &Value {
+        &self.0
+    }
+
+    fn operator(operator: &str, value: Value) -> Self {
+        let mut map = Map::new();
+        map.insert(operator.to_string(), value);
+        Selector(Value::Object(map))
+    }
+
+    /// Appends to an existing `$and` / `$or` list, instead of nesting them
+    fn combine(self, operator: &str, other: Selector) -> Self {
+        let mut selectors = match self.0 {
+            Value::Object(mut map) if map.len() == 1 && map.contains_key(operator) => match map.remove(operator) {
+                Some(Value::Array(list)) => list,
+                Some(value) => vec![value],
+                None => vec![],
+            },
+            value => vec![value],
+        };
+
+        selectors.push(other.0);
+        Selector::operator(operator, Value::Array(selectors))
+    }
+}
+
+impl FieldSelector {
+    /// The field is equal to the argument
+    pub fn eq<T: Into<Value>>(self, value: T) -> Selector {
+        self.condition("$eq", value.into())
+    }
+
+    /// The field is not equal to the argument
+    pub fn ne<T: Into<Value>>(self, value: T) -> Selector {
+        self.condition("$ne", value.into())
+    }
+
+    /// The field is greater than the argument
+    pub fn gt<T: Into<Value>>(self, value: T) -> Selector {
+        self.condition("$gt", value.into())
+    }
+
+    /// The field is greater than or equal to the argument
+    pub fn gte<T: Into<Value>>(self, value: T) -> Selector {
+        self.condition("$gte", value.into())
+    }
+
+    /// The field is less than the argument
+    pub fn lt<T: Into<Value>>(self, value: T) -> Selector {
+        self.condition("$lt", value.into())
+    }
+
+    /// The field is less than or equal to the argument
+    pub fn lte<T: Into<Value>>(self, value: T) -> Selector {
+        self.condition("$lte", value.into())
+    }
+
+    /// Checks whether the field exists or not, regardless of its value
+    pub fn exists(self, exists: bool) -> Selector {
+        self.condition("$exists", Value::Bool(exists))
+    }
+
+    /// Checks the type of the field. Valid values are "null", "boolean", "number", "string", "array",
+    /// and "object"
+    pub fn type_of(self, type_name: &str) -> Selector {
+        self.condition("$type", Value::from(type_name))
+    }
+
+    /// The field must exist in the list provided
+    pub fn in_<T: Into<Value>>(self, values: Vec<T>) -> Selector {
+        self.condition("$in", values.into())
+    }
+
+    /// The field must not exist in the list provided
+    pub fn nin<T: Into<Value>>(self, values: Vec<T>) -> Selector {
+        self.condition("$nin", values.into())
+    }
+
+    /// The field is an array that contains all the elements of the argument
+    pub fn all<T: Into<Value>>(self, values: Vec<T>) -> Selector {
+        self.condition("$all", values.into())
+    }
+
+    /// The field is an array with the given length
+    pub fn size(self, size: u64) -> Selector {
+        self.condition("$size", Value::from(size))
+    }
+
+    /// The field is an integer, and the remainder of a division by the divisor equals the remainder
+    pub fn modulo(self, divisor: i64, remainder: i64) -> Selector {
+        self.condition("$mod", Value::from(vec![divisor, remainder]))
+    }
+
+    /// The field is a string matching the (Erlang flavored) regular expression
+    pub fn regex(self, pattern: &str) -> Selector {
+        self.condition("$regex", Value::from(pattern))
+    }
+
+    /// The field is a string starting with the prefix (CouchDB 3.4+)
+    pub fn begins_with(self, prefix: &str) -> Selector {
+        self.condition("$beginsWith", Value::from(prefix))
+    }
+
+    /// The field is an array, and at least one of its elements matches the selector
+    pub fn elem_match(self, selector: Selector) -> Selector {
+        self.condition("$elemMatch", selector.0)
+    }
+
+    /// The field is an array, and all of its elements match the selector
+    pub fn all_match(self, selector: Selector) -> Selector {
+        self.condition("$allMatch", selector.0)
+    }
+
+    /// The field is an object, and at least one of its keys matches the selector
+    pub fn key_map_match(self, selector: Selector) -> Selector {
+        self.condition("$keyMapMatch", selector.0)
+    }
+
+    fn condition(self, operator: &str, value: Value) -> Selector {
+        let mut map = Map::new();
+        map.insert(self.field, Selector::operator(operator, value).0);
+        Selector(Value::Object(map))
+    }
+}
+
+impl From<Selector> for Value {
+    fn from(selector: Selector) -> Value {
+        selector.0
+    }
+}
+
+impl From<Selector> for FindQuery {
+    fn from(selector: Selector) -> FindQuery {
+        FindQuery::new(selector.0)
+    }
+}
+
+impl Display for Selector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_field_conditions() {
+        assert_eq!(
+            Value::from(Selector::field("age").gte(21)),
+            json!({"age": {"$gte": 21}})
+        );
+        assert_eq!(
+            Value::from(Selector::field("type").in_(vec!["user", "admin"])),
+            json!({"type": {"$in": ["user", "admin"]}})
+        );
+        assert_eq!(
+            Value::from(Selector::field("count").modulo(4, 0)),
+            json!({"count": {"$mod": [4, 0]}})
+        );
+        assert_eq!(
+            Value::from(Selector::field("genre").elem_match(Selector::field("name").regex("^Horror"))),
+            json!({"genre": {"$elemMatch": {"name": {"$regex": "^Horror"}}}})
+        );
+        assert_eq!(Value::from(Selector::all()), json!({"_id": {"$gt": null}}));
+    }
+
+    #[test]
+    fn test_combinations() {
+        let selector = Selector::field("a")
+            .eq(1)
+            .and(Selector::field("b").exists(true))
+            .and(Selector::not(Selector::field("c").eq("x")));
+
+        assert_eq!(
+            Value::from(selector),
+            json!({"$and": [
+                {"a": {"$eq": 1}},
+                {"b": {"$exists": true}},
+                {"$not": {"c": {"$eq": "x"}}}
+            ]})
+        );
+
+        let selector = Selector::field("a")
+            .eq(1)
+            .or(Selector::field("a").eq(2))
+            .and(Selector::field("b").lt(3));
+        assert_eq!(
+            Value::from(selector),
+            json!({"$and": [{"$or": [{"a": {"$eq": 1}}, {"a": {"$eq": 2}}]}, {"b": {"$lt": 3}}]})
+        );
+    }
+
+    #[test]
+    fn test_into_find_query() {
+        let query = FindQuery::from(Selector::field("thing").eq(true)).limit(1);
+        assert_eq!(query.to_string(), r#"{"limit":1,"selector":{"thing":{"$eq":true}}}"#);
+    }
+}
@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
 
 /// Couch vendor abstraction
 #[derive(Serialize, Deserialize, Debug)]
@@ -15,6 +17,15 @@ pub struct CouchStatus {
     pub uuid: Option<String>,
     pub version: String,
     pub vendor: CouchVendor,
+    /// Optional features enabled on the server, such as `nouveau` or `partitioned`
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+impl CouchStatus {
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
 }
 
 /// Couch response abstraction
@@ -45,7 +56,8 @@ pub struct SizeInfo {
 /// Database information
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DbProperties {
-    partitioned: Option<bool>,
+    /// Whether the database was created partitioned
+    pub partitioned: Option<bool>,
 }
 
 /// Database information
@@ -63,3 +75,119 @@ pub struct DbInfo {
     pub update_seq: String,
     pub props: DbProperties,
 }
+
+/// Options for `Client::rename_db`. Use the builder paradigm to override the defaults.
+#[derive(Clone)]
+pub struct RenameOptions {
+    /// Time the replication may take. When it did not complete in time, the rename fails, and the
+    /// old database is kept.
+    pub timeout: Duration,
+    /// Credentials the replicator uses to read the old database and write the new one. They are
+    /// stored in the replication document until the replication ended; without them, the
+    /// replicator connects anonymously.
+    pub credentials: Option<(String, String)>,
+}
+
+impl Default for RenameOptions {
+    fn default() -> Self {
+        RenameOptions {
+            timeout: Duration::from_secs(600),
+            credentials: None,
+        }
+    }
+}
+
+impl RenameOptions {
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn credentials(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some((username.to_string(), password.to_string()));
+        self
+    }
+}
+
+impl fmt::Debug for RenameOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RenameOptions")
+            .field("timeout", &self.timeout)
+            .field("credentials", &self.credentials.as_ref().map(|(username, _)| username))
+            .finish()
+    }
+}
+
+/// Steps of `Client::rename_db`, reported to its progress callback
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenameProgress {
+    /// The new database was created with the shards, replicas and partitioning of the old one
+    Created { q: u32, n: u32, partitioned: bool },
+    /// The replication is running, and wrote `docs_written` documents so far; the old database
+    /// held `doc_count` documents when the rename started
+    Replicating { docs_written: u64, doc_count: u64 },
+    /// The new database holds as many documents as the old one
+    Verified { doc_count: u64 },
+    /// The old database was deleted
+    Deleted,
+}
+
+/// A task running on the server, as listed by `_active_tasks`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ActiveTask {
+    /// Task type, such as `indexer`, `database_compaction` or `replication`
+    #[serde(rename = "type")]
+    pub task_type: String,
+    pub node: Option<String>,
+    pub pid: Option<String>,
+    /// Database (shard) the task runs on
+    pub database: Option<String>,
+    pub design_document: Option<String>,
+    /// Progress in percent
+    pub progress: Option<u32>,
+    pub changes_done: Option<u64>,
+    pub total_changes: Option<u64>,
+    pub started_on: Option<u64>,
+    pub updated_on: Option<u64>,
+}
+
+impl ActiveTask {
+    /// Whether the task runs on the given database. In a cluster, tasks run on the shards of a
+    /// database, named like `shards/00000000-1fffffff/{dbname}.1591254301`.
+    pub fn runs_on(&self, dbname: &str) -> bool {
+        match &self.database {
+            Some(database) if database == dbname => true,
+            Some(database) => database
+                .strip_prefix("shards/")
+                .and_then(|shard| shard.split_once('/'))
+                .and_then(|(_, shard)| shard.rsplit_once('.'))
+                .is_some_and(|(name, _)| name == dbname),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_active_task_runs_on() {
+        let task: ActiveTask = serde_json::from_value(json!({
+            "type": "indexer",
+            "database": "shards/00000000-1fffffff/some/db.1591254301",
+            "design_document": "_design/warm",
+            "progress": 42
+        }))
+        .unwrap();
+        assert!(task.runs_on("some/db"));
+        assert!(!task.runs_on("db"));
+
+        let task = ActiveTask {
+            database: Some("db".to_string()),
+            ..task
+        };
+        assert!(task.runs_on("db"));
+    }
+}
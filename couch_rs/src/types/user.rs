@@ -0,0 +1,136 @@
+use crate::document::TypedCouchDocument;
+use crate::types::document::DocumentId;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+
+/// Name of the database that holds the users of the server
+pub const USERS_DB: &str = "_users";
+
+/// Prefix of the document ID of every user in the `_users` database
+pub const USER_ID_PREFIX: &str = "org.couchdb.user:";
+
+/// A user document in the `_users` database.
+/// See [users](https://docs.couchdb.org/en/stable/intro/security.html#users-documents) for more details.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct User {
+    #[serde(rename = "_id")]
+    pub id: DocumentId,
+    #[serde(rename = "_rev", default, skip_serializing_if = "String::is_empty")]
+    pub rev: String,
+    pub name: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(rename = "type")]
+    pub user_type: String,
+    /// Plain text password to set. CouchDB replaces it with a hash when the document is saved, so
+    /// it is never returned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Other fields, such as the `derived_key` and `salt` of the hashed password, which are kept
+    /// when the user is saved again
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+impl User {
+    pub fn new(name: &str, password: &str, roles: Vec<String>) -> User {
+        User {
+            id: User::id_for(name),
+            rev: String::default(),
+            name: name.to_string(),
+            roles,
+            user_type: "user".to_string(),
+            password: Some(password.to_string()),
+            other: Map::new(),
+        }
+    }
+
+    /// The document ID of the user with the given name, such as `org.couchdb.user:john`
+    pub fn id_for(name: &str) -> DocumentId {
+        format!("{}{}", USER_ID_PREFIX, name)
+    }
+
+    /// Adds the roles the user does not have yet, keeping the order of the existing roles
+    pub fn add_roles(&mut self, roles: &[String]) {
+        for role in roles {
+            if !self.roles.contains(role) {
+                self.roles.push(role.clone());
+            }
+        }
+    }
+
+    pub fn remove_roles(&mut self, roles: &[String]) {
+        self.roles.retain(|role| !roles.contains(role));
+    }
+}
+
+impl TypedCouchDocument for User {
+    fn get_id(&self) -> Cow<'_, str> {
+        Cow::from(&self.id)
+    }
+
+    fn get_rev(&self) -> Cow<'_, str> {
+        Cow::from(&self.rev)
+    }
+
+    fn set_rev(&mut self, rev: &str) {
+        self.rev = rev.to_string();
+    }
+
+    fn set_id(&mut self, id: &str) {
+        self.id = id.to_string();
+    }
+
+    fn merge_ids(&mut self, other: &Self) {
+        self.set_id(&other.get_id());
+        self.set_rev(&other.get_rev());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_user_serde() {
+        let user = User::new("john", "secret", vec![s!("reader")]);
+        assert_eq!(
+            serde_json::to_value(&user).unwrap(),
+            json!({
+                "_id": "org.couchdb.user:john",
+                "name": "john",
+                "roles": ["reader"],
+                "type": "user",
+                "password": "secret"
+            })
+        );
+
+        // the hashed password is kept when the user is written back
+        let stored = json!({
+            "_id": "org.couchdb.user:john",
+            "_rev": "1-abc",
+            "name": "john",
+            "roles": [],
+            "type": "user",
+            "password_scheme": "pbkdf2",
+            "derived_key": "d3a1",
+            "salt": "5a17"
+        });
+        let user: User = serde_json::from_value(stored.clone()).unwrap();
+        assert_eq!(user.password, None);
+        assert_eq!(user.other["derived_key"], "d3a1");
+        assert_eq!(serde_json::to_value(&user).unwrap(), stored);
+    }
+
+    #[test]
+    fn test_roles() {
+        let mut user = User::new("john", "secret", vec![s!("reader"), s!("writer")]);
+        user.add_roles(&[s!("admin"), s!("reader")]);
+        assert_eq!(user.roles, vec![s!("reader"), s!("writer"), s!("admin")]);
+
+        user.remove_roles(&[s!("writer"), s!("unknown")]);
+        assert_eq!(user.roles, vec![s!("reader"), s!("admin")]);
+    }
+}
@@ -1,22 +1,35 @@
-use crate::document::TypedCouchDocument;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 
+/// Rows returned by a view query, with keys of type `K`, values of type `V` and, when the query
+/// ran with `include_docs`, documents of type `T`. Each of them can be any `DeserializeOwned`
+/// type, such as a `String`, a tuple for array keys, a struct, or a `serde_json::Value`.
 #[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
-#[serde(bound(deserialize = "T: TypedCouchDocument"))]
-pub struct ViewCollection<K: DeserializeOwned, V: DeserializeOwned, T: TypedCouchDocument> {
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+pub struct ViewCollection<K: DeserializeOwned, V: DeserializeOwned, T: DeserializeOwned> {
     pub offset: Option<u32>,
     pub rows: Vec<ViewItem<K, V, T>>,
     pub total_rows: Option<u32>,
+    /// Sequence of the database the rows were read at; only returned for queries with `update_seq(true)`.
+    /// A changes feed started from this sequence continues exactly after the rows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_seq: Option<String>,
 }
 
 pub type RawViewCollection<K, V> = ViewCollection<K, V, Value>;
 
+impl<K: DeserializeOwned, V: DeserializeOwned, T: DeserializeOwned> ViewCollection<K, V, T> {
+    /// Returns the documents included by `include_docs`; rows without a document are skipped
+    pub fn into_docs(self) -> Vec<T> {
+        self.rows.into_iter().filter_map(|row| row.doc).collect()
+    }
+}
+
 #[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
-#[serde(bound(deserialize = "T: TypedCouchDocument"))]
-pub struct ViewItem<K: DeserializeOwned, V: DeserializeOwned, T: TypedCouchDocument> {
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+pub struct ViewItem<K: DeserializeOwned, V: DeserializeOwned, T: DeserializeOwned> {
     pub key: K,
     pub value: V,
     pub id: Option<String>,
@@ -42,10 +55,10 @@ pub struct CouchViews {
 ///     reduce: None,
 /// };
 /// ```
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 pub struct CouchFunc {
     pub map: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub reduce: Option<String>,
 }
 
@@ -82,17 +95,134 @@ impl CouchFunc {
             reduce: reduce.map(|r| r.to_string()),
         }
     }
+
+    /// Creates a view function that reduces with one of the reducers built into CouchDB
+    pub fn with_builtin_reduce(map: &str, reduce: BuiltinReduce) -> Self {
+        CouchFunc::new(map, Some(reduce.as_str()))
+    }
+}
+
+/// The reduce functions built into CouchDB. These run natively, and are much faster than
+/// their JavaScript equivalents. See
+/// [built-in reduce functions](https://docs.couchdb.org/en/stable/ddocs/ddocs.html#reduce-and-rereduce-functions).
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum BuiltinReduce {
+    /// Sums the numeric values, or arrays of numbers
+    #[serde(rename = "_sum")]
+    Sum,
+    /// Counts the rows
+    #[serde(rename = "_count")]
+    Count,
+    /// Computes the sum, count, min, max and sum of squares of the numeric values
+    #[serde(rename = "_stats")]
+    Stats,
+    /// Approximates the number of distinct keys
+    #[serde(rename = "_approx_count_distinct")]
+    ApproxCountDistinct,
 }
 
-impl Into<serde_json::Value> for CouchViews {
-    fn into(self) -> Value {
-        serde_json::to_value(self).unwrap()
+impl BuiltinReduce {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BuiltinReduce::Sum => "_sum",
+            BuiltinReduce::Count => "_count",
+            BuiltinReduce::Stats => "_stats",
+            BuiltinReduce::ApproxCountDistinct => "_approx_count_distinct",
+        }
     }
 }
 
-impl Into<serde_json::Value> for CouchFunc {
-    fn into(self) -> Value {
-        serde_json::to_value(self).unwrap()
+/// Builds a JavaScript map function that emits one or more rows per document, optionally only for
+/// the documents that match all conditions. Keys and values are JavaScript expressions over `doc`.
+///
+/// ```
+/// use couch_rs::types::view::{BuiltinReduce, MapFunction};
+///
+/// let func = MapFunction::new()
+///     .when_type("order")
+///     .emit_field("customer", "doc.total")
+///     .emit("[doc.year, doc.month]", "doc.total")
+///     .reduce(BuiltinReduce::Stats);
+///
+/// assert_eq!(
+///     func.map,
+///     r#"function (doc) { if (doc.type === "order") { emit(doc["customer"], doc.total); emit([doc.year, doc.month], doc.total); } }"#
+/// );
+/// assert_eq!(func.reduce.as_deref(), Some("_stats"));
+/// ```
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct MapFunction {
+    conditions: Vec<String>,
+    emits: Vec<(String, String)>,
+}
+
+impl MapFunction {
+    pub fn new() -> Self {
+        MapFunction::default()
+    }
+
+    /// Only emits rows for the documents for which the JavaScript condition holds
+    pub fn when(mut self, condition: &str) -> Self {
+        self.conditions.push(condition.to_string());
+        self
+    }
+
+    /// Only emits rows for the documents with the given `type` field
+    pub fn when_type(self, doc_type: &str) -> Self {
+        self.when(&format!("doc.type === {}", Value::from(doc_type)))
+    }
+
+    /// Emits a row with the given key and value expressions
+    pub fn emit(mut self, key: &str, value: &str) -> Self {
+        self.emits.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Emits a row keyed on a field of the document
+    pub fn emit_field(self, field: &str, value: &str) -> Self {
+        self.emit(&format!("doc[{}]", Value::from(field)), value)
+    }
+
+    /// Completes the view function, reducing with a built-in reducer
+    pub fn reduce(self, reduce: BuiltinReduce) -> CouchFunc {
+        CouchFunc::with_builtin_reduce(&self.build(), reduce)
+    }
+
+    /// Returns the JavaScript source of the map function
+    pub fn build(&self) -> String {
+        let emits: String = self
+            .emits
+            .iter()
+            .map(|(key, value)| format!("emit({}, {}); ", key, value))
+            .collect();
+
+        if self.conditions.is_empty() {
+            format!("function (doc) {{ {}}}", emits)
+        } else {
+            format!(
+                "function (doc) {{ if ({}) {{ {}}} }}",
+                self.conditions.join(" && "),
+                emits
+            )
+        }
+    }
+}
+
+impl From<MapFunction> for CouchFunc {
+    fn from(map: MapFunction) -> Self {
+        CouchFunc::new(&map.build(), None)
+    }
+}
+
+impl From<CouchViews> for serde_json::Value {
+    fn from(value: CouchViews) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+}
+
+impl From<CouchFunc> for serde_json::Value {
+    fn from(value: CouchFunc) -> Value {
+        serde_json::to_value(value).unwrap()
     }
 }
 
@@ -110,8 +240,75 @@ impl CouchUpdate {
     }
 }
 
-impl Into<serde_json::Value> for CouchUpdate {
-    fn into(self) -> Value {
-        serde_json::to_value(self).unwrap()
+impl From<CouchUpdate> for serde_json::Value {
+    fn from(value: CouchUpdate) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_view_collection() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Order {
+            total: u32,
+        }
+
+        let collection: ViewCollection<(u32, u32), u32, Order> = serde_json::from_value(serde_json::json!({
+            "total_rows": 2,
+            "offset": 0,
+            "rows": [
+                {"id": "a", "key": [2021, 3], "value": 10, "doc": {"_id": "a", "total": 10}},
+                {"id": "b", "key": [2021, 4], "value": 20}
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(collection.update_seq, None);
+        assert_eq!(collection.rows[0].key, (2021, 3));
+        assert_eq!(collection.rows[1].value, 20);
+        assert_eq!(collection.into_docs(), vec![Order { total: 10 }]);
+    }
+
+    #[test]
+    fn test_update_seq() {
+        let collection: RawViewCollection<Value, Value> = serde_json::from_value(serde_json::json!({
+            "total_rows": 0,
+            "offset": 0,
+            "update_seq": "12-g1AAAA",
+            "rows": []
+        }))
+        .unwrap();
+        assert_eq!(collection.update_seq.as_deref(), Some("12-g1AAAA"));
+    }
+
+    #[test]
+    fn test_builtin_reduce() {
+        let func = CouchFunc::with_builtin_reduce("function (doc) { emit(doc._id, 1); }", BuiltinReduce::Sum);
+        assert_eq!(func.reduce.as_deref(), Some("_sum"));
+        assert_eq!(
+            serde_json::to_string(&BuiltinReduce::ApproxCountDistinct).unwrap(),
+            r#""_approx_count_distinct""#
+        );
+    }
+
+    #[test]
+    fn test_map_function() {
+        let func: CouchFunc = MapFunction::new().emit("doc._id", "null").into();
+        assert_eq!(func.map, "function (doc) { emit(doc._id, null); }");
+        assert_eq!(func.reduce, None);
+
+        let map = MapFunction::new()
+            .when("doc.active")
+            .when_type("user \"admin\"")
+            .emit_field("na\"me", "1")
+            .build();
+        assert_eq!(
+            map,
+            r#"function (doc) { if (doc.active && doc.type === "user \"admin\"") { emit(doc["na\"me"], 1); } }"#
+        );
     }
 }
@@ -0,0 +1,136 @@
+use crate::error::CouchResult;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+
+/// A check that runs on every document before the `Database` writes it, through `save`, `create`,
+/// `bulk_docs`, `bulk_import` and the `BulkWriter`. A hook can change the document, for example to
+/// stamp an `updated_at` field, or reject it by returning an error, in which case nothing is written.
+/// Deleted documents are not validated.
+///
+/// The changes of the hooks are written back into the document returned by `save` and `create`, so
+/// it matches the stored revision. When the changed document no longer deserializes into the type of
+/// the caller, that document is returned as it was, with only its new `_id` and `_rev`.
+///
+/// Closures taking a `&mut Value` are hooks too:
+/// ```
+/// use couch_rs::error::{CouchError, CouchResult};
+/// use serde_json::{json, Value};
+///
+/// const TEST_DB: &str = "test_db";
+///
+/// #[tokio::main]
+/// async fn main() -> CouchResult<()> {
+///     let client = couch_rs::Client::new_local_test()?;
+///     let db = client
+///         .db(TEST_DB)
+///         .await?
+///         .with_validation_hook(|doc: &mut Value| {
+///             if doc["name"].is_string() {
+///                 Ok(())
+///             } else {
+///                 Err(CouchError::new("name is required".to_string(), reqwest::StatusCode::BAD_REQUEST))
+///             }
+///         })
+///         .with_validation_hook(|doc: &mut Value| {
+///             doc["updated_at"] = json!(1_600_000_000);
+///             Ok(())
+///         });
+///
+///     assert!(db.create(json!({"age": 42})).await.is_err());
+///     let doc = db.create(json!({"name": "John"})).await?;
+///     assert_eq!(doc["updated_at"], 1_600_000_000);
+///     Ok(())
+/// }
+/// ```
+pub trait ValidationHook: Send + Sync {
+    /// Checks the document, which may be changed in place. Return an error to reject it.
+    fn validate(&self, doc: &mut Value) -> CouchResult<()>;
+}
+
+impl<F> ValidationHook for F
+where
+    F: Fn(&mut Value) -> CouchResult<()> + Send + Sync,
+{
+    fn validate(&self, doc: &mut Value) -> CouchResult<()> {
+        self(doc)
+    }
+}
+
+/// The hooks registered on a `Database`, run in the order they were added
+#[derive(Clone, Default)]
+pub(crate) struct ValidationHooks(Vec<Arc<dyn ValidationHook>>);
+
+impl ValidationHooks {
+    pub(crate) fn push(&mut self, hook: Arc<dyn ValidationHook>) {
+        self.0.push(hook);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Serializes the document and runs all hooks on it. A rejection is returned with the id of the
+    /// document, when it has one.
+    pub(crate) fn apply<T: Serialize>(&self, doc: &T) -> CouchResult<Value> {
+        let mut value = serde_json::to_value(doc)?;
+        if value["_deleted"] == Value::Bool(true) {
+            return Ok(value);
+        }
+
+        for hook in &self.0 {
+            hook.validate(&mut value)
+                .map_err(|err| err.with_id(value["_id"].as_str().map(String::from)))?;
+        }
+        Ok(value)
+    }
+}
+
+impl fmt::Debug for ValidationHooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ValidationHooks({})", self.0.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CouchError;
+    use serde_json::json;
+
+    fn hooks() -> ValidationHooks {
+        let mut hooks = ValidationHooks::default();
+        hooks.push(Arc::new(|doc: &mut Value| {
+            if doc["name"].is_string() {
+                Ok(())
+            } else {
+                Err(CouchError::new(
+                    "name is required".to_string(),
+                    reqwest::StatusCode::BAD_REQUEST,
+                ))
+            }
+        }));
+        hooks.push(Arc::new(|doc: &mut Value| {
+            doc["updated_at"] = json!(42);
+            Ok(())
+        }));
+        hooks
+    }
+
+    #[test]
+    fn test_apply_hooks() {
+        let hooks = hooks();
+        assert_eq!(format!("{:?}", hooks), "ValidationHooks(2)");
+
+        let doc = hooks.apply(&json!({"_id": "a", "name": "John"})).unwrap();
+        assert_eq!(doc, json!({"_id": "a", "name": "John", "updated_at": 42}));
+
+        let err = hooks.apply(&json!({"_id": "b"})).unwrap_err();
+        assert_eq!(err.id(), Some("b"));
+        assert_eq!(err.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        let tombstone = json!({"_id": "c", "_rev": "1-c", "_deleted": true});
+        assert_eq!(hooks.apply(&tombstone).unwrap(), tombstone);
+    }
+}
@@ -0,0 +1,219 @@
+use crate::database::Database;
+use crate::document::TypedCouchDocument;
+use crate::error::{CouchError, CouchResult};
+use crate::runtime::{sleep, BoxFuture, Sleep};
+use crate::types::document::DocumentCreatedResult;
+use futures_sink::Sink;
+use std::future::{poll_fn, Future};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+type BulkRequest = BoxFuture<CouchResult<Vec<DocumentCreatedResult>>>;
+
+/// Options for the `BulkWriter`. Use the builder paradigm to override the defaults.
+#[derive(Debug, Clone)]
+pub struct BulkWriterOptions {
+    /// Flush when this number of documents is buffered
+    pub max_docs: usize,
+    /// Flush when the buffered documents reach this size, in bytes
+    pub max_bytes: usize,
+    /// Flush documents that have been buffered for this long. The interval is only checked when a
+    /// document is written or the writer is flushed; no timer flushes an idle writer.
+    pub flush_interval: Option<Duration>,
+}
+
+impl Default for BulkWriterOptions {
+    fn default() -> Self {
+        BulkWriterOptions {
+            max_docs: 1000,
+            max_bytes: 8 * 1024 * 1024,
+            flush_interval: Some(Duration::from_secs(1)),
+        }
+    }
+}
+
+impl BulkWriterOptions {
+    pub fn max_docs(mut self, max_docs: usize) -> Self {
+        self.max_docs = max_docs.max(1);
+        self
+    }
+
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn flush_interval(mut self, flush_interval: Option<Duration>) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+}
+
+/// Long-lived writer that buffers documents and writes them through `_bulk_docs`, once `max_docs`
+/// documents or `max_bytes` bytes are buffered, or when the oldest buffered document has waited for
+/// `flush_interval`. The writer does not run a background task, so an expired interval only triggers
+/// a write on the next call to `write`: documents buffered before the writer goes idle stay in the
+/// buffer until `flush` or `close` is called.
+///
+/// `BulkWriter` implements `futures::Sink`, so a stream of documents can be forwarded into it.
+/// The results per document are accumulated, and returned by `flush` and `close`.
+///
+/// Usage:
+/// ```
+/// use couch_rs::error::CouchResult;
+/// use couch_rs::writer::BulkWriterOptions;
+/// use serde_json::{json, Value};
+///
+/// const TEST_DB: &str = "test_db";
+///
+/// #[tokio::main]
+/// async fn main() -> CouchResult<()> {
+///     let client = couch_rs::Client::new_local_test()?;
+///     let db = client.db(TEST_DB).await?;
+///     let mut writer = db.bulk_writer::<Value>(BulkWriterOptions::default().max_docs(100));
+///
+///     for i in 0..250 {
+///         writer.write(json!({"event": i})).await?;
+///     }
+///
+///     let results = writer.close().await?;
+///     assert_eq!(results.len(), 250);
+///     Ok(())
+/// }
+/// ```
+pub struct BulkWriter<T: TypedCouchDocument> {
+    db: Database,
+    options: BulkWriterOptions,
+    buffer: Vec<String>,
+    buffer_bytes: usize,
+    deadline: Option<Pin<Box<Sleep>>>,
+    in_flight: Option<BulkRequest>,
+    results: Vec<DocumentCreatedResult>,
+    _doc: PhantomData<fn(T)>,
+}
+
+impl<T: TypedCouchDocument> BulkWriter<T> {
+    pub fn new(db: Database, options: BulkWriterOptions) -> Self {
+        BulkWriter {
+            db,
+            options,
+            buffer: vec![],
+            buffer_bytes: 0,
+            deadline: None,
+            in_flight: None,
+            results: vec![],
+            _doc: PhantomData,
+        }
+    }
+
+    /// Buffers a document, writing the buffer first when it is full
+    pub async fn write(&mut self, doc: T) -> CouchResult<()> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_ready(cx)).await?;
+        Pin::new(&mut *self).start_send(doc)
+    }
+
+    /// Writes all buffered documents, and returns the results accumulated since the previous call
+    pub async fn flush(&mut self) -> CouchResult<Vec<DocumentCreatedResult>> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_flush(cx)).await?;
+        Ok(std::mem::take(&mut self.results))
+    }
+
+    /// Writes all buffered documents, and returns the results accumulated since the previous flush
+    pub async fn close(mut self) -> CouchResult<Vec<DocumentCreatedResult>> {
+        self.flush().await
+    }
+
+    /// Number of documents waiting to be written
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn is_full(&self) -> bool {
+        self.buffer.len() >= self.options.max_docs || self.buffer_bytes >= self.options.max_bytes
+    }
+
+    fn is_expired(&mut self, cx: &mut Context<'_>) -> bool {
+        match self.deadline.as_mut() {
+            Some(deadline) => deadline.as_mut().poll(cx).is_ready(),
+            None => false,
+        }
+    }
+
+    /// Sends the buffered documents, unless a request is still running
+    fn start_request(&mut self) {
+        if self.in_flight.is_some() || self.buffer.is_empty() {
+            return;
+        }
+
+        let body = format!("{{\"docs\":[{}]}}", self.buffer.join(","));
+        self.buffer.clear();
+        self.buffer_bytes = 0;
+        self.deadline = None;
+
+        let db = self.db.clone();
+        self.in_flight = Some(Box::pin(async move { db.post_bulk_body(body).await }));
+    }
+
+    /// Drives the running request, if any, to completion
+    fn poll_in_flight(&mut self, cx: &mut Context<'_>) -> Poll<CouchResult<()>> {
+        if let Some(request) = self.in_flight.as_mut() {
+            let outcome = match request.as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(outcome) => outcome,
+            };
+            self.in_flight = None;
+            self.results.extend(outcome?);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: TypedCouchDocument> Sink<T> for BulkWriter<T> {
+    type Error = CouchError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<CouchResult<()>> {
+        let this = self.get_mut();
+        loop {
+            if let Poll::Ready(Err(err)) = this.poll_in_flight(cx) {
+                return Poll::Ready(Err(err));
+            }
+            if this.in_flight.is_some() {
+                return Poll::Pending;
+            }
+            if this.buffer.is_empty() || !(this.is_full() || this.is_expired(cx)) {
+                return Poll::Ready(Ok(()));
+            }
+            this.start_request();
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, doc: T) -> CouchResult<()> {
+        let this = self.get_mut();
+        let doc = this.db.write_body(&doc)?;
+
+        if this.buffer.is_empty() {
+            this.deadline = this.options.flush_interval.map(|interval| Box::pin(sleep(interval)));
+        }
+
+        this.buffer_bytes += doc.len();
+        this.buffer.push(doc);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<CouchResult<()>> {
+        let this = self.get_mut();
+        loop {
+            match this.poll_in_flight(cx) {
+                Poll::Ready(Ok(())) if !this.buffer.is_empty() => this.start_request(),
+                other => return other,
+            }
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<CouchResult<()>> {
+        self.poll_flush(cx)
+    }
+}
@@ -2,39 +2,115 @@
 extern crate quote;
 
 use proc_macro::TokenStream;
+use syn::{Data, DeriveInput, Fields, Ident, Lit, Meta, NestedMeta};
 
-#[proc_macro_derive(CouchDocument, attributes(serde))]
+#[proc_macro_derive(CouchDocument, attributes(serde, couch))]
 pub fn derive_couch_doc(input: TokenStream) -> TokenStream {
     impl_derive_couch_doc(&syn::parse(input).unwrap())
 }
 
-fn impl_derive_couch_doc(ast: &syn::DeriveInput) -> TokenStream {
+fn impl_derive_couch_doc(ast: &DeriveInput) -> TokenStream {
     let name = &ast.ident;
 
+    let id = match find_field(ast, "_id") {
+        Ok(field) => field,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let rev = match find_field(ast, "_rev") {
+        Ok(field) => field,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let doc_type = match find_doc_type(ast) {
+        Ok(Some(doc_type)) => quote! {
+            fn doc_type() -> Option<&'static str> {
+                Some(#doc_type)
+            }
+        },
+        Ok(None) => quote! {},
+        Err(err) => return err.to_compile_error().into(),
+    };
+
     let gen = quote! {
         impl TypedCouchDocument for #name {
-            fn get_id(&self) -> couch_rs::Cow<str> {
-                couch_rs::Cow::from(&self._id)
+            fn get_id(&self) -> couch_rs::Cow<'_, str> {
+                couch_rs::Cow::from(&self.#id)
             }
 
-            fn get_rev(&self) -> couch_rs::Cow<str> {
-                couch_rs::Cow::from(&self._rev)
+            fn get_rev(&self) -> couch_rs::Cow<'_, str> {
+                couch_rs::Cow::from(&self.#rev)
             }
 
             fn set_id(&mut self, id: &str) {
-                self._id = id.to_string();
+                self.#id = id.to_string();
             }
 
             fn set_rev(&mut self, rev: &str) {
-                self._rev = rev.to_string();
+                self.#rev = rev.to_string();
             }
 
             fn merge_ids(&mut self, other: &Self) {
                 self.set_id(&other.get_id());
                 self.set_rev(&other.get_rev());
             }
+
+            #doc_type
         }
     };
 
     gen.into()
 }
+
+/// Finds the field that holds the CouchDB field `name`; either a field with that name,
+/// or a field renamed to it with `#[serde(rename = "...")]`
+fn find_field<'a>(ast: &'a DeriveInput, name: &str) -> syn::Result<&'a Ident> {
+    if let Data::Struct(data) = &ast.data {
+        if let Fields::Named(fields) = &data.fields {
+            for field in &fields.named {
+                let renamed = attribute_value(&field.attrs, "serde", "rename")?;
+                if let Some(ident) = &field.ident {
+                    if renamed.as_deref() == Some(name) || (renamed.is_none() && ident == name) {
+                        return Ok(ident);
+                    }
+                }
+            }
+        }
+    }
+
+    Err(syn::Error::new_spanned(
+        &ast.ident,
+        format!(
+            "CouchDocument requires a `{}` field, or a field with `#[serde(rename = \"{}\")]`",
+            name, name
+        ),
+    ))
+}
+
+/// Reads the `#[couch(doc_type = "...")]` attribute
+fn find_doc_type(ast: &DeriveInput) -> syn::Result<Option<String>> {
+    attribute_value(&ast.attrs, "couch", "doc_type")
+}
+
+fn attribute_value(attrs: &[syn::Attribute], attribute: &str, key: &str) -> syn::Result<Option<String>> {
+    for attr in attrs.iter().filter(|a| a.path.is_ident(attribute)) {
+        // other serde attributes may not be valid meta items; those are not ours to judge
+        let list = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list,
+            _ => continue,
+        };
+
+        for nested in list.nested {
+            if let NestedMeta::Meta(Meta::NameValue(name_value)) = nested {
+                if name_value.path.is_ident(key) {
+                    return match name_value.lit {
+                        Lit::Str(value) => Ok(Some(value.value())),
+                        lit => Err(syn::Error::new_spanned(lit, format!("`{}` must be a string", key))),
+                    };
+                }
+            }
+        }
+    }
+
+    Ok(None)
+}